@@ -0,0 +1,317 @@
+use crate::{mem, register, syscall::get_regs, variable::Type};
+use nix::unistd::Pid;
+use std::io;
+
+/// class of an eightbyte of a value in the System V ABI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// rax, rdx
+    Integer,
+    /// low half of xmm0, xmm1
+    Sse,
+    /// high half of the xmm register of the previous eightbyte
+    SseUp,
+    /// st0 of the x87 FPU, `long double`
+    X87,
+    X87Up,
+    /// the caller passes the address in rdi and it comes back in rax
+    Memory,
+}
+
+/// scalar in a value, the unit which the Rust ABI places in a register
+#[derive(Debug, Clone, Copy)]
+struct Leaf {
+    offset: u64,
+    size: u64,
+    class: Class,
+}
+
+/// SIMD vector of up to 16 bytes, or a struct wrapping one like `__m128i` of Rust
+pub fn in_xmm(ty: &Type) -> bool {
+    if ty.size() > 16 {
+        return false;
+    }
+    match ty.canonical() {
+        Type::Vector { .. } => true,
+        // rustcはcore::archのベクトル型を配列1つの構造体として出力する
+        Type::Struct { name, members, .. } => match members.as_slice() {
+            [member] if member.ty.size() == ty.size() => {
+                member.ty.is_vector()
+                    || (name.starts_with("__m")
+                        && matches!(member.ty.canonical(), Type::Array { .. }))
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// scalars of the type at `base`, None if a part of the type is unknown
+fn leaves(ty: &Type, base: u64, out: &mut Vec<Leaf>) -> Option<()> {
+    let leaf = |size, class| Leaf {
+        offset: base,
+        size,
+        class,
+    };
+    match ty.canonical() {
+        Type::Base { size: 16, .. } if ty.is_float() => out.push(leaf(16, Class::X87)),
+        Type::Base { size, .. } if ty.is_float() => out.push(leaf(size, Class::Sse)),
+        Type::Base { size, .. } | Type::Enum { size, .. } => out.push(leaf(size, Class::Integer)),
+        Type::Pointer { .. } => out.push(leaf(8, Class::Integer)),
+        Type::Vector { .. } => out.push(leaf(ty.size(), Class::Sse)),
+        // Rustのenumは判別子と中身が重なるので整数として扱う
+        Type::Struct {
+            variant_part: Some(_),
+            size,
+            ..
+        } => out.push(leaf(size, Class::Integer)),
+        Type::Struct { members, .. } => {
+            for member in &members {
+                leaves(&member.ty, base + member.offset, out)?;
+            }
+        }
+        Type::Array { elem, count } => {
+            for i in 0..count? {
+                leaves(&elem, base + i * elem.size(), out)?;
+            }
+        }
+        Type::Void | Type::Typedef { .. } | Type::Lazy(_) | Type::Unknown(_) => return None,
+    }
+    Some(())
+}
+
+/// classes of the eightbytes of the type, a single Memory if it is returned in memory
+pub fn classify(ty: &Type) -> Option<Vec<Class>> {
+    let size = ty.size();
+    if size > 16 {
+        return Some(vec![Class::Memory]);
+    }
+    let mut found = Vec::new();
+    leaves(ty, 0, &mut found)?;
+    let mut classes: Vec<Option<Class>> = vec![None; size.div_ceil(8) as usize];
+    for leaf in found {
+        let first = (leaf.offset / 8) as usize;
+        let last = ((leaf.offset + leaf.size.max(1) - 1) / 8) as usize;
+        for i in first..=last.min(classes.len() - 1) {
+            let class = match (leaf.class, i == first) {
+                (Class::Sse, false) => Class::SseUp,
+                (Class::X87, false) => Class::X87Up,
+                (class, _) => class,
+            };
+            classes[i] = Some(match (classes[i], class) {
+                (None, class) => class,
+                (Some(old), class) if old == class => class,
+                (Some(Class::Memory), _) | (_, Class::Memory) => Class::Memory,
+                (Some(Class::Integer), _) | (_, Class::Integer) => Class::Integer,
+                (Some(Class::X87 | Class::X87Up), _) | (_, Class::X87 | Class::X87Up) => {
+                    Class::Memory
+                }
+                _ => Class::Sse,
+            });
+        }
+    }
+    // メンバーのない8バイトはパディングだけなのでSSEとしておく
+    let classes: Vec<Class> = classes
+        .into_iter()
+        .map(|class| class.unwrap_or(Class::Sse))
+        .collect();
+    let orphan_x87up = classes
+        .iter()
+        .enumerate()
+        .any(|(i, class)| *class == Class::X87Up && (i == 0 || classes[i - 1] != Class::X87));
+    if classes.contains(&Class::Memory) || orphan_x87up {
+        return Some(vec![Class::Memory]);
+    }
+    Some(classes)
+}
+
+/// return registers read in the order the ABI assigns them
+struct Registers {
+    pid: Pid,
+    integer: Vec<u64>,
+    next_integer: usize,
+    next_sse: usize,
+}
+
+impl Registers {
+    fn new(pid: Pid) -> Self {
+        let regs = get_regs(pid);
+        Self {
+            pid,
+            integer: vec![regs.rax, regs.rdx],
+            next_integer: 0,
+            next_sse: 0,
+        }
+    }
+
+    fn integer(&mut self) -> Result<[u8; 8], Box<dyn std::error::Error>> {
+        let value = self
+            .integer
+            .get(self.next_integer)
+            .ok_or_else(|| unsupported("more than two integer registers"))?;
+        self.next_integer += 1;
+        Ok(value.to_le_bytes())
+    }
+
+    fn sse(&mut self) -> Result<[u8; 16], Box<dyn std::error::Error>> {
+        if self.next_sse >= 2 {
+            return Err(unsupported("more than two xmm registers"));
+        }
+        self.next_sse += 1;
+        Ok(register::get_xmm(self.pid, self.next_sse - 1)?)
+    }
+
+    /// the xmm register which the previous eightbyte used
+    fn sse_up(&self) -> Result<[u8; 16], Box<dyn std::error::Error>> {
+        let n = self
+            .next_sse
+            .checked_sub(1)
+            .ok_or_else(|| unsupported("SSEUP without SSE"))?;
+        Ok(register::get_xmm(self.pid, n)?)
+    }
+}
+
+fn unsupported(what: &str) -> Box<dyn std::error::Error> {
+    Box::new(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("return value in {what} is not decoded"),
+    ))
+}
+
+/// true if the caller passes the address of the return value in rdi
+pub fn returns_in_memory(ty: &Type, rust: bool) -> bool {
+    match rust {
+        true => ty.size() > 16 || in_xmm(ty),
+        false => classify(ty).is_some_and(|classes| classes == [Class::Memory]),
+    }
+}
+
+/// bytes of the value which the function just returned
+/// `rust` if the function has the Rust ABI, which differs for aggregates and SIMD vectors
+pub fn return_value(
+    pid: Pid,
+    ty: &Type,
+    rust: bool,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let size = ty.size() as usize;
+    let classes = match rust {
+        true => return rust_return_value(pid, ty),
+        false => classify(ty).ok_or_else(|| unsupported(&format!("type {}", ty.name())))?,
+    };
+    if classes == [Class::Memory] {
+        // 戻り値を書いた領域のアドレスがraxに返る
+        return Ok(mem::read_bytes(pid, get_regs(pid).rax, size)?);
+    }
+    let mut regs = Registers::new(pid);
+    let mut bytes = Vec::new();
+    for class in classes {
+        match class {
+            Class::Integer => bytes.extend(regs.integer()?),
+            Class::Sse => bytes.extend(&regs.sse()?[..8]),
+            Class::SseUp => bytes.extend(&regs.sse_up()?[8..]),
+            Class::X87 => bytes.extend(&register::get_st(pid, 0)?[..8]),
+            Class::X87Up => bytes.extend(&register::get_st(pid, 0)?[8..]),
+            Class::Memory => unreachable!(),
+        }
+    }
+    bytes.truncate(size);
+    Ok(bytes)
+}
+
+/// the Rust ABI returns one or two scalars in their own registers, other values up to
+/// 16 bytes in rax and rdx, and larger values and SIMD vectors in memory
+fn rust_return_value(pid: Pid, ty: &Type) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let size = ty.size() as usize;
+    if size > 16 || in_xmm(ty) {
+        return Ok(mem::read_bytes(pid, get_regs(pid).rax, size)?);
+    }
+    let mut found = Vec::new();
+    leaves(ty, 0, &mut found).ok_or_else(|| unsupported(&format!("type {}", ty.name())))?;
+    let mut regs = Registers::new(pid);
+    let mut bytes = vec![0; size];
+    if found.len() > 2 {
+        let mut integer = regs.integer()?.to_vec();
+        integer.extend(regs.integer()?);
+        bytes.copy_from_slice(&integer[..size]);
+        return Ok(bytes);
+    }
+    for leaf in found {
+        let value = match leaf.class {
+            Class::Sse => regs.sse()?.to_vec(),
+            Class::Integer if leaf.size > 8 => {
+                let mut value = regs.integer()?.to_vec();
+                value.extend(regs.integer()?);
+                value
+            }
+            _ => regs.integer()?.to_vec(),
+        };
+        let (start, len) = (leaf.offset as usize, leaf.size as usize);
+        bytes[start..start + len].copy_from_slice(&value[..len]);
+    }
+    Ok(bytes)
+}
+
+/// how an argument is passed to a function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Passing {
+    /// parts of the value (offset, length) in the next integer or xmm registers
+    Registers(Vec<(u64, u64, Class)>),
+    /// copied onto the stack above the return address
+    Stack,
+    /// address of a copy in the next integer register, for large values of the Rust ABI
+    Reference,
+}
+
+/// how an argument of the type is passed, None if a part of the type is unknown
+pub fn passing(ty: &Type, rust: bool) -> Option<Passing> {
+    let size = ty.size();
+    if rust {
+        return rust_passing(ty);
+    }
+    let classes = classify(ty)?;
+    if classes == [Class::Memory] || classes.contains(&Class::X87) {
+        return Some(Passing::Stack);
+    }
+    let mut parts: Vec<(u64, u64, Class)> = Vec::new();
+    for (i, class) in classes.into_iter().enumerate() {
+        let offset = i as u64 * 8;
+        let len = (size - offset).min(8);
+        match (class, parts.last_mut()) {
+            // SSEUPは前の8バイトと同じxmmレジスタの上半分
+            (Class::SseUp, Some((_, prev, Class::Sse))) => *prev += len,
+            (Class::SseUp, _) => return None,
+            (class, _) => parts.push((offset, len, class)),
+        }
+    }
+    Some(Passing::Registers(parts))
+}
+
+/// the Rust ABI passes one or two scalars in their own registers, other values up to
+/// 16 bytes in integer registers, and larger values and SIMD vectors by reference
+fn rust_passing(ty: &Type) -> Option<Passing> {
+    let size = ty.size();
+    if size > 16 || in_xmm(ty) {
+        return Some(Passing::Reference);
+    }
+    let mut found = Vec::new();
+    leaves(ty, 0, &mut found)?;
+    if found.len() > 2 {
+        let parts = (0..size.div_ceil(8))
+            .map(|i| (i * 8, (size - i * 8).min(8), Class::Integer))
+            .collect();
+        return Some(Passing::Registers(parts));
+    }
+    let mut parts = Vec::new();
+    for leaf in found {
+        match leaf.class {
+            Class::Integer if leaf.size > 8 => {
+                parts.push((leaf.offset, 8, Class::Integer));
+                parts.push((leaf.offset + 8, leaf.size - 8, Class::Integer));
+            }
+            Class::Sse => parts.push((leaf.offset, leaf.size, Class::Sse)),
+            Class::Integer => parts.push((leaf.offset, leaf.size, Class::Integer)),
+            _ => return None,
+        }
+    }
+    Some(Passing::Registers(parts))
+}
@@ -1,19 +1,69 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
+    #[clap(subcommand)]
+    pub mode: Option<Mode>,
+
     /// target file
-    pub file: String,
+    #[clap(required_unless_present = "pid")]
+    pub file: Option<String>,
+
+    /// attach to the running process instead of starting the target file
+    #[clap(short, long, conflicts_with = "file")]
+    pub pid: Option<i32>,
 
     /// arguments passed target file
     #[clap(short, long)]
     pub args: Vec<String>,
+
+    /// run the commands in the file instead of reading stdin, then quit
+    #[clap(short, long)]
+    pub script: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Mode {
+    /// run the script against every file in the input directory in parallel
+    Batch(BatchArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct BatchArgs {
+    /// target file
+    pub file: String,
+
+    /// commands run for each input
+    #[clap(short, long)]
+    pub script: String,
+
+    /// directory of the inputs
+    #[clap(short, long)]
+    pub inputs: String,
+
+    /// number of worker processes, the number of CPUs by default
+    #[clap(short, long)]
+    pub jobs: Option<usize>,
+
+    /// seconds after which a worker is killed
+    #[clap(short, long)]
+    pub timeout: Option<u64>,
+
+    /// arguments passed target file from argv[0], `@@` is replaced with the input path
+    /// the input path is appended if no argument has `@@`, argv[0] is the target file if none
+    #[clap(short, long)]
+    pub args: Vec<String>,
 }
 
 impl Args {
     pub fn print_info(&self) {
         println!("TDB - Totsugekitai DeBugger");
-        println!("target: {}", self.file);
+        match (&self.file, self.pid) {
+            (Some(file), _) => println!("target: {}", file),
+            (None, Some(pid)) => println!("target: pid {}", pid),
+            (None, None) => {}
+        }
     }
 }
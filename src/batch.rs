@@ -0,0 +1,209 @@
+use crate::args::BatchArgs;
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::Pid,
+};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, Read},
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// result of the session for an input
+#[derive(Debug)]
+struct Outcome {
+    input: PathBuf,
+    /// exit code of the worker, the target's own code or 128 + signal if it terminated
+    code: Option<i32>,
+    timed_out: bool,
+    seconds: f64,
+    /// everything the worker printed
+    output: String,
+    error: Option<String>,
+}
+
+impl Outcome {
+    fn json(&self) -> String {
+        let mut fields = vec![format!(
+            "\"input\": {}",
+            json_string(&self.input.to_string_lossy())
+        )];
+        match self.code {
+            Some(code) => fields.push(format!("\"code\": {code}")),
+            None => fields.push(String::from("\"code\": null")),
+        }
+        // シェルと同じく128+シグナル番号はシグナルで終わったことを表す
+        if let Some(signal) = self
+            .code
+            .filter(|code| *code > 128)
+            .and_then(|code| Signal::try_from(code - 128).ok())
+        {
+            fields.push(format!("\"signal\": \"{signal}\""));
+        }
+        fields.push(format!("\"timed_out\": {}", self.timed_out));
+        fields.push(format!("\"seconds\": {:.3}", self.seconds));
+        if let Some(error) = &self.error {
+            fields.push(format!("\"error\": {}", json_string(error)));
+        }
+        fields.push(format!("\"output\": {}", json_string(&self.output)));
+        format!("{{{}}}", fields.join(", "))
+    }
+}
+
+/// run the script against every input with worker processes of tdb and print the results as JSON
+pub fn batch_main(args: BatchArgs) {
+    let mut inputs: Vec<PathBuf> = match fs::read_dir(&args.inputs) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.is_file())
+            .collect(),
+        Err(e) => {
+            eprintln!("cannot read {}: {e}", args.inputs);
+            std::process::exit(1);
+        }
+    };
+    inputs.sort();
+    let jobs = args
+        .jobs
+        .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .max(1);
+    eprintln!("running {} inputs with {jobs} jobs", inputs.len());
+
+    let queue = Mutex::new(inputs.into_iter().collect::<VecDeque<PathBuf>>());
+    let outcomes = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let Some(input) = queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+                let outcome = run(&args, input);
+                eprintln!(
+                    "{}: {}",
+                    outcome.input.display(),
+                    match (outcome.timed_out, outcome.code) {
+                        (true, _) => String::from("timed out"),
+                        (false, Some(code)) => format!("code {code}"),
+                        (false, None) => String::from("error"),
+                    }
+                );
+                outcomes.lock().unwrap().push(outcome);
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by(|a, b| a.input.cmp(&b.input));
+    println!("[");
+    for (i, outcome) in outcomes.iter().enumerate() {
+        let comma = if i + 1 < outcomes.len() { "," } else { "" };
+        println!("  {}{comma}", outcome.json());
+    }
+    println!("]");
+}
+
+/// run a worker for the input and wait for it
+fn run(args: &BatchArgs, input: PathBuf) -> Outcome {
+    let started = Instant::now();
+    let mut outcome = Outcome {
+        input,
+        code: None,
+        timed_out: false,
+        seconds: 0.0,
+        output: String::new(),
+        error: None,
+    };
+    match spawn_worker(args, &mut outcome) {
+        Ok(code) => outcome.code = code,
+        Err(e) => outcome.error = Some(e.to_string()),
+    }
+    outcome.seconds = started.elapsed().as_secs_f64();
+    outcome
+}
+
+fn spawn_worker(args: &BatchArgs, outcome: &mut Outcome) -> io::Result<Option<i32>> {
+    let input = outcome.input.to_string_lossy().into_owned();
+    // --argsはargv[0]から渡すので、なければターゲットのパスを使う
+    let mut target_args: Vec<String> = match args.args.is_empty() {
+        true => vec![args.file.clone()],
+        false => args
+            .args
+            .iter()
+            .map(|arg| arg.replace("@@", &input))
+            .collect(),
+    };
+    if !args.args.iter().any(|arg| arg.contains("@@")) {
+        target_args.push(input);
+    }
+    let mut command = Command::new("/proc/self/exe");
+    command
+        .arg(&args.file)
+        .arg(format!("--script={}", args.script));
+    for arg in target_args {
+        command.arg(format!("--args={arg}"));
+    }
+    // タイムアウトしたときにターゲットごと止められるようにプロセスグループを分ける
+    let mut child = command
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // パイプが詰まらないように終了を待つ間も読み続ける
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+    let error_reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stderr.read_to_end(&mut output);
+        output
+    });
+    let deadline = args
+        .timeout
+        .map(|timeout| Instant::now() + Duration::from_secs(timeout));
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            // tdbだけを止めるとターゲットはデタッチされて動き続ける
+            let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+            child.wait()?;
+            outcome.timed_out = true;
+            break None;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let mut output = reader.join().unwrap_or_default();
+    output.extend(error_reader.join().unwrap_or_default());
+    outcome.output = String::from_utf8_lossy(&output).into_owned();
+    Ok(status.and_then(|status| status.code()))
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
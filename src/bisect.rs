@@ -0,0 +1,206 @@
+use crate::{
+    bounded::{Bounded, HeapSize},
+    debug_info::TdbDebugInfo,
+    debugger::DebuggerInfo,
+    dump, mem,
+    signal::Cancel,
+    syscall::get_regs,
+    variable::{self, Type},
+};
+use nix::{
+    sys::{signal::Signal, wait::WaitStatus},
+    unistd::Pid,
+};
+use std::path::Path;
+
+/// write to the value found by `bisect-watch`
+#[derive(Debug, Clone)]
+pub struct Write {
+    /// where `replay` stops, the instruction which wrote
+    /// or the start of the line which called the library that wrote
+    pub addr: u64,
+    /// `in func at file:line` of the instruction
+    pub location: String,
+    /// function of the target which wrote
+    pub function: String,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+/// writes to [addr, addr + len) recorded by stepping the target
+#[derive(Debug, Clone)]
+pub struct Bisect {
+    /// expression as typed by the user
+    pub text: String,
+    pub addr: u64,
+    pub len: u64,
+    pub ty: Option<Type>,
+    pub writes: Bounded<Write>,
+}
+
+impl HeapSize for Write {
+    fn heap_size(&self) -> usize {
+        self.location.capacity()
+            + self.function.capacity()
+            + self.old.capacity()
+            + self.new.capacity()
+    }
+}
+
+impl Bisect {
+    /// step the target until it terminates or Ctrl-C and record every write to the value
+    pub fn record(
+        debugger_info: &mut DebuggerInfo,
+        text: String,
+        addr: u64,
+        len: u64,
+        ty: Option<Type>,
+    ) -> Result<(Self, WaitStatus), Box<dyn std::error::Error>> {
+        let pid = debugger_info.debug_info.target_pid();
+        let mut bisect = Self {
+            text,
+            addr,
+            len,
+            ty,
+            writes: Bounded::new(debugger_info.bisect_policy),
+        };
+        let mut count = 0;
+        let mut old = mem::read_bytes(pid, addr, len as usize)?;
+        let mut status = WaitStatus::Stopped(pid, Signal::SIGTRAP);
+        let target = Path::new(debugger_info.debug_info.filename()).file_name();
+        let exec_maps: Vec<(u64, u64)> = debugger_info
+            .debug_info
+            .exec_maps()?
+            .iter()
+            .filter(|map| map.filename().and_then(|path| path.file_name()) == target)
+            .map(|map| (map.start() as u64, (map.start() + map.size()) as u64))
+            .collect();
+        // ライブラリの中の書き込みはそれを呼び出したターゲットの関数の命令のせいにする
+        let mut caller = get_regs(pid).rip;
+        let mut function: Option<(u64, u64)> = None;
+        let cancel = Cancel::start();
+        while !cancel.is_cancelled() {
+            let rip = get_regs(pid).rip;
+            let in_target = exec_maps
+                .iter()
+                .any(|(start, end)| *start <= rip && rip < *end);
+            if function.is_some_and(|(start, end)| start <= rip && rip < end) {
+                caller = rip;
+            } else if in_target {
+                // PLTなど関数の外の命令は飛ばす
+                let debug_info = &debugger_info.debug_info;
+                if let Some(f) = debug_info.find_function_in(rip) {
+                    let start = debug_info.get_actual_symbol_address(f).unwrap_or(rip);
+                    function = Some((start, start + f.size()));
+                    caller = rip;
+                }
+            }
+            status = debugger_info.breakpoint_manager.step()?;
+            match status {
+                WaitStatus::Stopped(_, Signal::SIGTRAP) => {}
+                WaitStatus::Stopped(_, signal) => {
+                    println!("stopped recording, the target received {signal}");
+                    break;
+                }
+                _ => break,
+            }
+            let new = mem::read_bytes(pid, addr, len as usize)?;
+            if new == old {
+                continue;
+            }
+            let (addr, location, function) = writer(&debugger_info.debug_info, rip, caller);
+            let write = Write {
+                addr,
+                location,
+                function,
+                old,
+                new: new.clone(),
+            };
+            println!(
+                "write {}: {} -> {} {}",
+                count,
+                bisect.format(pid, &write.old),
+                bisect.format(pid, &write.new),
+                write.location
+            );
+            bisect.writes.push(write);
+            count += 1;
+            old = new;
+        }
+        Ok((bisect, status))
+    }
+
+    /// functions which wrote to the value in the order of their first writes
+    pub fn writers(&self) -> Vec<(&str, Vec<&Write>)> {
+        let mut writers: Vec<(&str, Vec<&Write>)> = Vec::new();
+        for (_, write) in self.writes.iter() {
+            match writers.iter_mut().find(|(name, _)| *name == write.function) {
+                Some((_, writes)) => writes.push(write),
+                None => writers.push((&write.function, vec![write])),
+            }
+        }
+        writers
+    }
+
+    /// list the writers, the last write is the one which left the current value
+    pub fn summary(&self, pid: Pid) {
+        println!(
+            "writes to {} (0x{:016x}, {} bytes): {}",
+            self.text,
+            self.addr,
+            self.len,
+            self.writes.len()
+        );
+        if self.writes.dropped() > 0 {
+            println!(
+                "{} writes are dropped by the limit, see `info buffers`",
+                self.writes.dropped()
+            );
+        }
+        let writers = self.writers();
+        if writers.is_empty() {
+            return;
+        }
+        println!("#   Writes Function");
+        for (i, (name, writes)) in writers.iter().enumerate() {
+            println!("{:<3} {:<6} {}", i, writes.len(), name);
+        }
+        if let Some(last) = self.writes.last() {
+            println!(
+                "last write: {} -> {} {}",
+                self.format(pid, &last.old),
+                self.format(pid, &last.new),
+                last.location
+            );
+        }
+        println!("use `bisect-watch replay <#>` to stop at the writes of a function");
+    }
+
+    fn format(&self, pid: Pid, bytes: &[u8]) -> String {
+        match &self.ty {
+            Some(ty) => variable::format_value(pid, ty, bytes),
+            None => format!("0x{:x}", variable::read_uint(bytes)),
+        }
+    }
+}
+
+/// where to stop, location and function of the write by the instruction at `rip`
+/// `caller` is the last instruction of the target executed before `rip`
+fn writer(debug_info: &TdbDebugInfo, rip: u64, caller: u64) -> (u64, String, String) {
+    if let Some(f) = debug_info.find_function_in(rip) {
+        return (rip, dump::location(debug_info, rip), f.name().to_string());
+    }
+    let function = debug_info
+        .find_function_in(caller)
+        .map_or_else(|| String::from("??"), |f| f.name().to_string());
+    // 呼び出した行の先頭で止める
+    let addr = debug_info
+        .to_file_addr(caller)
+        .and_then(|file_addr| debug_info.find_line_range(file_addr))
+        .map_or(caller, |(start, _)| start + debug_info.load_bias());
+    let location = format!(
+        "at 0x{rip:016x} called {}",
+        dump::location(debug_info, caller)
+    );
+    (addr, location, function)
+}
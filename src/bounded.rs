@@ -0,0 +1,255 @@
+use std::{collections::VecDeque, fmt, mem};
+
+/// entry dropped when a bounded buffer is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Eviction {
+    /// keep the latest entries
+    #[default]
+    Oldest,
+    /// keep the earliest entries
+    Newest,
+}
+
+impl fmt::Display for Eviction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Eviction::Oldest => write!(f, "oldest"),
+            Eviction::Newest => write!(f, "newest"),
+        }
+    }
+}
+
+/// maximum number of entries and what is dropped beyond it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    pub limit: usize,
+    pub eviction: Eviction,
+}
+
+impl Policy {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            eviction: Eviction::default(),
+        }
+    }
+}
+
+/// bytes an entry owns on the heap
+pub trait HeapSize {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+/// buffer which keeps at most `policy.limit` entries
+/// entries are numbered from the first one ever pushed, evicting the oldest ones keeps the numbers
+#[derive(Debug, Clone)]
+pub struct Bounded<T> {
+    entries: VecDeque<T>,
+    policy: Policy,
+    /// number of the oldest entry kept
+    first: usize,
+    /// entries dropped by the limit
+    dropped: usize,
+}
+
+impl<T> Bounded<T> {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            policy,
+            first: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn push(&mut self, entry: T) {
+        if self.entries.len() >= self.policy.limit {
+            self.dropped += 1;
+            match self.policy.eviction {
+                Eviction::Newest => return,
+                Eviction::Oldest if self.entries.pop_front().is_some() => self.first += 1,
+                // 上限が0なら何も残さない
+                Eviction::Oldest => return,
+            }
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.entries.pop_back()
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.entries.back()
+    }
+
+    /// entry numbered `num`
+    pub fn get(&self, num: usize) -> Option<&T> {
+        num.checked_sub(self.first)
+            .and_then(|index| self.entries.get(index))
+    }
+
+    /// entries with their numbers
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (self.first + index, entry))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.first = 0;
+        self.dropped = 0;
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// change the limit and the eviction, entries beyond the new limit are dropped now
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy;
+        while self.entries.len() > policy.limit {
+            self.dropped += 1;
+            match policy.eviction {
+                Eviction::Oldest => {
+                    self.entries.pop_front();
+                    self.first += 1;
+                }
+                Eviction::Newest => {
+                    self.entries.pop_back();
+                }
+            }
+        }
+        self.entries.shrink_to_fit();
+    }
+}
+
+impl<T: HeapSize> Bounded<T> {
+    /// bytes used by the buffer and its entries
+    pub fn memory_usage(&self) -> usize {
+        self.entries.capacity() * mem::size_of::<T>()
+            + self.entries.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl HeapSize for String {
+        fn heap_size(&self) -> usize {
+            self.capacity()
+        }
+    }
+
+    fn bounded(limit: usize, eviction: Eviction) -> Bounded<usize> {
+        Bounded::new(Policy { limit, eviction })
+    }
+
+    fn entries<T: Clone>(bounded: &Bounded<T>) -> Vec<(usize, T)> {
+        bounded
+            .iter()
+            .map(|(num, entry)| (num, entry.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn evict_oldest_keeps_numbers() {
+        let mut b = bounded(2, Eviction::Oldest);
+        for i in 0..5 {
+            b.push(i);
+        }
+        assert_eq!(entries(&b), vec![(3, 3), (4, 4)]);
+        assert_eq!(b.dropped(), 3);
+        assert_eq!(b.get(2), None);
+        assert_eq!(b.get(4), Some(&4));
+        assert_eq!(b.last(), Some(&4));
+    }
+
+    #[test]
+    fn evict_newest_keeps_the_first_entries() {
+        let mut b = bounded(2, Eviction::Newest);
+        for i in 0..5 {
+            b.push(i);
+        }
+        assert_eq!(entries(&b), vec![(0, 0), (1, 1)]);
+        assert_eq!(b.dropped(), 3);
+    }
+
+    #[test]
+    fn zero_limit_keeps_nothing() {
+        let mut b = bounded(0, Eviction::Oldest);
+        b.push(1);
+        assert!(b.is_empty());
+        assert_eq!(b.dropped(), 1);
+    }
+
+    #[test]
+    fn pop_and_clear() {
+        let mut b = bounded(3, Eviction::Oldest);
+        for i in 0..4 {
+            b.push(i);
+        }
+        assert_eq!(b.pop(), Some(3));
+        assert_eq!(b.len(), 2);
+        b.clear();
+        assert!(b.is_empty());
+        assert_eq!(b.dropped(), 0);
+        b.push(7);
+        assert_eq!(entries(&b), vec![(0, 7)]);
+    }
+
+    #[test]
+    fn shrinking_the_limit_drops_entries_now() {
+        let mut b = bounded(5, Eviction::Oldest);
+        for i in 0..5 {
+            b.push(i);
+        }
+        b.set_policy(Policy {
+            limit: 2,
+            eviction: Eviction::Oldest,
+        });
+        assert_eq!(entries(&b), vec![(3, 3), (4, 4)]);
+
+        let mut b = bounded(5, Eviction::Oldest);
+        for i in 0..5 {
+            b.push(i);
+        }
+        b.set_policy(Policy {
+            limit: 2,
+            eviction: Eviction::Newest,
+        });
+        assert_eq!(entries(&b), vec![(0, 0), (1, 1)]);
+        assert_eq!(b.dropped(), 3);
+    }
+
+    #[test]
+    fn memory_usage_counts_the_heap_of_entries() {
+        let mut b = Bounded::new(Policy::new(4));
+        b.push(String::with_capacity(100));
+        assert!(b.memory_usage() >= 100 + mem::size_of::<String>());
+    }
+
+    #[test]
+    fn eviction_names() {
+        assert_eq!(Eviction::Oldest.to_string(), "oldest");
+        assert_eq!(Eviction::Newest.to_string(), "newest");
+        assert_eq!(Policy::new(8).eviction, Eviction::Oldest);
+    }
+}
@@ -1,59 +1,232 @@
+use crate::{
+    debugreg::{self, Trigger},
+    disasm, displaced,
+    dprintf::Dprintf,
+    expr::Expr,
+    mem::{self, Access},
+    pagewatch::{self, Page},
+    step::MAX_INSTRUCTION_LEN,
+    syscall::get_regs,
+    variable::{self, Type},
+};
 use nix::{
-    libc::{c_void, user_regs_struct},
-    sys::ptrace,
+    sys::{
+        ptrace::{self, Options},
+        signal::Signal,
+        wait::{waitpid, WaitStatus},
+    },
     unistd::Pid,
 };
+use std::time::Instant;
+use syscalls::Sysno;
+
+/// si_code of SIGTRAP raised by int3
+pub const SI_KERNEL: i32 = 0x80;
+/// si_code of SIGTRAP raised by single-stepping
+const TRAP_TRACE: i32 = 2;
+/// si_code of SIGTRAP raised by debug registers
+const TRAP_HWBKPT: i32 = 4;
+
+/// expression with the text typed by the user
+pub type Condition = (String, Expr);
 
 #[derive(Debug)]
 pub struct Breakpoint {
+    pub num: usize,
     pub addr: u64,
     pub value: u8,
+    /// stop only if the expression is true
+    pub condition: Option<Condition>,
+    /// deleted after the first hit
+    pub temporary: bool,
+    /// number of hits whose condition was true, including ignored ones
+    pub hit_count: usize,
+    /// time of the first and the last hit in this run
+    pub first_hit: Option<Instant>,
+    pub last_hit: Option<Instant>,
+    /// number of hits to be ignored from now on
+    pub ignore_count: usize,
+    /// debug register used instead of int3
+    pub slot: Option<usize>,
+    /// disabled breakpoints keep their number but do not trap
+    pub enabled: bool,
+    /// commands executed when the breakpoint stops the target
+    pub commands: Vec<String>,
+    /// print and continue instead of stopping
+    pub dprintf: Option<Dprintf>,
+    /// false while the original byte is written back
+    inserted: bool,
 }
 
 impl Breakpoint {
-    pub fn new(addr: u64, value: u8) -> Self {
-        Self { addr, value }
+    pub fn new(num: usize, addr: u64, value: u8) -> Self {
+        Self {
+            num,
+            addr,
+            value,
+            condition: None,
+            temporary: false,
+            hit_count: 0,
+            first_hit: None,
+            last_hit: None,
+            ignore_count: 0,
+            slot: None,
+            enabled: true,
+            commands: Vec::new(),
+            dprintf: None,
+            inserted: true,
+        }
     }
 
-    pub fn restore_memory(
-        &self,
-        pid: Pid,
-        regs: user_regs_struct,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        println!("breakpoint!");
-        let data = ptrace::read(pid, self.addr as *const c_void as *mut c_void).unwrap();
-        let mut data_vec = data.to_le_bytes();
-        if data_vec[0] == 0xcc {
-            data_vec[0] = self.value;
-        } else {
-            panic!(
-                "bad breakpoint! addr: 0x{:x}, value: 0x{:x}",
-                self.addr, data_vec[0]
-            );
-        }
-        let mut data_long = 0;
-        for i in 0..(data_vec.len()) {
-            data_long += (data_vec[i as usize] as u64) << (i * 8);
-        }
-        unsafe {
-            ptrace::write(
-                pid,
-                self.addr as *const c_void as *mut c_void,
-                data_long as *mut c_void,
-            )
-            .unwrap();
-        }
-        let mut regs = regs;
-        regs.rip = self.addr;
-        ptrace::setregs(pid, regs).unwrap();
-        Ok(())
+    /// count a hit whose condition is true
+    pub fn hit(&mut self) {
+        let now = Instant::now();
+        self.hit_count += 1;
+        self.first_hit.get_or_insert(now);
+        self.last_hit = Some(now);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    /// `watch`, reports changed values
+    Write,
+    /// `rwatch`, reports accesses which keep the value
+    Read,
+    /// `awatch`, reports all accesses
+    Access,
+}
+
+impl WatchKind {
+    fn trigger(&self) -> Trigger {
+        match self {
+            WatchKind::Write => Trigger::Write,
+            WatchKind::Read | WatchKind::Access => Trigger::ReadWrite,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatchKind::Write => "watchpoint",
+            WatchKind::Read => "read watchpoint",
+            WatchKind::Access => "access watchpoint",
+        }
+    }
+}
+
+/// data watchpoint with a debug register
+#[derive(Debug)]
+pub struct Watchpoint {
+    pub num: usize,
+    pub kind: WatchKind,
+    /// expression as typed by the user
+    pub text: String,
+    pub addr: u64,
+    pub len: u64,
+    /// None if an address is watched instead of an expression
+    pub ty: Option<Type>,
+    /// value when the watchpoint was set or reported last
+    pub old: Vec<u8>,
+    pub hit_count: usize,
+    /// time of the first and the last hit in this run
+    pub first_hit: Option<Instant>,
+    pub last_hit: Option<Instant>,
+    pub enabled: bool,
+    watcher: Watcher,
+}
+
+#[derive(Debug)]
+enum Watcher {
+    DebugRegister(usize),
+    /// write-protected pages for regions larger than a debug register
+    Pages(Vec<Page>),
+}
+
+impl Watchpoint {
+    /// count a reported access
+    pub fn hit(&mut self) {
+        let now = Instant::now();
+        self.hit_count += 1;
+        self.first_hit.get_or_insert(now);
+        self.last_hit = Some(now);
+    }
+
+    /// true if the pages are write-protected instead of using a debug register
+    pub fn uses_pages(&self) -> bool {
+        matches!(self.watcher, Watcher::Pages(_))
+    }
+
+    pub fn format(&self, pid: Pid, bytes: &[u8]) -> String {
+        match &self.ty {
+            Some(ty) => variable::format_value(pid, ty, bytes),
+            None => format!("0x{:x}", variable::read_uint(bytes)),
+        }
+    }
+}
+
+/// event which stops the target with `catch`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CatchKind {
+    Fork,
+    Exec,
+    Exit,
+    Signal(Signal),
+    /// entry and exit of the syscalls, any syscall if empty
+    Syscall(Vec<Sysno>),
+}
+
+impl CatchKind {
+    /// ptrace options to report the event
+    fn options(&self) -> Options {
+        match self {
+            CatchKind::Fork => Options::PTRACE_O_TRACEFORK | Options::PTRACE_O_TRACEVFORK,
+            CatchKind::Exec => Options::PTRACE_O_TRACEEXEC,
+            CatchKind::Exit => Options::PTRACE_O_TRACEEXIT,
+            // シグナルは常に報告される
+            CatchKind::Signal(_) => Options::empty(),
+            // SIGTRAPと区別できるようにする
+            CatchKind::Syscall(_) => Options::PTRACE_O_TRACESYSGOOD,
+        }
+    }
+}
+
+impl std::fmt::Display for CatchKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CatchKind::Fork => write!(f, "fork"),
+            CatchKind::Exec => write!(f, "exec"),
+            CatchKind::Exit => write!(f, "exit"),
+            CatchKind::Signal(signal) => write!(f, "signal {}", signal),
+            CatchKind::Syscall(sysnos) => {
+                write!(f, "syscall")?;
+                for sysno in sysnos {
+                    write!(f, " {}", sysno.name())?;
+                }
+                Ok(())
+            }
+        }
     }
 }
 
+#[derive(Debug)]
+pub struct Catchpoint {
+    pub num: usize,
+    pub kind: CatchKind,
+    pub hit_count: usize,
+    pub enabled: bool,
+    /// evaluated with the arguments of the syscall
+    pub condition: Option<Condition>,
+}
+
 #[derive(Debug)]
 pub struct BreakpointManager {
     pid: Pid,
     breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    catchpoints: Vec<Catchpoint>,
+    next_num: usize,
+    /// address where instructions under breakpoints are copied and stepped
+    scratch: Option<u64>,
 }
 
 impl BreakpointManager {
@@ -61,37 +234,707 @@ impl BreakpointManager {
         Self {
             pid,
             breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            catchpoints: Vec::new(),
+            next_num: 0,
+            scratch: None,
         }
     }
 
+    /// the scratch area must not be executed by the target, like its entry point
+    pub fn set_scratch(&mut self, addr: u64) {
+        self.scratch = Some(addr);
+    }
+
     /// set breakpoint
-    pub fn set(&mut self, addr: u64) -> Result<u8, Box<dyn std::error::Error>> {
-        let read = ptrace::read(self.pid, addr as *mut c_void)?;
-        let mut read_vec = read.to_le_bytes();
+    pub fn set(&mut self, addr: u64) -> Result<&mut Breakpoint, Box<dyn std::error::Error>> {
+        mem::check_mapping(self.pid, addr, 1, Access::Execute)?;
+        // 同じアドレスに既にあるときは元のバイトを引き継ぐ
+        let value = match self.at(addr).find(|bp| bp.slot.is_none()) {
+            Some(bp) => bp.value,
+            None => {
+                let head = mem::read_bytes(self.pid, addr, 1)?[0];
+                mem::write_bytes(self.pid, addr, &[0xcc])?;
+                head
+            }
+        };
+        self.next_num += 1;
+        self.breakpoints
+            .push(Breakpoint::new(self.next_num, addr, value));
+        Ok(self.breakpoints.last_mut().unwrap())
+    }
+
+    /// set breakpoint which is deleted after its first hit
+    pub fn set_temporary(
+        &mut self,
+        addr: u64,
+    ) -> Result<&mut Breakpoint, Box<dyn std::error::Error>> {
+        let bp = self.set(addr)?;
+        bp.temporary = true;
+        Ok(bp)
+    }
+
+    /// set breakpoint with a debug register, the code is not modified
+    pub fn set_hardware(
+        &mut self,
+        addr: u64,
+    ) -> Result<&mut Breakpoint, Box<dyn std::error::Error>> {
+        mem::check_mapping(self.pid, addr, 1, Access::Execute)?;
+        let slot = self.free_slot()?;
+        debugreg::set(self.pid, slot, addr, Trigger::Execute, 1)?;
+        self.next_num += 1;
+        let mut bp = Breakpoint::new(self.next_num, addr, 0);
+        bp.slot = Some(slot);
+        self.breakpoints.push(bp);
+        Ok(self.breakpoints.last_mut().unwrap())
+    }
+
+    /// watch `len` bytes at `addr` with a debug register
+    pub fn set_watchpoint(
+        &mut self,
+        kind: WatchKind,
+        text: String,
+        addr: u64,
+        len: u64,
+        ty: Option<Type>,
+    ) -> Result<&Watchpoint, Box<dyn std::error::Error>> {
+        mem::check_mapping(self.pid, addr, len, Access::Data)?;
+        let slot = self.free_slot()?;
+        let old = mem::read_bytes(self.pid, addr, len as usize)?;
+        debugreg::set(self.pid, slot, addr, kind.trigger(), len)?;
+        self.next_num += 1;
+        self.watchpoints.push(Watchpoint {
+            num: self.next_num,
+            kind,
+            text,
+            addr,
+            len,
+            ty,
+            old,
+            hit_count: 0,
+            first_hit: None,
+            last_hit: None,
+            enabled: true,
+            watcher: Watcher::DebugRegister(slot),
+        });
+        Ok(self.watchpoints.last().unwrap())
+    }
+
+    /// watch writes to [addr, addr + len) by write-protecting its pages
+    /// writes by the kernel (e.g. read(2)) fail with EFAULT instead of being reported
+    pub fn set_page_watchpoint(
+        &mut self,
+        text: String,
+        addr: u64,
+        len: u64,
+        ty: Option<Type>,
+    ) -> Result<&Watchpoint, Box<dyn std::error::Error>> {
+        mem::check_mapping(self.pid, addr, len, Access::Data)?;
+        let pages = pagewatch::pages(self.pid, addr, len)?;
+        let old = mem::read_bytes(self.pid, addr, len as usize)?;
+        pagewatch::protect(self.pid, &pages)?;
+        self.next_num += 1;
+        self.watchpoints.push(Watchpoint {
+            num: self.next_num,
+            kind: WatchKind::Write,
+            text,
+            addr,
+            len,
+            ty,
+            old,
+            hit_count: 0,
+            first_hit: None,
+            last_hit: None,
+            enabled: true,
+            watcher: Watcher::Pages(pages),
+        });
+        Ok(self.watchpoints.last().unwrap())
+    }
+
+    /// stop the target when the event occurs
+    pub fn set_catchpoint(
+        &mut self,
+        kind: CatchKind,
+        condition: Option<Condition>,
+    ) -> Result<&Catchpoint, nix::Error> {
+        self.next_num += 1;
+        self.catchpoints.push(Catchpoint {
+            num: self.next_num,
+            kind,
+            hit_count: 0,
+            enabled: true,
+            condition,
+        });
+        if let Err(e) = self.update_options() {
+            self.catchpoints.pop();
+            return Err(e);
+        }
+        Ok(self.catchpoints.last().unwrap())
+    }
+
+    /// insert everything into the restarted target, the addresses do not change without ASLR
+    /// returns the numbers disabled because their addresses are not mapped yet
+    pub fn restart(&mut self, pid: Pid) -> Vec<usize> {
+        self.pid = pid;
+        let mut disabled = Vec::new();
+        // ヒット数は実行ごとに数える
+        for bp in &mut self.breakpoints {
+            bp.hit_count = 0;
+            bp.first_hit = None;
+            bp.last_hit = None;
+        }
+        for wp in &mut self.watchpoints {
+            wp.hit_count = 0;
+            wp.first_hit = None;
+            wp.last_hit = None;
+        }
+        // 同じアドレスのint3を読まないように先に全ての元のバイトを読む
+        for bp in self.breakpoints.iter_mut().filter(|bp| bp.enabled) {
+            let ok = match bp.slot {
+                Some(slot) => debugreg::set(pid, slot, bp.addr, Trigger::Execute, 1).is_ok(),
+                None => match mem::read_bytes(pid, bp.addr, 1) {
+                    Ok(value) => {
+                        bp.value = value[0];
+                        true
+                    }
+                    Err(_) => false,
+                },
+            };
+            if !ok {
+                bp.enabled = false;
+                bp.inserted = false;
+                disabled.push(bp.num);
+            }
+        }
+        for bp in self.breakpoints.iter_mut().filter(|bp| bp.enabled) {
+            if bp.slot.is_none() && mem::write_bytes(pid, bp.addr, &[0xcc]).is_err() {
+                bp.enabled = false;
+                bp.inserted = false;
+                disabled.push(bp.num);
+                continue;
+            }
+            bp.inserted = true;
+        }
+        for wp in self.watchpoints.iter_mut().filter(|wp| wp.enabled) {
+            let ok = mem::read_bytes(pid, wp.addr, wp.len as usize).is_ok_and(|old| {
+                wp.old = old;
+                match &wp.watcher {
+                    Watcher::DebugRegister(slot) => {
+                        debugreg::set(pid, *slot, wp.addr, wp.kind.trigger(), wp.len).is_ok()
+                    }
+                    Watcher::Pages(pages) => pagewatch::protect(pid, pages).is_ok(),
+                }
+            });
+            if !ok {
+                wp.enabled = false;
+                disabled.push(wp.num);
+            }
+        }
+        let _ = self.update_options();
+        disabled
+    }
+
+    /// disable everything after the target executed another file, the old addresses are meaningless
+    /// returns the numbers disabled
+    pub fn disable_all(&mut self) -> Vec<usize> {
+        let mut disabled = Vec::new();
+        for bp in self.breakpoints.iter_mut().filter(|bp| bp.enabled) {
+            bp.enabled = false;
+            bp.inserted = false;
+            disabled.push(bp.num);
+        }
+        for wp in self.watchpoints.iter_mut().filter(|wp| wp.enabled) {
+            wp.enabled = false;
+            disabled.push(wp.num);
+        }
+        disabled
+    }
+
+    /// report only the events which enabled catchpoints wait for
+    /// forks are always reported to remove the breakpoints from the child,
+    /// execs to read the new file and clones to trace the new threads
+    pub fn options(&self) -> Options {
+        let always =
+            CatchKind::Fork.options() | CatchKind::Exec.options() | Options::PTRACE_O_TRACECLONE;
+        self.catchpoints
+            .iter()
+            .filter(|cp| cp.enabled)
+            .fold(always, |options, cp| options | cp.kind.options())
+    }
+
+    pub fn update_options(&self) -> Result<(), nix::Error> {
+        ptrace::setoptions(self.pid, self.options())
+    }
+
+    /// thread whose registers are used to step and to find the hit breakpoint
+    pub fn select(&mut self, tid: Pid) {
+        self.pid = tid;
+    }
+
+    pub fn catchpoints(&self) -> &[Catchpoint] {
+        &self.catchpoints
+    }
+
+    /// count a hit of the enabled catchpoints for the event and return their numbers
+    pub fn caught(&mut self, kind: &CatchKind) -> Vec<usize> {
+        self.catchpoints
+            .iter_mut()
+            .filter(|cp| cp.enabled && cp.kind == *kind)
+            .map(|cp| {
+                cp.hit_count += 1;
+                cp.num
+            })
+            .collect()
+    }
 
-        let head = read_vec[0];
+    /// whether the target has to be resumed with PTRACE_SYSCALL
+    pub fn catches_syscalls(&self) -> bool {
+        self.catchpoints
+            .iter()
+            .any(|cp| cp.enabled && matches!(cp.kind, CatchKind::Syscall(_)))
+    }
 
-        read_vec[0] = 0xcc;
+    /// enabled catchpoints for the syscall, their conditions are not evaluated
+    pub fn syscall_catchpoints(&self, sysno: Sysno) -> impl Iterator<Item = &Catchpoint> {
+        self.catchpoints.iter().filter(move |cp| match &cp.kind {
+            CatchKind::Syscall(sysnos) => {
+                cp.enabled && (sysnos.is_empty() || sysnos.contains(&sysno))
+            }
+            _ => false,
+        })
+    }
 
-        let mut write = 0;
-        for i in 0..(read_vec.len()) {
-            write += (read_vec[i as usize] as u64) << (i * 8);
+    /// count a hit of the catchpoint
+    pub fn hit_catchpoint(&mut self, num: usize) {
+        if let Some(cp) = self.catchpoints.iter_mut().find(|cp| cp.num == num) {
+            cp.hit_count += 1;
         }
+    }
 
-        unsafe { ptrace::write(self.pid, addr as *mut c_void, write as *mut c_void)? };
+    /// remove the breakpoints which the forked child inherited and let it run
+    pub fn detach_child(&self, child: Pid, shared: bool) -> Result<(), nix::Error> {
+        self.release(child, shared)
+    }
 
-        self.breakpoints.push(Breakpoint::new(addr, head));
+    /// int3 in the memory of the target, (address, original byte)
+    pub fn int3s(&self) -> Vec<(u64, u8)> {
+        self.breakpoints
+            .iter()
+            .filter(|bp| bp.inserted && bp.slot.is_none())
+            .map(|bp| (bp.addr, bp.value))
+            .collect()
+    }
 
-        Ok(head)
+    /// remove everything inserted into the target and let it run
+    pub fn detach(&mut self) -> Result<(), nix::Error> {
+        self.release(self.pid, false)?;
+        for bp in &mut self.breakpoints {
+            bp.inserted = false;
+        }
+        Ok(())
     }
 
-    /// get breakpoint value if exists
+    /// let the target run and debug the forked child instead
+    /// the int3 of software breakpoints are already in the memory of the child
+    pub fn follow_child(
+        &mut self,
+        child: Pid,
+        shared: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.release(self.pid, shared)?;
+        self.pid = child;
+        // デバッグレジスタは子プロセスに引き継がれない
+        for bp in self.breakpoints.iter().filter(|bp| bp.enabled) {
+            if let Some(slot) = bp.slot {
+                debugreg::set(child, slot, bp.addr, Trigger::Execute, 1)?;
+                if !bp.inserted {
+                    debugreg::enable(child, slot, false)?;
+                }
+            }
+        }
+        for wp in self.watchpoints.iter().filter(|wp| wp.enabled) {
+            if let Watcher::DebugRegister(slot) = wp.watcher {
+                debugreg::set(child, slot, wp.addr, wp.kind.trigger(), wp.len)?;
+            }
+        }
+        Ok(self.update_options()?)
+    }
+
+    /// remove the int3, page protections and debug registers from `pid` and detach from it
+    /// the memory of a vfork child is shared with the target, so it is left as is
+    fn release(&self, pid: Pid, shared: bool) -> Result<(), nix::Error> {
+        if !shared {
+            for bp in &self.breakpoints {
+                if bp.inserted && bp.slot.is_none() {
+                    mem::write_bytes(pid, bp.addr, &[bp.value])?;
+                }
+            }
+            for (_, pages) in self.page_watchpoints() {
+                pagewatch::unprotect(pid, pages)?;
+            }
+        }
+        for slot in 0..debugreg::SLOTS {
+            debugreg::clear(pid, slot)?;
+        }
+        mem::detach(pid, None)
+    }
+
+    fn free_slot(&self) -> Result<usize, std::io::Error> {
+        (0..debugreg::SLOTS)
+            .find(|slot| {
+                self.breakpoints.iter().all(|bp| bp.slot != Some(*slot))
+                    && self
+                        .watchpoints
+                        .iter()
+                        .all(|wp| !matches!(wp.watcher, Watcher::DebugRegister(s) if s == *slot))
+            })
+            .ok_or(std::io::Error::other("no free debug register"))
+    }
+
+    /// delete breakpoint, watchpoint or catchpoint, returns false if it does not exist
+    pub fn delete(&mut self, num: usize) -> Result<bool, nix::Error> {
+        if let Some(index) = self.catchpoints.iter().position(|cp| cp.num == num) {
+            self.catchpoints.remove(index);
+            self.update_options()?;
+            return Ok(true);
+        }
+        if let Some(index) = self.watchpoints.iter().position(|wp| wp.num == num) {
+            self.disable_watchpoint(index)?;
+            let wp = self.watchpoints.remove(index);
+            if let Watcher::DebugRegister(slot) = wp.watcher {
+                debugreg::clear(self.pid, slot)?;
+            }
+            return Ok(true);
+        }
+        let index = match self.breakpoints.iter().position(|bp| bp.num == num) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        self.disable_breakpoint(index)?;
+        let bp = self.breakpoints.remove(index);
+        if let Some(slot) = bp.slot {
+            debugreg::clear(self.pid, slot)?;
+        }
+        Ok(true)
+    }
+
+    /// enable or disable breakpoint, watchpoint or catchpoint, returns false if it does not exist
+    pub fn enable(&mut self, num: usize, enabled: bool) -> Result<bool, nix::Error> {
+        if let Some(cp) = self.catchpoints.iter_mut().find(|cp| cp.num == num) {
+            cp.enabled = enabled;
+            self.update_options()?;
+            return Ok(true);
+        }
+        if let Some(index) = self.watchpoints.iter().position(|wp| wp.num == num) {
+            if enabled {
+                self.enable_watchpoint(index)?;
+            } else {
+                self.disable_watchpoint(index)?;
+            }
+            return Ok(true);
+        }
+        let index = match self.breakpoints.iter().position(|bp| bp.num == num) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        if enabled {
+            if !self.breakpoints[index].enabled {
+                // 無効の間に元のバイトが変わっていないとは限らない
+                let addr = self.breakpoints[index].addr;
+                if self.breakpoints[index].slot.is_none() && self.at(addr).next().is_none() {
+                    self.breakpoints[index].value = mem::read_bytes(self.pid, addr, 1)?[0];
+                }
+                self.breakpoints[index].enabled = true;
+                self.breakpoints[index].inserted = false;
+                self.insert_all()?;
+            }
+        } else {
+            self.disable_breakpoint(index)?;
+        }
+        Ok(true)
+    }
+
+    fn disable_breakpoint(&mut self, index: usize) -> Result<(), nix::Error> {
+        let bp = &mut self.breakpoints[index];
+        let was_inserted = bp.enabled && bp.inserted;
+        bp.enabled = false;
+        bp.inserted = false;
+        let (addr, value, slot) = (bp.addr, bp.value, bp.slot);
+        if !was_inserted {
+            return Ok(());
+        }
+        match slot {
+            Some(slot) => debugreg::enable(self.pid, slot, false),
+            // 同じアドレスに他のブレークポイントが残っていればint3はそのまま
+            None if self.at(addr).any(|other| other.slot.is_none()) => Ok(()),
+            None => mem::write_bytes(self.pid, addr, &[value]),
+        }
+    }
+
+    fn enable_watchpoint(&mut self, index: usize) -> Result<(), nix::Error> {
+        if self.watchpoints[index].enabled {
+            return Ok(());
+        }
+        let wp = &mut self.watchpoints[index];
+        // 無効の間の変化は報告しない
+        wp.old = mem::read_bytes(self.pid, wp.addr, wp.len as usize)?;
+        wp.enabled = true;
+        match &wp.watcher {
+            Watcher::DebugRegister(slot) => debugreg::enable(self.pid, *slot, true),
+            Watcher::Pages(pages) => pagewatch::protect(self.pid, pages),
+        }
+    }
+
+    fn disable_watchpoint(&mut self, index: usize) -> Result<(), nix::Error> {
+        if !self.watchpoints[index].enabled {
+            return Ok(());
+        }
+        self.watchpoints[index].enabled = false;
+        match &self.watchpoints[index].watcher {
+            Watcher::DebugRegister(slot) => debugreg::enable(self.pid, *slot, false),
+            Watcher::Pages(pages) => {
+                // 他のウォッチポイントが見張っているページは保護したまま
+                let in_use: Vec<u64> = self
+                    .page_watchpoints()
+                    .flat_map(|(_, pages)| pages.iter().map(|p| p.addr))
+                    .collect();
+                let unused: Vec<Page> = pages
+                    .iter()
+                    .filter(|p| !in_use.contains(&p.addr))
+                    .copied()
+                    .collect();
+                pagewatch::unprotect(self.pid, &unused)
+            }
+        }
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// numbers of all breakpoints, watchpoints and catchpoints
+    pub fn numbers(&self) -> Vec<usize> {
+        let mut nums: Vec<usize> = self
+            .breakpoints
+            .iter()
+            .map(|bp| bp.num)
+            .chain(self.watchpoints.iter().map(|wp| wp.num))
+            .chain(self.catchpoints.iter().map(|cp| cp.num))
+            .collect();
+        nums.sort();
+        nums
+    }
+
+    /// get enabled breakpoint if exists
     pub fn get(&self, addr: u64) -> Option<&Breakpoint> {
-        for breakpoint in &self.breakpoints {
-            if addr == breakpoint.addr {
-                return Some(breakpoint);
+        self.at(addr).next()
+    }
+
+    /// all enabled breakpoints at the address
+    pub fn at(&self, addr: u64) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints
+            .iter()
+            .filter(move |bp| bp.enabled && bp.addr == addr)
+    }
+
+    pub fn find_mut(&mut self, num: usize) -> Option<&mut Breakpoint> {
+        self.breakpoints.iter_mut().find(|bp| bp.num == num)
+    }
+
+    /// number of the breakpoint set most recently
+    pub fn last_breakpoint(&self) -> Option<usize> {
+        self.breakpoints.iter().map(|bp| bp.num).max()
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn find_watchpoint_mut(&mut self, num: usize) -> Option<&mut Watchpoint> {
+        self.watchpoints.iter_mut().find(|wp| wp.num == num)
+    }
+
+    /// numbers of the watchpoints whose debug registers trapped
+    pub fn hit_watchpoints(&self) -> Vec<usize> {
+        let nums = self.trapped_watchpoints().unwrap_or_default();
+        // 同じ停止で二度報告しないように消しておく
+        if !nums.is_empty() {
+            let _ = debugreg::reset(self.pid);
+        }
+        nums
+    }
+
+    fn trapped_watchpoints(&self) -> Option<Vec<usize>> {
+        // ウォッチポイントはアクセスした命令の実行後に止まる
+        // ステップ実行中に引っかかったときはTRAP_TRACEになる
+        let siginfo = ptrace::getsiginfo(self.pid).ok()?;
+        if siginfo.si_signo != Signal::SIGTRAP as i32
+            || (siginfo.si_code != TRAP_HWBKPT && siginfo.si_code != TRAP_TRACE)
+        {
+            return None;
+        }
+        let slots = debugreg::triggered(self.pid).ok()?;
+        Some(
+            self.watchpoints
+                .iter()
+                .filter(|wp| matches!(wp.watcher, Watcher::DebugRegister(slot) if slots.contains(&slot)))
+                .map(|wp| wp.num)
+                .collect(),
+        )
+    }
+
+    /// if the target faulted on a write-protected page, execute the write with the
+    /// permission given back and protect the pages again
+    /// returns the status after the write and the numbers of the page watchpoints
+    pub fn step_page_fault(&mut self) -> Result<Option<(WaitStatus, Vec<usize>)>, nix::Error> {
+        let fault = match pagewatch::fault_addr(self.pid) {
+            Some(fault) => fault,
+            None => return Ok(None),
+        };
+        let page = fault / pagewatch::PAGE_SIZE * pagewatch::PAGE_SIZE;
+        let watched = self
+            .page_watchpoints()
+            .any(|(_, pages)| pages.iter().any(|p| p.addr == page));
+        if !watched {
+            return Ok(None);
+        }
+        // 1命令で複数のページに書き込むこともあるので全て戻す
+        let pages: Vec<Page> = self
+            .page_watchpoints()
+            .flat_map(|(_, pages)| pages.iter().copied())
+            .collect();
+        pagewatch::unprotect(self.pid, &pages)?;
+        let status = self.step()?;
+        if let WaitStatus::Stopped(..) = status {
+            pagewatch::protect(self.pid, &pages)?;
+        }
+        let nums = self.page_watchpoints().map(|(num, _)| num).collect();
+        Ok(Some((status, nums)))
+    }
+
+    pub fn has_page_watchpoints(&self) -> bool {
+        self.page_watchpoints().next().is_some()
+    }
+
+    fn page_watchpoints(&self) -> impl Iterator<Item = (usize, &[Page])> {
+        self.watchpoints.iter().filter_map(|wp| match &wp.watcher {
+            Watcher::Pages(pages) if wp.enabled => Some((wp.num, pages.as_slice())),
+            _ => None,
+        })
+    }
+
+    /// address of the breakpoint if the target stopped by its int3 or debug register
+    pub fn hit_addr(&self) -> Option<u64> {
+        // シングルステップで止まった直後もrip-1にブレークポイントがあり得るので
+        // int3によるSIGTRAPかどうかを確かめる
+        let siginfo = ptrace::getsiginfo(self.pid).ok()?;
+        if siginfo.si_signo != Signal::SIGTRAP as i32 {
+            return None;
+        }
+        match siginfo.si_code {
+            SI_KERNEL => {
+                let addr = get_regs(self.pid).rip.wrapping_sub(1);
+                self.at(addr).find(|bp| bp.slot.is_none()).map(|bp| bp.addr)
+            }
+            // デバッグレジスタは命令の実行前に止まるのでripがそのままアドレス
+            TRAP_HWBKPT => {
+                let slots = debugreg::triggered(self.pid).ok()?;
+                self.breakpoints
+                    .iter()
+                    .find(|bp| bp.slot.is_some_and(|slot| slots.contains(&slot)))
+                    .map(|bp| bp.addr)
             }
+            _ => None,
         }
-        None
+    }
+
+    /// write back the original byte of the breakpoint at `addr` and rewind rip to it
+    /// the int3 is inserted again after the target steps over the address
+    pub fn rewind(&mut self, addr: u64) -> Result<(), nix::Error> {
+        self.lift(addr)?;
+        let mut regs = get_regs(self.pid);
+        regs.rip = addr;
+        ptrace::setregs(self.pid, regs)
+    }
+
+    /// execute one instruction, the breakpoint at rip does not trap
+    pub fn step(&mut self) -> Result<WaitStatus, nix::Error> {
+        let rip = get_regs(self.pid).rip;
+        if let Some(status) = self.step_displaced(rip)? {
+            return Ok(status);
+        }
+        self.lift(rip)?;
+        mem::step(self.pid, None)?;
+        let status = waitpid(self.pid, None)?;
+        // 終了していたら書き戻せない
+        if let WaitStatus::Stopped(..) = status {
+            self.insert_all()?;
+        }
+        Ok(status)
+    }
+
+    /// step over the breakpoint at rip before the target continues
+    /// returns the status if the target stopped for other reasons during the step
+    pub fn step_over(&mut self) -> Result<Option<WaitStatus>, nix::Error> {
+        let rip = get_regs(self.pid).rip;
+        if self.get(rip).is_none() {
+            self.insert_all()?;
+            return Ok(None);
+        }
+        // REP命令は1回のステップでは同じアドレスに留まることがある
+        loop {
+            match self.step()? {
+                WaitStatus::Stopped(_, Signal::SIGTRAP) if get_regs(self.pid).rip == rip => {}
+                WaitStatus::Stopped(_, Signal::SIGTRAP) => return Ok(None),
+                status => return Ok(Some(status)),
+            }
+        }
+    }
+
+    /// step the copy of the instruction under the int3 at rip, which is kept in place
+    /// returns None if there is no int3 at rip or the instruction can not be moved
+    fn step_displaced(&mut self, rip: u64) -> Result<Option<WaitStatus>, nix::Error> {
+        let Some(scratch) = self.scratch else {
+            return Ok(None);
+        };
+        if !self.at(rip).any(|bp| bp.slot.is_none()) {
+            return Ok(None);
+        }
+        // 他のスレッドが止まれるようにrewindで外したint3も戻しておく
+        self.insert_all()?;
+        let Ok(instructions) = disasm::disassemble(self.pid, self, rip, rip + MAX_INSTRUCTION_LEN)
+        else {
+            return Ok(None);
+        };
+        displaced::step(self.pid, &instructions[0], scratch)
+    }
+
+    fn lift(&mut self, addr: u64) -> Result<(), nix::Error> {
+        for bp in self.breakpoints.iter_mut().filter(|bp| bp.addr == addr) {
+            if bp.inserted {
+                match bp.slot {
+                    Some(slot) => debugreg::enable(self.pid, slot, false)?,
+                    // 同じアドレスのint3は共有なので何度書き戻してもよい
+                    None => mem::write_bytes(self.pid, addr, &[bp.value])?,
+                }
+            }
+            bp.inserted = false;
+        }
+        Ok(())
+    }
+
+    fn insert_all(&mut self) -> Result<(), nix::Error> {
+        for bp in self
+            .breakpoints
+            .iter_mut()
+            .filter(|bp| bp.enabled && !bp.inserted)
+        {
+            match bp.slot {
+                Some(slot) => debugreg::enable(self.pid, slot, true)?,
+                None => mem::write_bytes(self.pid, bp.addr, &[0xcc])?,
+            }
+            bp.inserted = true;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,144 @@
+use crate::{mem, syscall::get_regs};
+use nix::{
+    errno::Errno,
+    libc,
+    sys::{
+        ptrace::{self, Options},
+        signal::{self, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::Pid,
+};
+use std::io;
+
+/// `syscall` instruction
+const SYSCALL: [u8; 2] = [0x0f, 0x05];
+
+/// stopped copy of the target made by fork, `restart` continues from a copy of it
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub num: usize,
+    pub pid: Pid,
+    /// rip when the checkpoint was made
+    pub pc: u64,
+    /// int3 of the software breakpoints in the memory at that time, (address, original byte)
+    pub int3s: Vec<(u64, u8)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Checkpoints {
+    checkpoints: Vec<Checkpoint>,
+    next_num: usize,
+}
+
+impl Checkpoints {
+    pub fn add(&mut self, pid: Pid, pc: u64, int3s: Vec<(u64, u8)>) -> &Checkpoint {
+        self.next_num += 1;
+        self.checkpoints.push(Checkpoint {
+            num: self.next_num,
+            pid,
+            pc,
+            int3s,
+        });
+        self.checkpoints.last().unwrap()
+    }
+
+    pub fn get(&self, num: usize) -> Option<&Checkpoint> {
+        self.checkpoints.iter().find(|c| c.num == num)
+    }
+
+    /// kill the process of the checkpoint, returns false if there is no such checkpoint
+    pub fn delete(&mut self, num: usize) -> bool {
+        let Some(index) = self.checkpoints.iter().position(|c| c.num == num) else {
+            return false;
+        };
+        let pid = self.checkpoints.remove(index).pid;
+        if signal::kill(pid, Signal::SIGKILL).is_ok() {
+            let _ = waitpid(pid, Some(WaitPidFlag::__WALL));
+        }
+        true
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.checkpoints.iter()
+    }
+}
+
+/// fork the stopped process by executing the fork syscall at its rip
+/// the registers and the memory of both processes are restored, the child stays stopped
+pub fn fork(pid: Pid) -> Result<Pid, Box<dyn std::error::Error>> {
+    let saved = get_regs(pid);
+    let orig = mem::read_bytes(pid, saved.rip, SYSCALL.len())?;
+    mem::write_bytes(pid, saved.rip, &SYSCALL)?;
+    let mut regs = saved;
+    regs.rax = libc::SYS_fork as u64;
+    // システムコールの途中で止まっていても再開させない
+    regs.orig_rax = u64::MAX;
+    let result = match ptrace::setregs(pid, regs) {
+        Ok(()) => run_fork(pid),
+        Err(e) => Err(e.into()),
+    };
+    // 子プロセスもforkの時点のメモリを持っているので両方を元に戻す
+    mem::write_bytes(pid, saved.rip, &orig)?;
+    ptrace::setregs(pid, saved)?;
+    let child = result?;
+    mem::write_bytes(child, saved.rip, &orig)?;
+    ptrace::setregs(child, saved)?;
+    // tdbが終了したらチェックポイントも消える
+    ptrace::setoptions(
+        child,
+        Options::PTRACE_O_TRACEFORK | Options::PTRACE_O_EXITKILL,
+    )?;
+    Ok(child)
+}
+
+/// step over the syscall instruction and wait for the child stopped by SIGSTOP
+/// PTRACE_O_TRACEFORK is always set on the target and on the checkpoints
+fn run_fork(pid: Pid) -> Result<Pid, Box<dyn std::error::Error>> {
+    // 例えばチェックポイントはコピーが終了したときのSIGCHLDを受け取っている
+    let mut signals = Vec::new();
+    mem::step(pid, None)?;
+    let child = match wait_step(pid, &mut signals)? {
+        WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_FORK) => {
+            Pid::from_raw(ptrace::getevent(pid)? as i32)
+        }
+        status => {
+            let rax = get_regs(pid).rax as i64;
+            let msg = match rax < 0 {
+                true => format!("fork failed: {}", Errno::from_i32(-rax as i32)),
+                false => format!("fork did not stop the target: {status:?}"),
+            };
+            return Err(Box::new(io::Error::other(msg)));
+        }
+    };
+    // forkから戻ってsyscall命令の次で止まる
+    mem::step(pid, None)?;
+    wait_step(pid, &mut signals)?;
+    waitpid(child, Some(WaitPidFlag::__WALL))?;
+    // 止めておいたシグナルは次に再開したときに届く
+    for signal in signals {
+        signal::kill(pid, signal)?;
+    }
+    Ok(child)
+}
+
+/// wait for the step, signals which arrive before it are held back in `signals`
+fn wait_step(pid: Pid, signals: &mut Vec<Signal>) -> Result<WaitStatus, nix::Error> {
+    loop {
+        match waitpid(pid, Some(WaitPidFlag::__WALL))? {
+            WaitStatus::Stopped(_, signal) if signal != Signal::SIGTRAP => {
+                signals.push(signal);
+                mem::step(pid, None)?;
+            }
+            status => return Ok(status),
+        }
+    }
+}
+
+/// write back the bytes under the int3 which were in the memory when the checkpoint was made
+pub fn clean(pid: Pid, int3s: &[(u64, u8)]) -> Result<(), nix::Error> {
+    for (addr, value) in int3s.iter().rev() {
+        mem::write_bytes(pid, *addr, &[*value])?;
+    }
+    Ok(())
+}
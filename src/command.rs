@@ -1,14 +1,31 @@
 use crate::{
+    bisect::Bisect,
+    bounded::{Eviction, Policy},
+    breakpoint::{self, BreakpointManager, CatchKind, Condition, WatchKind},
     call_vmm::VmWatchpoint,
-    debugger::{DebuggerInfo, WatchPoint},
+    debug_info::{self, TdbMapRangeTrait},
+    debugger::{self, check_watchpoints, BufferKind, DebuggerInfo, FollowForkMode, WatchPoint},
+    doctor,
+    dprintf::{self, Dprintf},
     dump,
+    examine::{self, Examine},
+    expr::{self, Expr},
+    find,
     fini::fini,
-    mem, register,
-    syscall::{get_regs, SyscallInfo, SyscallStack},
+    infcall, mem,
+    pattern::{self, Pattern},
+    record, register,
+    signal::{self, Cancel, HandleAction},
+    step,
+    symbolize::{self, demangle, split_path},
+    syscall::{self, get_regs, SyscallInfo},
+    thread::SchedulerLocking,
+    unwind,
     util::parse_demical_or_hex,
+    variable::{self, Type},
 };
 use nix::{
-    libc::c_void,
+    libc::{self, c_void},
     sys::{
         ptrace,
         signal::Signal,
@@ -16,38 +33,360 @@ use nix::{
     },
     unistd::Pid,
 };
+use object::SymbolKind;
 use std::{
+    fmt, fs,
     io::{self, BufRead, Error, ErrorKind, Write},
+    path::{Path, PathBuf},
     process::exit,
 };
+use syscalls::Sysno;
 
 #[derive(Debug, Clone)]
 pub enum Command {
     Empty(Box<Option<Command>>),
-    StepInstruction,
-    Breakpoint(u64),
+    /// execute N instructions
+    StepInstruction(usize),
+    /// step each iteration of REP string instructions
+    StepIteration,
+    StepInChoose,
+    /// step to the next source line
+    StepLine,
+    /// step to the next source line without entering calls
+    Next,
+    /// run until the selected frame returns
+    Finish,
+    /// run to the address in the current frame or until it returns
+    Until(u64),
+    /// run to the address in any frame or until the current frame returns
+    Advance(u64),
+    /// set rip to the address and continue
+    Jump(u64),
+    /// pop the selected frame with the return value
+    Return(Option<Expr>),
+    /// call the function in the target with the arguments
+    Call(String, Vec<Expr>),
+    /// start the target again with the arguments (the previous ones if None) and continue
+    Run(Option<Vec<String>>),
+    /// `run` with a temporary breakpoint at the address of main
+    Start(u64, Option<Vec<String>>),
+    /// actual address and condition
+    Breakpoint(u64, Option<Condition>),
+    /// deleted after the first hit
+    TemporaryBreakpoint(u64, Option<Condition>),
+    /// uses a debug register instead of int3
+    HardwareBreakpoint(u64, Option<Condition>),
+    /// remove the condition if None
+    Condition(usize, Option<Condition>),
+    /// breakpoint number and count
+    Ignore(usize, usize),
+    /// all breakpoints and watchpoints if empty
+    Enable(Vec<usize>),
+    Disable(Vec<usize>),
+    Delete(Vec<usize>),
     Continue,
     DumpRegisters,
     ExamineMemory(u64, u64),
+    /// `x/NFU <expr>`
+    Examine(Examine, Expr),
+    /// write the pattern repeated to the length at the address
+    Fill(Expr, u64, Pattern),
+    /// print the cyclic pattern of the length
+    PatternCreate(usize),
+    /// find the value or the string in the cyclic pattern
+    PatternOffset(Expr),
+    /// search the memory for a sequence of values
+    Find(find::Find),
+    /// step over the function, the current one if None
+    Skip(Option<String>),
+    SkipDelete,
+    /// replace the first word `name` of commands, None lists the aliases
+    Alias(Option<(String, String)>),
+    Unalias(String),
     ExamineMemoryMap,
     List(Vec<String>),
+    Info(InfoCommand),
+    Print(Expr),
+    /// `print lhs = rhs`, assign and print the new value of `lhs`
+    PrintAssign(Expr, Expr),
+    /// print the memory at the address as a struct defined by `struct`
+    PrintAs(String, Expr),
+    Struct(StructCommand),
+    /// show all expressions if None
+    Display(Option<(String, Expr)>),
+    /// delete all expressions if empty
+    Undisplay(Vec<usize>),
     Backtrace,
+    Frame(Option<usize>),
+    /// select the thread by its number, print the current thread if None
+    Thread(Option<usize>),
+    /// run the command in each thread, all threads if None
+    ThreadApply(Option<Vec<usize>>, String),
+    Up(usize),
+    Down(usize),
     Watch(WatchCommand),
     Set(SetCommand),
     Vmcall(VmWatchpoint),
+    /// mark name and memory ranges (address, length), list marks if None
+    Mark(Option<String>, Vec<(Expr, u64)>),
+    DiffMarks(String, String),
+    /// two memory dump files, address of their first byte
+    DumpDiff(String, String, Option<Expr>),
+    HistoryStops,
+    /// print the registers and the stack recorded at the stop
+    GotoStop(usize),
+    /// event and condition on the arguments of syscalls
+    Catch(CatchKind, Option<Condition>),
+    /// change whether tdb stops, prints and passes the signal
+    Handle(Signal, Vec<HandleAction>),
+    Label(LabelCommand),
+    /// check the environment for problems
+    Doctor,
+    BisectWatch(BisectCommand),
+    Dump(DumpCommand),
+    Restore(DumpCommand),
+    /// breakpoint which prints instead of stopping
+    Dprintf(u64, Option<Condition>, Dprintf),
+    /// breakpoint number (the last one if None) and commands run when it stops
+    Commands(Option<usize>, Vec<String>),
+    /// release the target and leave it running
+    Detach,
+    /// how the target has to terminate for tdb to exit with 0
+    Expect(Termination),
+    /// fork the target into a snapshot which `restart` returns to
+    Checkpoint,
+    /// continue from a copy of the checkpoint instead of the target
+    RestartCheckpoint(usize),
+    DeleteCheckpoint(usize),
+    /// record the instructions stepped from now on so that they can be undone
+    Record,
+    /// stop recording and discard the history
+    RecordStop,
+    /// undo N recorded instructions
+    ReverseStepInstruction(usize),
+    /// undo recorded instructions until a breakpoint or the start of the history
+    ReverseContinue,
+    /// kill the target and wait for `run`
+    Kill,
+    /// kill the target if it is running and exit
+    Quit,
     Exit(i32),
 }
 
+#[derive(Debug, Clone)]
+pub enum InfoCommand {
+    Functions(Option<String>),
+    ModulesTree(Option<String>),
+    Locals,
+    Args,
+    Display,
+    Breakpoints,
+    Frame,
+    /// stack usage of each frame, frames larger than the bytes are flagged
+    FrameSizes(u64),
+    /// hits of the breakpoints and watchpoints in this run
+    HitCounts,
+    /// entries, limits and memory of the bounded buffers
+    Buffers,
+    /// traced threads with their names, states and locations
+    Threads,
+    /// loaded objects, with the tree of their DT_NEEDED if true
+    SharedLibrary(bool),
+    /// handling of the signal, or of all signals if None
+    Signals(Option<Signal>),
+    Checkpoints,
+    Record,
+    Skip,
+}
+
 #[derive(Debug, Clone)]
 pub enum WatchCommand {
-    Memory(mem::Memory),
-    Register(register::Register),
+    /// address or lvalue watched by a debug register
+    Expression(WatchKind, String, Expr),
+    /// register and its current value
+    Register(register::Register, u64),
+}
+
+#[derive(Debug, Clone)]
+pub enum StructCommand {
+    /// `[packed] Name { field: type, ... }`
+    Define(String),
+    /// TOML file
+    Load(String),
+    List,
+}
+
+#[derive(Debug, Clone)]
+pub enum BisectCommand {
+    /// record the writes to the value until the target terminates
+    Record(String, Expr),
+    /// run again and stop at the writes of the n-th writer
+    Replay(usize),
+    /// list the writers of the last recording
+    Summary,
+}
+
+/// what `dump` writes to and `restore` reads from a file
+#[derive(Debug, Clone)]
+pub enum DumpCommand {
+    /// all registers as JSON
+    Registers(String),
+    /// raw bytes of the file and the range, `restore` writes the whole file if the end is None
+    Memory(String, Expr, Option<find::End>),
+}
+
+#[derive(Debug, Clone)]
+pub enum LabelCommand {
+    /// address, length and name
+    Add(Expr, u64, String),
+    /// name or address
+    Delete(String),
+    List,
+    /// file path, `<target>.labels` if None
+    Save(Option<String>),
+    Load(Option<String>),
 }
 
 #[derive(Debug, Clone)]
 pub enum SetCommand {
     Memory(mem::Memory),
-    Register(register::Register),
+    /// register and the value to write
+    Register(register::Register, u64),
+    /// `set var lhs = rhs`
+    Variable(Expr, Expr),
+    /// `set {uN} addr = value`, size in bytes, address and value
+    Typed(u64, Expr, Expr),
+    FollowForkMode(FollowForkMode),
+    /// maximum number of entries of a buffer
+    Limit(BufferKind, usize),
+    /// what a full buffer drops
+    Eviction(BufferKind, Eviction),
+    /// external program for unresolved addresses, `off` stops it, None prints it
+    SymbolizerCmd(Option<String>),
+    SchedulerLocking(SchedulerLocking),
+    /// commands run at every stop, `unset stop-hook` empties them, None prints them
+    StopHook(Option<Vec<String>>),
+    /// radix of printed integers, None prints it
+    OutputRadix(Option<u32>),
+    /// (from, to) directories of the paths in the line programs, None prints the rules
+    SubstitutePath(Option<(String, String)>),
+    UnsetSubstitutePath,
+}
+
+/// what a command did to the target
+#[derive(Debug)]
+pub enum ExecOutcome {
+    /// the target ran and stopped again
+    Stopped {
+        status: WaitStatus,
+        reason: StopReason,
+    },
+    /// the target is still running, `next` waits for it
+    Continued { status: WaitStatus, next: Command },
+    /// the target is gone and tdb exits with the code
+    Exited { status: WaitStatus, code: i32 },
+    /// the target is gone but tdb waits for `run`
+    Killed { status: WaitStatus },
+    /// the command did not run the target
+    NoChange,
+}
+
+/// why the target stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// breakpoint at the address
+    Breakpoint(u64),
+    /// end of a step, watchpoint or catchpoint
+    Trap,
+    Signal(Signal),
+    /// entry or exit of a syscall
+    Syscall,
+}
+
+/// how the target terminated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Exit(i32),
+    Signal(Signal),
+}
+
+impl Termination {
+    pub fn from_status(status: WaitStatus) -> Option<Self> {
+        match status {
+            WaitStatus::Exited(_, code) => Some(Termination::Exit(code)),
+            WaitStatus::Signaled(_, signal, _) => Some(Termination::Signal(signal)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Termination::Exit(code) => write!(f, "exit {code}"),
+            Termination::Signal(signal) => write!(f, "signal {signal}"),
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Breakpoint(_) => write!(f, "breakpoint"),
+            StopReason::Trap => write!(f, "trap"),
+            StopReason::Signal(signal) => write!(f, "{signal}"),
+            StopReason::Syscall => write!(f, "syscall"),
+        }
+    }
+}
+
+impl ExecOutcome {
+    fn new(
+        debugger_info: &DebuggerInfo,
+        resumed: bool,
+        old: WaitStatus,
+        (status, next): (WaitStatus, Option<Command>),
+    ) -> ExecOutcome {
+        match (status, next) {
+            (_, Some(Command::Exit(code))) => ExecOutcome::Exited { status, code },
+            (_, Some(next)) => ExecOutcome::Continued { status, next },
+            (WaitStatus::Signaled(..) | WaitStatus::Exited(..), None) => {
+                ExecOutcome::Killed { status }
+            }
+            (_, None) if !resumed && status == old => ExecOutcome::NoChange,
+            (WaitStatus::Stopped(pid, Signal::SIGTRAP), None) => {
+                let rip = get_regs(pid).rip;
+                let reason = match debugger_info.breakpoint_manager.get(rip) {
+                    Some(bp) => StopReason::Breakpoint(bp.addr),
+                    None => StopReason::Trap,
+                };
+                ExecOutcome::Stopped { status, reason }
+            }
+            (WaitStatus::Stopped(_, signal), None) => ExecOutcome::Stopped {
+                status,
+                reason: StopReason::Signal(signal),
+            },
+            (WaitStatus::PtraceSyscall(_), None) => ExecOutcome::Stopped {
+                status,
+                reason: StopReason::Syscall,
+            },
+            (_, None) => ExecOutcome::Stopped {
+                status,
+                reason: StopReason::Trap,
+            },
+        }
+    }
+
+    /// wait status after the command, None if it did not change
+    pub fn status(&self) -> Option<WaitStatus> {
+        match self {
+            ExecOutcome::Stopped { status, .. }
+            | ExecOutcome::Continued { status, .. }
+            | ExecOutcome::Exited { status, .. }
+            | ExecOutcome::Killed { status } => Some(*status),
+            ExecOutcome::NoChange => None,
+        }
+    }
 }
 
 impl Command {
@@ -61,7 +400,33 @@ impl Command {
         let mut in_handle = stdin.lock();
 
         let mut buf = String::new();
-        in_handle.read_line(&mut buf)?;
+        signal::set_prompt(true);
+        let read = in_handle.read_line(&mut buf);
+        signal::set_prompt(false);
+        drop(in_handle);
+        // 入力の終わりでは前のコマンドを繰り返さずに終わる
+        if read? == 0 {
+            println!();
+            return Ok(Command::Quit);
+        }
+        Self::parse(debugger_info, &buf)
+    }
+
+    /// parse a line of a command
+    pub fn parse(
+        debugger_info: &mut DebuggerInfo,
+        buf: &str,
+    ) -> Result<Command, Box<dyn std::error::Error>> {
+        // 別名は最初の単語だけを置き換える
+        let trimmed = buf.trim();
+        let (first, rest) = trimmed
+            .split_once(char::is_whitespace)
+            .unwrap_or((trimmed, ""));
+        let expanded = debugger_info
+            .aliases
+            .get(first)
+            .map(|command| format!("{command} {rest}"));
+        let buf = expanded.as_deref().unwrap_or(buf);
         let buf_vec: Vec<&str> = buf.split(' ').collect();
         let buf_vec: Vec<&str> = buf_vec
             .iter()
@@ -78,31 +443,326 @@ impl Command {
         match buf_vec[0] {
             "stepi" | "si" => {
                 debugger_info.cont_flag = false;
-                Ok(StepInstruction)
+                match buf_vec.get(1) {
+                    None => Ok(StepInstruction(1)),
+                    Some(&"--iterations") => Ok(StepIteration),
+                    Some(count) => match count.parse::<usize>() {
+                        Ok(count) if count > 0 => Ok(StepInstruction(count)),
+                        _ => Err(Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "invalid argument",
+                        ))),
+                    },
+                }
+            }
+            "step" | "s" => match buf_vec[1..] {
+                [] => Ok(StepLine),
+                ["in", "--choose"] => Ok(StepInChoose),
+                _ => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "invalid argument",
+                ))),
+            },
+            "next" | "n" => Ok(Next),
+            "finish" | "fin" => Ok(Finish),
+            "until" | "u" => Ok(Until(run_location(debugger_info, &buf_vec[1..])?)),
+            "advance" => Ok(Advance(run_location(debugger_info, &buf_vec[1..])?)),
+            "jump" | "j" => Ok(Jump(run_location(debugger_info, &buf_vec[1..])?)),
+            "run" | "r" => Ok(Run(run_args(&buf_vec[1..]))),
+            "start" => Ok(Start(main_address(debugger_info)?, run_args(&buf_vec[1..]))),
+            "return" => match buf_vec.len() {
+                1 => Ok(Return(None)),
+                _ => Ok(Return(Some(expr::parse(&buf_vec[1..].join(" "))?))),
+            },
+            "call" => {
+                let (name, args) = infcall::parse(&buf_vec[1..].join(" "))?;
+                Ok(Call(name, args))
             }
             "break" | "b" => {
-                if buf_vec.len() == 2 {
-                    let bp = buf_vec[1];
-                    let off = debugger_info.debug_info.get_breakpoint_offset(bp);
-                    let off = match off {
-                        Some(off) => off as u64,
-                        None => bp.parse::<u64>()?,
+                let (off, condition) = parse_breakpoint(debugger_info, &buf_vec[1..])?;
+                Ok(Breakpoint(off, condition))
+            }
+            "tbreak" => {
+                let (off, condition) = parse_breakpoint(debugger_info, &buf_vec[1..])?;
+                Ok(TemporaryBreakpoint(off, condition))
+            }
+            "hbreak" => {
+                let (off, condition) = parse_breakpoint(debugger_info, &buf_vec[1..])?;
+                Ok(HardwareBreakpoint(off, condition))
+            }
+            "dprintf" => {
+                // 書式文字列の空白を保つため行をそのまま使う
+                let args = buf.trim().strip_prefix("dprintf").unwrap_or_default();
+                let (location, format) = dprintf::split_location(args)?;
+                let location = location.split_whitespace().collect::<Vec<&str>>();
+                let (addr, condition) = parse_breakpoint(debugger_info, &location)?;
+                Ok(Dprintf(addr, condition, dprintf::parse(format)?))
+            }
+            "label" => parse_label(buf, &buf_vec[1..]),
+            "doctor" => Ok(Doctor),
+            "detach" => Ok(Detach),
+            "checkpoint" => Ok(Checkpoint),
+            "restart" => match buf_vec[1..] {
+                [num] => Ok(RestartCheckpoint(num.parse::<usize>()?)),
+                _ => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "usage: restart <checkpoint>",
+                ))),
+            },
+            "record" => match buf_vec[1..] {
+                [] => Ok(Record),
+                ["stop"] => Ok(RecordStop),
+                _ => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "usage: record [stop]",
+                ))),
+            },
+            "reverse-stepi" | "rsi" => match buf_vec.get(1) {
+                None => Ok(ReverseStepInstruction(1)),
+                Some(count) => match count.parse::<usize>() {
+                    Ok(count) if count > 0 => Ok(ReverseStepInstruction(count)),
+                    _ => Err(Box::new(Error::new(
+                        ErrorKind::InvalidInput,
+                        "invalid argument",
+                    ))),
+                },
+            },
+            "reverse-continue" | "rc" => Ok(ReverseContinue),
+            "kill" | "k" => Ok(Kill),
+            "quit" | "q" => Ok(Quit),
+            "expect" => Ok(Expect(parse_termination(&buf_vec[1..])?)),
+            "bisect-watch" => match buf_vec[1..] {
+                [] => Ok(BisectWatch(BisectCommand::Summary)),
+                ["replay", n] => Ok(BisectWatch(BisectCommand::Replay(n.parse::<usize>()?))),
+                _ => {
+                    let text = buf_vec[1..].join(" ");
+                    let expr = expr::parse(&text)?;
+                    Ok(BisectWatch(BisectCommand::Record(text, expr)))
+                }
+            },
+            "dump" | "restore" => {
+                if buf_vec[0] == "dump" && buf_vec.get(1) == Some(&"diff") {
+                    return match buf_vec[2..] {
+                        [a, b] => Ok(DumpDiff(a.to_string(), b.to_string(), None)),
+                        [a, b, "--base", ref base @ ..] if !base.is_empty() => Ok(DumpDiff(
+                            a.to_string(),
+                            b.to_string(),
+                            Some(expr::parse(&base.join(" "))?),
+                        )),
+                        _ => Err(Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "usage: dump diff <a.bin> <b.bin> [--base <addr>]",
+                        ))),
                     };
-                    Ok(Breakpoint(off))
-                } else {
-                    Err(Box::new(Error::new(
+                }
+                let end = |end: &str| -> Result<find::End, Box<dyn std::error::Error>> {
+                    match end.strip_prefix('+') {
+                        Some(len) => Ok(find::End::Length(expr::parse(len)?)),
+                        None => Ok(find::End::Address(expr::parse(end)?)),
+                    }
+                };
+                let dump_command = match (buf_vec[0], &buf_vec[1..]) {
+                    (_, ["regs", path]) => DumpCommand::Registers(path.to_string()),
+                    (_, ["memory", path, start, last]) => {
+                        DumpCommand::Memory(path.to_string(), expr::parse(start)?, Some(end(last)?))
+                    }
+                    ("restore", ["memory", path, start]) => {
+                        DumpCommand::Memory(path.to_string(), expr::parse(start)?, None)
+                    }
+                    _ => {
+                        return Err(Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            match buf_vec[0] {
+                                "dump" => "usage: dump regs <file>, dump memory <file> <start> <end|+len>",
+                                _ => "usage: restore regs <file>, restore memory <file> <addr> [<end|+len>]",
+                            },
+                        )))
+                    }
+                };
+                match buf_vec[0] {
+                    "dump" => Ok(Dump(dump_command)),
+                    _ => Ok(Restore(dump_command)),
+                }
+            }
+            "struct" => match buf_vec[1..] {
+                [] | ["list"] => Ok(Struct(StructCommand::List)),
+                ["load", path] => Ok(Struct(StructCommand::Load(path.to_string()))),
+                _ => {
+                    let def = buf.trim().strip_prefix("struct").unwrap_or_default();
+                    Ok(Struct(StructCommand::Define(def.trim().to_string())))
+                }
+            },
+            "catch" => {
+                // `catch syscall write if fd == 2`
+                let (buf, condition) = match buf.split_once(" if ") {
+                    Some((buf, text)) => {
+                        let text = text.trim().to_string();
+                        let expr = expr::parse(&text)?;
+                        (buf, Some((text, expr)))
+                    }
+                    None => (buf, None),
+                };
+                let buf_vec: Vec<&str> = buf.split_whitespace().collect();
+                let kind = match buf_vec[1..] {
+                    ["fork"] => CatchKind::Fork,
+                    ["exec"] => CatchKind::Exec,
+                    ["exit"] => CatchKind::Exit,
+                    ["signal", name] => CatchKind::Signal(parse_signal(name)?),
+                    ["syscall", ref names @ ..] => CatchKind::Syscall(
+                        names
+                            .iter()
+                            .map(|name| parse_syscall(name))
+                            .collect::<Result<Vec<Sysno>, _>>()?,
+                    ),
+                    _ => return Err(Box::new(Error::new(
+                        ErrorKind::InvalidInput,
+                        "usage: catch fork|exec|exit|signal <SIG>|syscall [<name>...] [if <cond>]",
+                    ))),
+                };
+                if condition.is_some() && !matches!(kind, CatchKind::Syscall(_)) {
+                    return Err(Box::new(Error::new(
+                        ErrorKind::InvalidInput,
+                        "conditions are only supported for syscall catchpoints",
+                    )));
+                }
+                Ok(Catch(kind, condition))
+            }
+            "handle" => {
+                let usage = || {
+                    Box::new(Error::new(
+                        ErrorKind::InvalidInput,
+                        "usage: handle <SIG> [stop|nostop] [print|noprint] [pass|nopass]",
+                    )) as Box<dyn std::error::Error>
+                };
+                let signal = parse_signal(buf_vec.get(1).ok_or_else(usage)?)?;
+                let actions = buf_vec[2..]
+                    .iter()
+                    .map(|keyword| HandleAction::from_keyword(keyword).ok_or_else(usage))
+                    .collect::<Result<Vec<HandleAction>, _>>()?;
+                Ok(Handle(signal, actions))
+            }
+            "ignore" => {
+                if buf_vec.len() != 3 {
+                    return Err(Box::new(Error::new(
                         ErrorKind::InvalidInput,
                         "invalid argument",
-                    )))
+                    )));
+                }
+                let num = buf_vec[1].parse::<usize>()?;
+                let count = buf_vec[2].parse::<usize>()?;
+                Ok(Ignore(num, count))
+            }
+            "condition" => {
+                let num = match buf_vec.get(1) {
+                    Some(num) => num.parse::<usize>()?,
+                    None => {
+                        return Err(Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "invalid argument",
+                        )))
+                    }
+                };
+                if buf_vec.len() == 2 {
+                    return Ok(Condition(num, None));
+                }
+                let text = buf_vec[2..].join(" ");
+                let expr = expr::parse(&text)?;
+                Ok(Condition(num, Some((text, expr))))
+            }
+            "delete" | "d" if buf_vec.get(1) == Some(&"checkpoint") => match buf_vec[2..] {
+                [num] => Ok(DeleteCheckpoint(num.parse::<usize>()?)),
+                _ => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "usage: delete checkpoint <num>",
+                ))),
+            },
+            "enable" | "disable" | "delete" | "d" => {
+                let nums = buf_vec[1..]
+                    .iter()
+                    .map(|n| n.parse::<usize>())
+                    .collect::<Result<Vec<usize>, _>>()?;
+                match buf_vec[0] {
+                    "enable" => Ok(Enable(nums)),
+                    "disable" => Ok(Disable(nums)),
+                    _ => Ok(Delete(nums)),
                 }
             }
             "continue" | "c" => Ok(Continue),
             "regs" => Ok(DumpRegisters),
-            "examine" | "x" => {
+            "examine" | "x" if buf_vec.len() == 3 => {
                 let addr = parse_demical_or_hex(buf_vec[1])?;
                 let len = parse_demical_or_hex(buf_vec[2])?;
                 Ok(ExamineMemory(addr, len))
             }
+            "examine" | "x" if buf_vec.len() > 1 => Ok(Examine(
+                examine::Examine::default(),
+                expr::parse(&buf_vec[1..].join(" "))?,
+            )),
+            command if command.starts_with("x/") && buf_vec.len() > 1 => Ok(Examine(
+                examine::parse(&command[2..])?,
+                expr::parse(&buf_vec[1..].join(" "))?,
+            )),
+            "fill" => match buf_vec[1..] {
+                [addr, len, _, ..] => Ok(Fill(
+                    expr::parse(addr)?,
+                    parse_demical_or_hex(len)?,
+                    Pattern::parse(&buf_vec[3..].join(" "))?,
+                )),
+                _ => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "usage: fill <addr> <len> <byte|\"string\"|cyclic>",
+                ))),
+            },
+            "pattern" => match buf_vec[1..] {
+                ["create", len] => Ok(PatternCreate(parse_demical_or_hex(len)? as usize)),
+                ["offset", _, ..] => Ok(PatternOffset(expr::parse(&buf_vec[2..].join(" "))?)),
+                _ => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "usage: pattern create <len>, pattern offset <value|\"string\">",
+                ))),
+            },
+            command if command == "find" || command.starts_with("find/") => {
+                // 文字列の空白を保つために行をそのまま渡す
+                let args = buf
+                    .trim()
+                    .split_once(char::is_whitespace)
+                    .map_or("", |(_, args)| args);
+                Ok(Find(find::parse(command.strip_prefix("find/"), args)?))
+            }
+            "skip" => match buf_vec[1..] {
+                [] => Ok(Skip(None)),
+                ["delete"] => Ok(SkipDelete),
+                _ => Ok(Skip(Some(buf_vec[1..].join(" ")))),
+            },
+            "alias" => {
+                let def = buf.trim_start()[5..].trim();
+                if def.is_empty() {
+                    return Ok(Alias(None));
+                }
+                let usage = || {
+                    Box::new(Error::new(
+                        ErrorKind::InvalidInput,
+                        "usage: alias <name> = <command>",
+                    )) as Box<dyn std::error::Error>
+                };
+                let (name, command) = def.split_once('=').ok_or_else(usage)?;
+                let (name, command) = (name.trim(), command.trim());
+                if name.is_empty()
+                    || name.contains(char::is_whitespace)
+                    || matches!(name, "alias" | "unalias")
+                    || command.is_empty()
+                {
+                    return Err(usage());
+                }
+                Ok(Alias(Some((name.to_string(), command.to_string()))))
+            }
+            "unalias" => match buf_vec[1..] {
+                [name] => Ok(Unalias(name.to_string())),
+                _ => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "usage: unalias <name>",
+                ))),
+            },
             "mmap" => Ok(ExamineMemoryMap),
             "ls" => {
                 let sub_commands = buf_vec[1..]
@@ -111,38 +771,283 @@ impl Command {
                     .collect::<Vec<String>>();
                 Ok(List(sub_commands))
             }
+            "info" | "i" => {
+                let arg = buf_vec.get(2).map(|arg| arg.to_string());
+                match buf_vec.get(1) {
+                    Some(&"functions") | Some(&"f") => Ok(Info(InfoCommand::Functions(arg))),
+                    Some(&"modules-tree") => Ok(Info(InfoCommand::ModulesTree(arg))),
+                    Some(&"locals") => Ok(Info(InfoCommand::Locals)),
+                    Some(&"args") => Ok(Info(InfoCommand::Args)),
+                    Some(&"display") => Ok(Info(InfoCommand::Display)),
+                    Some(&"breakpoints") | Some(&"b") => Ok(Info(InfoCommand::Breakpoints)),
+                    Some(&"frame") => match buf_vec[2..] {
+                        [] => Ok(Info(InfoCommand::Frame)),
+                        ["--size"] => Ok(Info(InfoCommand::FrameSizes(dump::LARGE_FRAME))),
+                        ["--size", bytes] => {
+                            Ok(Info(InfoCommand::FrameSizes(parse_demical_or_hex(bytes)?)))
+                        }
+                        _ => Err(Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "usage: info frame [--size [bytes]]",
+                        ))),
+                    },
+                    Some(&"hit-counts") => Ok(Info(InfoCommand::HitCounts)),
+                    Some(&"buffers") => Ok(Info(InfoCommand::Buffers)),
+                    Some(&"threads") => Ok(Info(InfoCommand::Threads)),
+                    Some(&"checkpoints") => Ok(Info(InfoCommand::Checkpoints)),
+                    Some(&"record") => Ok(Info(InfoCommand::Record)),
+                    Some(&"skip") => Ok(Info(InfoCommand::Skip)),
+                    Some(&"signals") | Some(&"handle") => match buf_vec.get(2) {
+                        Some(name) => Ok(Info(InfoCommand::Signals(Some(parse_signal(name)?)))),
+                        None => Ok(Info(InfoCommand::Signals(None))),
+                    },
+                    Some(&"sharedlibrary") | Some(&"shared") => match buf_vec.get(2) {
+                        None => Ok(Info(InfoCommand::SharedLibrary(false))),
+                        Some(&"--deps") => Ok(Info(InfoCommand::SharedLibrary(true))),
+                        _ => Err(Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "usage: info sharedlibrary [--deps]",
+                        ))),
+                    },
+                    _ => Err(Box::new(Error::new(
+                        ErrorKind::InvalidInput,
+                        "invalid info sub command",
+                    ))),
+                }
+            }
+            "print" | "p" => {
+                if buf_vec.len() < 2 {
+                    return Err(Box::new(Error::new(
+                        ErrorKind::InvalidInput,
+                        "invalid argument",
+                    )));
+                }
+                if buf_vec[1] == "as" {
+                    return match buf_vec[2..] {
+                        [name, _, ..] => Ok(PrintAs(
+                            name.to_string(),
+                            expr::parse(&buf_vec[3..].join(" "))?,
+                        )),
+                        _ => Err(Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "usage: print as <struct> <addr>",
+                        ))),
+                    };
+                }
+                let text = buf_vec[1..].join(" ");
+                if let Ok((lhs, rhs)) = expr::parse_assignment(&text) {
+                    return Ok(PrintAssign(lhs, rhs));
+                }
+                Ok(Print(expr::parse(&text)?))
+            }
+            "display" => {
+                if buf_vec.len() < 2 {
+                    return Ok(Display(None));
+                }
+                let text = buf_vec[1..].join(" ");
+                let expr = expr::parse(&text)?;
+                Ok(Display(Some((text, expr))))
+            }
+            "undisplay" => {
+                let nums = buf_vec[1..]
+                    .iter()
+                    .map(|n| n.parse::<usize>())
+                    .collect::<Result<Vec<usize>, _>>()?;
+                Ok(Undisplay(nums))
+            }
             "backtrace" | "bt" => Ok(Backtrace),
-            "watch" | "w" => {
-                if let Ok(addr) = parse_demical_or_hex(buf_vec[1]) {
-                    let value =
-                        ptrace::read(debugger_info.debug_info.target_pid(), addr as *mut c_void)
-                            .unwrap() as u64;
-                    Ok(Watch(WatchCommand::Memory(mem::Memory { addr, value })))
-                } else if let Ok(reg_type) = register::RegisterType::parse(buf_vec[1]) {
-                    let value = reg_type.get_current_value(debugger_info.debug_info.target_pid());
-                    Ok(Watch(WatchCommand::Register(register::Register {
-                        reg_type,
-                        value,
-                    })))
-                } else {
-                    Err(Box::new(Error::new(
+            "frame" | "f" => {
+                let level = match buf_vec.get(1) {
+                    Some(level) => Some(level.parse::<usize>()?),
+                    None => None,
+                };
+                Ok(Frame(level))
+            }
+            "thread" => match buf_vec[1..] {
+                ["apply", ..] => {
+                    // `thread apply all bt`, `thread apply 1 3 bt`
+                    let rest = &buf_vec[2..];
+                    let (nums, line) = match rest {
+                        ["all", line @ ..] => (None, line),
+                        _ => {
+                            let count = rest
+                                .iter()
+                                .take_while(|arg| arg.parse::<usize>().is_ok())
+                                .count();
+                            let nums = rest[..count].iter().map(|num| num.parse().unwrap());
+                            (Some(nums.collect::<Vec<usize>>()), &rest[count..])
+                        }
+                    };
+                    if line.is_empty() || nums.as_ref().is_some_and(|nums| nums.is_empty()) {
+                        return Err(Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "usage: thread apply all|<N>... <command>",
+                        )));
+                    }
+                    Ok(ThreadApply(nums, line.join(" ")))
+                }
+                [num] => Ok(Thread(Some(num.parse::<usize>()?))),
+                _ => Ok(Thread(None)),
+            },
+            "up" => match buf_vec.get(1) {
+                Some(n) => Ok(Up(n.parse::<usize>()?)),
+                None => Ok(Up(1)),
+            },
+            "down" => match buf_vec.get(1) {
+                Some(n) => Ok(Down(n.parse::<usize>()?)),
+                None => Ok(Down(1)),
+            },
+            "watch" | "w" | "rwatch" | "awatch" => {
+                if buf_vec.len() < 2 {
+                    return Err(Box::new(Error::new(
                         ErrorKind::InvalidInput,
                         "invalid argument",
-                    )))
+                    )));
+                }
+                let kind = match buf_vec[0] {
+                    "rwatch" => WatchKind::Read,
+                    "awatch" => WatchKind::Access,
+                    _ => WatchKind::Write,
+                };
+                // レジスタはデバッグレジスタでは見張れないのでステップ実行で見る
+                if let Ok(reg) = register::Register::parse(buf_vec[1]) {
+                    if kind == WatchKind::Write && buf_vec.len() == 2 {
+                        let value = reg.get_current_value(debugger_info.debug_info.target_pid());
+                        return Ok(Watch(WatchCommand::Register(reg, value)));
+                    }
                 }
+                let text = buf_vec[1..].join(" ");
+                let expr = expr::parse(&text)?;
+                Ok(Watch(WatchCommand::Expression(kind, text, expr)))
             }
             "set" => {
+                if buf_vec.get(1) == Some(&"follow-fork-mode") {
+                    return match buf_vec.get(2) {
+                        Some(&"parent") => {
+                            Ok(Set(SetCommand::FollowForkMode(FollowForkMode::Parent)))
+                        }
+                        Some(&"child") => {
+                            Ok(Set(SetCommand::FollowForkMode(FollowForkMode::Child)))
+                        }
+                        _ => Err(Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "usage: set follow-fork-mode parent|child",
+                        ))),
+                    };
+                }
+                if buf_vec.get(1) == Some(&"scheduler-locking") {
+                    let locking = match buf_vec.get(2) {
+                        Some(&"off") => Some(SchedulerLocking::Off),
+                        Some(&"step") => Some(SchedulerLocking::Step),
+                        Some(&"on") => Some(SchedulerLocking::On),
+                        _ => None,
+                    };
+                    return locking
+                        .map(|locking| Set(SetCommand::SchedulerLocking(locking)))
+                        .ok_or_else(|| {
+                            Box::new(Error::new(
+                                ErrorKind::InvalidInput,
+                                "usage: set scheduler-locking on|step|off",
+                            )) as Box<dyn std::error::Error>
+                        });
+                }
+                if buf_vec.get(1) == Some(&"symbolizer-cmd") {
+                    // 引用符やパイプはシェルに任せるので行をそのまま渡す
+                    let command = buf.trim_start()[3..]
+                        .trim_start()
+                        .trim_start_matches("symbolizer-cmd")
+                        .trim();
+                    return match command {
+                        "" => Ok(Set(SetCommand::SymbolizerCmd(None))),
+                        command => Ok(Set(SetCommand::SymbolizerCmd(Some(command.to_string())))),
+                    };
+                }
+                if buf_vec.get(1) == Some(&"output-radix") {
+                    return match buf_vec.get(2) {
+                        None => Ok(Set(SetCommand::OutputRadix(None))),
+                        Some(radix) => Ok(Set(SetCommand::OutputRadix(Some(radix.parse()?)))),
+                    };
+                }
+                if buf_vec.get(1) == Some(&"substitute-path") {
+                    return match buf_vec[2..] {
+                        [] => Ok(Set(SetCommand::SubstitutePath(None))),
+                        [from, to] => Ok(Set(SetCommand::SubstitutePath(Some((
+                            from.to_string(),
+                            to.to_string(),
+                        ))))),
+                        _ => Err(Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "usage: set substitute-path <from> <to>",
+                        ))),
+                    };
+                }
+                if buf_vec.get(1) == Some(&"stop-hook") {
+                    // 複数のコマンドは;で区切る
+                    let commands: Vec<String> = buf.trim_start()[3..]
+                        .trim_start()
+                        .trim_start_matches("stop-hook")
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|command| !command.is_empty())
+                        .map(String::from)
+                        .collect();
+                    return match commands.is_empty() {
+                        true => Ok(Set(SetCommand::StopHook(None))),
+                        false => Ok(Set(SetCommand::StopHook(Some(commands)))),
+                    };
+                }
+                if let Some(&name @ ("limit" | "eviction")) = buf_vec.get(1) {
+                    let kind = buf_vec.get(2).and_then(|kind| BufferKind::from_name(kind));
+                    let command = match (name, kind, buf_vec.get(3)) {
+                        ("limit", Some(kind), Some(limit)) => limit
+                            .parse()
+                            .ok()
+                            .map(|limit| SetCommand::Limit(kind, limit)),
+                        ("eviction", Some(kind), Some(&"oldest")) => {
+                            Some(SetCommand::Eviction(kind, Eviction::Oldest))
+                        }
+                        ("eviction", Some(kind), Some(&"newest")) => {
+                            Some(SetCommand::Eviction(kind, Eviction::Newest))
+                        }
+                        _ => None,
+                    };
+                    return command.map(Set).ok_or_else(|| {
+                        Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "usage: set limit stops|syscalls|bisect|record <N>, set eviction stops|syscalls|bisect|record oldest|newest",
+                        )) as Box<dyn std::error::Error>
+                    });
+                }
+                if let Some(typed) = buf.trim_start()[3..].trim_start().strip_prefix('{') {
+                    let usage = || {
+                        Box::new(Error::new(
+                            ErrorKind::InvalidInput,
+                            "usage: set {u8|u16|u32|u64} <addr> = <value>",
+                        )) as Box<dyn std::error::Error>
+                    };
+                    let (ty, rest) = typed.split_once('}').ok_or_else(usage)?;
+                    let size = match ty.trim() {
+                        "u8" => 1,
+                        "u16" => 2,
+                        "u32" => 4,
+                        "u64" => 8,
+                        _ => return Err(usage()),
+                    };
+                    let (addr, value) = expr::parse_assignment(rest)?;
+                    return Ok(Set(SetCommand::Typed(size, addr, value)));
+                }
+                if buf_vec.get(1) == Some(&"var") {
+                    let (lhs, rhs) = expr::parse_assignment(&buf_vec[2..].join(" "))?;
+                    return Ok(Set(SetCommand::Variable(lhs, rhs)));
+                }
                 if buf_vec.len() == 3 {
                     if let Ok(addr) = parse_demical_or_hex(buf_vec[1]) {
                         if let Ok(value) = parse_demical_or_hex(buf_vec[2]) {
                             return Ok(Set(SetCommand::Memory(mem::Memory { addr, value })));
                         }
-                    } else if let Ok(reg_type) = register::RegisterType::parse(buf_vec[1]) {
+                    } else if let Ok(reg) = register::Register::parse(buf_vec[1]) {
                         if let Ok(value) = parse_demical_or_hex(buf_vec[2]) {
-                            return Ok(Set(SetCommand::Register(register::Register {
-                                reg_type,
-                                value,
-                            })));
+                            return Ok(Set(SetCommand::Register(reg, value)));
                         }
                     }
                 }
@@ -151,6 +1056,14 @@ impl Command {
                     "invalid argument",
                 )))
             }
+            "unset" => match buf_vec[1..] {
+                ["stop-hook"] => Ok(Set(SetCommand::StopHook(Some(Vec::new())))),
+                ["substitute-path"] => Ok(Set(SetCommand::UnsetSubstitutePath)),
+                _ => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "usage: unset stop-hook|substitute-path",
+                ))),
+            },
             "vmcall" => {
                 if buf_vec.len() == 3 {
                     if let Ok(addr) = parse_demical_or_hex(buf_vec[1]) {
@@ -164,6 +1077,52 @@ impl Command {
                     "invalid argument",
                 )))
             }
+            "mark" => {
+                let name = match buf_vec.get(1) {
+                    Some(name) => name.to_string(),
+                    None => return Ok(Mark(None, Vec::new())),
+                };
+                let args = &buf_vec[2..];
+                if !args.len().is_multiple_of(2) {
+                    return Err(Box::new(Error::new(
+                        ErrorKind::InvalidInput,
+                        "usage: mark <name> [<addr expr> <len>]...",
+                    )));
+                }
+                let ranges = args
+                    .chunks(2)
+                    .map(|range| Ok((expr::parse(range[0])?, parse_demical_or_hex(range[1])?)))
+                    .collect::<Result<Vec<(Expr, u64)>, Box<dyn std::error::Error>>>()?;
+                Ok(Mark(Some(name), ranges))
+            }
+            "diff" => match buf_vec[1..] {
+                ["marks", a, b] => Ok(DiffMarks(a.to_string(), b.to_string())),
+                _ => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "usage: diff marks <a> <b>",
+                ))),
+            },
+            "history" => match buf_vec[1..] {
+                ["stops"] => Ok(HistoryStops),
+                _ => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "usage: history stops",
+                ))),
+            },
+            "goto-stop" => match buf_vec.get(1) {
+                Some(n) => Ok(GotoStop(n.parse::<usize>()?)),
+                None => Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    "usage: goto-stop <N>",
+                ))),
+            },
+            "commands" => {
+                let num = match buf_vec.get(1) {
+                    Some(num) => Some(num.parse::<usize>()?),
+                    None => None,
+                };
+                Ok(Commands(num, read_command_list(debugger_info)?))
+            }
             _ => Err(Box::new(Error::new(
                 ErrorKind::NotFound,
                 "command not found",
@@ -171,115 +1130,958 @@ impl Command {
         }
     }
 
+    /// true if the command resumes the target
+    pub fn resumes(&self) -> bool {
+        match self {
+            Command::Empty(prev) => prev.as_ref().as_ref().is_some_and(|c| c.resumes()),
+            Command::StepInstruction(_)
+            | Command::StepIteration
+            | Command::StepInChoose
+            | Command::StepLine
+            | Command::Next
+            | Command::Finish
+            | Command::Until(_)
+            | Command::Advance(_)
+            | Command::Jump(_)
+            | Command::Run(_)
+            | Command::Start(..)
+            | Command::BisectWatch(BisectCommand::Record(..))
+            | Command::BisectWatch(BisectCommand::Replay(_))
+            | Command::ReverseStepInstruction(_)
+            | Command::ReverseContinue
+            | Command::Continue => true,
+            _ => false,
+        }
+    }
+
+    /// true if the command runs the current thread by steps
+    /// the other threads run during it only with `scheduler-locking off`
+    pub fn steps(&self) -> bool {
+        match self {
+            Command::Empty(prev) => prev.as_ref().as_ref().is_some_and(|c| c.steps()),
+            Command::StepInstruction(_)
+            | Command::StepIteration
+            | Command::StepInChoose
+            | Command::StepLine
+            | Command::Next
+            | Command::Finish
+            | Command::Until(_)
+            | Command::Advance(_) => true,
+            _ => false,
+        }
+    }
+
+    /// true if the command cannot be executed after the target is killed
+    pub fn needs_target(&self) -> bool {
+        match self {
+            Command::Empty(prev) => prev.as_ref().as_ref().is_some_and(|c| c.needs_target()),
+            Command::Run(_)
+            | Command::Start(..)
+            | Command::Expect(_)
+            | Command::Handle(..)
+            | Command::RestartCheckpoint(_)
+            | Command::DeleteCheckpoint(_)
+            | Command::DumpDiff(..)
+            | Command::Set(SetCommand::StopHook(_))
+            | Command::Set(SetCommand::OutputRadix(_))
+            | Command::Set(SetCommand::SubstitutePath(_))
+            | Command::Set(SetCommand::UnsetSubstitutePath)
+            | Command::Skip(Some(_))
+            | Command::SkipDelete
+            | Command::Info(InfoCommand::Skip)
+            | Command::Alias(_)
+            | Command::Unalias(_)
+            | Command::PatternCreate(_)
+            | Command::BisectWatch(BisectCommand::Replay(_))
+            | Command::BisectWatch(BisectCommand::Summary)
+            | Command::Quit
+            | Command::Exit(_) => false,
+            _ => true,
+        }
+    }
+
     /// command execution
-    /// returns (wait status after command execution, additional command)
     pub fn exec(
         command: Command,
         debugger_info: &mut DebuggerInfo,
         status: WaitStatus,
+    ) -> Result<ExecOutcome, Box<dyn std::error::Error>> {
+        if is_terminated(status) && command.needs_target() {
+            println!("the program is not being run");
+            return Ok(ExecOutcome::NoChange);
+        }
+        let resumed = command.resumes();
+        // ステップ中に他のスレッドを動かすときは前後でまとめて再開と停止をする
+        let others_run = command.steps()
+            && debugger_info.scheduler_locking == SchedulerLocking::Off
+            && !debugger_info.threads.others().is_empty();
+        if others_run {
+            debugger_info.resume_others()?;
+        }
+        let result = Self::exec_command(command, debugger_info, status)?;
+        if others_run && !is_terminated(result.0) {
+            debugger_info.stop_others()?;
+        }
+        Ok(ExecOutcome::new(debugger_info, resumed, status, result))
+    }
+
+    /// returns (wait status after command execution, additional command)
+    fn exec_command(
+        command: Command,
+        debugger_info: &mut DebuggerInfo,
+        status: WaitStatus,
     ) -> Result<(WaitStatus, Option<Command>), Box<dyn std::error::Error>> {
         use Command::*;
         let status_and_additional_command = match command {
             Empty(prev_command) => {
                 if let Some(command) = *prev_command {
-                    Self::exec(command, debugger_info, status).unwrap()
+                    Self::exec_command(command, debugger_info, status)?
                 } else {
                     println!("command not found");
                     (status, None)
                 }
             }
-            Breakpoint(bin_offset) => {
-                // とりあえずコードセグメントが1つだけのバイナリに対応
-                let exec_map = debugger_info.debug_info.exec_maps().unwrap()[0];
-                // mapが実際にある仮想アドレス
-                let start = exec_map.start() as u64;
-                // バイナリファイルのどこからがこの領域にマップされているかを指し示す値
-                // addrの計算に必要
-                let offset = exec_map.offset as u64;
-                // ブレークポイントの実際のアドレス
-                let addr = start + (bin_offset - offset);
-                let _byte = debugger_info.breakpoint_manager.set(addr)?;
-                println!("set breakpoint at 0x{:016x}", addr);
+            Breakpoint(addr, ref condition) => {
+                set_breakpoint(
+                    debugger_info,
+                    addr,
+                    condition.clone(),
+                    BreakpointManager::set,
+                );
                 debugger_info.prev_command = Some(command);
                 (status, None)
             }
-            StepInstruction => {
-                debugger_info.prev_command = Some(command);
-                let status = single_step(debugger_info).unwrap();
-                let regs = get_regs(debugger_info.debug_info.target_pid());
-                let rip = regs.rip;
-                if !debugger_info.cont_flag {
-                    println!("rip = 0x{:016x}", rip);
-                }
-                status
-            }
-            Continue => {
-                debugger_info.cont_flag = true;
-                debugger_info.prev_command = Some(command);
-                continue_run(status, debugger_info).unwrap()
-            }
-            DumpRegisters => {
-                dump::register(debugger_info.debug_info.target_pid());
+            TemporaryBreakpoint(addr, ref condition) => {
+                set_breakpoint(
+                    debugger_info,
+                    addr,
+                    condition.clone(),
+                    BreakpointManager::set_temporary,
+                );
                 debugger_info.prev_command = Some(command);
                 (status, None)
             }
-            ExamineMemory(addr, len) => {
-                dump::memory(&debugger_info.debug_info, addr, len);
+            HardwareBreakpoint(addr, ref condition) => {
+                set_breakpoint(
+                    debugger_info,
+                    addr,
+                    condition.clone(),
+                    BreakpointManager::set_hardware,
+                );
                 debugger_info.prev_command = Some(command);
                 (status, None)
             }
-            ExamineMemoryMap => {
-                dump::memory_map(debugger_info.debug_info.target_pid());
-                debugger_info.prev_command = Some(command);
+            Ignore(num, count) => {
+                match debugger_info.breakpoint_manager.find_mut(num) {
+                    Some(bp) => {
+                        bp.ignore_count = count;
+                        println!("will ignore next {count} crossings of breakpoint {num}");
+                    }
+                    None => println!("no breakpoint number {num}"),
+                }
                 (status, None)
             }
-            List(sub_commands) => {
-                if sub_commands.is_empty() {
-                    dump::all_symbols(debugger_info);
+            Enable(ref nums) | Disable(ref nums) | Delete(ref nums) => {
+                let manager = &mut debugger_info.breakpoint_manager;
+                let nums = if nums.is_empty() {
+                    manager.numbers()
                 } else {
-                    let sub_command = &sub_commands[0];
-                    match sub_command.as_str() {
-                        "f" => dump::functions(debugger_info),
-                        "v" => dump::variables(debugger_info),
-                        "misc" => dump::misc_symbols(&debugger_info.debug_info),
-                        "w" => dump::watchpoints(debugger_info),
-                        _ => println!("invalid ls sub command"),
+                    nums.clone()
+                };
+                for num in nums {
+                    let found = match command {
+                        Enable(_) => manager.enable(num, true),
+                        Disable(_) => manager.enable(num, false),
+                        _ => manager.delete(num),
+                    };
+                    match found {
+                        Ok(true) => {}
+                        Ok(false) => println!("no breakpoint number {num}"),
+                        // 1つ失敗しても残りの番号は続ける
+                        Err(e) => println!("breakpoint number {num}: {e}"),
                     }
                 }
-                let command = Command::List(sub_commands);
-                debugger_info.prev_command = Some(command);
                 (status, None)
             }
-            Backtrace => {
-                dump::backtrace(&debugger_info.debug_info);
-                debugger_info.prev_command = Some(command);
+            Condition(num, condition) => {
+                match debugger_info.breakpoint_manager.find_mut(num) {
+                    Some(bp) => bp.condition = condition,
+                    None => println!("no breakpoint number {num}"),
+                }
                 (status, None)
             }
-            Watch(watch_command) => match watch_command {
-                WatchCommand::Memory(mem) => {
-                    debugger_info.set_watchpoint(WatchPoint::Memory(mem));
-                    (status, None)
+            StepInstruction(count) => {
+                debugger_info.prev_command = Some(command);
+                debugger_info.selected_frame = 0;
+                // continueの途中でウォッチポイントを調べているとき
+                if debugger_info.cont_flag {
+                    return single_step(debugger_info);
                 }
-                WatchCommand::Register(reg) => {
-                    debugger_info.set_watchpoint(WatchPoint::Register(reg));
-                    (status, None)
+                let pid = debugger_info.debug_info.target_pid();
+                let mut status = WaitStatus::Stopped(pid, Signal::SIGTRAP);
+                let cancel = Cancel::start();
+                for _ in 0..count {
+                    if cancel.is_cancelled() {
+                        break;
+                    }
+                    // REP命令は全ての繰り返しをまとめて1ステップとする
+                    let rep = step::step_over_rep(debugger_info).unwrap_or_else(|e| {
+                        println!("{e}");
+                        None
+                    });
+                    let (wait_status, stop) = match rep {
+                        Some(rep) => rep,
+                        None => step_once(debugger_info)?,
+                    };
+                    if let WaitStatus::Exited(pid, code) = wait_status {
+                        return Ok(exited(pid, code));
+                    }
+                    status = wait_status;
+                    // ウォッチポイントやブレークポイントで途中でも止まる
+                    if stop || step::check_breakpoint(debugger_info)? {
+                        break;
+                    }
                 }
-            },
-            Set(set_command) => match set_command {
-                SetCommand::Memory(mem) => {
-                    mem.write_value(debugger_info.debug_info.target_pid());
-                    (status, None)
+                if let WaitStatus::Stopped(..) = status {
+                    let rip = get_regs(pid).rip;
+                    match step::instruction_at(debugger_info, rip) {
+                        Ok(instruction) => dump::instruction(debugger_info, &instruction),
+                        Err(_) => println!("0x{:016x}: <unreadable>", rip),
+                    }
                 }
-                SetCommand::Register(reg) => {
-                    reg.write_value(debugger_info.debug_info.target_pid());
-                    (status, None)
+                (status, None)
+            }
+            StepIteration => {
+                debugger_info.prev_command = Some(command);
+                debugger_info.selected_frame = 0;
+                let status = single_step(debugger_info)?;
+                if let (WaitStatus::Stopped(..), None) = status {
+                    if !debugger_info.cont_flag {
+                        let rip = get_regs(debugger_info.debug_info.target_pid()).rip;
+                        println!("rip = 0x{:016x}", rip);
+                    }
                 }
-            },
-            Vmcall(vm_watchpoint) => {
-                let vmcall_number = crate::call_vmm::VmcallStruct::get_function(
-                    std::ffi::CString::new("tdb").unwrap(),
-                );
-                let mut vmcall_struct = crate::call_vmm::VmcallStruct::default();
+                status
+            }
+            StepLine | Next => {
+                let over = matches!(command, Next);
+                debugger_info.prev_command = Some(command);
+                debugger_info.selected_frame = 0;
+                match step::step_line(debugger_info, over) {
+                    Ok(Some(WaitStatus::Exited(pid, code))) => exited(pid, code),
+                    Ok(Some(status)) => (status, None),
+                    Ok(None) => (status, Some(Continue)),
+                    Err(e) => {
+                        println!("{e}");
+                        (status, None)
+                    }
+                }
+            }
+            Finish => {
+                debugger_info.prev_command = Some(command);
+                match step::finish(debugger_info) {
+                    Ok(WaitStatus::Exited(pid, code)) => exited(pid, code),
+                    Ok(status) => (status, None),
+                    Err(e) => {
+                        println!("{e}");
+                        (status, None)
+                    }
+                }
+            }
+            Until(addr) | Advance(addr) => {
+                let frame_only = matches!(command, Until(_));
+                debugger_info.prev_command = Some(command);
+                match step::run_until(debugger_info, addr, frame_only) {
+                    Ok(WaitStatus::Exited(pid, code)) => exited(pid, code),
+                    Ok(status) => (status, None),
+                    Err(e) => {
+                        println!("{e}");
+                        (status, None)
+                    }
+                }
+            }
+            Run(args) => {
+                if let Some(args) = args {
+                    debugger_info.args = args;
+                }
+                let status = match debugger_info.restart() {
+                    Ok(new) => new,
+                    Err(e) => {
+                        println!("cannot run the program: {e}");
+                        // 前のプロセスはkill済み
+                        if is_terminated(status) {
+                            return Ok((status, None));
+                        }
+                        let pid = debugger_info.threads.pid();
+                        return Ok((WaitStatus::Signaled(pid, Signal::SIGKILL, false), None));
+                    }
+                };
+                println!("started {}", debugger_info.debug_info.target_pid());
+                (status, Some(Continue))
+            }
+            Start(addr, args) => {
+                set_breakpoint(debugger_info, addr, None, BreakpointManager::set_temporary);
+                Self::exec_command(Run(args), debugger_info, status)?
+            }
+            Return(value) => {
+                if let Err(e) = step::force_return(debugger_info, value.as_ref()) {
+                    println!("{e}");
+                }
+                (status, None)
+            }
+            Call(ref name, ref args) => {
+                let result = infcall::call(debugger_info, name, args);
+                debugger_info.prev_command = Some(command);
+                match result {
+                    Ok(Some(WaitStatus::Exited(pid, code))) => exited(pid, code),
+                    Ok(Some(status)) => (status, None),
+                    Ok(None) => (status, None),
+                    Err(e) => {
+                        println!("{e}");
+                        (status, None)
+                    }
+                }
+            }
+            Jump(addr) => {
+                let pid = debugger_info.debug_info.target_pid();
+                if let Err(e) = mem::check_mapping(pid, addr, 1, mem::Access::Execute) {
+                    println!("{e}");
+                    return Ok((status, None));
+                }
+                let debug_info = &debugger_info.debug_info;
+                let current = debug_info.find_function_in(get_regs(pid).rip);
+                let target = debug_info.find_function_in(addr);
+                // 別の関数に飛ぶとスタックフレームが合わない
+                if current.map(|f| f.name()) != target.map(|f| f.name()) {
+                    println!(
+                        "warning: 0x{addr:016x} is outside of the current function {}",
+                        current.map_or("??", |f| f.name())
+                    );
+                }
+                let rip = register::Register::from(register::RegisterType::Rip);
+                if let Err(e) = rip.set_current_value(pid, addr) {
+                    println!("{e}");
+                    return Ok((status, None));
+                }
+                debugger_info.selected_frame = 0;
+                println!("continuing at 0x{addr:016x}");
+                // 飛び先のブレークポイントは越えずにそこで止まる
+                if debugger_info.breakpoint_manager.get(addr).is_some() {
+                    match debugger_info.hit_breakpoint(addr) {
+                        Ok(true) => {
+                            step::print_frame(debugger_info);
+                            return Ok((status, None));
+                        }
+                        Ok(false) => {}
+                        // 飛び先で止まったまま続けない
+                        Err(e) => {
+                            println!("{e}");
+                            return Ok((status, None));
+                        }
+                    }
+                }
+                (status, Some(Continue))
+            }
+            StepInChoose => {
+                debugger_info.prev_command = Some(command);
+                debugger_info.selected_frame = 0;
+                match step::step_in_choose(debugger_info) {
+                    Ok(WaitStatus::Exited(pid, code)) => {
+                        (WaitStatus::Exited(pid, code), Some(Exit(code)))
+                    }
+                    Ok(status) => (status, None),
+                    Err(e) => {
+                        println!("{e}");
+                        (status, None)
+                    }
+                }
+            }
+            Continue => {
+                debugger_info.selected_frame = 0;
+                debugger_info.cont_flag = true;
+                debugger_info.prev_command = Some(command);
+                // 記録中は全ての命令を1つずつ実行する
+                if debugger_info.record.is_some() {
+                    return continue_recording(debugger_info);
+                }
+                // ブレークポイントで止まっているときは先にそこを越える
+                let mut stepped = match status {
+                    WaitStatus::Stopped(..) => debugger_info.breakpoint_manager.step_over()?,
+                    _ => None,
+                };
+                if let Some(WaitStatus::Stopped(_, Signal::SIGSEGV)) = stepped {
+                    stepped = match step::check_page_watchpoint(debugger_info)? {
+                        Some((status, true)) => {
+                            debugger_info.cont_flag = false;
+                            Some(status)
+                        }
+                        // 見張っている範囲の外への書き込みだったので続ける
+                        Some((WaitStatus::Stopped(..), false)) => None,
+                        Some((status, false)) => Some(status),
+                        None => stepped,
+                    };
+                }
+                match stepped {
+                    Some(WaitStatus::Exited(pid, code)) => exited(pid, code),
+                    Some(status) => (status, None),
+                    None => continue_run(status, debugger_info)?,
+                }
+            }
+            DumpRegisters => {
+                dump::register(&debugger_info.frame_regs());
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            ExamineMemory(addr, len) => {
+                dump::memory(debugger_info, addr, len);
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            Examine(ref spec, ref expr) => {
+                let examined = examine::address(debugger_info, expr)
+                    .and_then(|addr| examine::examine(debugger_info, spec, addr));
+                if let Err(e) = examined {
+                    println!("{e}");
+                }
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            Fill(ref addr, len, ref pattern) => {
+                if let Err(e) = fill(debugger_info, addr, len, pattern) {
+                    println!("{e}");
+                }
+                (status, None)
+            }
+            PatternCreate(len) => {
+                println!("{}", String::from_utf8_lossy(&pattern::cyclic(len)));
+                (status, None)
+            }
+            PatternOffset(ref expr) => {
+                if let Err(e) = pattern_offset(debugger_info, expr) {
+                    println!("{e}");
+                }
+                (status, None)
+            }
+            Find(ref spec) => {
+                if let Err(e) = find::find(debugger_info, spec) {
+                    println!("{e}");
+                }
+                (status, None)
+            }
+            Skip(Some(ref name)) => {
+                if !debugger_info.skip.contains(name) {
+                    debugger_info.skip.push(name.clone());
+                }
+                println!("function {name} will be skipped when stepping");
+                (status, None)
+            }
+            Skip(None) => {
+                let rip = get_regs(debugger_info.debug_info.target_pid()).rip;
+                match debugger_info.debug_info.find_function_in(rip) {
+                    Some(f) => {
+                        let name = demangle(f.name());
+                        println!("function {name} will be skipped when stepping");
+                        if !debugger_info.skip.contains(&name) {
+                            debugger_info.skip.push(name);
+                        }
+                    }
+                    None => println!("no function at the current location"),
+                }
+                (status, None)
+            }
+            SkipDelete => {
+                debugger_info.skip.clear();
+                println!("deleted all skips");
+                (status, None)
+            }
+            Alias(None) => {
+                if debugger_info.aliases.is_empty() {
+                    println!("no aliases");
+                }
+                for (name, command) in &debugger_info.aliases {
+                    println!("{name} = {command}");
+                }
+                (status, None)
+            }
+            Alias(Some((ref name, ref command))) => {
+                println!("alias {name} = {command}");
+                debugger_info.aliases.insert(name.clone(), command.clone());
+                (status, None)
+            }
+            Unalias(ref name) => {
+                match debugger_info.aliases.remove(name) {
+                    Some(_) => println!("removed alias {name}"),
+                    None => println!("no alias {name}"),
+                }
+                (status, None)
+            }
+            ExamineMemoryMap => {
+                dump::memory_map(debugger_info.debug_info.target_pid());
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            List(sub_commands) => {
+                if sub_commands.is_empty() {
+                    dump::all_symbols(debugger_info);
+                } else {
+                    let sub_command = &sub_commands[0];
+                    match sub_command.as_str() {
+                        "f" => dump::functions(debugger_info, None),
+                        "v" => dump::variables(debugger_info),
+                        "misc" => dump::misc_symbols(&debugger_info.debug_info),
+                        "w" => dump::watchpoints(debugger_info),
+                        _ => println!("invalid ls sub command"),
+                    }
+                }
+                let command = Command::List(sub_commands);
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            Info(ref info_command) => {
+                match info_command {
+                    InfoCommand::Functions(pattern) => {
+                        dump::functions(debugger_info, pattern.as_deref())
+                    }
+                    InfoCommand::ModulesTree(root) => {
+                        dump::modules_tree(debugger_info, root.as_deref())
+                    }
+                    InfoCommand::Locals => dump::locals(debugger_info),
+                    InfoCommand::Args => dump::args(debugger_info),
+                    InfoCommand::Display => dump::display_list(debugger_info),
+                    InfoCommand::Breakpoints => dump::breakpoints(debugger_info),
+                    InfoCommand::Frame => dump::frame_info(debugger_info),
+                    InfoCommand::FrameSizes(large) => dump::frame_sizes(debugger_info, *large),
+                    InfoCommand::HitCounts => dump::hit_counts(debugger_info),
+                    InfoCommand::Buffers => dump::buffers(debugger_info),
+                    InfoCommand::Threads => dump::threads(debugger_info),
+                    InfoCommand::Checkpoints => dump::checkpoints(debugger_info),
+                    InfoCommand::Record => dump::record(debugger_info),
+                    InfoCommand::Skip => dump::skips(debugger_info),
+                    InfoCommand::Signals(Some(signal)) => dump::signals(debugger_info, &[*signal]),
+                    InfoCommand::Signals(None) => {
+                        let signals: Vec<Signal> = Signal::iterator()
+                            .filter(|signal| *signal != Signal::SIGTRAP)
+                            .collect();
+                        dump::signals(debugger_info, &signals)
+                    }
+                    InfoCommand::SharedLibrary(deps) => {
+                        dump::shared_libraries(debugger_info, *deps)
+                    }
+                }
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            Print(ref expr) => {
+                let scope = debugger_info.scope();
+                match expr::eval(expr, &scope) {
+                    Ok(value) => println!("{}", value.format(&scope)),
+                    Err(e) => println!("{e}"),
+                }
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            PrintAssign(ref lhs, ref rhs) => {
+                let scope = debugger_info.scope();
+                match expr::assign(lhs, rhs, &scope).and_then(|_| expr::eval(lhs, &scope)) {
+                    Ok(value) => println!("{}", value.format(&scope)),
+                    Err(e) => println!("{e}"),
+                }
+                (status, None)
+            }
+            PrintAs(ref name, ref expr) => {
+                print_as(debugger_info, name, expr);
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            BisectWatch(BisectCommand::Record(text, expr)) => {
+                let (addr, len, ty) = match watch_target(debugger_info, &text, &expr) {
+                    Ok(target) => target,
+                    Err(e) => {
+                        println!("{e}");
+                        return Ok((status, None));
+                    }
+                };
+                println!("recording writes to {text}, Ctrl-C to stop");
+                let (bisect, status) = Bisect::record(debugger_info, text, addr, len, ty)?;
+                match status {
+                    WaitStatus::Exited(pid, code) => {
+                        println!("process {pid} exited with code {code}")
+                    }
+                    WaitStatus::Signaled(pid, signal, _) => {
+                        println!("process {pid} killed by {signal}")
+                    }
+                    _ => {}
+                }
+                bisect.summary(debugger_info.debug_info.target_pid());
+                debugger_info.bisect = Some(bisect);
+                debugger_info.selected_frame = 0;
+                (status, None)
+            }
+            BisectWatch(BisectCommand::Replay(n)) => {
+                let addrs = match &debugger_info.bisect {
+                    Some(bisect) => match bisect.writers().get(n) {
+                        Some((_, writes)) => {
+                            let mut addrs: Vec<u64> = writes.iter().map(|w| w.addr).collect();
+                            addrs.dedup();
+                            addrs
+                        }
+                        None => {
+                            println!("no writer #{n}");
+                            return Ok((status, None));
+                        }
+                    },
+                    None => {
+                        println!("nothing recorded by bisect-watch");
+                        return Ok((status, None));
+                    }
+                };
+                let status = debugger_info.restart()?;
+                println!("started {}", debugger_info.debug_info.target_pid());
+                for addr in addrs {
+                    set_breakpoint(debugger_info, addr, None, BreakpointManager::set);
+                }
+                (status, Some(Continue))
+            }
+            BisectWatch(BisectCommand::Summary) => {
+                match &debugger_info.bisect {
+                    Some(bisect) => bisect.summary(debugger_info.debug_info.target_pid()),
+                    None => println!("nothing recorded by bisect-watch"),
+                }
+                (status, None)
+            }
+            Dump(DumpCommand::Registers(path)) => {
+                let regs = get_regs(debugger_info.debug_info.target_pid());
+                match dump::save_registers(&regs, Path::new(&path)) {
+                    Ok(()) => println!("saved registers to {path}"),
+                    Err(e) => println!("{e}"),
+                }
+                (status, None)
+            }
+            Dump(DumpCommand::Memory(path, start, end)) => {
+                if let Err(e) = dump_memory(debugger_info, &path, &start, end.as_ref()) {
+                    println!("{e}");
+                }
+                (status, None)
+            }
+            Restore(DumpCommand::Memory(path, start, end)) => {
+                if let Err(e) = restore_memory(debugger_info, &path, &start, end.as_ref()) {
+                    println!("{e}");
+                }
+                (status, None)
+            }
+            DumpDiff(a, b, base) => {
+                let base = match base.map(|base| expr::eval(&base, &debugger_info.scope())) {
+                    Some(Ok(base)) => Some(base.as_u64()),
+                    Some(Err(e)) => {
+                        println!("{e}");
+                        return Ok((status, None));
+                    }
+                    None => None,
+                };
+                if let Err(e) = dump::diff_files(debugger_info, Path::new(&a), Path::new(&b), base)
+                {
+                    println!("{e}");
+                }
+                (status, None)
+            }
+            Restore(DumpCommand::Registers(path)) => {
+                let pid = debugger_info.debug_info.target_pid();
+                let mut regs = get_regs(pid);
+                let restored = dump::load_registers(&mut regs, Path::new(&path))
+                    .and_then(|count| Ok(ptrace::setregs(pid, regs).map(|()| count)?));
+                match restored {
+                    Ok(count) => println!("restored {count} registers from {path}"),
+                    Err(e) => println!("{e}"),
+                }
+                debugger_info.selected_frame = 0;
+                (status, None)
+            }
+            Struct(struct_command) => {
+                let overlays = &mut debugger_info.overlays;
+                match struct_command {
+                    StructCommand::Define(def) => match overlays.define(&def) {
+                        Ok(name) => println!("defined struct {name}"),
+                        Err(e) => println!("{e}"),
+                    },
+                    StructCommand::Load(path) => match overlays.load(Path::new(&path)) {
+                        Ok(names) => println!("defined struct {}", names.join(", ")),
+                        Err(e) => println!("{e}"),
+                    },
+                    StructCommand::List => dump::structs(debugger_info),
+                }
+                (status, None)
+            }
+            Display(display) => {
+                match display {
+                    Some((text, expr)) => {
+                        let d = debugger_info.add_display(text, expr).clone();
+                        dump::display(debugger_info, &d);
+                    }
+                    None => dump::displays(debugger_info),
+                }
+                (status, None)
+            }
+            Undisplay(nums) => {
+                if nums.is_empty() {
+                    debugger_info.display_list.clear();
+                }
+                for num in nums {
+                    let len = debugger_info.display_list.len();
+                    debugger_info.display_list.retain(|d| d.num != num);
+                    if debugger_info.display_list.len() == len {
+                        println!("no display number {num}");
+                    }
+                }
+                (status, None)
+            }
+            Backtrace => {
+                dump::backtrace(debugger_info);
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            Frame(level) => {
+                let level = level.unwrap_or(debugger_info.selected_frame);
+                select_frame(debugger_info, level);
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            Thread(None) => {
+                let tid = debugger_info.threads.current();
+                if let Some(thread) = debugger_info.threads.get(tid) {
+                    println!("current thread is {} ({tid})", thread.num);
+                }
+                (status, None)
+            }
+            Thread(Some(num)) => {
+                match debugger_info.threads.find(num).map(|thread| thread.tid) {
+                    Some(tid) => {
+                        // 前のスレッドが受け取ったシグナルはそのスレッドの再開時に渡す
+                        if let WaitStatus::Stopped(old, signal) = status {
+                            if let Some(thread) = debugger_info.threads.get_mut(old) {
+                                if signal != Signal::SIGTRAP {
+                                    thread.pending = Some(signal);
+                                }
+                                thread.step_over = true;
+                            }
+                        }
+                        debugger_info.select_thread(tid);
+                        println!("[switching to thread {num} ({tid})]");
+                        select_frame(debugger_info, 0);
+                        (WaitStatus::Stopped(tid, Signal::SIGTRAP), None)
+                    }
+                    None => {
+                        println!("no thread {num}");
+                        (status, None)
+                    }
+                }
+            }
+            ThreadApply(ref nums, ref line) => {
+                let current = debugger_info.threads.current();
+                let frame = debugger_info.selected_frame;
+                let mut targets = Vec::new();
+                match nums {
+                    None => targets.extend(debugger_info.threads.iter().map(|t| (t.num, t.tid))),
+                    Some(nums) => {
+                        for num in nums {
+                            match debugger_info.threads.find(*num) {
+                                Some(thread) => targets.push((thread.num, thread.tid)),
+                                None => println!("no thread {num}"),
+                            }
+                        }
+                    }
+                }
+                // 他のスレッドの状態を変えるコマンドは実行しない
+                let applicable = Command::parse(debugger_info, line).is_ok_and(|applied| {
+                    !applied.resumes()
+                        && !matches!(
+                            applied,
+                            Thread(_) | ThreadApply(..) | Kill | Detach | Quit | Exit(_)
+                        )
+                });
+                if !applicable {
+                    println!("`{line}` cannot be applied to threads");
+                    targets.clear();
+                }
+                for (num, tid) in targets {
+                    println!("\nthread {num} ({tid}):");
+                    debugger_info.select_thread(tid);
+                    let applied = match Command::parse(debugger_info, line) {
+                        Ok(applied) => applied,
+                        Err(e) => {
+                            println!("{e}");
+                            break;
+                        }
+                    };
+                    // 失敗したスレッドは報告して次のスレッドに進む
+                    if let Err(e) = Self::exec_command(
+                        applied,
+                        debugger_info,
+                        WaitStatus::Stopped(tid, Signal::SIGTRAP),
+                    ) {
+                        println!("{e}");
+                    }
+                }
+                // 選んでいたスレッドとフレームに戻す
+                debugger_info.select_thread(current);
+                debugger_info.selected_frame = frame;
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            Up(n) => {
+                let level = debugger_info.selected_frame + n;
+                select_frame(debugger_info, level);
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            Down(n) => {
+                if n > debugger_info.selected_frame {
+                    println!("bottom (innermost) frame selected; you cannot go down.");
+                } else {
+                    let level = debugger_info.selected_frame - n;
+                    select_frame(debugger_info, level);
+                }
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            Watch(watch_command) => match watch_command {
+                WatchCommand::Expression(kind, text, expr) => {
+                    set_watchpoint(debugger_info, kind, text, &expr);
+                    (status, None)
+                }
+                WatchCommand::Register(reg, value) => {
+                    println!("set software watchpoint ${}", reg.name());
+                    debugger_info.set_watchpoint(WatchPoint::Register(reg, value));
+                    (status, None)
+                }
+            },
+            Set(set_command) => match set_command {
+                SetCommand::Memory(mem) => {
+                    mem.write_value(debugger_info.debug_info.target_pid());
+                    (status, None)
+                }
+                SetCommand::Register(reg, value) => {
+                    if let Err(e) =
+                        reg.set_current_value(debugger_info.debug_info.target_pid(), value)
+                    {
+                        println!("{e}");
+                    }
+                    (status, None)
+                }
+                SetCommand::Variable(lhs, rhs) => {
+                    if let Err(e) = expr::assign(&lhs, &rhs, &debugger_info.scope()) {
+                        println!("{e}");
+                    }
+                    (status, None)
+                }
+                SetCommand::Typed(size, addr, value) => {
+                    if let Err(e) = write_typed(debugger_info, size, &addr, &value) {
+                        println!("{e}");
+                    }
+                    (status, None)
+                }
+                SetCommand::FollowForkMode(mode) => {
+                    debugger_info.follow_fork_mode = mode;
+                    println!("follow-fork-mode is {mode}");
+                    (status, None)
+                }
+                SetCommand::SchedulerLocking(locking) => {
+                    debugger_info.scheduler_locking = locking;
+                    println!("scheduler-locking is {locking}");
+                    (status, None)
+                }
+                SetCommand::SymbolizerCmd(None) => {
+                    match symbolize::external_command() {
+                        Some(command) => println!("symbolizer-cmd is `{command}`"),
+                        None => println!("symbolizer-cmd is off"),
+                    }
+                    (status, None)
+                }
+                SetCommand::SymbolizerCmd(Some(command)) => {
+                    let command = (command != "off").then_some(command);
+                    match symbolize::set_external(command.as_deref()) {
+                        Ok(()) if command.is_some() => println!("started symbolizer-cmd"),
+                        Ok(()) => println!("symbolizer-cmd is off"),
+                        Err(e) => println!("cannot start symbolizer-cmd: {e}"),
+                    }
+                    (status, None)
+                }
+                SetCommand::OutputRadix(None) => {
+                    println!("output-radix is {}", variable::output_radix());
+                    (status, None)
+                }
+                SetCommand::OutputRadix(Some(radix)) => {
+                    match variable::set_output_radix(radix) {
+                        Ok(()) => println!("output-radix is {radix}"),
+                        Err(e) => println!("{e}"),
+                    }
+                    (status, None)
+                }
+                SetCommand::SubstitutePath(None) => {
+                    let substitutions = debug_info::substitutions();
+                    if substitutions.is_empty() {
+                        println!("no substitute-path rules");
+                    }
+                    for (from, to) in substitutions {
+                        println!("{from} -> {to}");
+                    }
+                    (status, None)
+                }
+                SetCommand::SubstitutePath(Some((from, to))) => {
+                    debug_info::set_substitution(&from, &to);
+                    println!("substitute-path {from} -> {to}");
+                    (status, None)
+                }
+                SetCommand::UnsetSubstitutePath => {
+                    debug_info::clear_substitutions();
+                    println!("substitute-path rules are removed");
+                    (status, None)
+                }
+                SetCommand::StopHook(None) => {
+                    match debugger_info.stop_hook.is_empty() {
+                        true => println!("no stop-hook"),
+                        false => println!("stop-hook: {}", debugger_info.stop_hook.join("; ")),
+                    }
+                    (status, None)
+                }
+                SetCommand::StopHook(Some(commands)) => {
+                    // 止まるたびに再開すると止まらなくなる
+                    let resuming = commands.iter().find(|line| {
+                        Command::parse(debugger_info, line).is_ok_and(|command| command.resumes())
+                    });
+                    match resuming {
+                        Some(line) => println!("stop-hook cannot resume the target: {line}"),
+                        None if commands.is_empty() => println!("stop-hook is removed"),
+                        None => println!("stop-hook: {}", commands.join("; ")),
+                    }
+                    if resuming.is_none() {
+                        debugger_info.stop_hook = commands;
+                    }
+                    (status, None)
+                }
+                SetCommand::Limit(kind, limit) => {
+                    let policy = Policy {
+                        limit,
+                        ..debugger_info.policy(kind)
+                    };
+                    debugger_info.set_policy(kind, policy);
+                    println!("limit of {} is {limit}", kind.name());
+                    (status, None)
+                }
+                SetCommand::Eviction(kind, eviction) => {
+                    let policy = Policy {
+                        eviction,
+                        ..debugger_info.policy(kind)
+                    };
+                    debugger_info.set_policy(kind, policy);
+                    println!("{} evicts the {eviction} entries", kind.name());
+                    (status, None)
+                }
+            },
+            Vmcall(vm_watchpoint) => {
+                let vmcall_number = crate::call_vmm::VmcallStruct::get_function(
+                    std::ffi::CString::new("tdb").unwrap(),
+                );
+                let mut vmcall_struct = crate::call_vmm::VmcallStruct::default();
                 vmcall_struct.set_vmcall_number(vmcall_number);
 
                 let phys = crate::debugger::virt2phys(
@@ -292,8 +2094,182 @@ impl Command {
                 debugger_info.vm_watchpoint_manager.set(vm_watchpoint);
                 (status, None)
             }
+            Mark(name, ref ranges) => {
+                match name {
+                    Some(name) => set_mark(debugger_info, name, ranges),
+                    None => dump::marks(debugger_info),
+                }
+                (status, None)
+            }
+            DiffMarks(ref a, ref b) => {
+                dump::diff_marks(debugger_info, a, b);
+                debugger_info.prev_command = Some(command);
+                (status, None)
+            }
+            HistoryStops => {
+                dump::stops(debugger_info);
+                (status, None)
+            }
+            GotoStop(n) => {
+                dump::stop(debugger_info, n);
+                (status, None)
+            }
+            Label(label_command) => {
+                label(debugger_info, label_command);
+                (status, None)
+            }
+            Doctor => {
+                doctor::diagnose(debugger_info);
+                (status, None)
+            }
+            Catch(kind, condition) => {
+                match debugger_info
+                    .breakpoint_manager
+                    .set_catchpoint(kind.clone(), condition)
+                {
+                    Ok(cp) => println!("set catchpoint {} ({})", cp.num, cp.kind),
+                    Err(e) => println!("cannot catch {kind}: {e}"),
+                }
+                (status, None)
+            }
+            Handle(signal, actions) => {
+                let mut handling = debugger_info.handling(signal);
+                for action in actions {
+                    handling.apply(action);
+                }
+                debugger_info.signals.insert(signal, handling);
+                dump::signals(debugger_info, &[signal]);
+                (status, None)
+            }
+            Dprintf(addr, condition, dprintf) => {
+                set_breakpoint(debugger_info, addr, condition, |manager, addr| {
+                    let bp = manager.set(addr)?;
+                    bp.dprintf = Some(dprintf);
+                    Ok(bp)
+                });
+                (status, None)
+            }
+            Commands(num, commands) => {
+                let num = num.or(debugger_info.breakpoint_manager.last_breakpoint());
+                match num.and_then(|num| debugger_info.breakpoint_manager.find_mut(num)) {
+                    Some(bp) => bp.commands = commands,
+                    None => println!("no breakpoint to attach the commands"),
+                }
+                (status, None)
+            }
+            Detach => {
+                let pid = debugger_info.threads.pid();
+                fini(debugger_info);
+                if let Err(e) = debugger_info.breakpoint_manager.detach() {
+                    println!("{e}");
+                    return Ok((status, None));
+                }
+                debugger_info.detach_others();
+                println!("detached from {pid}");
+                exit(0);
+            }
+            Expect(termination) => {
+                debugger_info.expected = Some(termination);
+                println!("expecting the target to terminate with {termination}");
+                (status, None)
+            }
+            Checkpoint => {
+                if !debugger_info.threads.others().is_empty() {
+                    println!("warning: the checkpoint has only the current thread");
+                }
+                match debugger_info.checkpoint() {
+                    Ok(checkpoint) => {
+                        println!("checkpoint {}: process {}", checkpoint.num, checkpoint.pid)
+                    }
+                    Err(e) => println!("cannot make a checkpoint: {e}"),
+                }
+                (status, None)
+            }
+            RestartCheckpoint(num) => {
+                match debugger_info.restart_checkpoint(num, !is_terminated(status)) {
+                    Ok(status) => {
+                        println!(
+                            "switching to process {} from checkpoint {num}",
+                            debugger_info.threads.pid()
+                        );
+                        step::print_frame(debugger_info);
+                        (status, None)
+                    }
+                    Err(e) => {
+                        println!("{e}");
+                        (status, None)
+                    }
+                }
+            }
+            DeleteCheckpoint(num) => {
+                if !debugger_info.checkpoints.delete(num) {
+                    println!("no checkpoint {num}");
+                }
+                (status, None)
+            }
+            Record => {
+                let pid = debugger_info.debug_info.target_pid();
+                match debugger_info.record {
+                    Some(_) => println!("already recording"),
+                    None => {
+                        if !debugger_info.threads.others().is_empty() {
+                            println!("warning: only the current thread is recorded");
+                        }
+                        debugger_info.record =
+                            Some(record::Record::new(pid, debugger_info.record_policy));
+                        println!("recording thread {pid}");
+                    }
+                }
+                (status, None)
+            }
+            RecordStop => {
+                match debugger_info.record.take() {
+                    Some(record) => println!(
+                        "recording stopped, {} instructions discarded",
+                        record.steps.len()
+                    ),
+                    None => println!("not recording"),
+                }
+                (status, None)
+            }
+            ReverseStepInstruction(count) => {
+                debugger_info.prev_command = Some(command);
+                reverse(debugger_info, status, Some(count))
+            }
+            ReverseContinue => {
+                debugger_info.prev_command = Some(command);
+                reverse(debugger_info, status, None)
+            }
+            Kill => {
+                let pid = debugger_info.threads.pid();
+                let status = debugger_info.kill();
+                println!("killed {pid}");
+                (status, None)
+            }
+            Quit => {
+                if !is_terminated(status) {
+                    debugger_info.kill();
+                }
+                Self::exec_command(Exit(0), debugger_info, status)?
+            }
             Exit(code) => {
+                let manager = &debugger_info.breakpoint_manager;
+                if !manager.breakpoints().is_empty() || !manager.watchpoints().is_empty() {
+                    dump::hit_counts(debugger_info);
+                }
                 fini(debugger_info);
+                // 期待した終わり方なら0、違えば1で終わる
+                if let Some(expected) = debugger_info.expected {
+                    let actual = Termination::from_status(status);
+                    if actual == Some(expected) {
+                        exit(0);
+                    }
+                    match actual {
+                        Some(actual) => println!("expected {expected}, but got {actual}"),
+                        None => println!("expected {expected}, but the target did not terminate"),
+                    }
+                    exit(1);
+                }
                 exit(code);
             }
         };
@@ -301,6 +2277,39 @@ impl Command {
     }
 }
 
+/// `exit <code>` or `signal <name>` of `expect`
+fn parse_termination(args: &[&str]) -> Result<Termination, Box<dyn std::error::Error>> {
+    let invalid = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "usage: expect exit <code> | expect signal <name>",
+        )
+    };
+    match args {
+        ["exit", code] => Ok(Termination::Exit(code.parse().map_err(|_| invalid())?)),
+        ["signal", name] => Ok(Termination::Signal(parse_signal(name)?)),
+        _ => Err(Box::new(invalid())),
+    }
+}
+
+/// read commands until `end` for `commands`
+fn read_command_list(
+    debugger_info: &mut DebuggerInfo,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    println!("type commands one per line, end with a line saying just \"end\"");
+    let mut commands = Vec::new();
+    // EOFでも打ち切る
+    while let Some(line) = debugger_info.read_line(">> ")? {
+        if line == "end" {
+            break;
+        }
+        if !line.is_empty() {
+            commands.push(line);
+        }
+    }
+    Ok(commands)
+}
+
 fn switch_by_wait_status(
     status: WaitStatus,
     debugger_info: &mut DebuggerInfo,
@@ -308,26 +2317,29 @@ fn switch_by_wait_status(
     let status = match status {
         WaitStatus::Continued(pid) => (continued(pid), None),
         WaitStatus::Exited(pid, exit_code) => exited(pid, exit_code),
-        WaitStatus::PtraceEvent(pid, signal, event) => (ptrace_event(pid, signal, event), None),
-        WaitStatus::PtraceSyscall(pid) => {
-            (ptrace_syscall(pid, &mut debugger_info.syscall_stack), None)
-        }
+        WaitStatus::PtraceEvent(pid, _, _) => cont_and_wait(pid, None, debugger_info)?,
+        WaitStatus::PtraceSyscall(pid) => cont_and_wait(pid, None, debugger_info)?,
         WaitStatus::Signaled(pid, signal, dump) => (signaled(pid, signal, dump), None),
         WaitStatus::StillAlive => (still_alive(debugger_info.debug_info.target_pid()), None),
-        WaitStatus::Stopped(pid, signal) => stopped(pid, signal, debugger_info),
+        WaitStatus::Stopped(pid, signal) => stopped(pid, signal, debugger_info)?,
     };
     Ok(status)
 }
 
 fn continued(pid: Pid) -> WaitStatus {
     println!("continued: PID: {pid}");
-    if let Err(e) = ptrace::cont(pid, None) {
+    if let Err(e) = mem::cont(pid, None) {
         panic!("ptrace::cont failed: errno = {:?}", e);
     }
 
     waitpid(pid, None).unwrap()
 }
 
+/// true if the target is gone
+fn is_terminated(status: WaitStatus) -> bool {
+    matches!(status, WaitStatus::Exited(..) | WaitStatus::Signaled(..))
+}
+
 fn exited(pid: Pid, exit_code: i32) -> (WaitStatus, Option<Command>) {
     (
         WaitStatus::Exited(pid, exit_code),
@@ -335,55 +2347,215 @@ fn exited(pid: Pid, exit_code: i32) -> (WaitStatus, Option<Command>) {
     )
 }
 
-fn ptrace_event(pid: Pid, signal: Signal, event: i32) -> WaitStatus {
-    println!("evented: PID: {pid}, Signal: {:?}, Event: {event}", signal);
-
-    let regs = ptrace::getregs(pid).unwrap();
-    println!("{:x?}", regs);
+/// stop at the catchpoints of the ptrace event, otherwise continue again
+fn ptrace_event(
+    pid: Pid,
+    event: i32,
+    debugger_info: &mut DebuggerInfo,
+) -> (WaitStatus, Option<Command>) {
+    // 次のcontinueではシグナルを渡さないようにSIGTRAPとして扱う
+    let mut status = WaitStatus::Stopped(pid, Signal::SIGTRAP);
+    let message = ptrace::getevent(pid).unwrap_or(0);
+    let (kind, description) = match event {
+        libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK => {
+            let child = Pid::from_raw(message as i32);
+            // 子プロセスは最初にSIGSTOPで止まる
+            if !debugger_info.threads.take_early(child) {
+                let _ = waitpid(child, Some(WaitPidFlag::__WALL));
+            }
+            let shared = event == libc::PTRACE_EVENT_VFORK;
+            let description = format!("process {pid} forked child {child}");
+            match debugger_info.follow_fork_mode {
+                FollowForkMode::Parent => {
+                    if let Err(e) = debugger_info.breakpoint_manager.detach_child(child, shared) {
+                        println!("cannot detach child process {child}: {e}");
+                    }
+                }
+                FollowForkMode::Child => {
+                    match debugger_info.breakpoint_manager.follow_child(child, shared) {
+                        Ok(()) => {
+                            println!("following child process {child}");
+                            // 子プロセスにはforkしたスレッドしかない
+                            debugger_info.detach_others();
+                            debugger_info.threads.reset(child);
+                            debugger_info.select_thread(child);
+                            signal::init_child_pid(child);
+                            debugger_info.syscall_stack.clear();
+                            status = WaitStatus::Stopped(child, Signal::SIGTRAP);
+                        }
+                        Err(e) => println!("cannot follow child process {child}: {e}"),
+                    }
+                }
+            }
+            (CatchKind::Fork, description)
+        }
+        libc::PTRACE_EVENT_EXEC => {
+            let path = std::fs::read_link(format!("/proc/{pid}/exe"))
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|_| String::from("??"));
+            if let Err(e) = debugger_info.follow_exec(&path) {
+                println!("cannot read {path}: {e}");
+            }
+            (
+                CatchKind::Exec,
+                format!("process {pid} is executing new program: {path}"),
+            )
+        }
+        libc::PTRACE_EVENT_CLONE => {
+            // 新しいスレッドは次のcontinueで他のスレッドと一緒に再開する
+            if let Err(e) = debugger_info.new_thread(pid) {
+                println!("cannot trace the new thread: {e}");
+            }
+            return (status, Some(Command::Continue));
+        }
+        libc::PTRACE_EVENT_EXIT => {
+            // メッセージはwaitpidのステータス
+            let wstatus = message as i32;
+            let description = if libc::WIFSIGNALED(wstatus) {
+                match Signal::try_from(libc::WTERMSIG(wstatus)) {
+                    Ok(signal) => format!("process {pid} is being killed by {signal}"),
+                    Err(_) => format!("process {pid} is being killed by a signal"),
+                }
+            } else {
+                format!(
+                    "process {pid} is exiting with code {}",
+                    libc::WEXITSTATUS(wstatus)
+                )
+            };
+            (CatchKind::Exit, description)
+        }
+        _ => return (status, Some(Command::Continue)),
+    };
+    let nums = debugger_info.breakpoint_manager.caught(&kind);
+    // execの後は新しいプログラムの最初の命令で止める
+    if nums.is_empty() && kind == CatchKind::Exec {
+        println!("{description}");
+        debugger_info.cont_flag = false;
+        return (status, None);
+    }
+    if nums.is_empty() {
+        return (status, Some(Command::Continue));
+    }
+    for num in nums {
+        println!("catchpoint {num} ({kind}), {description}");
+    }
+    debugger_info.cont_flag = false;
+    (status, None)
+}
 
-    if let Err(e) = ptrace::cont(pid, signal) {
-        panic!("ptrace::cont failed: errno = {:?}", e);
+/// report the catchpoints of the signal, the target stops at every signal
+fn caught_signal(debugger_info: &mut DebuggerInfo, signal: Signal) -> bool {
+    let nums = debugger_info
+        .breakpoint_manager
+        .caught(&CatchKind::Signal(signal));
+    for num in &nums {
+        println!("catchpoint {num} (signal {signal})");
     }
+    !nums.is_empty()
+}
 
-    waitpid(pid, None).unwrap()
+/// `SIGUSR1`, `USR1` or a signal number
+fn parse_signal(name: &str) -> Result<Signal, Box<dyn std::error::Error>> {
+    let signal = match name.parse::<i32>() {
+        Ok(number) => Signal::try_from(number)?,
+        Err(_) if name.starts_with("SIG") => name.parse::<Signal>()?,
+        Err(_) => format!("SIG{name}").parse::<Signal>()?,
+    };
+    // SIGTRAPはデバッガが使う
+    if signal == Signal::SIGTRAP {
+        return Err(Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            "SIGTRAP is used by the debugger",
+        )));
+    }
+    Ok(signal)
 }
 
-fn ptrace_syscall(pid: Pid, syscall_stack: &mut SyscallStack) -> WaitStatus {
-    let syscall_info = SyscallInfo::from_regs(&get_regs(pid));
+/// `openat` or a syscall number
+fn parse_syscall(name: &str) -> Result<Sysno, Box<dyn std::error::Error>> {
+    let sysno = match name.parse::<usize>() {
+        Ok(number) => Sysno::new(number),
+        Err(_) => name.parse::<Sysno>().ok(),
+    };
+    sysno.ok_or_else(|| {
+        Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            format!("unknown syscall `{name}`"),
+        )) as Box<dyn std::error::Error>
+    })
+}
 
-    if let Some(top) = syscall_stack.top() {
-        // syscallの入口だった場合
-        if top.number() != syscall_info.number() {
+/// stop at the entry or exit of the caught syscalls, otherwise continue again
+fn syscall_stop(pid: Pid, debugger_info: &mut DebuggerInfo) -> (WaitStatus, Option<Command>) {
+    let status = WaitStatus::Stopped(pid, Signal::SIGTRAP);
+    let regs = get_regs(pid);
+    let info = SyscallInfo::from_regs(&regs);
+    // 入口ではraxが-ENOSYSになっている
+    let entry = regs.rax as i64 == -(libc::ENOSYS as i64);
+    let stack = &mut debugger_info.syscall_stack;
+    if entry {
+        stack.push(info.clone());
+    } else if stack.is_exit(info.number()) {
+        stack.pop();
+    }
+    let Some(sysno) = Sysno::new(regs.orig_rax as usize) else {
+        return (status, Some(Command::Continue));
+    };
+    let catchpoints: Vec<(usize, Option<Condition>)> = debugger_info
+        .breakpoint_manager
+        .syscall_catchpoints(sysno)
+        .map(|cp| (cp.num, cp.condition.clone()))
+        .collect();
+    let mut scope = debugger_info.scope();
+    if catchpoints.iter().any(|(_, condition)| condition.is_some()) {
+        scope.bindings = syscall::bindings(pid, &regs, !entry);
+    }
+    let mut nums = Vec::new();
+    for (num, condition) in catchpoints {
+        let hit = match condition {
+            None => true,
+            Some((text, expr)) => match expr::eval(&expr, &scope) {
+                Ok(value) => value.is_true(),
+                // 評価できない条件では止める
+                Err(e) => {
+                    println!("error in condition of catchpoint {num} `{text}`: {e}");
+                    true
+                }
+            },
+        };
+        if hit {
+            nums.push(num);
+        }
+    }
+    for num in &nums {
+        debugger_info.breakpoint_manager.hit_catchpoint(*num);
+    }
+    if nums.is_empty() {
+        return (status, Some(Command::Continue));
+    }
+    for num in nums {
+        if entry {
             println!(
-                "syscall enter: PID: {pid}, {:03}: {}",
-                syscall_info.number(),
-                syscall_info.name()
+                "catchpoint {num} (call to syscall {}), {}",
+                info.name(),
+                syscall::format_call(pid, &regs)
+            );
+        } else {
+            println!(
+                "catchpoint {num} (returned from syscall {}), {} = {}",
+                info.name(),
+                info.name(),
+                syscall::format_return(&regs)
             );
-            syscall_stack.push(syscall_info);
-        }
-        // syscallの出口だった場合
-        else if syscall_stack.pop().is_none() {
-            panic!("syscall count failed");
         }
-    } else {
-        println!(
-            "syscall enter: PID: {pid}, {:03}: {}",
-            syscall_info.number(),
-            syscall_info.name()
-        );
-        syscall_stack.push(syscall_info);
     }
-
-    if let Err(e) = ptrace::cont(pid, None) {
-        panic!("ptrace::syscall failed: errno = {:?}", e);
-    }
-
-    waitpid(pid, None).unwrap()
+    debugger_info.cont_flag = false;
+    (status, None)
 }
 
 fn signaled(pid: Pid, signal: Signal, _core_dump: bool) -> WaitStatus {
     println!("signaled: PID: {pid}, Signal: {:?}", signal);
-    if let Err(e) = ptrace::cont(pid, None) {
+    if let Err(e) = mem::cont(pid, None) {
         println!("ptrace::cont failed: errno = {e}");
     }
     waitpid(pid, None).unwrap()
@@ -391,7 +2563,7 @@ fn signaled(pid: Pid, signal: Signal, _core_dump: bool) -> WaitStatus {
 
 fn still_alive(pid: Pid) -> WaitStatus {
     println!("still alive");
-    if let Err(e) = ptrace::cont(pid, None) {
+    if let Err(e) = mem::cont(pid, None) {
         println!("ptrace::cont failed: errno = {e}");
     }
     waitpid(pid, None).unwrap()
@@ -401,18 +2573,18 @@ fn stopped(
     pid: Pid,
     signal: Signal,
     debugger_info: &mut DebuggerInfo,
-) -> (WaitStatus, Option<Command>) {
+) -> Result<(WaitStatus, Option<Command>), Box<dyn std::error::Error>> {
     if signal == Signal::SIGTRAP {
         let regs = get_regs(pid);
         // もしブレークポイントだったら0xCCより1byte次にいるはず
         let addr = regs.rip - 1;
         // 上のアドレスがブレークポイントだったとき
-        if let Some(bp) = debugger_info.breakpoint_manager.get(addr) {
-            bp.restore_memory(pid, regs).unwrap();
-            return (
-                WaitStatus::Stopped(debugger_info.debug_info.target_pid(), Signal::SIGTRAP),
-                None,
-            );
+        if debugger_info.breakpoint_manager.hit_addr().is_some() {
+            let stop = step::check_breakpoint(debugger_info)?;
+            let status =
+                WaitStatus::Stopped(debugger_info.debug_info.target_pid(), Signal::SIGTRAP);
+            // 条件が偽なので止まらずに再開する
+            return Ok((status, (!stop).then_some(Command::Continue)));
         }
         // ブレークポイントではなかったとき
         else {
@@ -429,48 +2601,119 @@ fn stopped(
             }
             // ウォッチポイントが仕掛けられていないときはcontしてもどる
             if debugger_info.watch_list.is_empty() {
-                if let Err(e) = ptrace::cont(pid, None) {
-                    panic!("ptrace::cont failed: errno = {e}");
-                }
-                let status = waitpid(debugger_info.debug_info.target_pid(), None).unwrap();
-                match status {
-                    WaitStatus::Stopped(pid, signal) => match signal {
-                        Signal::SIGSEGV => {
-                            handle_sigsegv(pid);
-                        }
-                        Signal::SIGTRAP => return (status, Some(Command::Continue)),
-                        _ => return (status, None),
-                    },
-                    _ => return (status, None),
-                }
+                return cont_and_wait(pid, None, debugger_info);
             }
-            // ウォッチポイントが仕掛けられているときはstepしてさらにStep Instruction Commandを発行
+            // ウォッチポイントが仕掛けられているときはStep Instruction Commandで1命令ずつ調べる
             else {
-                ptrace::step(debugger_info.debug_info.target_pid(), None).unwrap();
-                let status = waitpid(debugger_info.debug_info.target_pid(), None).unwrap();
                 debugger_info.cont_flag = true;
-                return (status, Some(Command::StepInstruction));
+                return Ok((
+                    WaitStatus::Stopped(pid, signal),
+                    Some(Command::StepInstruction(1)),
+                ));
             }
         }
     }
 
-    if let Err(e) = ptrace::cont(pid, signal) {
-        panic!("ptrace::cont failed: errno = {e}");
+    // シグナルを渡して再開する、ハンドラ内のブレークポイントも同じように扱う
+    let signal = debugger_info.passed(signal);
+    cont_and_wait(pid, signal, debugger_info)
+}
+
+fn cont_and_wait(
+    pid: Pid,
+    signal: Option<Signal>,
+    debugger_info: &mut DebuggerInfo,
+) -> Result<(WaitStatus, Option<Command>), Box<dyn std::error::Error>> {
+    // syscallのキャッチポイントがある時だけsyscallごとに止める
+    let status = debugger_info.continue_all(pid, signal)?;
+    let status_and_additional_command = match status {
+        WaitStatus::Stopped(pid, signal) => match signal {
+            Signal::SIGSEGV => match step::check_page_watchpoint(debugger_info)? {
+                Some((WaitStatus::Exited(pid, code), _)) => exited(pid, code),
+                Some((status, true)) => {
+                    debugger_info.cont_flag = false;
+                    (status, None)
+                }
+                Some((status, false)) => (status, Some(Command::Continue)),
+                None if caught_signal(debugger_info, signal) => (status, None),
+                None if debugger_info.handling(signal).stop => handle_sigsegv(status, pid),
+                None => received_signal(status, debugger_info),
+            },
+            Signal::SIGTRAP => trapped(status, debugger_info)?,
+            _ if caught_signal(debugger_info, signal) => (status, None),
+            _ => received_signal(status, debugger_info),
+        },
+        WaitStatus::PtraceEvent(pid, _, event) => ptrace_event(pid, event, debugger_info),
+        WaitStatus::PtraceSyscall(pid) => syscall_stop(pid, debugger_info),
+        WaitStatus::Exited(pid, code) => exited(pid, code),
+        WaitStatus::Signaled(pid, signal, _) => {
+            println!("process {pid} killed by {signal}");
+            // シェルと同じく128+シグナル番号で終わる
+            (status, Some(Command::Exit(128 + signal as i32)))
+        }
+        _ => (status, None),
+    };
+    Ok(status_and_additional_command)
+}
+
+/// stop if a breakpoint trapped, otherwise continue again
+fn trapped(
+    status: WaitStatus,
+    debugger_info: &mut DebuggerInfo,
+) -> Result<(WaitStatus, Option<Command>), Box<dyn std::error::Error>> {
+    if let Some(stop) = step::check_watchpoint(debugger_info)? {
+        if stop {
+            debugger_info.cont_flag = false;
+            return Ok((status, None));
+        }
+        return Ok((status, Some(Command::Continue)));
     }
-    (
-        waitpid(debugger_info.debug_info.target_pid(), None).unwrap(),
-        None,
-    )
+    // デバッグレジスタではripがアドレスのままなので、次のcontinueで踏み越える前に調べる
+    if debugger_info.breakpoint_manager.hit_addr().is_some()
+        && step::check_breakpoint(debugger_info)?
+    {
+        debugger_info.cont_flag = false;
+        Ok((status, None))
+    } else {
+        Ok((status, Some(Command::Continue)))
+    }
+}
+
+/// execute one instruction and check the watchpoints
+/// returns true with the status if the target should stop
+fn step_once(
+    debugger_info: &mut DebuggerInfo,
+) -> Result<(WaitStatus, bool), Box<dyn std::error::Error>> {
+    let wait_status = record::step(debugger_info)?;
+    // 保護したページへの書き込みは権限を戻して実行し直す
+    if let WaitStatus::Stopped(_, Signal::SIGSEGV) = wait_status {
+        if let Some((status, stop)) = step::check_page_watchpoint(debugger_info)? {
+            return Ok((status, stop || matches!(status, WaitStatus::Exited(..))));
+        }
+    }
+    if let WaitStatus::Exited(..) = wait_status {
+        return Ok((wait_status, true));
+    }
+    if step::check_watchpoint(debugger_info)? == Some(true) {
+        return Ok((wait_status, true));
+    }
+    // ソフトウェアウォッチポイントは1命令ごとに値を読み直す
+    let changed = check_watchpoints(debugger_info);
+    Ok((wait_status, changed))
 }
 
 fn single_step(
     debugger_info: &mut DebuggerInfo,
 ) -> Result<(WaitStatus, Option<Command>), Box<dyn std::error::Error>> {
-    ptrace::step(debugger_info.debug_info.target_pid(), None)?;
-    let wait_options =
-        WaitPidFlag::from_bits(WaitPidFlag::WCONTINUED.bits() | WaitPidFlag::WUNTRACED.bits());
-
-    let wait_status = waitpid(debugger_info.debug_info.target_pid(), wait_options).unwrap();
+    let (wait_status, stop) = step_once(debugger_info)?;
+    if let WaitStatus::Exited(pid, code) = wait_status {
+        debugger_info.cont_flag = false;
+        return Ok(exited(pid, code));
+    }
+    if stop {
+        debugger_info.cont_flag = false;
+        return Ok((wait_status, None));
+    }
 
     if !debugger_info.cont_flag {
         return Ok((
@@ -478,17 +2721,8 @@ fn single_step(
             None,
         ));
     }
-
-    if let WaitStatus::Exited(_pid, code) = wait_status {
-        return Ok((wait_status, Some(Command::Exit(code))));
-    }
-    let regs = get_regs(debugger_info.debug_info.target_pid());
-    // もしブレークポイントだったら0xCCより1byte次にいるはず
-    let addr = regs.rip - 1;
-    // 上のアドレスがブレークポイントだったとき
-    if let Some(bp) = debugger_info.breakpoint_manager.get(addr) {
-        bp.restore_memory(debugger_info.debug_info.target_pid(), regs)
-            .unwrap();
+    // ブレークポイントで条件が真だったとき
+    if step::check_breakpoint(debugger_info)? {
         debugger_info.cont_flag = false;
         Ok((
             WaitStatus::Stopped(debugger_info.debug_info.target_pid(), Signal::SIGTRAP),
@@ -500,25 +2734,236 @@ fn single_step(
         // ウォッチポイントが仕掛けられていないときはcontしてもどる
         if debugger_info.watch_list.is_empty() {
             debugger_info.cont_flag = false;
-            if let Err(e) = ptrace::cont(debugger_info.debug_info.target_pid(), None) {
-                panic!("ptrace::cont failed: errno = {e}");
+            if let Some(status) = debugger_info.breakpoint_manager.step_over()? {
+                return Ok((status, None));
             }
-            let status = waitpid(debugger_info.debug_info.target_pid(), None).unwrap();
+            let pid = debugger_info.debug_info.target_pid();
+            let status = debugger_info.continue_all(pid, None)?;
             if status == WaitStatus::Stopped(debugger_info.debug_info.target_pid(), Signal::SIGTRAP)
             {
-                Ok((status, Some(Command::Continue)))
+                trapped(status, debugger_info)
             } else {
                 Ok((status, None))
             }
         }
-        // ウォッチポイントが仕掛けられているときはstepしてさらにStep Instruction Commandを発行
+        // ウォッチポイントが仕掛けられているときはさらにStep Instruction Commandを発行
         else {
-            debugger_info.cont_flag = true;
-            ptrace::step(debugger_info.debug_info.target_pid(), None).unwrap();
-            let status = waitpid(debugger_info.debug_info.target_pid(), None).unwrap();
-            Ok((status, Some(Command::StepInstruction)))
+            Ok((wait_status, Some(Command::StepInstruction(1))))
+        }
+    }
+}
+
+/// write the pattern at the address after checking that the range is writable
+fn fill(
+    debugger_info: &DebuggerInfo,
+    addr: &Expr,
+    len: u64,
+    pattern: &Pattern,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let addr = examine::address(debugger_info, addr)?;
+    mem::check_mapping(pid, addr, len, mem::Access::Write)?;
+    mem::write_bytes(pid, addr, &pattern.bytes(len as usize))?;
+    println!("filled {len} bytes at 0x{addr:016x}");
+    Ok(())
+}
+
+/// address and length of `<start> <end|+len>`
+fn memory_range(
+    debugger_info: &DebuggerInfo,
+    start: &Expr,
+    end: &find::End,
+) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    let start = examine::address(debugger_info, start)?;
+    let len = match end {
+        find::End::Address(end) => examine::address(debugger_info, end)?.saturating_sub(start),
+        find::End::Length(len) => expr::eval(len, &debugger_info.scope())?.as_u64(),
+    };
+    if len == 0 {
+        return Err(Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            "the range is empty",
+        )));
+    }
+    Ok((start, len))
+}
+
+/// write the memory in the range to the file as raw bytes
+fn dump_memory(
+    debugger_info: &DebuggerInfo,
+    path: &str,
+    start: &Expr,
+    end: Option<&find::End>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let end = end.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "the end is missing"))?;
+    let (start, len) = memory_range(debugger_info, start, end)?;
+    mem::check_mapping(pid, start, len, mem::Access::Data)?;
+    let bytes = mem::read_bytes(pid, start, len as usize)?;
+    fs::write(path, &bytes)?;
+    println!("dumped {len} bytes at 0x{start:016x} to {path}");
+    Ok(())
+}
+
+/// write the file to the memory at `start`, only the first bytes up to the end if it is given
+fn restore_memory(
+    debugger_info: &DebuggerInfo,
+    path: &str,
+    start: &Expr,
+    end: Option<&find::End>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let mut bytes = fs::read(path)?;
+    let start = match end {
+        Some(end) => {
+            let (start, len) = memory_range(debugger_info, start, end)?;
+            if bytes.len() < len as usize {
+                return Err(Box::new(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{path} has only {} bytes", bytes.len()),
+                )));
+            }
+            bytes.truncate(len as usize);
+            start
+        }
+        None => examine::address(debugger_info, start)?,
+    };
+    if bytes.is_empty() {
+        return Err(Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{path} is empty"),
+        )));
+    }
+    mem::check_mapping(pid, start, bytes.len() as u64, mem::Access::Write)?;
+    mem::write_bytes(pid, start, &bytes)?;
+    println!(
+        "restored {} bytes from {path} at 0x{start:016x}",
+        bytes.len()
+    );
+    Ok(())
+}
+
+/// print the offset of the value in the cyclic pattern
+fn pattern_offset(
+    debugger_info: &DebuggerInfo,
+    expr: &Expr,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let needle = find::bytes(debugger_info, expr, None)?;
+    if needle.len() < pattern::SUBSEQUENCE_LEN {
+        return Err(Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "at least {} bytes are needed to find an offset",
+                pattern::SUBSEQUENCE_LEN
+            ),
+        )));
+    }
+    let text = String::from_utf8_lossy(&needle).into_owned();
+    match pattern::offset(&needle) {
+        Some(offset) => println!("found {text:?} at offset {offset}"),
+        None => println!("{text:?} is not in the cyclic pattern"),
+    }
+    Ok(())
+}
+
+/// write `value` as a `size` byte integer at the address, the rest of the word is kept
+fn write_typed(
+    debugger_info: &DebuggerInfo,
+    size: u64,
+    addr: &Expr,
+    value: &Expr,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let addr = examine::address(debugger_info, addr)?;
+    let value = expr::eval(value, &debugger_info.scope())?.as_u128();
+    let bits = size * 8;
+    // 負の値は符号拡張されているので下位bitsに収まれば書ける
+    if value >> bits != 0 && (value as i128) >> (bits - 1) != -1 {
+        return Err(Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            format!("value 0x{value:x} does not fit in u{bits}"),
+        )));
+    }
+    mem::check_mapping(pid, addr, size, mem::Access::Write)?;
+    mem::write_bytes(pid, addr, &value.to_le_bytes()[..size as usize])?;
+    Ok(())
+}
+
+/// continue by recorded steps until a breakpoint, a watchpoint, a signal or Ctrl-C
+fn continue_recording(
+    debugger_info: &mut DebuggerInfo,
+) -> Result<(WaitStatus, Option<Command>), Box<dyn std::error::Error>> {
+    debugger_info.cont_flag = false;
+    let pid = debugger_info.debug_info.target_pid();
+    let cancel = Cancel::start();
+    loop {
+        if cancel.is_cancelled() {
+            return Ok((WaitStatus::Stopped(pid, Signal::SIGTRAP), None));
+        }
+        let (status, stop) = step_once(debugger_info)?;
+        match status {
+            WaitStatus::Exited(pid, code) => return Ok(exited(pid, code)),
+            WaitStatus::Stopped(_, Signal::SIGTRAP) => {}
+            WaitStatus::Stopped(_, signal) => {
+                println!("the target received {signal}");
+                return Ok((status, None));
+            }
+            _ => return Ok((status, None)),
+        }
+        if stop || step::check_breakpoint(debugger_info)? {
+            return Ok((status, None));
+        }
+    }
+}
+
+/// undo `count` recorded instructions, or until a breakpoint if None
+fn reverse(
+    debugger_info: &mut DebuggerInfo,
+    status: WaitStatus,
+    count: Option<usize>,
+) -> (WaitStatus, Option<Command>) {
+    let pid = debugger_info.debug_info.target_pid();
+    let Some(record) = &mut debugger_info.record else {
+        println!("not recording");
+        return (status, None);
+    };
+    debugger_info.selected_frame = 0;
+    let cancel = Cancel::start();
+    let mut syscalls = 0;
+    let mut undone = 0;
+    while count.is_none_or(|count| undone < count) && !cancel.is_cancelled() {
+        match record::reverse_step(pid, record) {
+            Ok(Some(step)) => syscalls += step.syscall as usize,
+            Ok(None) => {
+                println!("no more recorded history");
+                break;
+            }
+            Err(e) => {
+                println!("{e}");
+                break;
+            }
+        }
+        undone += 1;
+        let rip = get_regs(pid).rip;
+        if count.is_none() {
+            if let Some(bp) = debugger_info.breakpoint_manager.get(rip) {
+                println!("breakpoint {}", bp.num);
+                break;
+            }
         }
     }
+    if syscalls > 0 {
+        println!("warning: memory written by {syscalls} syscalls is not restored");
+    }
+    let rip = get_regs(pid).rip;
+    match count {
+        Some(_) => match step::instruction_at(debugger_info, rip) {
+            Ok(instruction) => dump::instruction(debugger_info, &instruction),
+            Err(_) => println!("0x{:016x}: <unreadable>", rip),
+        },
+        None => step::print_frame(debugger_info),
+    }
+    (WaitStatus::Stopped(pid, Signal::SIGTRAP), None)
 }
 
 fn continue_run(
@@ -526,14 +2971,484 @@ fn continue_run(
     debugger_info: &mut DebuggerInfo,
 ) -> Result<(WaitStatus, Option<Command>), Box<dyn std::error::Error>> {
     debugger_info.cont_flag = true;
-    let wait_status = switch_by_wait_status(status, debugger_info).unwrap();
-    Ok(wait_status)
+    switch_by_wait_status(status, debugger_info)
 }
 
-fn handle_sigsegv(pid: Pid) -> ! {
-    dump::register(pid);
-    // TODO: implementation
-    loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
+/// `<location> [if <expr>]`
+/// `[--entry] <location> [if <expr>]`, returns the actual address of the location
+/// location is `*<expr>` (address), `file:line`, a symbol or a file offset
+fn parse_breakpoint(
+    debugger_info: &DebuggerInfo,
+    args: &[&str],
+) -> Result<(u64, Option<Condition>), Box<dyn std::error::Error>> {
+    // --entryなら関数の先頭そのものに置く
+    let entry = args.first() == Some(&"--entry");
+    let args = if entry { &args[1..] } else { args };
+    let (location, condition) = match args.iter().position(|arg| *arg == "if") {
+        Some(pos) if pos + 1 < args.len() => {
+            let text = args[pos + 1..].join(" ");
+            let expr = expr::parse(&text)?;
+            (args[..pos].join(" "), Some((text, expr)))
+        }
+        Some(_) => {
+            return Err(Box::new(Error::new(
+                ErrorKind::InvalidInput,
+                "invalid argument",
+            )))
+        }
+        None => (args.join(" "), None),
+    };
+    if location.is_empty() {
+        return Err(Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            "invalid argument",
+        )));
+    }
+    if let Some(text) = location.strip_prefix('*') {
+        return Ok((address_location(debugger_info, text)?, condition));
+    }
+    if location.contains(' ') {
+        return Err(Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            "invalid argument",
+        )));
+    }
+    let load_bias = debugger_info.debug_info.load_bias();
+    if let Some((file, line)) = parse_file_line(&location) {
+        return Ok((
+            line_address(debugger_info, file, line)? + load_bias,
+            condition,
+        ));
+    }
+    let mut location = location;
+    if let Some((module, symbol)) = split_module(&location) {
+        let target = Path::new(debugger_info.debug_info.filename()).file_name();
+        if target.and_then(|name| name.to_str()) != Some(module) {
+            let addr = debugger_info
+                .debug_info
+                .find_module_symbol(module, symbol)?;
+            return Ok((addr, condition));
+        }
+        // 対象ファイル自身のシンボルはデバッグ情報を使って通常通り解決する
+        location = symbol.to_string();
+    }
+    let addr = match debugger_info.debug_info.find_symbol(&location) {
+        Some(f) if entry => f.address() + load_bias,
+        Some(f) => step::after_prologue(debugger_info, f.address(), f.size()) + load_bias,
+        None => file_offset_address(debugger_info, location.parse::<u64>()?),
+    };
+    Ok((addr, condition))
+}
+
+/// arguments of `run` and `start`, None to reuse the previous ones
+fn run_args(args: &[&str]) -> Option<Vec<String>> {
+    match args {
+        [] => None,
+        _ => Some(args.iter().map(|arg| arg.to_string()).collect()),
+    }
+}
+
+/// address after the prologue of the main function of the user
+/// `<crate>::main` of Rust is preferred to `main` which calls it through `lang_start`
+fn main_address(debugger_info: &DebuggerInfo) -> Result<u64, Box<dyn std::error::Error>> {
+    let symbols = debugger_info.debug_info.symbols();
+    let rust_main = symbols.iter().find(|f| {
+        f.kind() == SymbolKind::Text && {
+            let demangled = demangle(f.name());
+            let segments = split_path(&demangled);
+            segments.len() == 2 && segments[1] == "main"
+        }
+    });
+    let main = rust_main
+        .or_else(|| debugger_info.debug_info.find_symbol("main"))
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "no main function"))?;
+    let load_bias = debugger_info.debug_info.load_bias();
+    Ok(step::after_prologue(debugger_info, main.address(), main.size()) + load_bias)
+}
+
+/// split `module:symbol`, `a::b` is a path rather than a module
+fn split_module(location: &str) -> Option<(&str, &str)> {
+    let (module, symbol) = location.split_once(':')?;
+    if module.is_empty() || symbol.is_empty() || symbol.starts_with(':') {
+        return None;
+    }
+    Some((module, symbol))
+}
+
+/// location of `until` and `advance`, a bare number is a line of the current file
+fn run_location(
+    debugger_info: &DebuggerInfo,
+    args: &[&str],
+) -> Result<u64, Box<dyn std::error::Error>> {
+    if let [line] = args {
+        if let Ok(line) = line.parse::<u64>() {
+            let path = debugger_info
+                .debug_info
+                .to_file_addr(get_regs(debugger_info.debug_info.target_pid()).rip)
+                .and_then(|file_pc| debugger_info.debug_info.find_line(file_pc))
+                .map(|info| info.path)
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::NotFound, "no line information at the current pc")
+                })?;
+            let load_bias = debugger_info.debug_info.load_bias();
+            return Ok(line_address(debugger_info, &path, line)? + load_bias);
+        }
+    }
+    match parse_breakpoint(debugger_info, args)? {
+        (addr, None) => Ok(addr),
+        (_, Some(_)) => Err(Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            "conditions cannot be used here",
+        ))),
+    }
+}
+
+/// actual address of `*<expr>`
+/// addresses in the target file (e.g. from objdump) are moved by the load bias
+fn address_location(
+    debugger_info: &DebuggerInfo,
+    text: &str,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let expr = expr::parse(text)?;
+    let addr = expr::eval(&expr, &debugger_info.scope())?.as_u64();
+    let exec_maps = debugger_info.debug_info.exec_maps()?;
+    if exec_maps.iter().any(|m| m.is_included(addr)) {
+        return Ok(addr);
+    }
+    let biased = addr.wrapping_add(debugger_info.debug_info.load_bias());
+    if exec_maps.iter().any(|m| m.is_included(biased)) {
+        println!("0x{addr:x} is an address in the file, using 0x{biased:016x}");
+        return Ok(biased);
+    }
+    // 起動後にロードされた共有ライブラリは現在のmapで確かめる
+    mem::check_mapping(
+        debugger_info.debug_info.target_pid(),
+        addr,
+        1,
+        mem::Access::Execute,
+    )?;
+    Ok(addr)
+}
+
+/// actual address of the offset in the target file
+fn file_offset_address(debugger_info: &DebuggerInfo, bin_offset: u64) -> u64 {
+    // とりあえずコードセグメントが1つだけのバイナリに対応
+    let exec_map = debugger_info.debug_info.exec_maps().unwrap()[0];
+    // mapが実際にある仮想アドレス
+    let start = exec_map.start() as u64;
+    // バイナリファイルのどこからがこの領域にマップされているかを指し示す値
+    // addrの計算に必要
+    let offset = exec_map.offset as u64;
+    start + (bin_offset - offset)
+}
+
+/// `file:line`, Rust paths like `crate::func` are not
+fn parse_file_line(location: &str) -> Option<(&str, u64)> {
+    let (file, line) = location.rsplit_once(':')?;
+    if file.is_empty() || file.ends_with(':') {
+        return None;
+    }
+    Some((file, line.parse::<u64>().ok()?))
+}
+
+/// address of the first statement of `file:line`
+/// tells which line is used if the line has no code or several locations
+fn line_address(
+    debugger_info: &DebuggerInfo,
+    file: &str,
+    line: u64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let lookup = match debugger_info.debug_info.find_line_addresses(file, line) {
+        Some(lookup) => lookup,
+        None => {
+            return Err(Box::new(Error::new(
+                ErrorKind::NotFound,
+                format!("no code at or after {file}:{line}"),
+            )))
+        }
+    };
+    let load_bias = debugger_info.debug_info.load_bias();
+    let (addr, info) = &lookup.locations[0];
+    if info.line != lookup.requested {
+        println!(
+            "no code at {file}:{}, using {info} at 0x{:016x}",
+            lookup.requested,
+            addr + load_bias
+        );
+        if let Some((prev_addr, prev)) = &lookup.previous {
+            println!("  alternative: {prev} at 0x{:016x}", prev_addr + load_bias);
+        }
+    }
+    // インライン展開やループで同じ行が複数の場所にあることがある
+    if lookup.locations.len() > 1 {
+        println!(
+            "{info} has {} locations, using 0x{:016x}",
+            lookup.locations.len(),
+            addr + load_bias
+        );
+        for (other, _) in &lookup.locations[1..] {
+            println!("  alternative: 0x{:016x}", other + load_bias);
+        }
+    }
+    Ok(*addr)
+}
+
+/// `set` is one of the `BreakpointManager::set*` functions or a closure calling them
+fn set_breakpoint(
+    debugger_info: &mut DebuggerInfo,
+    addr: u64,
+    condition: Option<Condition>,
+    set: impl FnOnce(
+        &mut BreakpointManager,
+        u64,
+    ) -> Result<&mut breakpoint::Breakpoint, Box<dyn std::error::Error>>,
+) {
+    let bp = match set(&mut debugger_info.breakpoint_manager, addr) {
+        Ok(bp) => bp,
+        Err(e) => {
+            println!("cannot set breakpoint at 0x{:016x}: {}", addr, e);
+            return;
+        }
+    };
+    bp.condition = condition;
+    let kind = if bp.dprintf.is_some() {
+        "dprintf"
+    } else if bp.temporary {
+        "temporary breakpoint"
+    } else if bp.slot.is_some() {
+        "hardware breakpoint"
+    } else {
+        "breakpoint"
+    };
+    println!("set {} {} at 0x{:016x}", kind, bp.num, addr);
+}
+
+/// address, size and type of the memory watched for `expr`
+fn watch_target(
+    debugger_info: &DebuggerInfo,
+    text: &str,
+    expr: &Expr,
+) -> Result<(u64, u64, Option<Type>), Box<dyn std::error::Error>> {
+    let value = expr::eval(expr, &debugger_info.scope())?;
+    // 数値はアドレスとして8バイトを見張る
+    match (expr, value.addr()) {
+        (Expr::Integer(addr), _) => Ok((*addr as u64, 8, None)),
+        (_, Some(addr)) => Ok((addr, value.ty.size(), Some(value.ty))),
+        _ => Err(Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            format!("cannot watch `{text}`: not in memory"),
+        ))),
+    }
+}
+
+/// watch the address or the lvalue of `expr` with a debug register
+fn set_watchpoint(debugger_info: &mut DebuggerInfo, kind: WatchKind, text: String, expr: &Expr) {
+    let (addr, len, ty) = match watch_target(debugger_info, &text, expr) {
+        Ok(target) => target,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
+    let pid = debugger_info.debug_info.target_pid();
+    // デバッグレジスタより大きい領域はページの書き込み保護で見張る
+    if kind == WatchKind::Write && len > 8 {
+        match debugger_info
+            .breakpoint_manager
+            .set_page_watchpoint(text, addr, len, ty)
+        {
+            Ok(wp) => println!(
+                "set {} {} at 0x{:016x} ({} bytes, page protection)",
+                kind.name(),
+                wp.num,
+                addr,
+                len
+            ),
+            Err(e) => println!("cannot set {}: {}", kind.name(), e),
+        }
+        return;
+    }
+    match debugger_info
+        .breakpoint_manager
+        .set_watchpoint(kind, text, addr, len, ty)
+    {
+        Ok(wp) => println!(
+            "set {} {} at 0x{:016x} ({} bytes)",
+            kind.name(),
+            wp.num,
+            addr,
+            len
+        ),
+        // 書き込みはステップ実行で代わりに見張れる
+        Err(e) if kind == WatchKind::Write => {
+            println!("cannot use a debug register: {e}");
+            let value = ptrace::read(pid, addr as *mut c_void).unwrap_or(0) as u64;
+            debugger_info.set_watchpoint(WatchPoint::Memory(mem::Memory { addr, value }));
+            println!("set software watchpoint at 0x{:016x}", addr);
+        }
+        Err(e) => println!("cannot set {}: {}", kind.name(), e),
+    }
+}
+
+/// `label add <addr expr> [<len>] <name>`, `label del <name|addr>`, `label [list]`,
+/// `label save [<path>]` and `label load [<path>]`
+fn parse_label(buf: &str, args: &[&str]) -> Result<Command, Box<dyn std::error::Error>> {
+    let usage = || {
+        Box::new(Error::new(
+            ErrorKind::InvalidInput,
+            "usage: label add <addr> [<len>] \"name\" | del <name|addr> | list | save [<path>] | load [<path>]",
+        ))
+    };
+    let command = match args {
+        [] | ["list"] => LabelCommand::List,
+        ["save"] => LabelCommand::Save(None),
+        ["save", path] => LabelCommand::Save(Some(path.to_string())),
+        ["load"] => LabelCommand::Load(None),
+        ["load", path] => LabelCommand::Load(Some(path.to_string())),
+        ["del", ..] if args.len() > 1 => LabelCommand::Delete(unquote(&args[1..].join(" "))),
+        ["add", ..] => {
+            // 名前は引用符で囲めば空白を含められる
+            let (head, name) = match (buf.find('"'), buf.rfind('"')) {
+                (Some(start), Some(end)) if start < end => {
+                    let head = buf[..start]
+                        .split_whitespace()
+                        .skip(2)
+                        .collect::<Vec<&str>>();
+                    (head, buf[start + 1..end].to_string())
+                }
+                _ => match args.split_last() {
+                    Some((name, head)) if head.len() > 1 => (head[1..].to_vec(), name.to_string()),
+                    _ => return Err(usage()),
+                },
+            };
+            let (addr, len) = match head.as_slice() {
+                [addr] => (*addr, 1),
+                [addr, len] => (*addr, parse_demical_or_hex(len)?),
+                _ => return Err(usage()),
+            };
+            if name.is_empty() {
+                return Err(usage());
+            }
+            LabelCommand::Add(expr::parse(addr)?, len, name)
+        }
+        _ => return Err(usage()),
+    };
+    Ok(Command::Label(command))
+}
+
+fn unquote(s: &str) -> String {
+    s.trim_matches('"').to_string()
+}
+
+fn label(debugger_info: &mut DebuggerInfo, command: LabelCommand) {
+    match command {
+        LabelCommand::Add(expr, len, name) => {
+            let addr = match expr::eval(&expr, &debugger_info.scope()) {
+                Ok(value) => value.as_u64(),
+                Err(e) => return println!("{e}"),
+            };
+            println!("label {name}: 0x{addr:016x}-0x{:016x}", addr + len);
+            debugger_info.labels.add(addr, len, name);
+        }
+        LabelCommand::Delete(name) => {
+            let addr = parse_demical_or_hex(&name).ok();
+            if !debugger_info.labels.remove(&name, addr) {
+                println!("no label {name}");
+            }
+        }
+        LabelCommand::List => dump::labels(debugger_info),
+        LabelCommand::Save(path) => {
+            let labels = &debugger_info.labels;
+            let path = path.map(PathBuf::from).unwrap_or(labels.path.clone());
+            match labels.save(&path) {
+                Ok(()) => println!("labels saved to {}", path.display()),
+                Err(e) => println!("cannot save labels to {}: {}", path.display(), e),
+            }
+        }
+        LabelCommand::Load(path) => {
+            let labels = &mut debugger_info.labels;
+            let path = path.map(PathBuf::from).unwrap_or(labels.path.clone());
+            match labels.load(&path) {
+                Ok(count) => println!("{} labels loaded from {}", count, path.display()),
+                Err(e) => println!("cannot load labels from {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+fn print_as(debugger_info: &DebuggerInfo, name: &str, expr: &Expr) {
+    let ty = match debugger_info.overlays.get(name) {
+        Some(ty) => ty,
+        None => return println!("no struct {name}, define it with `struct`"),
+    };
+    let scope = debugger_info.scope();
+    let addr = match expr::eval(expr, &scope) {
+        Ok(value) => value.as_u64(),
+        Err(e) => return println!("{e}"),
+    };
+    let pid = debugger_info.debug_info.target_pid();
+    match mem::read_bytes(pid, addr, ty.size() as usize) {
+        Ok(bytes) => println!("({name}) {}", variable::format_value(pid, ty, &bytes)),
+        Err(_) => println!("cannot access memory at address 0x{addr:x}"),
+    }
+}
+
+fn set_mark(debugger_info: &mut DebuggerInfo, name: String, ranges: &[(Expr, u64)]) {
+    let pid = debugger_info.debug_info.target_pid();
+    let mut memory = Vec::new();
+    for (expr, len) in ranges {
+        let addr = match expr::eval(expr, &debugger_info.scope()) {
+            Ok(value) => value.as_u64(),
+            Err(e) => {
+                println!("{e}");
+                return;
+            }
+        };
+        match mem::read_bytes(pid, addr, *len as usize) {
+            Ok(bytes) => memory.push((addr, bytes)),
+            Err(e) => {
+                println!("cannot read 0x{addr:016x}: {e}");
+                return;
+            }
+        }
+    }
+    let regs = get_regs(pid);
+    println!("mark {name} at 0x{:016x}", regs.rip);
+    debugger_info
+        .marks
+        .insert(name, debugger::Mark { regs, memory });
+}
+
+fn select_frame(debugger_info: &mut DebuggerInfo, level: usize) {
+    let frames = unwind::backtrace(&debugger_info.debug_info);
+    match frames.get(level) {
+        Some(frame) => {
+            debugger_info.selected_frame = level;
+            dump::frame(debugger_info, level, frame);
+        }
+        None => println!("no frame at level {level}"),
     }
 }
+
+/// announce the signal and stop or continue as `handle` says
+fn received_signal(
+    status: WaitStatus,
+    debugger_info: &DebuggerInfo,
+) -> (WaitStatus, Option<Command>) {
+    let WaitStatus::Stopped(pid, signal) = status else {
+        return (status, None);
+    };
+    let handling = debugger_info.handling(signal);
+    if handling.print {
+        println!("process {pid} received {signal}");
+    }
+    // 再開するときに渡すかどうかはstoppedで決める
+    (status, (!handling.stop).then_some(Command::Continue))
+}
+
+/// stop at the segmentation fault, the signal is delivered when the target is resumed
+fn handle_sigsegv(status: WaitStatus, pid: Pid) -> (WaitStatus, Option<Command>) {
+    println!("process {pid} received SIGSEGV");
+    dump::register(&get_regs(pid));
+    (status, None)
+}
@@ -0,0 +1,183 @@
+use crate::overlay::strip_comment;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// config of the project read from the current directory after the one of the user
+const PROJECT_CONFIG: &str = ".rdbg.toml";
+
+/// config files in the order they are applied, later ones override earlier ones
+pub fn paths() -> Vec<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    let mut paths = Vec::new();
+    if let Some(config_home) = config_home {
+        paths.push(config_home.join("rdbg").join("config.toml"));
+    }
+    paths.push(PathBuf::from(PROJECT_CONFIG));
+    paths
+}
+
+/// commands which apply the config files, run before the script
+/// a file which cannot be parsed is reported and skipped
+pub fn commands() -> Vec<String> {
+    let mut commands = Vec::new();
+    for path in paths() {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                eprintln!("cannot read {}: {e}", path.display());
+                continue;
+            }
+        };
+        match parse(&path, &text) {
+            Ok(new) => commands.extend(new),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+    commands
+}
+
+/// TOML with `key = value` at the top and in `[substitute-path]` and `[alias]` tables
+///
+/// ```toml
+/// radix = 16
+/// skip = ["printf", "std::vec::Vec<T,A>::push"]
+///
+/// [substitute-path]
+/// "/build" = "/home/me/src"
+///
+/// [alias]
+/// bb = "break"
+/// ```
+pub fn parse(path: &Path, text: &str) -> Result<Vec<String>, String> {
+    let mut commands = Vec::new();
+    let mut table = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let at = format!("{}:{}", path.display(), i + 1);
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| format!("{at}: invalid table"))?;
+            table = name.trim().trim_matches('"').to_string();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("{at}: expected `key = value`"))?;
+        let (key, value) = (key.trim().trim_matches('"'), value.trim());
+        match (table.as_str(), key) {
+            ("", "radix") => commands.push(format!("set output-radix {value}")),
+            ("", "skip") => {
+                let names = value
+                    .strip_prefix('[')
+                    .and_then(|names| names.strip_suffix(']'))
+                    .ok_or_else(|| format!("{at}: skip is a list of function names"))?;
+                // 名前にカンマを含むことがあるので引用符の内側を取り出す
+                commands.extend(
+                    names
+                        .split('"')
+                        .skip(1)
+                        .step_by(2)
+                        .map(|name| format!("skip {name}")),
+                );
+            }
+            ("substitute-path", from) => {
+                commands.push(format!("set substitute-path {from} {}", unquote(value)))
+            }
+            ("alias", name) => commands.push(format!("alias {name} = {}", unquote(value))),
+            // 他の版の設定ファイルでも起動できるように知らないキーは無視する
+            (table, key) => {
+                let key = match table {
+                    "" => key.to_string(),
+                    table => format!("{table}.{key}"),
+                };
+                // スクリプトの出力に混ざらないように標準エラーに出す
+                eprintln!("{at}: unknown key {key} is ignored");
+            }
+        }
+    }
+    Ok(commands)
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Vec<String>, String> {
+        super::parse(Path::new("config.toml"), text)
+    }
+
+    #[test]
+    fn top_level_keys() {
+        assert_eq!(
+            parse("radix = 16\nskip = [\"printf\", \"std::vec::Vec<T,A>::push\"]\n"),
+            Ok(vec![
+                "set output-radix 16".to_string(),
+                "skip printf".to_string(),
+                "skip std::vec::Vec<T,A>::push".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn tables() {
+        let text =
+            "[substitute-path]\n\"/build\" = \"/home/me/src\"\n[ \"alias\" ]\nbb = \"break\"\n";
+        assert_eq!(
+            parse(text),
+            Ok(vec![
+                "set substitute-path /build /home/me/src".to_string(),
+                "alias bb = break".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let text = "# radix = 8\nradix = 16 # hex\n[alias]\nh = \"echo #1\" # not a comment inside quotes\n";
+        assert_eq!(
+            parse(text),
+            Ok(vec![
+                "set output-radix 16".to_string(),
+                "alias h = echo #1".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let text = "color = true\n[alias]\nbb = \"break\"\n[tui]\nlayout = \"src\"\n";
+        assert_eq!(parse(text), Ok(vec!["alias bb = break".to_string()]));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse("radix = 16\n[alias\n"),
+            Err("config.toml:2: invalid table".to_string())
+        );
+        assert_eq!(
+            parse("radix\n"),
+            Err("config.toml:1: expected `key = value`".to_string())
+        );
+        assert_eq!(
+            parse("skip = \"printf\"\n"),
+            Err("config.toml:1: skip is a list of function names".to_string())
+        );
+    }
+}
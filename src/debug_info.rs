@@ -5,14 +5,17 @@ use nix::{
     unistd::Pid,
 };
 use object::{
-    Object, ObjectSection, ObjectSymbol, RelocationTarget, SectionIndex, SymbolFlags, SymbolIndex,
-    SymbolKind, SymbolScope, SymbolSection,
+    elf,
+    read::elf::{ElfFile64, ProgramHeader},
+    Endianness, Object, ObjectKind, ObjectSection, ObjectSegment, ObjectSymbol, RelocationTarget,
+    SectionIndex, SymbolFlags, SymbolIndex, SymbolKind, SymbolScope, SymbolSection,
 };
-use once_cell::sync::OnceCell;
-use proc_maps::MapRange;
+use proc_maps::{get_process_maps, MapRange};
 use std::{
     borrow::{self, Cow},
-    fs, io,
+    fmt, fs, io,
+    path::Path,
+    sync::RwLock,
 };
 // use symbolic::{
 //     common::Name,
@@ -96,19 +99,67 @@ impl Symbol {
     }
 }
 
-static FILE_MMAP: OnceCell<memmap2::Mmap> = OnceCell::new();
-static OBJECT: OnceCell<object::File> = OnceCell::new();
-static DWARF_COW: OnceCell<Option<Dwarf<Cow<[u8]>>>> = OnceCell::new();
-static DWARF: OnceCell<Option<Dwarf<EndianSlice<RunTimeEndian>>>> = OnceCell::new();
+/// target file and its contents, replaced when the target executes another file
+/// the old ones are leaked because references to them may still be alive
+struct Globals {
+    filename: String,
+    map: &'static memmap2::Mmap,
+    object: &'static object::File<'static>,
+    dwarf: Option<&'static Dwarf<EndianSlice<'static, RunTimeEndian>>>,
+}
+
+static GLOBALS: RwLock<Option<Globals>> = RwLock::new(None);
+
+/// (from, to) of `set substitute-path`, applied to the paths in the line programs
+static SUBSTITUTIONS: RwLock<Vec<(String, String)>> = RwLock::new(Vec::new());
+
+/// rewrite paths starting with the directory `from` to `to`, replacing the rule for `from`
+pub fn set_substitution(from: &str, to: &str) {
+    let from = from.trim_end_matches('/').to_string();
+    let to = to.trim_end_matches('/').to_string();
+    let mut substitutions = SUBSTITUTIONS.write().unwrap();
+    match substitutions.iter_mut().find(|(f, _)| *f == from) {
+        Some(rule) => rule.1 = to,
+        None => substitutions.push((from, to)),
+    }
+}
+
+pub fn clear_substitutions() {
+    SUBSTITUTIONS.write().unwrap().clear();
+}
+
+pub fn substitutions() -> Vec<(String, String)> {
+    SUBSTITUTIONS.read().unwrap().clone()
+}
+
+/// path with the first rule whose directory contains it applied
+fn substitute(path: String) -> String {
+    for (from, to) in SUBSTITUTIONS.read().unwrap().iter() {
+        // /buildが/build2にも当たらないようにディレクトリの区切りで比べる
+        match path.strip_prefix(from.as_str()) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+                return format!("{to}{rest}");
+            }
+            _ => {}
+        }
+    }
+    path
+}
 
 fn init_global_objects(filename: &str) {
+    // runで起動し直しても同じファイルなので読み直さない
+    if GLOBALS
+        .read()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|globals| globals.filename == filename)
+    {
+        return;
+    }
     let file = fs::File::open(filename).unwrap();
-    let map = unsafe { memmap2::Mmap::map(&file).unwrap() };
-    FILE_MMAP.set(map).unwrap();
-    let map = FILE_MMAP.get().unwrap();
-
-    OBJECT.set(object::File::parse(&**map).unwrap()).unwrap();
-    let obj = OBJECT.get().unwrap();
+    let map: &'static memmap2::Mmap =
+        Box::leak(Box::new(unsafe { memmap2::Mmap::map(&file).unwrap() }));
+    let obj: &'static object::File = Box::leak(Box::new(object::File::parse(&**map).unwrap()));
 
     let endian = if obj.is_little_endian() {
         gimli::RunTimeEndian::Little
@@ -116,15 +167,68 @@ fn init_global_objects(filename: &str) {
         gimli::RunTimeEndian::Big
     };
 
-    if let Ok(dwarf_cow) = get_dwarf_cow(obj) {
-        DWARF_COW.set(Some(dwarf_cow)).unwrap();
-        let dwarf_cow = DWARF_COW.get().unwrap().as_ref().unwrap();
+    let dwarf = get_dwarf_cow(obj).ok().map(|dwarf_cow| {
+        let dwarf_cow: &'static Dwarf<Cow<[u8]>> = Box::leak(Box::new(dwarf_cow));
+        let dwarf: &'static Dwarf<EndianSlice<RunTimeEndian>> =
+            Box::leak(Box::new(get_dwarf(dwarf_cow, endian)));
+        dwarf
+    });
+    *GLOBALS.write().unwrap() = Some(Globals {
+        filename: filename.to_string(),
+        map,
+        object: obj,
+        dwarf,
+    });
+}
+
+fn file_map() -> &'static memmap2::Mmap {
+    GLOBALS.read().unwrap().as_ref().unwrap().map
+}
+
+pub fn object() -> &'static object::File<'static> {
+    GLOBALS.read().unwrap().as_ref().unwrap().object
+}
 
-        let dwarf = get_dwarf(dwarf_cow, endian);
-        DWARF.set(Some(dwarf)).unwrap();
+pub fn endian() -> RunTimeEndian {
+    if object().is_little_endian() {
+        RunTimeEndian::Little
     } else {
-        DWARF_COW.set(None).unwrap();
-        DWARF.set(None).unwrap();
+        RunTimeEndian::Big
+    }
+}
+
+pub fn dwarf() -> Option<&'static Dwarf<EndianSlice<'static, RunTimeEndian>>> {
+    GLOBALS
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|globals| globals.dwarf)
+}
+
+/// source location from the line table
+#[derive(Debug, Clone)]
+pub struct LineInfo {
+    pub path: String,
+    pub line: u64,
+}
+
+/// result of looking up `file:line` in the line table
+#[derive(Debug, Clone)]
+pub struct LineLookup {
+    pub requested: u64,
+    /// (file address, line) of the statements, the line may follow the requested one
+    pub locations: Vec<(u64, LineInfo)>,
+    /// nearest preceding line with code
+    pub previous: Option<(u64, LineInfo)>,
+}
+
+impl fmt::Display for LineInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file_name = Path::new(&self.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.path);
+        write!(f, "{}:{}", file_name, self.line)
     }
 }
 
@@ -162,6 +266,11 @@ impl TdbDebugInfo {
         self.target_pid
     }
 
+    /// debug the forked child, its mappings are the same as the target
+    pub fn set_target_pid(&mut self, pid: Pid) {
+        self.target_pid = pid;
+    }
+
     pub fn mmaps(&self) -> &[MapRange] {
         &self.mmaps
     }
@@ -183,9 +292,18 @@ impl TdbDebugInfo {
     }
 
     pub fn init(filename: &str, pid: Pid, syscall_stack: &mut SyscallStack) -> (Self, WaitStatus) {
-        let mut debug_info = Self::new(filename, pid);
-
         let (mmaps, status) = Self::get_mmaps(pid, filename, syscall_stack);
+        (Self::with_mmaps(filename, pid, mmaps), status)
+    }
+
+    /// debug info of the running process whose file is already mapped
+    pub fn attach(filename: &str, pid: Pid) -> Result<Self, io::Error> {
+        let mmaps = mem::get_mmap_info(pid, filename)?;
+        Ok(Self::with_mmaps(filename, pid, mmaps))
+    }
+
+    fn with_mmaps(filename: &str, pid: Pid, mmaps: Vec<MapRange>) -> Self {
+        let mut debug_info = Self::new(filename, pid);
         debug_info.mmaps = mmaps;
 
         let symbols = debug_info.get_symbols();
@@ -198,14 +316,13 @@ impl TdbDebugInfo {
             }
         }
         debug_info.base_addr = base_addr;
-
-        (debug_info, status)
+        debug_info
     }
 
     fn get_symbols(&self) -> Vec<Symbol> {
         let mut symbols = Vec::new();
 
-        for s in OBJECT.get().unwrap().symbols() {
+        for s in object().symbols() {
             let addend = self.get_addend();
             let new_symbol = Symbol::new(s, addend);
             symbols.push(new_symbol);
@@ -214,7 +331,7 @@ impl TdbDebugInfo {
     }
 
     fn get_addend(&self) -> Option<i64> {
-        for (apply_to, reloc) in OBJECT.get().unwrap().dynamic_relocations().unwrap() {
+        for (apply_to, reloc) in object().dynamic_relocations().unwrap() {
             match reloc.target() {
                 RelocationTarget::Absolute => {
                     if Self::find_target_symbol_from_rel_info(apply_to, &self.symbols).is_some() {
@@ -241,13 +358,8 @@ impl TdbDebugInfo {
         None
     }
 
-    pub fn get_breakpoint_offset(&self, bp_symbol_name: &str) -> Option<u64> {
-        for f in self.symbols() {
-            if f.name == bp_symbol_name {
-                return Some(f.address);
-            }
-        }
-        None
+    pub fn find_symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols().iter().find(|f| f.name == name)
     }
 
     fn get_mmaps(
@@ -324,21 +436,13 @@ impl TdbDebugInfo {
         target_symbol_value: u64,
         symbols: &[Symbol],
     ) -> Option<&Symbol> {
-        for sym in symbols {
-            if target_symbol_value == sym.address {
-                return Some(sym);
-            }
-        }
-        None
+        symbols
+            .iter()
+            .find(|sym| target_symbol_value == sym.address)
     }
 
     fn find_target_symbol_from_index(index: SymbolIndex, symbol_vec: &[Symbol]) -> Option<&Symbol> {
-        for sym in symbol_vec {
-            if index == sym.index {
-                return Some(sym);
-            }
-        }
-        None
+        symbol_vec.iter().find(|sym| index == sym.index)
     }
 
     pub fn get_actual_symbol_address(&self, sym: &Symbol) -> Option<u64> {
@@ -350,39 +454,35 @@ impl TdbDebugInfo {
             };
 
             match sym.scope {
+                // シンボルの値はファイルオフセットではなく仮想アドレスなのでロードバイアスだけずらす
                 SymbolScope::Compilation | SymbolScope::Linkage => {
-                    let start = map.start() as u64;
-                    let offset = map.offset as u64;
-                    let size = map.size() as u64;
-                    if (offset <= sym.address) && (sym.address < offset + size) {
-                        let diff = sym.address - offset;
-                        return Some(start + diff);
-                    } else {
-                        continue;
-                    };
+                    let actual = sym.address + self.load_bias();
+                    if sym.address != 0 && self.file_maps().any(|m| m.is_included(actual)) {
+                        return Some(actual);
+                    }
+                    return None;
                 }
                 SymbolScope::Dynamic => {
-                    if self.filename() == filename {
+                    let target_filename = Path::new(self.filename())
+                        .file_name()
+                        .and_then(|name| name.to_str());
+                    if target_filename == Some(filename) {
                         let mem_base = self.base_addr;
                         match sym.addend {
                             Some(addend) => {
                                 let actual = if addend >= 0 {
                                     mem_base + addend as u64
                                 } else {
-                                    mem_base - (addend.unsigned_abs() as u64)
+                                    mem_base - addend.unsigned_abs()
                                 };
                                 return Some(actual);
                             }
                             None => {
-                                let start = map.start() as u64;
-                                let offset = map.offset as u64;
-                                let size = map.size() as u64;
-                                if (offset <= sym.address) && (sym.address < offset + size) {
-                                    let diff = sym.address - offset;
-                                    return Some(start + diff);
-                                } else {
-                                    continue;
-                                };
+                                let actual = sym.address + self.load_bias();
+                                if sym.address != 0 && map.is_included(actual) {
+                                    return Some(actual);
+                                }
+                                continue;
                             }
                         };
                     } else {
@@ -395,6 +495,351 @@ impl TdbDebugInfo {
         None
     }
 
+    /// maps of the target file itself
+    fn file_maps(&self) -> impl Iterator<Item = &MapRange> {
+        let proc_filename = Path::new(&self.filename).file_name();
+        self.mmaps.iter().filter(move |m| {
+            m.filename().and_then(|path| path.file_name()) == proc_filename
+                && proc_filename.is_some()
+        })
+    }
+
+    /// actual address of the entry point, which is not executed again after the start
+    pub fn entry_address(&self) -> u64 {
+        object().entry() + self.load_bias()
+    }
+
+    /// difference between actual address and address in the target file
+    pub fn load_bias(&self) -> u64 {
+        if object().kind() != ObjectKind::Dynamic {
+            return 0;
+        }
+        self.file_maps()
+            .find(|m| m.offset == 0)
+            .map(|m| m.start() as u64)
+            .unwrap_or(self.base_addr)
+    }
+
+    /// actual address of `symbol` defined in the loaded object named `module`
+    /// `module` may omit the version suffix, e.g. `libc.so` matches `libc.so.6`
+    pub fn find_module_symbol(
+        &self,
+        module: &str,
+        symbol: &str,
+    ) -> Result<u64, Box<dyn std::error::Error>> {
+        let maps = get_process_maps(self.target_pid.as_raw())?;
+        let matches = |path: &Path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| {
+                    name == module
+                        || name
+                            .strip_prefix(module)
+                            .is_some_and(|rest| rest.starts_with(['.', '-']))
+                })
+        };
+        let mut paths: Vec<&Path> = maps
+            .iter()
+            .filter_map(|m| m.filename())
+            .filter(|path| matches(path))
+            .collect();
+        paths.dedup();
+        let path = match paths[..] {
+            [path] => path,
+            [] => {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no loaded object matches {module}, it may not be loaded yet"),
+                )))
+            }
+            _ => {
+                let names: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{module} is ambiguous: {}", names.join(", ")),
+                )));
+            }
+        };
+
+        let data = fs::read(path)?;
+        let obj = object::File::parse(&*data)?;
+        let sym = obj
+            .symbols()
+            .chain(obj.dynamic_symbols())
+            .find(|s| s.is_definition() && s.name() == Ok(symbol))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no symbol {symbol} in {}", path.display()),
+                )
+            })?;
+        // 共有ライブラリは先頭のセグメントがmmapされた位置からずれる
+        let load_bias = match obj.kind() {
+            ObjectKind::Dynamic => {
+                let start = maps
+                    .iter()
+                    .find(|m| m.filename() == Some(path) && m.offset == 0)
+                    .map_or(0, |m| m.start() as u64);
+                let first = obj.segments().map(|s| s.address()).min().unwrap_or(0);
+                start - (first & !0xfff)
+            }
+            _ => 0,
+        };
+        Ok(sym.address() + load_bias)
+    }
+
+    /// address of the thread-local variable at `offset` in the TLS block of the target file
+    /// x86_64 places the block of the executable just below the thread pointer (variant II)
+    pub fn tls_address(&self, fs_base: u64, offset: u64) -> Option<u64> {
+        let map = file_map();
+        let elf = ElfFile64::<Endianness>::parse(&**map).ok()?;
+        let endian = elf.endian();
+        let tls = elf
+            .raw_segments()
+            .iter()
+            .find(|segment| segment.p_type(endian) == elf::PT_TLS)?;
+        let align = tls.p_align(endian).max(1);
+        let size = tls.p_memsz(endian).div_ceil(align) * align;
+        Some(fs_base - size + offset)
+    }
+
+    /// convert actual address to the address in the target file
+    pub fn to_file_addr(&self, actual_addr: u64) -> Option<u64> {
+        if self.file_maps().any(|m| m.is_included(actual_addr)) {
+            Some(actual_addr - self.load_bias())
+        } else {
+            None
+        }
+    }
+
+    /// find the source line which contains `file_addr` from the line table
+    pub fn find_line(&self, file_addr: u64) -> Option<LineInfo> {
+        let dwarf = dwarf()?;
+        let mut units = dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let unit = match dwarf.unit(header) {
+                Ok(unit) => unit,
+                Err(_) => continue,
+            };
+            let program = match unit.line_program.clone() {
+                Some(program) => program,
+                None => continue,
+            };
+            let mut rows = program.rows();
+            // (address, file index, line)
+            let mut prev: Option<(u64, u64, u64)> = None;
+            while let Ok(Some((_, row))) = rows.next_row() {
+                if let Some((prev_addr, file_index, line)) = prev {
+                    if (prev_addr <= file_addr) && (file_addr < row.address()) {
+                        let path = file_path(dwarf, &unit, rows.header(), file_index)?;
+                        return Some(LineInfo { path, line });
+                    }
+                }
+                prev = if row.end_sequence() {
+                    None
+                } else {
+                    let line = row.line().map(|l| l.get()).unwrap_or(0);
+                    Some((row.address(), row.file_index(), line))
+                };
+            }
+        }
+        None
+    }
+
+    /// name and producer of the compilation unit which contains `file_addr`
+    pub fn find_unit(&self, file_addr: u64) -> Option<(String, Option<String>)> {
+        let dwarf = dwarf()?;
+        let mut units = dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let unit = match dwarf.unit(header) {
+                Ok(unit) => unit,
+                Err(_) => continue,
+            };
+            let mut ranges = match dwarf.unit_ranges(&unit) {
+                Ok(ranges) => ranges,
+                Err(_) => continue,
+            };
+            let mut contains = false;
+            while let Ok(Some(range)) = ranges.next() {
+                contains |= (range.begin <= file_addr) && (file_addr < range.end);
+            }
+            if !contains {
+                continue;
+            }
+            let name = unit
+                .name
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| String::from("??"));
+            let mut entries = unit.entries();
+            let producer = match entries.next_dfs() {
+                Ok(Some((_, root))) => root
+                    .attr_value(gimli::DW_AT_producer)
+                    .ok()
+                    .flatten()
+                    .and_then(|value| dwarf.attr_string(&unit, value).ok())
+                    .map(|producer| producer.to_string_lossy().to_string()),
+                _ => None,
+            };
+            return Some((name, producer));
+        }
+        None
+    }
+
+    /// statements of `file:line` in the line table
+    /// if the line has no code, the nearest following line with code is used
+    pub fn find_line_addresses(&self, file: &str, line: u64) -> Option<LineLookup> {
+        let dwarf = dwarf()?;
+        // (line, address, path)
+        let mut rows_of_file: Vec<(u64, u64, String)> = Vec::new();
+        let mut units = dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let unit = match dwarf.unit(header) {
+                Ok(unit) => unit,
+                Err(_) => continue,
+            };
+            let program = match unit.line_program.clone() {
+                Some(program) => program,
+                None => continue,
+            };
+            let mut rows = program.rows();
+            while let Ok(Some((header, row))) = rows.next_row() {
+                if row.end_sequence() || !row.is_stmt() {
+                    continue;
+                }
+                let path = match file_path(dwarf, &unit, header, row.file_index()) {
+                    Some(path) => path,
+                    None => continue,
+                };
+                if path == file || path.ends_with(&format!("/{file}")) {
+                    let row_line = row.line().map(|l| l.get()).unwrap_or(0);
+                    rows_of_file.push((row_line, row.address(), path));
+                }
+            }
+        }
+        let actual = rows_of_file
+            .iter()
+            .map(|(row_line, ..)| *row_line)
+            .filter(|row_line| *row_line >= line)
+            .min()?;
+        let mut locations: Vec<(u64, LineInfo)> = rows_of_file
+            .iter()
+            .filter(|(row_line, ..)| *row_line == actual)
+            .map(|(row_line, addr, path)| {
+                let info = LineInfo {
+                    path: path.clone(),
+                    line: *row_line,
+                };
+                (*addr, info)
+            })
+            .collect();
+        // ループなどで同じ関数に複数ある行は関数ごとに先頭の1つにまとめる
+        locations.sort_by_key(|(addr, _)| *addr);
+        let function_of = |addr: u64| {
+            self.symbols
+                .iter()
+                .find(|f| {
+                    f.kind == SymbolKind::Text && f.address <= addr && addr < f.address + f.size
+                })
+                .map(|f| f.address)
+                .unwrap_or(addr)
+        };
+        locations.dedup_by_key(|(addr, _)| function_of(*addr));
+        // 前の行も候補として挙げる
+        let previous = rows_of_file
+            .iter()
+            .filter(|(row_line, ..)| *row_line < line)
+            .max_by_key(|(row_line, addr, _)| (*row_line, std::cmp::Reverse(*addr)))
+            .map(|(row_line, addr, path)| {
+                let info = LineInfo {
+                    path: path.clone(),
+                    line: *row_line,
+                };
+                (*addr, info)
+            });
+        Some(LineLookup {
+            requested: line,
+            locations,
+            previous,
+        })
+    }
+
+    /// address range [start, end) of the line which contains `file_addr`
+    pub fn find_line_range(&self, file_addr: u64) -> Option<(u64, u64)> {
+        let dwarf = dwarf()?;
+        let mut units = dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let unit = match dwarf.unit(header) {
+                Ok(unit) => unit,
+                Err(_) => continue,
+            };
+            let program = match unit.line_program.clone() {
+                Some(program) => program,
+                None => continue,
+            };
+            let mut rows = program.rows();
+            // (address, file index, line)
+            let mut sequence: Vec<(u64, u64, u64)> = Vec::new();
+            while let Ok(Some((_, row))) = rows.next_row() {
+                let line = row.line().map(|l| l.get()).unwrap_or(0);
+                sequence.push((row.address(), row.file_index(), line));
+                if !row.end_sequence() {
+                    continue;
+                }
+                if let Some(range) = line_range_in(&sequence, file_addr) {
+                    return Some(range);
+                }
+                sequence.clear();
+            }
+        }
+        None
+    }
+
+    /// first address after the prologue of the function in [start, end) from the line table
+    /// prologue_end is preferred, otherwise the second line of the function is used
+    /// functions on one line use the second statement of the line like gdb
+    pub fn prologue_end(&self, start: u64, end: u64) -> Option<u64> {
+        let dwarf = dwarf()?;
+        let mut units = dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let unit = match dwarf.unit(header) {
+                Ok(unit) => unit,
+                Err(_) => continue,
+            };
+            let program = match unit.line_program.clone() {
+                Some(program) => program,
+                None => continue,
+            };
+            let mut rows = program.rows();
+            let mut first_line = None;
+            let mut second_line = None;
+            let mut second_stmt = None;
+            while let Ok(Some((_, row))) = rows.next_row() {
+                let addr = row.address();
+                if row.end_sequence() || addr < start || end <= addr {
+                    continue;
+                }
+                if row.prologue_end() {
+                    return Some(addr);
+                }
+                let line = row.line().map(|l| l.get());
+                match first_line {
+                    None if addr == start => first_line = Some(line),
+                    Some(first) if second_line.is_none() && row.is_stmt() && line != first => {
+                        second_line = Some(addr)
+                    }
+                    Some(_) if second_stmt.is_none() && row.is_stmt() && addr != start => {
+                        second_stmt = Some(addr)
+                    }
+                    _ => {}
+                }
+            }
+            if first_line.is_some() {
+                return second_line.or(second_stmt);
+            }
+        }
+        None
+    }
+
     pub fn find_function_in(&self, actual_addr: u64) -> Option<&Symbol> {
         for f in &self.symbols {
             if let Some(start) = self.get_actual_symbol_address(f) {
@@ -408,6 +853,49 @@ impl TdbDebugInfo {
     }
 }
 
+/// path of the file in the line program, joined with its directory if relative
+fn file_path<R: gimli::Reader>(
+    dwarf: &Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    header: &gimli::LineProgramHeader<R>,
+    file_index: u64,
+) -> Option<String> {
+    let file = header.file(file_index)?;
+    let mut path = dwarf
+        .attr_string(unit, file.path_name())
+        .ok()?
+        .to_string_lossy()
+        .ok()?
+        .to_string();
+    if Path::new(&path).is_relative() {
+        if let Some(dir) = file.directory(header) {
+            if let Ok(dir) = dwarf.attr_string(unit, dir) {
+                if let Ok(dir) = dir.to_string_lossy() {
+                    path = Path::new(&*dir).join(&path).to_string_lossy().to_string();
+                }
+            }
+        }
+    }
+    Some(substitute(path))
+}
+
+/// expand the row which contains `file_addr` to the adjacent rows of the same line
+fn line_range_in(sequence: &[(u64, u64, u64)], file_addr: u64) -> Option<(u64, u64)> {
+    let i = sequence
+        .windows(2)
+        .position(|rows| (rows[0].0 <= file_addr) && (file_addr < rows[1].0))?;
+    let same_line = |row: &(u64, u64, u64)| (row.1 == sequence[i].1) && (row.2 == sequence[i].2);
+    let mut start = i;
+    while start > 0 && same_line(&sequence[start - 1]) {
+        start -= 1;
+    }
+    let mut end = i + 1;
+    while end < sequence.len() - 1 && same_line(&sequence[end]) {
+        end += 1;
+    }
+    Some((sequence[start].0, sequence[end].0))
+}
+
 fn get_dwarf<'a>(
     dwarf_cow: &'a Dwarf<Cow<'a, [u8]>>,
     endian: gimli::RunTimeEndian,
@@ -425,8 +913,8 @@ fn get_dwarf_cow<'a>(object: &'a object::File) -> Result<Dwarf<Cow<'a, [u8]>>, g
             Some(ref section) => Ok(section
                 .uncompressed_data()
                 .unwrap_or(borrow::Cow::Borrowed(&[][..]))),
-            //None => Ok(borrow::Cow::Borrowed(&[][..])),
-            None => Err(gimli::Error::NoEntryAtGivenOffset),
+            // 存在しないセクションがあってもDWARF全体の読み込みは失敗させない
+            None => Ok(borrow::Cow::Borrowed(&[][..])),
         }
     };
 
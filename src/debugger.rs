@@ -1,18 +1,43 @@
 use std::{
-    fmt::Debug,
-    io::{Read, Seek},
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::{self, Debug},
+    io::{BufRead, Read, Seek, Write},
 };
 
 #[allow(unused)]
 use crate::{
-    breakpoint::BreakpointManager,
-    command::Command,
+    bisect::Bisect,
+    bounded::{Bounded, HeapSize, Policy},
+    breakpoint::{self, BreakpointManager, WatchKind},
+    checkpoint::{self, Checkpoint, Checkpoints},
+    command::{Command, ExecOutcome, StopReason, Termination},
+    config,
     debug_info::{self, TdbDebugInfo},
-    dump, mem, register,
+    debugreg, dump,
+    expr::{self, Expr, Scope},
+    fini::fini,
+    label::Labels,
+    mem,
+    overlay::Overlays,
+    record::Record,
+    register,
+    signal::Handling,
     syscall::{get_regs, SyscallInfo, SyscallStack},
+    target,
+    thread::{self, SchedulerLocking, ThreadState, Threads},
+    unwind::{self, Frame},
 };
-use nix::{libc::c_void, sys::ptrace, unistd::Pid};
-use std::process::exit;
+use nix::{
+    errno::Errno,
+    libc::{self, c_void, user_regs_struct},
+    sys::{
+        ptrace,
+        signal::{self, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::Pid,
+};
+use std::{fs, io, path::Path, process::exit, time::Instant};
 
 #[derive(Debug)]
 pub struct DebuggerInfo {
@@ -23,25 +48,791 @@ pub struct DebuggerInfo {
     pub prev_command: Option<crate::command::Command>,
     pub watch_list: Vec<WatchPoint>,
     pub cont_flag: bool,
+    /// frame number selected by `frame`, `up` and `down`
+    pub selected_frame: usize,
+    /// expressions printed on every stop
+    pub display_list: Vec<Display>,
+    pub next_display_num: usize,
+    /// snapshots for `diff marks`
+    pub marks: BTreeMap<String, Mark>,
+    /// commands of the breakpoints which stopped the target, executed before the prompt
+    pub pending_commands: VecDeque<String>,
+    /// commands of `set stop-hook` run at every stop before those of the breakpoint
+    pub stop_hook: Vec<String>,
+    /// functions which `step` steps over instead of into
+    pub skip: Vec<String>,
+    /// first words of commands replaced by `alias`
+    pub aliases: BTreeMap<String, String>,
+    /// every stop of the session for `history stops`
+    pub stops: Bounded<Stop>,
+    /// names of memory regions shown with their addresses
+    pub labels: Labels,
+    /// struct layouts for `print as`
+    pub overlays: Overlays,
+    /// arguments passed to the target by `run`
+    pub args: Vec<String>,
+    /// termination checked when the target terminates, set by `expect`
+    pub expected: Option<Termination>,
+    /// process debugged after the target forks
+    pub follow_fork_mode: FollowForkMode,
+    /// when the target was started, hit times are shown relative to it
+    pub started: Instant,
+    /// writes recorded by the last `bisect-watch`
+    pub bisect: Option<Bisect>,
+    /// limit of the writes recorded by `bisect-watch`
+    pub bisect_policy: Policy,
+    /// instructions stepped since `record`, None if not recording
+    pub record: Option<Record>,
+    /// limit of the instructions kept by `record`
+    pub record_policy: Policy,
+    /// threads of the target, the current one is `debug_info.target_pid()`
+    pub threads: Threads,
+    pub scheduler_locking: SchedulerLocking,
+    /// signals whose handling was changed by `handle`
+    pub signals: HashMap<Signal, Handling>,
+    /// commands of `--script` read instead of stdin, tdb quits after them
+    pub script: Option<VecDeque<String>>,
+    /// forked copies of the target made by `checkpoint`
+    pub checkpoints: Checkpoints,
 }
 
 impl DebuggerInfo {
+    /// kill the target and forget the state of the process, breakpoints are kept for `run`
+    pub fn kill(&mut self) -> WaitStatus {
+        let pid = self.threads.pid();
+        let mut status = WaitStatus::Signaled(pid, Signal::SIGKILL, false);
+        // 既に終了していればkillは失敗する
+        if signal::kill(pid, Signal::SIGKILL).is_ok() {
+            let terminated =
+                |new: &WaitStatus| matches!(new, WaitStatus::Exited(..) | WaitStatus::Signaled(..));
+            let flags = WaitPidFlag::__WALL | WaitPidFlag::WNOHANG;
+            // チェックポイントのコピーは回収済みでも親が回収するまでゾンビとして残りkillに成功する
+            // そのときはECHILDになるので待たない
+            while let Ok(new) = waitpid(pid, Some(flags)) {
+                if terminated(&new) {
+                    status = new;
+                    break;
+                }
+                if new != WaitStatus::StillAlive {
+                    continue;
+                }
+                // メインスレッドは他のスレッドが回収されるまで終了を報告しない
+                match waitpid(None, Some(WaitPidFlag::__WALL)) {
+                    Ok(new) if new.pid() == Some(pid) && terminated(&new) => {
+                        status = new;
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+        }
+        self.threads.reset(pid);
+        mem::clear_cache();
+        self.syscall_stack.clear();
+        self.prev_command = None;
+        self.cont_flag = false;
+        self.selected_frame = 0;
+        self.pending_commands.clear();
+        status
+    }
+
+    /// kill the target and start it again, breakpoints and watchpoints are inserted again
+    pub fn restart(&mut self) -> Result<WaitStatus, Box<dyn std::error::Error>> {
+        self.kill();
+        let filename = self.debug_info.filename().to_string();
+        let child = target::spawn(&filename, &self.args)?;
+        let (debug_info, status) = start(child, &filename, &mut self.syscall_stack);
+        self.debug_info = debug_info;
+        self.started = Instant::now();
+        self.threads.reset(child);
+        self.record = None;
+        self.breakpoint_manager
+            .set_scratch(self.debug_info.entry_address());
+
+        self.reinsert(child);
+        self.stops.clear();
+        Ok(status)
+    }
+
+    /// fork the current thread of the target into a checkpoint
+    pub fn checkpoint(&mut self) -> Result<&Checkpoint, Box<dyn std::error::Error>> {
+        let pid = self.debug_info.target_pid();
+        let child = checkpoint::fork(pid)?;
+        let pc = get_regs(pid).rip;
+        let int3s = self.breakpoint_manager.int3s();
+        Ok(self.checkpoints.add(child, pc, int3s))
+    }
+
+    /// kill the target if `running` and continue from a copy of the checkpoint
+    /// the checkpoint itself is kept stopped so that it can be restarted again
+    pub fn restart_checkpoint(
+        &mut self,
+        num: usize,
+        running: bool,
+    ) -> Result<WaitStatus, Box<dyn std::error::Error>> {
+        let checkpoint = self.checkpoints.get(num).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no checkpoint {num}"))
+        })?;
+        let copy = checkpoint::fork(checkpoint.pid)?;
+        // チェックポイントを作った時点のint3を消してから今のブレークポイントを入れ直す
+        checkpoint::clean(copy, &checkpoint.int3s)?;
+        // 終了したコピーは親のチェックポイントが回収するまでゾンビとして残るのでkillしない
+        if running {
+            self.kill();
+        }
+        self.threads.reset(copy);
+        self.record = None;
+        self.select_thread(copy);
+        crate::signal::init_child_pid(copy);
+        self.reinsert(copy);
+        self.stops.clear();
+        Ok(WaitStatus::Stopped(copy, Signal::SIGTRAP))
+    }
+
+    /// read the file which the target executed, the target stops at its first instruction
+    pub fn follow_exec(&mut self, path: &str) -> Result<(), io::Error> {
+        let pid = self.debug_info.target_pid();
+        let old = fs::canonicalize(self.debug_info.filename()).ok();
+        let same = old.as_deref() == Some(Path::new(path));
+        // 同じファイルなら読み直さない
+        let filename = if same {
+            self.debug_info.filename().to_string()
+        } else {
+            path.to_string()
+        };
+        self.debug_info = TdbDebugInfo::attach(&filename, pid)?;
+        self.breakpoint_manager
+            .set_scratch(self.debug_info.entry_address());
+        // execで他のスレッドはなくなる
+        self.threads.reset(pid);
+        self.syscall_stack.clear();
+        self.record = None;
+        self.selected_frame = 0;
+        if same {
+            self.reinsert(pid);
+        } else {
+            for num in self.breakpoint_manager.disable_all() {
+                println!("{num} is disabled, it was set in the previous program");
+            }
+            self.watch_list.clear();
+        }
+        Ok(())
+    }
+
+    /// insert the breakpoints and watchpoints into the new process of the same file
+    fn reinsert(&mut self, pid: Pid) {
+        for num in self.breakpoint_manager.restart(pid) {
+            println!("{num} is disabled, its address is not mapped yet");
+        }
+        // ソフトウェアウォッチポイントは新しいプロセスの値から見張る
+        self.watch_list.retain_mut(|w| match w.read_value(pid) {
+            Some(value) => {
+                w.update_value(value);
+                true
+            }
+            None => {
+                println!("{w} is deleted, its address is not mapped yet");
+                false
+            }
+        });
+    }
+
     pub fn set_watchpoint(&mut self, watchpoint: WatchPoint) {
         self.watch_list.push(watchpoint);
     }
+
+    pub fn add_display(&mut self, text: String, expr: Expr) -> &Display {
+        self.next_display_num += 1;
+        self.display_list.push(Display {
+            num: self.next_display_num,
+            text,
+            expr,
+        });
+        self.display_list.last().unwrap()
+    }
+
+    /// rewind the target to the breakpoint at `addr` whose int3 trapped
+    /// returns false if the breakpoints there are ignored or their conditions are false
+    pub fn hit_breakpoint(&mut self, addr: u64) -> Result<bool, nix::Error> {
+        self.breakpoint_manager.rewind(addr)?;
+        self.selected_frame = 0;
+        let scope = self.scope();
+        let mut hits = Vec::new();
+        for bp in self.breakpoint_manager.at(addr) {
+            let hit = match &bp.condition {
+                None => true,
+                Some((text, expr)) => match expr::eval(expr, &scope) {
+                    Ok(value) => value.is_true(),
+                    // 評価できない条件では止める
+                    Err(e) => {
+                        println!(
+                            "error in condition of breakpoint {} `{}`: {}",
+                            bp.num, text, e
+                        );
+                        true
+                    }
+                },
+            };
+            if hit {
+                hits.push(bp.num);
+            }
+        }
+
+        let mut stop = false;
+        let mut commands = Vec::new();
+        let mut dprintfs = Vec::new();
+        for num in hits {
+            let bp = match self.breakpoint_manager.find_mut(num) {
+                Some(bp) => bp,
+                None => continue,
+            };
+            bp.hit();
+            if bp.ignore_count > 0 {
+                bp.ignore_count -= 1;
+                continue;
+            }
+            if let Some(dprintf) = &bp.dprintf {
+                dprintfs.push((num, dprintf.clone()));
+                continue;
+            }
+            stop = true;
+            commands.extend(bp.commands.iter().cloned());
+            // 一時ブレークポイントは止まったら消す
+            if bp.temporary {
+                self.breakpoint_manager.delete(num)?;
+            }
+        }
+        // dprintfは表示して止まらない
+        let scope = self.scope();
+        for (num, dprintf) in dprintfs {
+            match dprintf.format(&scope) {
+                Ok(out) => print!("{out}"),
+                Err(e) => println!("error in dprintf {num}: {e}"),
+            }
+        }
+        if stop {
+            println!("breakpoint!");
+            // 前の停止で残ったコマンドは捨てる
+            self.pending_commands = commands.into();
+        }
+        Ok(stop)
+    }
+
+    /// report the watchpoint whose debug register trapped
+    /// returns false if the access is not the kind the watchpoint reports
+    pub fn hit_watchpoint(&mut self, num: usize) -> Result<bool, nix::Error> {
+        let pid = self.debug_info.target_pid();
+        let wp = match self.breakpoint_manager.find_watchpoint_mut(num) {
+            Some(wp) => wp,
+            None => return Ok(false),
+        };
+        let new = mem::read_bytes(pid, wp.addr, wp.len as usize)?;
+        let changed = new != wp.old;
+        let report = match wp.kind {
+            WatchKind::Write => changed,
+            // 書き込みでも発火するので値が変わっていれば読み込みではない
+            WatchKind::Read => !changed,
+            WatchKind::Access => true,
+        };
+        if !report {
+            return Ok(false);
+        }
+        wp.hit();
+        println!("{} {}: {}", wp.kind.name(), wp.num, wp.text);
+        if changed {
+            println!("old = {}", wp.format(pid, &wp.old));
+            println!("new = {}", wp.format(pid, &new));
+            // 大きな領域では値だけではどこが書き換わったか分かりにくい
+            if wp.uses_pages() {
+                let differs = |i: &usize| wp.old[*i] != new[*i];
+                let first = (0..new.len()).find(differs).unwrap_or(0);
+                let last = (0..new.len()).rfind(differs).unwrap_or(0);
+                println!(
+                    "changed bytes: 0x{:016x}-0x{:016x} (offset {}..{})",
+                    wp.addr + first as u64,
+                    wp.addr + last as u64 + 1,
+                    first,
+                    last + 1
+                );
+            }
+        } else {
+            println!("value = {}", wp.format(pid, &new));
+        }
+        wp.old = new;
+        Ok(true)
+    }
+
+    /// frame selected by `frame`, `up` and `down`
+    pub fn current_frame(&self) -> Option<Frame> {
+        unwind::backtrace(&self.debug_info)
+            .into_iter()
+            .nth(self.selected_frame)
+    }
+
+    /// remember the registers and the top of the stack at the stop
+    pub fn record_stop(&mut self, reason: StopReason) {
+        let pid = self.debug_info.target_pid();
+        let regs = get_regs(pid);
+        let stack = mem::read_bytes(pid, regs.rsp, STACK_SNAPSHOT_SIZE).unwrap_or_default();
+        self.stops.push(Stop {
+            reason,
+            regs,
+            stack,
+        });
+    }
+
+    pub fn handling(&self, signal: Signal) -> Handling {
+        self.signals
+            .get(&signal)
+            .copied()
+            .unwrap_or_else(|| Handling::of(signal))
+    }
+
+    /// the signal if it is passed to the target when it resumes
+    pub fn passed(&self, signal: Signal) -> Option<Signal> {
+        self.handling(signal).pass.then_some(signal)
+    }
+
+    pub fn policy(&self, kind: BufferKind) -> Policy {
+        match kind {
+            BufferKind::Stops => self.stops.policy(),
+            BufferKind::Syscalls => self.syscall_stack.buffer().policy(),
+            BufferKind::Bisect => self.bisect_policy,
+            BufferKind::Record => self.record_policy,
+        }
+    }
+
+    /// entries beyond the new limit are dropped now
+    pub fn set_policy(&mut self, kind: BufferKind, policy: Policy) {
+        match kind {
+            BufferKind::Stops => self.stops.set_policy(policy),
+            BufferKind::Syscalls => self.syscall_stack.buffer_mut().set_policy(policy),
+            BufferKind::Bisect => {
+                self.bisect_policy = policy;
+                if let Some(bisect) = &mut self.bisect {
+                    bisect.writes.set_policy(policy);
+                }
+            }
+            BufferKind::Record => {
+                self.record_policy = policy;
+                if let Some(record) = &mut self.record {
+                    record.steps.set_policy(policy);
+                }
+            }
+        }
+    }
+
+    /// make the thread current for the commands which use registers
+    pub fn select_thread(&mut self, tid: Pid) {
+        self.threads.set_current(tid);
+        self.debug_info.set_target_pid(tid);
+        self.breakpoint_manager.select(tid);
+        self.selected_frame = 0;
+    }
+
+    /// add the thread created by `clone` of `parent`, the new thread stays stopped
+    pub fn new_thread(&mut self, parent: Pid) -> Result<Pid, nix::Error> {
+        let tid = Pid::from_raw(ptrace::getevent(parent)? as i32);
+        // 新しいスレッドは最初にSIGSTOPで止まる
+        if !self.threads.take_early(tid) {
+            waitpid(tid, Some(WaitPidFlag::__WALL))?;
+        }
+        let num = self.threads.add(tid);
+        println!("[new thread {num} ({tid})]");
+        Ok(tid)
+    }
+
+    /// resume a thread, with PTRACE_SYSCALL if syscalls are caught
+    pub fn resume(&mut self, tid: Pid, signal: Option<Signal>) -> Result<(), nix::Error> {
+        if self.breakpoint_manager.catches_syscalls() {
+            mem::syscall(tid, signal)?;
+        } else {
+            mem::cont(tid, signal)?;
+        }
+        if let Some(thread) = self.threads.get_mut(tid) {
+            thread.state = ThreadState::Running;
+        }
+        Ok(())
+    }
+
+    /// resume the stopped threads other than the current one before the current one continues
+    /// nothing is resumed with `scheduler-locking on`
+    pub fn resume_others(&mut self) -> Result<(), nix::Error> {
+        if self.scheduler_locking == SchedulerLocking::On {
+            return Ok(());
+        }
+        let current = self.threads.current();
+        let options = self.breakpoint_manager.options();
+        for tid in self.threads.others() {
+            let Some(thread) = self.threads.get_mut(tid) else {
+                continue;
+            };
+            // 報告していない停止があるスレッドは止めたままにする
+            if thread.state != ThreadState::Stopped || thread.event.is_some() {
+                continue;
+            }
+            let signal = thread.pending.take();
+            let step_over = std::mem::take(&mut thread.step_over);
+            let signal = signal.and_then(|signal| self.passed(signal));
+            // デバッグレジスタとオプションはスレッドごとなので揃える
+            let resumed = debugreg::copy(current, tid)
+                .and_then(|_| ptrace::setoptions(tid, options))
+                .and_then(|_| match step_over {
+                    true => self.step_over_in(tid),
+                    false => Ok(true),
+                })
+                .and_then(|alive| match alive {
+                    true => self.resume(tid, signal),
+                    false => Ok(()),
+                });
+            match resumed {
+                // 終了はwaitで報告される
+                Err(Errno::ESRCH) => {}
+                resumed => resumed?,
+            }
+        }
+        Ok(())
+    }
+
+    /// step the thread over the breakpoint whose hit it already reported
+    /// returns false if the thread exited during the step
+    fn step_over_in(&mut self, tid: Pid) -> Result<bool, nix::Error> {
+        let current = self.threads.current();
+        self.breakpoint_manager.select(tid);
+        let stepped = self.breakpoint_manager.step_over();
+        self.breakpoint_manager.select(current);
+        match stepped? {
+            Some(WaitStatus::Stopped(_, signal)) => {
+                if let Some(thread) = self.threads.get_mut(tid) {
+                    thread.pending = thread.pending.or(Some(signal));
+                }
+                Ok(true)
+            }
+            Some(_) => {
+                self.threads.remove(tid);
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+
+    /// wait until a thread stops for the debugger and stop the other threads too
+    /// new threads and exits of threads are handled here and the target keeps running
+    pub fn wait_all(&mut self) -> Result<WaitStatus, nix::Error> {
+        loop {
+            let status = waitpid(None, Some(WaitPidFlag::__WALL))?;
+            let Some(tid) = status.pid() else {
+                return Ok(status);
+            };
+            if self.threads.get(tid).is_none() {
+                // cloneやforkのイベントより先に止まった新しいスレッドやプロセス
+                if let WaitStatus::Stopped(..) = status {
+                    self.threads.stopped_early(tid);
+                }
+                continue;
+            }
+            match status {
+                WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                    let new = self.new_thread(tid)?;
+                    let options = self.breakpoint_manager.options();
+                    debugreg::copy(tid, new)?;
+                    ptrace::setoptions(new, options)?;
+                    self.resume(new, None)?;
+                    self.resume(tid, None)?;
+                }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) if tid != self.threads.pid() => {
+                    if let Some(num) = self.threads.remove(tid) {
+                        println!("[thread {num} ({tid}) exited]");
+                    }
+                    if tid == self.threads.current() {
+                        self.select_thread(self.threads.pid());
+                    }
+                }
+                // プロセスの終了では他のスレッドはもういない
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => return Ok(status),
+                _ => {
+                    self.switch_to(tid);
+                    self.stop_others()?;
+                    return Ok(status);
+                }
+            }
+        }
+    }
+
+    /// make the thread which stopped current, the user is told when the stop is reported
+    fn switch_to(&mut self, tid: Pid) {
+        if tid != self.threads.current() {
+            self.select_thread(tid);
+        }
+    }
+
+    /// continue all threads and wait for the next stop
+    /// a stop queued by `stop_others` is reported first without resuming anything
+    pub fn continue_all(
+        &mut self,
+        tid: Pid,
+        signal: Option<Signal>,
+    ) -> Result<WaitStatus, nix::Error> {
+        if let Some(status) = self.take_event()? {
+            if let Some(thread) = self.threads.get_mut(tid) {
+                thread.pending = thread.pending.or(signal);
+            }
+            return Ok(status);
+        }
+        self.resume_others()?;
+        self.resume(tid, signal)?;
+        self.wait_all()
+    }
+
+    /// make the thread of a queued stop current and return the stop
+    fn take_event(&mut self) -> Result<Option<WaitStatus>, nix::Error> {
+        while let Some((tid, status, siginfo)) = self.threads.take_event() {
+            ptrace::setsiginfo(tid, &siginfo)?;
+            if siginfo.si_code == breakpoint::SI_KERNEL {
+                let mut regs = get_regs(tid);
+                // 報告する前に消されたブレークポイントのint3は元のバイトに戻っている
+                if mem::read_bytes(tid, regs.rip, 1)? != [0xcc] {
+                    continue;
+                }
+                // int3を実行した直後に戻して他のヒットと同じように扱う
+                regs.rip += 1;
+                ptrace::setregs(tid, regs)?;
+            }
+            self.switch_to(tid);
+            return Ok(Some(status));
+        }
+        Ok(None)
+    }
+
+    /// stop the running threads other than the current one
+    pub fn stop_others(&mut self) -> Result<(), nix::Error> {
+        // 読んでいる間も他のスレッドは書き換えていた
+        mem::clear_cache();
+        let current = self.threads.current();
+        if let Some(thread) = self.threads.get_mut(current) {
+            thread.state = ThreadState::Stopped;
+            thread.regs = thread::regs(current);
+        }
+        let pid = self.threads.pid();
+        for tid in self.threads.others() {
+            if self.threads.get(tid).map(|t| t.state) != Some(ThreadState::Running) {
+                continue;
+            }
+            // 既に終了しているときは止められない
+            if thread::interrupt(pid, tid).is_err() {
+                self.threads.remove(tid);
+                continue;
+            }
+            let mut pending = None;
+            let mut event = None;
+            let alive = loop {
+                match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+                    WaitStatus::Stopped(_, Signal::SIGSTOP) => break true,
+                    status @ WaitStatus::Stopped(_, Signal::SIGTRAP) => {
+                        // 同時に当たったブレークポイントは次のcontinueで1つずつ報告する
+                        let siginfo = ptrace::getsiginfo(tid)?;
+                        // 報告するまではブレークポイントのアドレスで止まっているように見せる
+                        if siginfo.si_code == breakpoint::SI_KERNEL {
+                            let mut regs = get_regs(tid);
+                            regs.rip -= 1;
+                            ptrace::setregs(tid, regs)?;
+                        }
+                        event = Some((status, siginfo));
+                    }
+                    WaitStatus::Stopped(_, signal) => pending = Some(signal),
+                    WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                        self.new_thread(tid)?;
+                    }
+                    WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_FORK)
+                    | WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_VFORK) => {
+                        // 現在のスレッド以外のforkは追わない
+                        let child = Pid::from_raw(ptrace::getevent(tid)? as i32);
+                        if !self.threads.take_early(child) {
+                            waitpid(child, Some(WaitPidFlag::__WALL))?;
+                        }
+                        let _ = self.breakpoint_manager.detach_child(child, false);
+                    }
+                    WaitStatus::Exited(..) | WaitStatus::Signaled(..) => break false,
+                    _ => {}
+                }
+                // 送ったSIGSTOPで止まるまで続ける
+                mem::cont(tid, None)?;
+            };
+            if !alive {
+                self.threads.remove(tid);
+                continue;
+            }
+            if let Some(thread) = self.threads.get_mut(tid) {
+                thread.state = ThreadState::Stopped;
+                thread.pending = thread.pending.or(pending);
+                thread.event = thread.event.or(event);
+                thread.regs = thread::regs(tid);
+            }
+        }
+        Ok(())
+    }
+
+    /// detach from the threads other than the current one, they keep running
+    pub fn detach_others(&mut self) {
+        for tid in self.threads.others() {
+            for slot in 0..debugreg::SLOTS {
+                let _ = debugreg::clear(tid, slot);
+            }
+            let signal = self.threads.get_mut(tid).and_then(|t| t.pending.take());
+            let _ = mem::detach(tid, signal);
+            self.threads.remove(tid);
+        }
+    }
+
+    /// line typed after `prompt` inside a command, taken from `--script` if it runs
+    /// returns None at the end of the input
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        print!("{prompt}");
+        if let Some(script) = &mut self.script {
+            let line = script.pop_front();
+            println!("{}", line.as_deref().unwrap_or_default());
+            return Ok(line);
+        }
+        io::stdout().flush()?;
+        let mut buf = String::new();
+        if io::stdin().lock().read_line(&mut buf)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(buf.trim().to_string()))
+    }
+
+    /// scope of the selected frame for expressions
+    pub fn scope(&self) -> Scope<'_> {
+        Scope {
+            debug_info: &self.debug_info,
+            frame: self.current_frame(),
+            level: self.selected_frame,
+            bindings: Vec::new(),
+        }
+    }
+
+    /// registers as of the selected frame
+    pub fn frame_regs(&self) -> user_regs_struct {
+        match self.current_frame() {
+            Some(frame) => frame.regs,
+            None => get_regs(self.debug_info.target_pid()),
+        }
+    }
+}
+
+/// process debugged after fork, the other one is detached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FollowForkMode {
+    #[default]
+    Parent,
+    Child,
+}
+
+impl std::fmt::Display for FollowForkMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FollowForkMode::Parent => write!(f, "parent"),
+            FollowForkMode::Child => write!(f, "child"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Display {
+    pub num: usize,
+    /// expression as typed by the user
+    pub text: String,
+    pub expr: Expr,
+}
+
+/// snapshot taken by `mark`
+#[derive(Debug, Clone)]
+pub struct Mark {
+    pub regs: user_regs_struct,
+    /// address and contents of the memory given to `mark`
+    pub memory: Vec<(u64, Vec<u8>)>,
+}
+
+/// bytes of the stack saved at each stop
+const STACK_SNAPSHOT_SIZE: usize = 256;
+
+/// stops kept by default for `history stops`
+pub const STOPS_LIMIT: usize = 1000;
+
+/// writes kept by default for `bisect-watch`
+pub const BISECT_LIMIT: usize = 10000;
+
+/// instructions kept by default for `record`
+pub const RECORD_LIMIT: usize = 20000;
+
+/// buffer which grows during a session, its limit is changed by `set limit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferKind {
+    Stops,
+    Syscalls,
+    Bisect,
+    Record,
+}
+
+impl BufferKind {
+    pub const ALL: [BufferKind; 4] = [
+        BufferKind::Stops,
+        BufferKind::Syscalls,
+        BufferKind::Bisect,
+        BufferKind::Record,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BufferKind::Stops => "stops",
+            BufferKind::Syscalls => "syscalls",
+            BufferKind::Bisect => "bisect",
+            BufferKind::Record => "record",
+        }
+    }
+}
+
+/// stop recorded for `history stops` and `goto-stop`
+#[derive(Debug, Clone)]
+pub struct Stop {
+    pub reason: StopReason,
+    pub regs: user_regs_struct,
+    /// memory from rsp
+    pub stack: Vec<u8>,
+}
+
+impl HeapSize for Stop {
+    fn heap_size(&self) -> usize {
+        self.stack.capacity()
+    }
 }
 
 #[derive(Debug)]
 pub enum WatchPoint {
     Memory(mem::Memory),
-    Register(register::Register),
+    /// register and its last value
+    Register(register::Register, u64),
+}
+
+impl fmt::Display for WatchPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memory(mem) => write!(f, "software watchpoint 0x{:016x}", mem.addr),
+            Self::Register(reg, _) => write!(f, "software watchpoint ${}", reg.name()),
+        }
+    }
 }
 
 impl WatchPoint {
-    fn get_value(&self) -> u64 {
+    pub fn get_value(&self) -> u64 {
         match *self {
             Self::Memory(m) => m.value,
-            Self::Register(r) => r.value,
+            Self::Register(_, value) => value,
         }
     }
 
@@ -52,9 +843,9 @@ impl WatchPoint {
                 mem.value = value;
                 old
             }
-            Self::Register(reg) => {
-                let old = reg.value;
-                reg.value = value;
+            Self::Register(_, last) => {
+                let old = *last;
+                *last = value;
                 old
             }
         }
@@ -66,17 +857,27 @@ impl WatchPoint {
                 let read = ptrace::read(pid, mem.addr as *mut c_void).unwrap() as u64;
                 mem.value != read
             }
-            Self::Register(reg) => {
-                let read = reg.reg_type.get_current_value(pid);
-                reg.value != read
+            Self::Register(reg, value) => {
+                let read = reg.get_current_value(pid);
+                value != read
             }
         }
     }
 
+    /// None if the memory can not be read
+    fn read_value(&self, pid: Pid) -> Option<u64> {
+        match *self {
+            Self::Memory(mem) => ptrace::read(pid, mem.addr as *mut c_void)
+                .ok()
+                .map(|value| value as u64),
+            Self::Register(reg, _) => Some(reg.get_current_value(pid)),
+        }
+    }
+
     fn fetch_new_value(&self, pid: Pid) -> u64 {
         match *self {
             Self::Memory(mem) => ptrace::read(pid, mem.addr as *mut c_void).unwrap() as u64,
-            Self::Register(reg) => reg.reg_type.get_current_value(pid),
+            Self::Register(reg, _) => reg.get_current_value(pid),
         }
     }
 }
@@ -119,16 +920,82 @@ pub fn virt2phys(pid: Pid, virt: u64) -> u64 {
     ((page & 0x007fffffffffffffu64) * page_size) + (virt % page_size)
 }
 
-pub fn debugger_main(child: Pid, filename: &str) {
+/// trace the forked target and wait until the target file is mapped
+fn start(
+    child: Pid,
+    filename: &str,
+    syscall_stack: &mut SyscallStack,
+) -> (TdbDebugInfo, WaitStatus) {
     if let Err(e) = ptrace::attach(child) {
         panic!("ptrace::attach failed, errno: {e}");
     }
 
-    // init
     crate::signal::init(child);
+    TdbDebugInfo::init(filename, child, syscall_stack)
+}
+
+pub fn debugger_main(
+    child: Pid,
+    filename: &str,
+    args: Vec<String>,
+    script: Option<VecDeque<String>>,
+) {
+    // init
     let mut syscall_stack = SyscallStack::new();
-    let breakpoint_manager = BreakpointManager::new(child);
-    let (debug_info, status) = TdbDebugInfo::init(filename, child, &mut syscall_stack);
+    let (debug_info, status) = start(child, filename, &mut syscall_stack);
+    let threads = Threads::new(child);
+    command_loop(debug_info, syscall_stack, threads, status, args, script);
+}
+
+/// debug the running process instead of forking the target
+pub fn attach_main(pid: Pid, script: Option<VecDeque<String>>) {
+    if let Err(e) = ptrace::attach(pid) {
+        panic!("ptrace::attach failed, errno: {e}");
+    }
+    // PTRACE_ATTACHはSIGSTOPで止める
+    if let Err(e) = waitpid(pid, None) {
+        panic!("waitpid failed, errno: {e}");
+    }
+    crate::signal::init(pid);
+    let filename = match std::fs::read_link(format!("/proc/{pid}/exe")) {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(e) => panic!("cannot read the executable of {pid}: {e}"),
+    };
+    let debug_info = match TdbDebugInfo::attach(&filename, pid) {
+        Ok(debug_info) => debug_info,
+        Err(e) => panic!("cannot read the mappings of {pid}: {e}"),
+    };
+    // 他のスレッドも1つずつアタッチする
+    let mut threads = Threads::new(pid);
+    for tid in thread::tasks(pid) {
+        if ptrace::attach(tid).is_ok() && waitpid(tid, Some(WaitPidFlag::__WALL)).is_ok() {
+            threads.add(tid);
+        }
+    }
+    println!("attached to {pid} ({filename})");
+    // 止めるのに使ったSIGSTOPは再開するときに渡さない
+    let status = WaitStatus::Stopped(pid, Signal::SIGTRAP);
+    command_loop(
+        debug_info,
+        SyscallStack::new(),
+        threads,
+        status,
+        Vec::new(),
+        script,
+    );
+}
+
+fn command_loop(
+    debug_info: TdbDebugInfo,
+    syscall_stack: SyscallStack,
+    threads: Threads,
+    status: WaitStatus,
+    args: Vec<String>,
+    script: Option<VecDeque<String>>,
+) {
+    let mut breakpoint_manager = BreakpointManager::new(debug_info.target_pid());
+    breakpoint_manager.set_scratch(debug_info.entry_address());
+    let labels = Labels::new(debug_info.filename());
     let mut debugger_info = DebuggerInfo {
         syscall_stack,
         breakpoint_manager,
@@ -137,62 +1004,137 @@ pub fn debugger_main(child: Pid, filename: &str) {
         watch_list: Vec::new(),
         prev_command: None,
         cont_flag: false,
+        selected_frame: 0,
+        display_list: Vec::new(),
+        next_display_num: 0,
+        marks: BTreeMap::new(),
+        pending_commands: VecDeque::new(),
+        stop_hook: Vec::new(),
+        skip: Vec::new(),
+        aliases: BTreeMap::new(),
+        stops: Bounded::new(Policy::new(STOPS_LIMIT)),
+        labels,
+        overlays: Overlays::default(),
+        args,
+        expected: None,
+        follow_fork_mode: FollowForkMode::default(),
+        started: Instant::now(),
+        bisect: None,
+        bisect_policy: Policy::new(BISECT_LIMIT),
+        record: None,
+        record_policy: Policy::new(RECORD_LIMIT),
+        threads,
+        scheduler_locking: SchedulerLocking::default(),
+        signals: HashMap::new(),
+        script,
+        checkpoints: Checkpoints::default(),
     };
+    if let Err(e) = debugger_info.breakpoint_manager.update_options() {
+        println!("cannot trace forks: {e}");
+    }
+    // 設定はスクリプトより先に適用する
+    debugger_info.pending_commands = config::commands().into();
 
     let mut status = status;
-    let mut additional_command = None;
+    let mut additional_command: Option<Command> = None;
+    // 内部で止まって再開したときの切り替えは表示しない
+    let mut shown_thread = debugger_info.threads.current();
     loop {
-        if let Some(command) = additional_command {
-            let exec_return = Command::exec(command, &mut debugger_info, status);
-            match exec_return {
-                Ok(exec_return) => {
-                    status = exec_return.0;
-                    additional_command = exec_return.1;
-                }
-                Err(e) => {
-                    println!("{:?}", e);
-                    exit(0);
+        let command = match additional_command.take() {
+            Some(command) => command,
+            None => {
+                shown_thread = debugger_info.threads.current();
+                let command = match debugger_info.pending_commands.pop_front() {
+                    Some(line) => {
+                        println!("> {line}");
+                        Command::parse(&mut debugger_info, &line)
+                    }
+                    None => match debugger_info.script.as_mut().map(VecDeque::pop_front) {
+                        Some(Some(line)) => {
+                            println!("> {line}");
+                            Command::parse(&mut debugger_info, &line)
+                        }
+                        // スクリプトの終わりでシグナルにより止まっていればシェルと同じく128+シグナル番号で終わる
+                        Some(None) => match status {
+                            WaitStatus::Stopped(pid, signal)
+                                if !matches!(signal, Signal::SIGTRAP | Signal::SIGSTOP) =>
+                            {
+                                debugger_info.kill();
+                                // 期待とはkillではなく止まったシグナルで比べる
+                                status = WaitStatus::Signaled(pid, signal, false);
+                                Ok(Command::Exit(128 + signal as i32))
+                            }
+                            _ => Ok(Command::Quit),
+                        },
+                        None => Command::read(&mut debugger_info),
+                    },
+                };
+                let command = match command {
+                    Ok(command) => command,
+                    Err(e) => {
+                        println!("{e}");
+                        continue;
+                    }
+                };
+                // 実行を再開したら残りのコマンドは実行しない
+                if command.resumes() {
+                    debugger_info.pending_commands.clear();
                 }
+                command
             }
-        } else {
-            let command = match Command::read(&mut debugger_info) {
-                Ok(command) => command,
-                Err(e) => {
-                    println!("{e}");
-                    continue;
-                }
-            };
+        };
 
-            let exec_return = Command::exec(command, &mut debugger_info, status);
-            match exec_return {
-                Ok(exec_return) => {
-                    status = exec_return.0;
-                    additional_command = exec_return.1;
+        let outcome = match Command::exec(command, &mut debugger_info, status) {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                eprintln!("{e}");
+                // スクリプトでは失敗として終わり、対話中は続ける
+                if debugger_info.script.is_some() {
+                    debugger_info.kill();
+                    fini(&debugger_info);
+                    exit(1);
+                }
+                ExecOutcome::NoChange
+            }
+        };
+        if let Some(new) = outcome.status() {
+            status = new;
+        }
+        match outcome {
+            ExecOutcome::Stopped { reason, .. } => {
+                let tid = debugger_info.threads.current();
+                if tid != shown_thread {
+                    if let Some(thread) = debugger_info.threads.get(tid) {
+                        println!("[switching to thread {} ({tid})]", thread.num);
+                    }
+                    shown_thread = tid;
                 }
-                Err(e) => {
-                    println!("{:?}", e);
-                    exit(0);
+                debugger_info.record_stop(reason);
+                dump::displays(&debugger_info);
+                for line in debugger_info.stop_hook.iter().rev() {
+                    debugger_info.pending_commands.push_front(line.clone());
                 }
             }
+            ExecOutcome::Continued { next, .. } => additional_command = Some(next),
+            ExecOutcome::Exited { code, .. } => additional_command = Some(Command::Exit(code)),
+            _ => {}
         }
-        // ウォッチポイントのチェック
-        check_watchpoints(&mut debugger_info, &mut additional_command);
     }
 }
 
-pub fn check_watchpoints(
-    debugger_info: &mut DebuggerInfo,
-    additional_command: &mut Option<Command>,
-) {
+/// report the software watchpoints whose values changed, returns true if any changed
+pub fn check_watchpoints(debugger_info: &mut DebuggerInfo) -> bool {
+    let pid = debugger_info.debug_info.target_pid();
+    let mut changed = false;
     for w in &mut debugger_info.watch_list {
-        if w.is_changed(debugger_info.debug_info.target_pid()) {
-            debugger_info.cont_flag = false;
-            *additional_command = None;
-            let new = w.fetch_new_value(debugger_info.debug_info.target_pid());
+        if w.is_changed(pid) {
+            changed = true;
+            let new = w.fetch_new_value(pid);
             let old = w.update_value(new);
-            println!("{:x?}: 0x{:x} -> 0x{:x}", w, old, w.get_value());
+            println!("{w}: 0x{:x} -> 0x{:x}", old, w.get_value());
         }
     }
+    changed
 }
 
 pub fn catch_syscall(pid: Pid, syscall_stack: &mut SyscallStack) {
@@ -211,7 +1153,7 @@ pub fn catch_syscall(pid: Pid, syscall_stack: &mut SyscallStack) {
         syscall_stack.push(syscall_info);
     }
 
-    if let Err(e) = ptrace::syscall(pid, None) {
+    if let Err(e) = mem::syscall(pid, None) {
         panic!("ptrace::syscall failed: errno = {:?}", e);
     }
 }
@@ -0,0 +1,106 @@
+use nix::{
+    libc::{c_void, user},
+    sys::ptrace,
+    unistd::Pid,
+};
+use std::mem;
+
+/// number of address registers DR0-DR3
+pub const SLOTS: usize = 4;
+
+const DR6: usize = 6;
+const DR7: usize = 7;
+
+/// access which triggers the debug register (R/W bits of DR7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Execute = 0b00,
+    Write = 0b01,
+    /// x86 cannot trigger on reads only
+    ReadWrite = 0b11,
+}
+
+fn read(pid: Pid, n: usize) -> Result<u64, nix::Error> {
+    let offset = mem::offset_of!(user, u_debugreg) + n * 8;
+    ptrace::read_user(pid, offset as *mut c_void).map(|value| value as u64)
+}
+
+fn write(pid: Pid, n: usize, value: u64) -> Result<(), nix::Error> {
+    let offset = mem::offset_of!(user, u_debugreg) + n * 8;
+    unsafe { ptrace::write_user(pid, offset as *mut c_void, value as *mut c_void) }
+}
+
+/// LEN bits of DR7, execute breakpoints must be 1 byte
+fn len_bits(len: u64) -> Option<u64> {
+    match len {
+        1 => Some(0b00),
+        2 => Some(0b01),
+        4 => Some(0b11),
+        8 => Some(0b10),
+        _ => None,
+    }
+}
+
+/// program the slot to trigger on `len` bytes at `addr` and enable it
+pub fn set(
+    pid: Pid,
+    slot: usize,
+    addr: u64,
+    trigger: Trigger,
+    len: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let len_bits = len_bits(len).ok_or(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "length should be 1, 2, 4 or 8",
+    ))?;
+    if !addr.is_multiple_of(len) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "address should be aligned to the length",
+        )));
+    }
+    write(pid, slot, addr)?;
+    let shift = 16 + slot * 4;
+    let mut dr7 = read(pid, DR7)?;
+    dr7 &= !(0b1111 << shift);
+    dr7 |= ((len_bits << 2) | trigger as u64) << shift;
+    dr7 |= 1 << (slot * 2);
+    write(pid, DR7, dr7)?;
+    Ok(())
+}
+
+/// turn the local enable bit of the slot on or off
+pub fn enable(pid: Pid, slot: usize, enabled: bool) -> Result<(), nix::Error> {
+    let mut dr7 = read(pid, DR7)?;
+    if enabled {
+        dr7 |= 1 << (slot * 2);
+    } else {
+        dr7 &= !(1 << (slot * 2));
+    }
+    write(pid, DR7, dr7)
+}
+
+pub fn clear(pid: Pid, slot: usize) -> Result<(), nix::Error> {
+    enable(pid, slot, false)?;
+    write(pid, slot, 0)
+}
+
+/// program the threads with the same slots, debug registers are per thread
+pub fn copy(from: Pid, to: Pid) -> Result<(), nix::Error> {
+    for n in (0..SLOTS).chain([DR7]) {
+        write(to, n, read(from, n)?)?;
+    }
+    Ok(())
+}
+
+/// slots which caused the last debug exception
+pub fn triggered(pid: Pid) -> Result<Vec<usize>, nix::Error> {
+    // カーネルは例外ごとにDR6をクリアして該当するビットだけを立てる
+    let dr6 = read(pid, DR6)?;
+    Ok((0..SLOTS).filter(|slot| dr6 & (1 << slot) != 0).collect())
+}
+
+/// forget the slots of the last debug exception once they are handled
+pub fn reset(pid: Pid) -> Result<(), nix::Error> {
+    write(pid, DR6, 0)
+}
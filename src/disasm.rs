@@ -0,0 +1,100 @@
+use crate::{breakpoint::BreakpointManager, mem};
+use iced_x86::{
+    Decoder, DecoderOptions, FlowControl, Formatter, Instruction, IntelFormatter, Mnemonic, OpKind,
+    Register,
+};
+use nix::unistd::Pid;
+
+const BITNESS: u32 = 64;
+
+/// decode instructions in [start, end) of the target memory
+/// bytes replaced by breakpoints are restored before decoding
+pub fn disassemble(
+    pid: Pid,
+    breakpoint_manager: &BreakpointManager,
+    start: u64,
+    end: u64,
+) -> Result<Vec<Instruction>, nix::Error> {
+    let mut bytes = mem::read_bytes(pid, start, (end - start) as usize)?;
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        if *byte != 0xcc {
+            continue;
+        }
+        let addr = start + i as u64;
+        if let Some(bp) = breakpoint_manager.at(addr).find(|bp| bp.slot.is_none()) {
+            *byte = bp.value;
+        }
+    }
+
+    let mut decoder = Decoder::with_ip(BITNESS, &bytes, start, DecoderOptions::NONE);
+    let mut instructions = Vec::new();
+    while decoder.can_decode() {
+        instructions.push(decoder.decode());
+    }
+    Ok(instructions)
+}
+
+/// intel syntax of the instruction
+pub fn format(instruction: &Instruction) -> String {
+    let mut formatter = IntelFormatter::new();
+    let mut output = String::new();
+    formatter.format(instruction, &mut output);
+    output
+}
+
+pub fn is_call(instruction: &Instruction) -> bool {
+    matches!(
+        instruction.flow_control(),
+        FlowControl::Call | FlowControl::IndirectCall
+    )
+}
+
+/// `rep movsb` and friends, single-stepping them executes only one iteration
+pub fn is_rep_string(instruction: &Instruction) -> bool {
+    instruction.is_string_instruction()
+        && (instruction.has_rep_prefix()
+            || instruction.has_repe_prefix()
+            || instruction.has_repne_prefix())
+}
+
+/// destination of the direct call, indirect calls return None
+pub fn call_target(instruction: &Instruction) -> Option<u64> {
+    match instruction.op0_kind() {
+        OpKind::NearBranch64 => Some(instruction.near_branch64()),
+        _ => None,
+    }
+}
+
+/// address after the usual prologue (endbr64, frame pointer, callee-saved pushes, stack allocation)
+/// returns None if the instructions do not start with a prologue
+pub fn skip_prologue(instructions: &[Instruction]) -> Option<u64> {
+    let is_prologue = |instruction: &Instruction| match instruction.mnemonic() {
+        Mnemonic::Endbr64 => true,
+        Mnemonic::Push => matches!(
+            instruction.op0_register(),
+            Register::RBP
+                | Register::RBX
+                | Register::R12
+                | Register::R13
+                | Register::R14
+                | Register::R15
+        ),
+        Mnemonic::Mov => {
+            instruction.op0_register() == Register::RBP
+                && instruction.op1_kind() == OpKind::Register
+                && instruction.op1_register() == Register::RSP
+        }
+        Mnemonic::Sub => {
+            instruction.op0_register() == Register::RSP
+                && instruction.op1_kind() != OpKind::Register
+        }
+        _ => false,
+    };
+    let skipped = instructions.iter().take_while(|i| is_prologue(i)).count();
+    if skipped == 0 {
+        return None;
+    }
+    instructions
+        .get(skipped)
+        .map(|instruction| instruction.ip())
+}
@@ -0,0 +1,93 @@
+use crate::{mem, syscall::get_regs};
+use iced_x86::{Encoder, FlowControl, Instruction, Mnemonic};
+use nix::{
+    sys::{
+        ptrace,
+        wait::{waitpid, WaitStatus},
+    },
+    unistd::Pid,
+};
+
+const BITNESS: u32 = 64;
+
+/// encode a copy of the instruction which behaves the same at `scratch`
+/// None if it can not be moved, for example its rel8 or rel32 does not reach from there
+pub fn relocate(instruction: &Instruction, scratch: u64) -> Option<Vec<u8>> {
+    match instruction.flow_control() {
+        // 割り込みや例外は元のアドレスで起きないと困る
+        FlowControl::Interrupt | FlowControl::Exception | FlowControl::XbeginXabortXend => {
+            return None
+        }
+        _ => {}
+    }
+    // clone/forkの子はスクラッチ領域から実行を始めてしまう
+    if matches!(
+        instruction.mnemonic(),
+        Mnemonic::Syscall | Mnemonic::Sysenter
+    ) {
+        return None;
+    }
+    let mut copy = *instruction;
+    // rel8は離れた場所から届かないのでrel32にする
+    copy.as_near_branch();
+    let mut encoder = Encoder::new(BITNESS);
+    encoder.encode(&copy, scratch).ok()?;
+    let code = encoder.take_buffer();
+    // 元の命令に重なると書き込んだ時点で壊れる
+    let (start, end) = (instruction.ip(), instruction.next_ip());
+    if scratch < end && start < scratch + code.len() as u64 {
+        return None;
+    }
+    Some(code)
+}
+
+/// execute a copy of the instruction at rip from `scratch` instead of the original address
+/// the int3 at rip stays, so other threads running meanwhile still hit the breakpoint
+/// returns None without executing anything if the instruction can not be moved
+pub fn step(
+    pid: Pid,
+    instruction: &Instruction,
+    scratch: u64,
+) -> Result<Option<WaitStatus>, nix::Error> {
+    let Some(code) = relocate(instruction, scratch) else {
+        return Ok(None);
+    };
+    let saved = mem::read_bytes(pid, scratch, code.len())?;
+    mem::write_bytes(pid, scratch, &code)?;
+    let mut regs = get_regs(pid);
+    regs.rip = scratch;
+    ptrace::setregs(pid, regs)?;
+    mem::step(pid, None)?;
+    let status = waitpid(pid, None)?;
+    // 終了していたら書き戻せない
+    if !matches!(status, WaitStatus::Stopped(..)) {
+        return Ok(Some(status));
+    }
+    mem::write_bytes(pid, scratch, &saved)?;
+    fixup(pid, instruction, scratch, scratch + code.len() as u64)?;
+    Ok(Some(status))
+}
+
+/// move the addresses the copy left in rip and the stack back to the original instruction
+fn fixup(pid: Pid, instruction: &Instruction, scratch: u64, end: u64) -> Result<(), nix::Error> {
+    let mut regs = get_regs(pid);
+    if regs.rip == end {
+        regs.rip = instruction.next_ip();
+    } else if regs.rip == scratch {
+        // 例外で実行されなかったときは元の命令で止まったことにする
+        regs.rip = instruction.ip();
+    }
+    ptrace::setregs(pid, regs)?;
+    // callが積んだ戻り先はコピーの次を指している
+    if matches!(
+        instruction.flow_control(),
+        FlowControl::Call | FlowControl::IndirectCall
+    ) && regs.rip != instruction.ip()
+    {
+        let ret = mem::read_bytes(pid, regs.rsp, 8)?;
+        if u64::from_le_bytes(ret.try_into().unwrap()) == end {
+            mem::write_bytes(pid, regs.rsp, &instruction.next_ip().to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use crate::{debug_info, debugger::DebuggerInfo, syscall::get_regs};
+use nix::{
+    sys::{
+        personality::Persona,
+        uio::{process_vm_readv, RemoteIoVec},
+    },
+    unistd::Pid,
+};
+use object::Object;
+use std::{fs, io::IoSliceMut};
+
+/// result of one check
+enum Check {
+    Ok(String),
+    /// problem and how to fix it
+    Warn(String, String),
+}
+
+/// check the environment and the target, and print how to fix the problems
+pub fn diagnose(debugger_info: &DebuggerInfo) {
+    let pid = debugger_info.debug_info.target_pid();
+    let checks = [
+        ("ptrace_scope", ptrace_scope()),
+        ("perf_event_paranoid", perf_event_paranoid()),
+        ("process_vm_readv", vm_readv(pid)),
+        ("debug info", debug_info()),
+        ("ASLR", aslr(pid)),
+    ];
+    let mut problems = 0;
+    for (name, check) in checks {
+        match check {
+            Check::Ok(detail) => println!("ok    {name}: {detail}"),
+            Check::Warn(detail, fix) => {
+                problems += 1;
+                println!("warn  {name}: {detail}");
+                println!("      fix: {fix}");
+            }
+        }
+    }
+    match problems {
+        0 => println!("no problems found"),
+        n => println!("{n} problem(s) found"),
+    }
+}
+
+fn read_sysctl(path: &str) -> Option<i32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn ptrace_scope() -> Check {
+    // Yamaがなければ制限はない
+    match read_sysctl("/proc/sys/kernel/yama/ptrace_scope") {
+        None => Check::Ok(String::from("Yama is not enabled")),
+        Some(0) => Check::Ok(String::from("0 (any process of the same user)")),
+        Some(1) => Check::Ok(String::from(
+            "1 (descendants only, enough for targets launched by tdb)",
+        )),
+        Some(2) => Check::Warn(
+            String::from("2 (only root can trace)"),
+            String::from("run tdb as root or `sudo sysctl kernel.yama.ptrace_scope=1`"),
+        ),
+        Some(n) => Check::Warn(
+            format!("{n} (ptrace is disabled until reboot)"),
+            String::from("reboot and do not set kernel.yama.ptrace_scope to 3"),
+        ),
+    }
+}
+
+fn perf_event_paranoid() -> Check {
+    match read_sysctl("/proc/sys/kernel/perf_event_paranoid") {
+        None => Check::Ok(String::from("perf events are not available")),
+        Some(n) if n <= 2 => Check::Ok(format!("{n}")),
+        Some(n) => Check::Warn(
+            format!("{n} (perf events are disabled for users)"),
+            String::from("`sudo sysctl kernel.perf_event_paranoid=2`"),
+        ),
+    }
+}
+
+fn vm_readv(pid: Pid) -> Check {
+    let rip = get_regs(pid).rip;
+    let mut buf = [0u8; 8];
+    let remote = [RemoteIoVec {
+        base: rip as usize,
+        len: buf.len(),
+    }];
+    match process_vm_readv(pid, &mut [IoSliceMut::new(&mut buf)], &remote) {
+        Ok(_) => Check::Ok(String::from("available")),
+        Err(e) => Check::Warn(
+            format!("not available ({e}), memory is read one word at a time"),
+            String::from("allow ptrace of the target (see ptrace_scope) or use a newer kernel"),
+        ),
+    }
+}
+
+fn debug_info() -> Check {
+    let has_units =
+        debug_info::dwarf().is_some_and(|dwarf| matches!(dwarf.units().next(), Ok(Some(_))));
+    if has_units {
+        return Check::Ok(String::from("DWARF found"));
+    }
+    let fix = String::from("rebuild the target with `-g` (C) or `debug = true` (Cargo)");
+    // 分離されたデバッグ情報は読めない
+    if debug_info::object()
+        .section_by_name(".gnu_debuglink")
+        .is_some()
+    {
+        return Check::Warn(
+            String::from("stripped, debug info is in a separate file"),
+            fix,
+        );
+    }
+    Check::Warn(String::from("no DWARF, only symbols can be used"), fix)
+}
+
+fn aslr(pid: Pid) -> Check {
+    let personality = fs::read_to_string(format!("/proc/{pid}/personality"))
+        .ok()
+        .and_then(|p| i32::from_str_radix(p.trim(), 16).ok());
+    match personality {
+        Some(p) if p & Persona::ADDR_NO_RANDOMIZE.bits() != 0 => {
+            Check::Ok(String::from("disabled for the target"))
+        }
+        _ if read_sysctl("/proc/sys/kernel/randomize_va_space") == Some(0) => {
+            Check::Ok(String::from("disabled system-wide"))
+        }
+        _ => Check::Warn(
+            String::from("enabled, addresses change between runs"),
+            String::from("run tdb where personality(2) is allowed, e.g. `--security-opt seccomp=unconfined` in docker"),
+        ),
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{
+    expr::{self, Expr, Scope, Value},
+    variable::{read_c_string, Type},
+};
+use std::io::{Error, ErrorKind};
+
+/// format and arguments printed by `dprintf` breakpoints
+#[derive(Debug, Clone)]
+pub struct Dprintf {
+    /// `"fmt", args...` as typed
+    pub text: String,
+    format: String,
+    args: Vec<Expr>,
+}
+
+/// one `%` conversion of the format
+#[derive(Debug, Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    alt: bool,
+    width: usize,
+    precision: Option<usize>,
+    /// number of bits the integer conversions use
+    bits: u32,
+}
+
+fn error(msg: impl Into<String>) -> Box<dyn std::error::Error> {
+    Box::new(Error::new(ErrorKind::InvalidInput, msg.into()))
+}
+
+/// split `<loc>, "fmt", args...` into the location and the rest
+pub fn split_location(input: &str) -> Result<(&str, &str), Box<dyn std::error::Error>> {
+    match input.split_once(',') {
+        Some((loc, rest)) if !loc.trim().is_empty() => Ok((loc.trim(), rest.trim())),
+        _ => Err(error("usage: dprintf <location>, \"format\", args...")),
+    }
+}
+
+/// parse `"fmt", args...`
+pub fn parse(input: &str) -> Result<Dprintf, Box<dyn std::error::Error>> {
+    let rest = input
+        .strip_prefix('"')
+        .ok_or_else(|| error("format string must start with '\"'"))?;
+    let mut format = String::new();
+    let mut chars = rest.char_indices();
+    let end = loop {
+        match chars.next() {
+            Some((i, '"')) => break i,
+            Some((_, '\\')) => match chars.next() {
+                Some((_, 'n')) => format.push('\n'),
+                Some((_, 't')) => format.push('\t'),
+                Some((_, c @ ('"' | '\\'))) => format.push(c),
+                Some((_, c)) => return Err(error(format!("unknown escape sequence \\{c}"))),
+                None => return Err(error("unterminated format string")),
+            },
+            Some((_, c)) => format.push(c),
+            None => return Err(error("unterminated format string")),
+        }
+    };
+    let rest = rest[end + 1..].trim();
+    let args = if rest.is_empty() {
+        Vec::new()
+    } else {
+        let rest = rest
+            .strip_prefix(',')
+            .ok_or_else(|| error("expected ',' after the format string"))?;
+        split_args(rest)
+            .iter()
+            .map(|arg| expr::parse(arg))
+            .collect::<Result<Vec<Expr>, _>>()?
+    };
+    let dprintf = Dprintf {
+        text: input.to_string(),
+        format,
+        args,
+    };
+    // 引数の数が合わないものは設定時に弾く
+    let conversions = dprintf.conversions()?;
+    if conversions != dprintf.args.len() {
+        return Err(error(format!(
+            "format needs {} arguments but {} given",
+            conversions,
+            dprintf.args.len()
+        )));
+    }
+    Ok(dprintf)
+}
+
+/// split the arguments at the commas outside of brackets
+pub fn split_args(input: &str) -> Vec<&str> {
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(input[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(input[start..].trim());
+    args
+}
+
+impl Dprintf {
+    /// number of conversions which consume an argument
+    fn conversions(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut chars = self.format.chars().peekable();
+        let mut count = 0;
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                continue;
+            }
+            let (_, conversion) = parse_spec(&mut chars)?;
+            if conversion != '%' {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// evaluate the arguments in the scope and format them
+    pub fn format(&self, scope: &Scope) -> Result<String, Box<dyn std::error::Error>> {
+        let mut out = String::new();
+        let mut args = self.args.iter();
+        let mut chars = self.format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            let (spec, conversion) = parse_spec(&mut chars)?;
+            if conversion == '%' {
+                out.push('%');
+                continue;
+            }
+            let value = expr::eval(
+                args.next().ok_or_else(|| error("too few arguments"))?,
+                scope,
+            )?;
+            out.push_str(&convert(scope, &spec, conversion, &value)?);
+        }
+        Ok(out)
+    }
+}
+
+/// parse flags, width, precision, length and conversion after `%`
+fn parse_spec(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+) -> Result<(Spec, char), Box<dyn std::error::Error>> {
+    let mut spec = Spec {
+        bits: 32,
+        ..Default::default()
+    };
+    while let Some(&c) = chars.peek() {
+        match c {
+            '-' => spec.left = true,
+            '0' => spec.zero = true,
+            '+' => spec.plus = true,
+            '#' => spec.alt = true,
+            _ => break,
+        }
+        chars.next();
+    }
+    while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+        spec.width = spec.width * 10 + d as usize;
+        chars.next();
+    }
+    if chars.peek() == Some(&'.') {
+        chars.next();
+        let mut precision = 0;
+        while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+            precision = precision * 10 + d as usize;
+            chars.next();
+        }
+        spec.precision = Some(precision);
+    }
+    while let Some(&c) = chars.peek() {
+        spec.bits = match c {
+            'h' if spec.bits == 16 => 8,
+            'h' => 16,
+            'l' | 'z' | 'j' | 't' => 64,
+            _ => break,
+        };
+        chars.next();
+    }
+    match chars.next() {
+        Some(c @ ('d' | 'i' | 'u' | 'x' | 'X' | 'o' | 'c' | 's' | 'p' | 'f' | 'e' | 'g' | '%')) => {
+            Ok((spec, c))
+        }
+        Some(c) => Err(error(format!("unsupported conversion %{c}"))),
+        None => Err(error("incomplete conversion at the end of the format")),
+    }
+}
+
+fn convert(
+    scope: &Scope,
+    spec: &Spec,
+    conversion: char,
+    value: &Value,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // Cと同じく長さ修飾子の幅に切り詰める
+    let mask = if spec.bits == 64 {
+        u64::MAX
+    } else {
+        (1u64 << spec.bits) - 1
+    };
+    let unsigned = value.as_u64() & mask;
+    let signed = {
+        let shift = 64 - spec.bits;
+        ((unsigned << shift) as i64) >> shift
+    };
+    let body = match conversion {
+        'd' | 'i' if spec.plus && signed >= 0 => format!("+{signed}"),
+        'd' | 'i' => signed.to_string(),
+        'u' => unsigned.to_string(),
+        'x' if spec.alt => format!("{unsigned:#x}"),
+        'x' => format!("{unsigned:x}"),
+        'X' if spec.alt => format!("0X{unsigned:X}"),
+        'X' => format!("{unsigned:X}"),
+        'o' if spec.alt => format!("0{unsigned:o}"),
+        'o' => format!("{unsigned:o}"),
+        'c' => (value.as_u64() as u8 as char).to_string(),
+        'p' => format!("0x{:x}", value.as_u64()),
+        's' => {
+            let s = match value.ty.canonical() {
+                Type::Array { .. } => {
+                    let len = value.bytes.iter().position(|b| *b == 0);
+                    String::from_utf8_lossy(&value.bytes[..len.unwrap_or(value.bytes.len())])
+                        .to_string()
+                }
+                _ => read_c_string(scope.debug_info.target_pid(), value.as_u64()).ok_or_else(
+                    || {
+                        error(format!(
+                            "cannot access memory at address 0x{:x}",
+                            value.as_u64()
+                        ))
+                    },
+                )?,
+            };
+            match spec.precision {
+                Some(precision) => s.chars().take(precision).collect(),
+                None => s,
+            }
+        }
+        'f' => format!("{:.*}", spec.precision.unwrap_or(6), value.as_f64()),
+        'e' => format!("{:.*e}", spec.precision.unwrap_or(6), value.as_f64()),
+        _ => value.as_f64().to_string(),
+    };
+    let pad = spec.width.saturating_sub(body.chars().count());
+    let numeric = !matches!(conversion, 'c' | 's');
+    Ok(if spec.left {
+        format!("{body}{}", " ".repeat(pad))
+    } else if spec.zero && numeric {
+        // 符号の後ろを0で埋める
+        let sign = usize::from(body.starts_with(['-', '+']));
+        format!("{}{}{}", &body[..sign], "0".repeat(pad), &body[sign..])
+    } else {
+        format!("{}{body}", " ".repeat(pad))
+    })
+}
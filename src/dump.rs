@@ -1,18 +1,36 @@
 #![allow(unused)]
 use crate::{
+    breakpoint::{Breakpoint, WatchKind, Watchpoint},
     debug_info::{Symbol, TdbDebugInfo, TdbMapRangeTrait},
-    debugger::DebuggerInfo,
+    debugger::{BufferKind, DebuggerInfo, Display},
+    disasm, expr, mem,
+    register::{Register, RegisterType},
+    sharedlib::{self, Library},
+    signal::Cancel,
+    symbolize::{self, demangle, module_path, split_path, symbolize, ExternalSymbol},
     syscall::get_regs,
+    thread,
+    unwind::{self, Frame, FrameKind},
+    util::glob_match,
+    variable::{self, Type, Variable},
+};
+use iced_x86::Instruction;
+use nix::{
+    libc::{c_void, user_regs_struct},
+    sys::{ptrace, signal::Signal},
+    unistd::Pid,
 };
-use nix::{libc::c_void, sys::ptrace, unistd::Pid};
 use object::{Endian, SymbolKind};
 use proc_maps::get_process_maps;
-use std::{borrow::Borrow, path::Path};
+use std::{borrow::Borrow, collections::BTreeMap, fmt::Write, fs, io, path::Path, time::Instant};
 use symbolic::{
     common::Name,
-    demangle::{demangle, Demangle, DemangleOptions},
+    demangle::{Demangle, DemangleOptions},
 };
 
+/// frames larger than this are flagged by `info frame --size` by default
+pub const LARGE_FRAME: u64 = 4096;
+
 pub fn memory_map(pid: Pid) {
     let maps = get_process_maps(pid.as_raw()).unwrap();
     for map in maps {
@@ -34,17 +52,22 @@ pub fn memory_map(pid: Pid) {
 
 const LONG_SIZE: u64 = 8;
 
-pub fn memory(debug_info: &TdbDebugInfo, addr: u64, len: u64) {
-    let num = if (len % LONG_SIZE) == 0 {
+pub fn memory(debugger_info: &DebuggerInfo, addr: u64, len: u64) {
+    let debug_info = &debugger_info.debug_info;
+    let num = if len.is_multiple_of(LONG_SIZE) {
         len / LONG_SIZE
     } else {
         len / LONG_SIZE + 1
     };
+    let cancel = Cancel::start();
     'outer: for i in 0..num {
+        if cancel.is_cancelled() {
+            break;
+        }
         let actual_addr = addr + i * LONG_SIZE;
         for map in debug_info.mmaps() {
             if map.is_included(actual_addr) {
-                memory_inner(debug_info, actual_addr);
+                memory_inner(debugger_info, actual_addr);
                 continue 'outer;
             }
         }
@@ -53,17 +76,23 @@ pub fn memory(debug_info: &TdbDebugInfo, addr: u64, len: u64) {
     }
 }
 
-fn memory_inner(debug_info: &TdbDebugInfo, actual_addr: u64) {
+fn memory_inner(debugger_info: &DebuggerInfo, actual_addr: u64) {
     print!("0x{:016x}  ", actual_addr);
 
-    let memdump = ptrace::read(debug_info.target_pid(), actual_addr as *mut c_void).unwrap();
-    let memvec = memdump.to_le_bytes();
+    let pid = debugger_info.debug_info.target_pid();
+    let memdump = mem::read_bytes(pid, actual_addr, LONG_SIZE as usize).unwrap();
+    let label = debugger_info.labels.lookup_range(actual_addr, LONG_SIZE);
+    memory_row(&memdump, label);
+}
+
+/// print 8 bytes in hex and ascii after the address, and the label of the address
+fn memory_row(memvec: &[u8], label: Option<String>) {
     for m in memvec {
         print!("{:02x} ", m);
     }
 
     print!(" |");
-    for m in memvec {
+    for &m in memvec {
         let c = if m.is_ascii() && (0x20 < m) && (m < 0x7f) {
             m
         } else {
@@ -72,12 +101,14 @@ fn memory_inner(debug_info: &TdbDebugInfo, actual_addr: u64) {
         print!("{}", c as char);
     }
     print!("|");
+    if let Some(label) = label {
+        print!(" <{}>", label);
+    }
 
     println!();
 }
 
-pub fn register(pid: Pid) {
-    let regs = ptrace::getregs(pid).unwrap();
+pub fn register(regs: &user_regs_struct) {
     println!("r15: 0x{:016x?}", regs.r15);
     println!("r14: 0x{:016x?}", regs.r14);
     println!("r13: 0x{:016x?}", regs.r13);
@@ -101,46 +132,443 @@ pub fn register(pid: Pid) {
     println!("fs : 0x{:016x?}", regs.fs);
     println!("gs : 0x{:016x?}", regs.gs);
     println!("ss : 0x{:016x?}", regs.ss);
-    println!("fs_base: 0x{:016x?}", regs.fs);
-    println!("gs_base: 0x{:016x?}", regs.gs);
+    println!("fs_base: 0x{:016x?}", regs.fs_base);
+    println!("gs_base: 0x{:016x?}", regs.gs_base);
     println!("orig_rax: 0x{:016x?}", regs.orig_rax);
     println!("eflags: 0x{:016x?}", regs.eflags);
 }
 
+pub fn marks(debugger_info: &DebuggerInfo) {
+    if debugger_info.marks.is_empty() {
+        println!("no marks");
+    }
+    for (name, mark) in &debugger_info.marks {
+        print!("{name}: rip = 0x{:016x}", mark.regs.rip);
+        for (addr, bytes) in &mark.memory {
+            print!(", 0x{addr:016x} ({} bytes)", bytes.len());
+        }
+        println!();
+    }
+}
+
+/// write all registers to `path` as a JSON object of hex strings
+pub fn save_registers(regs: &user_regs_struct, path: &Path) -> io::Result<()> {
+    let fields: Vec<String> = RegisterType::ALL
+        .iter()
+        .map(|reg_type| {
+            format!(
+                "  \"{}\": \"0x{:016x}\"",
+                reg_type.name(),
+                reg_type.get_value(regs)
+            )
+        })
+        .collect();
+    let mut text = String::new();
+    let _ = writeln!(text, "{{\n{}\n}}", fields.join(",\n"));
+    fs::write(path, text)
+}
+
+/// overwrite `regs` with the registers in the JSON object written by `save_registers`
+/// sub-registers like `eax` and decimal numbers are also accepted, missing registers are kept
+pub fn load_registers(
+    regs: &mut user_regs_struct,
+    path: &Path,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path)?;
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let body = text
+        .trim()
+        .strip_prefix('{')
+        .and_then(|body| body.strip_suffix('}'))
+        .ok_or_else(|| invalid(String::from("not a JSON object")))?;
+    let mut count = 0;
+    for field in body.split(',').filter(|field| !field.trim().is_empty()) {
+        let (name, value) = field
+            .split_once(':')
+            .ok_or_else(|| invalid(format!("invalid field: {}", field.trim())))?;
+        let name = name.trim().trim_matches('"');
+        let reg = Register::parse(&format!("${name}"))?;
+        let value = value.trim().trim_matches('"');
+        let value = match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => value.parse::<u64>(),
+        }
+        .map_err(|_| invalid(format!("invalid value of {name}: {value}")))?;
+        reg.set_value(regs, value);
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// print registers and memory which differ between two marks
+pub fn diff_marks(debugger_info: &DebuggerInfo, a: &str, b: &str) {
+    let (mark_a, mark_b) = match (debugger_info.marks.get(a), debugger_info.marks.get(b)) {
+        (Some(mark_a), Some(mark_b)) => (mark_a, mark_b),
+        (None, _) => return println!("no mark {a}"),
+        (_, None) => return println!("no mark {b}"),
+    };
+    let mut same = true;
+    for reg_type in RegisterType::ALL {
+        let old = reg_type.get_value(&mark_a.regs);
+        let new = reg_type.get_value(&mark_b.regs);
+        if old != new {
+            println!("{:<8} 0x{old:016x} -> 0x{new:016x}", reg_type.name());
+            same = false;
+        }
+    }
+    for (addr, old) in &mark_a.memory {
+        let new = match mark_b
+            .memory
+            .iter()
+            .find(|(other, bytes)| other == addr && bytes.len() == old.len())
+        {
+            Some((_, new)) => new,
+            None => {
+                println!("0x{addr:016x} ({} bytes) is only in {a}", old.len());
+                same = false;
+                continue;
+            }
+        };
+        // xと同じく8バイトごとに比べる
+        for (i, (old, new)) in old.chunks(8).zip(new.chunks(8)).enumerate() {
+            if old != new {
+                println!(
+                    "0x{:016x}  {} -> {}",
+                    addr + i as u64 * 8,
+                    hex_bytes(old),
+                    hex_bytes(new)
+                );
+                same = false;
+            }
+        }
+    }
+    for (addr, new) in &mark_b.memory {
+        if !mark_a
+            .memory
+            .iter()
+            .any(|(other, bytes)| other == addr && bytes.len() == new.len())
+        {
+            println!("0x{addr:016x} ({} bytes) is only in {b}", new.len());
+            same = false;
+        }
+    }
+    if same {
+        println!("no differences between {a} and {b}");
+    }
+}
+
+/// bytes per row of `dump diff`
+const DIFF_ROW: usize = 8;
+
+/// print the changed byte ranges of two memory dumps side by side
+/// offsets are shown as addresses from `base` with the symbols there
+pub fn diff_files(
+    debugger_info: &DebuggerInfo,
+    a: &Path,
+    b: &Path,
+    base: Option<u64>,
+) -> io::Result<()> {
+    let read = |path: &Path| {
+        fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+    };
+    let old = read(a)?;
+    let new = read(b)?;
+    println!("--- {} ({} bytes)", a.display(), old.len());
+    println!("+++ {} ({} bytes)", b.display(), new.len());
+    let changed: Vec<usize> = (0..old.len().min(new.len()))
+        .filter(|i| old[*i] != new[*i])
+        .collect();
+    // 1行に収まるくらい近い変更はまとめる
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for i in changed {
+        match ranges.last_mut() {
+            Some((_, end)) if i < *end + DIFF_ROW => *end = i + 1,
+            _ => ranges.push((i, i + 1)),
+        }
+    }
+    for (start, end) in &ranges {
+        let count = (*start..*end).filter(|i| old[*i] != new[*i]).count();
+        let at = match base {
+            Some(base) => diff_address(debugger_info, base + *start as u64),
+            None => format!("+0x{start:x}"),
+        };
+        println!("@@ {at} ({count} bytes changed)");
+        let first = start - start % DIFF_ROW;
+        for row in (first..*end).step_by(DIFF_ROW) {
+            let row_end = (row + DIFF_ROW).min(old.len()).min(new.len());
+            let offset = match base {
+                Some(base) => format!("0x{:016x}", base + row as u64),
+                None => format!("+0x{row:06x}"),
+            };
+            let width = DIFF_ROW * 3 - 1;
+            println!(
+                "{offset}  {:<width$} | {}",
+                hex_bytes(&old[row..row_end]),
+                hex_bytes(&new[row..row_end])
+            );
+            let marks: String = (row..row_end)
+                .map(|i| if old[i] != new[i] { "^^ " } else { "   " })
+                .collect();
+            println!(
+                "{:indent$}{}",
+                "",
+                marks.trim_end(),
+                indent = offset.len() + 2
+            );
+        }
+    }
+    if old.len() != new.len() {
+        let (longer, len) = if old.len() > new.len() {
+            (a, old.len())
+        } else {
+            (b, new.len())
+        };
+        let from = old.len().min(new.len());
+        let at = match base {
+            Some(base) => diff_address(debugger_info, base + from as u64),
+            None => format!("+0x{from:x}"),
+        };
+        println!(
+            "{} bytes from {at} are only in {}",
+            len - from,
+            longer.display()
+        );
+    }
+    if ranges.is_empty() && old.len() == new.len() {
+        println!("no differences");
+    }
+    Ok(())
+}
+
+/// address with the label or the symbol which contains it
+fn diff_address(debugger_info: &DebuggerInfo, addr: u64) -> String {
+    if debugger_info.labels.lookup(addr).is_some() {
+        return address(debugger_info, addr);
+    }
+    let debug_info = &debugger_info.debug_info;
+    match debug_info.find_function_in(addr) {
+        Some(sym) => {
+            let start = debug_info.get_actual_symbol_address(sym).unwrap_or(addr);
+            match addr - start {
+                0 => format!("0x{addr:016x} <{}>", sym.name()),
+                offset => format!("0x{addr:016x} <{}+{offset}>", sym.name()),
+            }
+        }
+        None => format!("0x{addr:016x}"),
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn stops(debugger_info: &DebuggerInfo) {
+    if debugger_info.stops.is_empty() {
+        println!("no stops");
+    }
+    for (n, stop) in debugger_info.stops.iter() {
+        println!(
+            "#{:<3} {} {} ({})",
+            n,
+            address(debugger_info, stop.regs.rip),
+            location(&debugger_info.debug_info, stop.regs.rip),
+            stop.reason
+        );
+    }
+}
+
+/// print the registers and the stack recorded at the n-th stop
+pub fn stop(debugger_info: &DebuggerInfo, n: usize) {
+    let stop = match debugger_info.stops.get(n) {
+        Some(stop) => stop,
+        None => return println!("no stop #{n}"),
+    };
+    println!(
+        "stop #{} at 0x{:016x} {} ({})",
+        n,
+        stop.regs.rip,
+        location(&debugger_info.debug_info, stop.regs.rip),
+        stop.reason
+    );
+    register(&stop.regs);
+    println!("[stack]");
+    for (i, row) in stop.stack.chunks(LONG_SIZE as usize).enumerate() {
+        let addr = stop.regs.rsp + i as u64 * LONG_SIZE;
+        print!("0x{:016x}  ", addr);
+        memory_row(row, debugger_info.labels.lookup_range(addr, LONG_SIZE));
+    }
+}
+
+/// `0x0000000000001000 <label+0x10>` or just the address if it is not labeled
+/// address and intel syntax of the instruction
+pub fn instruction(debugger_info: &DebuggerInfo, instruction: &Instruction) {
+    println!(
+        "{}: {}",
+        address(debugger_info, instruction.ip()),
+        disasm::format(instruction)
+    );
+}
+
+fn address(debugger_info: &DebuggerInfo, addr: u64) -> String {
+    match debugger_info.labels.lookup(addr) {
+        Some(label) => format!("0x{:016x} <{}>", addr, label),
+        None => format!("0x{:016x}", addr),
+    }
+}
+
+pub fn structs(debugger_info: &DebuggerInfo) {
+    let mut empty = true;
+    for ty in debugger_info.overlays.iter() {
+        if let Type::Struct { name, members, .. } = ty {
+            println!("struct {} ({} bytes)", name, ty.size());
+            for member in members {
+                println!(
+                    "    +0x{:<4x} {}: {}",
+                    member.offset,
+                    member.name,
+                    member.ty.name()
+                );
+            }
+        }
+        empty = false;
+    }
+    if empty {
+        println!("no structs");
+    }
+}
+
+pub fn labels(debugger_info: &DebuggerInfo) {
+    let mut empty = true;
+    for label in debugger_info.labels.iter() {
+        println!(
+            "0x{:016x}-0x{:016x} {:<6} {}",
+            label.addr,
+            label.addr + label.len,
+            label.len,
+            label.name
+        );
+        empty = false;
+    }
+    if empty {
+        println!("no labels");
+    }
+}
+
 pub fn all_symbols(debugger_info: &DebuggerInfo) {
     println!("[functions]");
-    functions(debugger_info);
+    functions(debugger_info, None);
     println!();
     println!("[variables]");
     variables(debugger_info);
 }
 
-pub fn functions(debugger_info: &DebuggerInfo) {
-    let base_addr = debugger_info.debug_info.base_addr();
-    let exec_map = debugger_info.debug_info.exec_maps().unwrap()[0]; // とりあえずコードセグメントが1つだけのバイナリに対応
-    let base_diff = exec_map.start() as u64 - base_addr;
+/// print functions, `pattern` filters demangled names (e.g. `mycrate::net::*`)
+pub fn functions(debugger_info: &DebuggerInfo, pattern: Option<&str>) {
+    let load_bias = debugger_info.debug_info.load_bias();
 
     let mut f_vec = Vec::new();
     for f in debugger_info.debug_info.symbols() {
-        let exec_map_offset = exec_map.offset as u64;
-        let addr = if f.address() >= exec_map_offset {
-            exec_map.start() as u64 + (f.address() - exec_map_offset)
+        // セグメントのアドレスとファイルオフセットが一致しないバイナリもあるのでload biasで計算する
+        let addr = if f.address() != 0 {
+            load_bias + f.address()
         } else {
-            f.address()
+            0
         };
         if f.kind() == SymbolKind::Text {
+            if let Some(pattern) = pattern {
+                if !glob_match(pattern, &demangle(f.name())) {
+                    continue;
+                }
+            }
             f_vec.push((addr, f.name()));
         }
     }
 
+    let cancel = Cancel::start();
     for f in f_vec {
-        let name = Name::from(f.1);
-        let name = name.try_demangle(DemangleOptions::name_only());
+        if cancel.is_cancelled() {
+            break;
+        }
+        let name = symbolize(&debugger_info.debug_info, f.1, f.0);
 
         println!("0x{:016x}: {}", f.0, name);
     }
 }
 
+#[derive(Debug, Default)]
+struct ModuleNode {
+    children: BTreeMap<String, ModuleNode>,
+    functions: usize,
+}
+
+impl ModuleNode {
+    fn total_functions(&self) -> usize {
+        self.functions
+            + self
+                .children
+                .values()
+                .map(|child| child.total_functions())
+                .sum::<usize>()
+    }
+
+    fn print(&self, name: &str, depth: usize) {
+        println!(
+            "{}{} ({} functions)",
+            "  ".repeat(depth),
+            name,
+            self.total_functions()
+        );
+        for (child_name, child) in &self.children {
+            child.print(child_name, depth + 1);
+        }
+    }
+}
+
+/// print crate/module hierarchy of functions, `root` limits the tree to the module
+pub fn modules_tree(debugger_info: &DebuggerInfo, root: Option<&str>) {
+    let root_path = root
+        .map(|root| {
+            split_path(root.trim_end_matches("::*"))
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+
+    let mut tree = ModuleNode::default();
+    for f in debugger_info.debug_info.symbols() {
+        if f.kind() != SymbolKind::Text || f.address() == 0 {
+            continue;
+        }
+        let (path, _name) = module_path(&demangle(f.name()));
+        if !path.starts_with(&root_path) {
+            continue;
+        }
+        let mut node = &mut tree;
+        for segment in &path[root_path.len()..] {
+            node = node.children.entry(segment.clone()).or_default();
+        }
+        node.functions += 1;
+    }
+
+    if root_path.is_empty() {
+        for (name, child) in &tree.children {
+            child.print(name, 0);
+        }
+        if tree.functions != 0 {
+            println!("(no module) ({} functions)", tree.functions);
+        }
+    } else if tree.total_functions() == 0 {
+        println!("module not found");
+    } else {
+        tree.print(&root_path.join("::"), 0);
+    }
+}
+
 pub fn variables(debugger_info: &DebuggerInfo) {
     let base_addr = debugger_info.debug_info.base_addr();
     let rodata_maps = debugger_info.debug_info.rodata_maps().unwrap();
@@ -192,37 +620,600 @@ pub fn misc_symbols(debug_info: &TdbDebugInfo) {
     }
 }
 
-pub fn backtrace(debug_info: &TdbDebugInfo) {
-    let regs = get_regs(debug_info.target_pid());
-    let rbp = regs.rbp;
-    let rip = regs.rip;
-    if let Some(f) = debug_info.find_function_in(rip) {
-        println!("0x{:016x} in {}(top)", rip, f.name());
-        backtrace_inner(debug_info, rbp).unwrap();
+pub fn backtrace(debugger_info: &DebuggerInfo) {
+    let frames = unwind::backtrace(&debugger_info.debug_info);
+    for (level, f) in frames.iter().enumerate() {
+        let mark = if level == debugger_info.selected_frame {
+            "*"
+        } else {
+            " "
+        };
+        print!("{}", mark);
+        frame(debugger_info, level, f);
     }
 }
 
-fn backtrace_inner(debug_info: &TdbDebugInfo, rbp: u64) -> Result<(), Box<dyn std::error::Error>> {
-    let prev_frame_addr = ptrace::read(debug_info.target_pid(), rbp as *mut c_void)? as u64;
-    let return_addr = ptrace::read(debug_info.target_pid(), (rbp + 8) as *mut c_void)? as u64;
-    if let Some(f) = debug_info.find_function_in(return_addr) {
-        // このコードブロックは、関数突入直後のスタックフレームが構築される前でも関数を表示したいために入れている
-        {
-            let rsp = get_regs(debug_info.target_pid()).rsp;
-            let tmp_frame_addr = ptrace::read(debug_info.target_pid(), rsp as *mut c_void)? as u64;
-            if let Some(f) = debug_info.find_function_in(tmp_frame_addr) {
-                println!("0x{:016x} in {}", tmp_frame_addr, f.name());
+pub fn frame(debugger_info: &DebuggerInfo, level: usize, frame: &Frame) {
+    let debug_info = &debugger_info.debug_info;
+    let pc = frame.pc();
+    if frame.kind == FrameKind::Signal {
+        return println!("#{:<2} 0x{:016x} <signal handler called>", level, pc);
+    }
+    let addr = address(debugger_info, pc);
+    let name = match debug_info.find_function_in(pc) {
+        Some(f) => symbolize::function_name(f.name()),
+        None => match symbolize::external(debug_info.target_pid(), pc) {
+            Some(symbol) => {
+                return match symbol.line {
+                    Some(line) => {
+                        println!("#{:<2} {} in {} at {}", level, addr, symbol.function, line)
+                    }
+                    None => println!("#{:<2} {} in {}", level, addr, symbol.function),
+                };
             }
+            None => String::from("??"),
+        },
+    };
+    // 呼び出し元のフレームではcall命令の行を表示する
+    let lookup_pc = frame.lookup_pc(level);
+    let line = debug_info
+        .to_file_addr(lookup_pc)
+        .and_then(|file_addr| debug_info.find_line(file_addr));
+    match line {
+        Some(line) => println!("#{:<2} {} in {} at {}", level, addr, name, line),
+        None => println!("#{:<2} {} in {}", level, addr, name),
+    }
+}
+
+/// print how the selected frame was unwound
+pub fn frame_info(debugger_info: &DebuggerInfo) {
+    let debug_info = &debugger_info.debug_info;
+    let level = debugger_info.selected_frame;
+    let frames = unwind::backtrace(debug_info);
+    let f = match frames.get(level) {
+        Some(f) => f,
+        None => return println!("no frame selected"),
+    };
+    println!("Stack level {}, frame at 0x{:016x}:", level, f.cfa);
+    frame(debugger_info, level, f);
+    let (base, offset) = f.cfa_rule;
+    println!(" cfa = {} + {}", base.name(), offset);
+    let kind = match f.kind {
+        FrameKind::Cfi => "CFI (.eh_frame)",
+        FrameKind::FramePointer => "frame pointer (rbp chain)",
+        FrameKind::Signal => "signal trampoline (ucontext)",
+        FrameKind::Vsyscall => "vsyscall",
+    };
+    println!(" frame type: {}", kind);
+    if let Some(outer) = frames.get(level + 1) {
+        println!(" called by frame at 0x{:016x}", outer.cfa);
+    }
+    if let Some(inner) = level.checked_sub(1).and_then(|inner| frames.get(inner)) {
+        println!(" caller of frame at 0x{:016x}", inner.cfa);
+    }
+    // 呼び出し元のフレームではcall命令で探す
+    let lookup_pc = f.lookup_pc(level);
+    match debug_info
+        .to_file_addr(lookup_pc)
+        .and_then(|file_addr| debug_info.find_unit(file_addr))
+    {
+        Some((name, Some(producer))) => println!(" compilation unit: {} ({})", name, producer),
+        Some((name, None)) => println!(" compilation unit: {}", name),
+        None => println!(" compilation unit: unknown"),
+    }
+    match f
+        .saved
+        .iter()
+        .find(|(reg, _)| matches!(reg, RegisterType::Rip))
+    {
+        Some((_, slot)) => println!(" return address at 0x{:016x}", slot),
+        None => println!(" return address: unknown"),
+    }
+    let saved: Vec<String> = f
+        .saved
+        .iter()
+        .map(|(reg, slot)| format!("{} at 0x{:016x}", reg.name(), slot))
+        .collect();
+    if saved.is_empty() {
+        println!(" no saved registers");
+    } else {
+        println!(" saved registers:");
+        println!("  {}", saved.join(", "));
+    }
+}
+
+/// print the stack usage of each frame, the distance from its rsp to its CFA
+/// space allocated by alloca or VLAs is included since rsp is taken as of the frame
+pub fn frame_sizes(debugger_info: &DebuggerInfo, large: u64) {
+    let frames = unwind::backtrace(&debugger_info.debug_info);
+    if frames.is_empty() {
+        return println!("no frames");
+    }
+    let mut total = 0;
+    let mut flagged = 0;
+    for (level, f) in frames.iter().enumerate() {
+        let size = f.cfa.saturating_sub(f.regs.rsp);
+        total += size;
+        let mark = if size > large {
+            flagged += 1;
+            "!"
+        } else {
+            " "
+        };
+        print!("{}{:>8} ", mark, size);
+        frame(debugger_info, level, f);
+    }
+    println!("total {} bytes in {} frames", total, frames.len());
+    if flagged > 0 {
+        println!("! {} frames larger than {} bytes", flagged, large);
+    }
+}
+
+/// print local variables of the selected frame
+pub fn locals(debugger_info: &DebuggerInfo) {
+    frame_variables(debugger_info, variable::locals, "no locals");
+}
+
+/// print arguments of the selected frame
+pub fn args(debugger_info: &DebuggerInfo) {
+    frame_variables(debugger_info, variable::args, "no arguments");
+}
+
+fn frame_variables(
+    debugger_info: &DebuggerInfo,
+    collect: fn(&TdbDebugInfo, &Frame, usize) -> Vec<Variable>,
+    empty_message: &str,
+) {
+    let frame = match debugger_info.current_frame() {
+        Some(frame) => frame,
+        None => {
+            println!("no frame selected");
+            return;
         }
+    };
+    let vars = collect(
+        &debugger_info.debug_info,
+        &frame,
+        debugger_info.selected_frame,
+    );
+    if vars.is_empty() {
+        println!("{}", empty_message);
+    }
+    for var in &vars {
+        self::variable(debugger_info.debug_info.target_pid(), &frame, var);
+    }
+}
 
-        println!("0x{:016x} in {}", return_addr, f.name());
-        backtrace_inner(debug_info, prev_frame_addr)?;
+fn variable(pid: Pid, frame: &Frame, var: &Variable) {
+    match var.read(pid, Some(frame)) {
+        Ok(bytes) => println!(
+            "{} = {}",
+            var.name,
+            variable::format_value(pid, &var.ty, &bytes)
+        ),
+        Err(e) => println!("{} = {}", var.name, e),
+    }
+}
+
+/// print all auto-display expressions
+pub fn displays(debugger_info: &DebuggerInfo) {
+    for d in &debugger_info.display_list {
+        display(debugger_info, d);
+    }
+}
+
+pub fn display(debugger_info: &DebuggerInfo, d: &Display) {
+    let scope = debugger_info.scope();
+    match expr::eval(&d.expr, &scope) {
+        Ok(value) => println!("{}: {} = {}", d.num, d.text, value.format(&scope)),
+        Err(e) => println!("{}: {} = <error: {}>", d.num, d.text, e),
+    }
+}
+
+pub fn display_list(debugger_info: &DebuggerInfo) {
+    if debugger_info.display_list.is_empty() {
+        println!("there are no auto-display expressions now");
+        return;
+    }
+    println!("Num Expression");
+    for d in &debugger_info.display_list {
+        println!("{:<3} {}", d.num, d.text);
+    }
+}
+
+pub fn breakpoints(debugger_info: &DebuggerInfo) {
+    let breakpoints = debugger_info.breakpoint_manager.breakpoints();
+    let watchpoints = debugger_info.breakpoint_manager.watchpoints();
+    let catchpoints = debugger_info.breakpoint_manager.catchpoints();
+    if breakpoints.is_empty() && watchpoints.is_empty() && catchpoints.is_empty() {
+        println!("no breakpoints, watchpoints or catchpoints");
+        return;
+    }
+    println!("Num Type       Enb Address            Hits Ignore What");
+    for bp in breakpoints {
+        let kind = breakpoint_kind(bp);
+        println!(
+            "{:<3} {:<10} {:<3} 0x{:016x} {:<4} {:<6} {}",
+            bp.num,
+            kind,
+            enabled_mark(bp.enabled),
+            bp.addr,
+            bp.hit_count,
+            bp.ignore_count,
+            location(&debugger_info.debug_info, bp.addr)
+        );
+        if let Some((text, _)) = &bp.condition {
+            println!("        stop only if {text}");
+        }
+        if let Some(dprintf) = &bp.dprintf {
+            println!("        printf {}", dprintf.text);
+        }
+        for command in &bp.commands {
+            println!("        {command}");
+        }
+    }
+    for wp in watchpoints {
+        let kind = watchpoint_kind(wp);
+        let how = if wp.uses_pages() {
+            ", page protection"
+        } else {
+            ""
+        };
+        println!(
+            "{:<3} {:<10} {:<3} 0x{:016x} {:<4} {:<6} {} ({} bytes{})",
+            wp.num,
+            kind,
+            enabled_mark(wp.enabled),
+            wp.addr,
+            wp.hit_count,
+            0,
+            wp.text,
+            wp.len,
+            how
+        );
+    }
+    for cp in catchpoints {
+        println!(
+            "{:<3} {:<10} {:<3} {:<18} {:<4} {:<6} {}",
+            cp.num,
+            "catchpoint",
+            enabled_mark(cp.enabled),
+            "",
+            cp.hit_count,
+            0,
+            cp.kind
+        );
+        if let Some((text, _)) = &cp.condition {
+            println!("        stop only if {text}");
+        }
+    }
+}
+
+/// breakpoints and watchpoints ordered by their hits, times are from the start of the target
+pub fn hit_counts(debugger_info: &DebuggerInfo) {
+    let manager = &debugger_info.breakpoint_manager;
+    let mut rows = Vec::new();
+    for bp in manager.breakpoints() {
+        let what = location(&debugger_info.debug_info, bp.addr);
+        rows.push((
+            bp.num,
+            breakpoint_kind(bp),
+            bp.hit_count,
+            bp.first_hit,
+            bp.last_hit,
+            what,
+        ));
+    }
+    for wp in manager.watchpoints() {
+        let what = wp.text.clone();
+        rows.push((
+            wp.num,
+            watchpoint_kind(wp),
+            wp.hit_count,
+            wp.first_hit,
+            wp.last_hit,
+            what,
+        ));
+    }
+    if rows.is_empty() {
+        println!("no breakpoints or watchpoints");
+        return;
+    }
+    rows.sort_by_key(|row| (std::cmp::Reverse(row.2), row.0));
+    let elapsed = |time: Option<Instant>| match time {
+        Some(time) => format!("+{:.3}s", (time - debugger_info.started).as_secs_f64()),
+        None => String::from("-"),
+    };
+    println!("Num Type       Hits  First      Last       What");
+    for (num, kind, hits, first, last, what) in rows {
+        println!(
+            "{:<3} {:<10} {:<5} {:<10} {:<10} {}",
+            num,
+            kind,
+            hits,
+            elapsed(first),
+            elapsed(last),
+            what
+        );
+    }
+}
+
+/// objects loaded into the target in load order
+/// `--deps` shows which object needs which, objects already shown are not expanded again
+pub fn shared_libraries(debugger_info: &DebuggerInfo, deps: bool) {
+    let pid = debugger_info.debug_info.target_pid();
+    let libraries = match sharedlib::loaded(pid, &debugger_info.debug_info) {
+        Ok(libraries) => libraries,
+        Err(e) => return println!("{e}"),
+    };
+    if deps {
+        if let Some(root) = libraries.first() {
+            let mut shown = Vec::new();
+            print_needed(&libraries, root, 0, &mut shown);
+        }
+        return;
+    }
+    println!("From               To                 Syms DWARF    Build ID                                 Path");
+    for library in &libraries {
+        let build_id = match &library.build_id {
+            Some(id) => id.iter().map(|byte| format!("{byte:02x}")).collect(),
+            None => String::from("-"),
+        };
+        println!(
+            "0x{:016x} 0x{:016x} {:<4} {:<8} {:<40} {}",
+            library.base,
+            library.base + library.size,
+            if library.symbols { "yes" } else { "no" },
+            library.dwarf.to_string(),
+            build_id,
+            library.path
+        );
+    }
+}
+
+fn print_needed<'a>(
+    libraries: &'a [Library],
+    library: &'a Library,
+    depth: usize,
+    shown: &mut Vec<&'a str>,
+) {
+    let expanded = shown.contains(&library.path.as_str());
+    println!(
+        "{}{}{}",
+        "  ".repeat(depth),
+        library.path,
+        if expanded && !library.needed.is_empty() {
+            " ..."
+        } else {
+            ""
+        }
+    );
+    if expanded {
+        return;
+    }
+    shown.push(&library.path);
+    for name in &library.needed {
+        match libraries.iter().find(|other| other.name() == name) {
+            Some(needed) => print_needed(libraries, needed, depth + 1, shown),
+            None => println!("{}{} (not loaded)", "  ".repeat(depth + 1), name),
+        }
+    }
+}
+
+/// traced threads, `*` marks the current one
+pub fn threads(debugger_info: &DebuggerInfo) {
+    let threads = &debugger_info.threads;
+    let pid = threads.pid();
+    println!("  Num TID      Name             State        Where");
+    for t in threads.iter() {
+        let mark = if t.tid == threads.current() { "*" } else { " " };
+        let name = thread::name(pid, t.tid).unwrap_or_else(|| String::from("??"));
+        let state = thread::kernel_state(pid, t.tid).unwrap_or("??");
+        let place = match thread::regs(t.tid).or(t.regs) {
+            Some(regs) => format!(
+                "0x{:016x} {}",
+                regs.rip,
+                location(&debugger_info.debug_info, regs.rip)
+            ),
+            None => String::from("??"),
+        };
+        println!(
+            "{mark} {:<3} {:<8} {:<16} {:<12} {}",
+            t.num,
+            t.tid.to_string(),
+            name,
+            state,
+            place
+        );
+    }
+}
+
+/// how tdb handles the signals
+pub fn signals(debugger_info: &DebuggerInfo, signals: &[Signal]) {
+    let yes_no = |b: bool| if b { "Yes" } else { "No" };
+    println!("Signal     Stop  Print Pass");
+    for signal in signals {
+        let handling = debugger_info.handling(*signal);
+        println!(
+            "{:<10} {:<5} {:<5} {}",
+            signal.as_str(),
+            yes_no(handling.stop),
+            yes_no(handling.print),
+            yes_no(handling.pass)
+        );
+    }
+}
+
+/// checkpoints with the location where they were made
+pub fn checkpoints(debugger_info: &DebuggerInfo) {
+    let debug_info = &debugger_info.debug_info;
+    let mut empty = true;
+    for checkpoint in debugger_info.checkpoints.iter() {
+        empty = false;
+        let pc = checkpoint.pc;
+        let name = match debug_info.find_function_in(pc) {
+            Some(f) => symbolize::function_name(f.name()),
+            None => String::from("??"),
+        };
+        let line = debug_info
+            .to_file_addr(pc)
+            .and_then(|file_addr| debug_info.find_line(file_addr));
+        let location = match line {
+            Some(line) => format!("{} in {} at {}", address(debugger_info, pc), name, line),
+            None => format!("{} in {}", address(debugger_info, pc), name),
+        };
+        println!(
+            "{:<3} process {:<8} {}",
+            checkpoint.num, checkpoint.pid, location
+        );
+    }
+    if empty {
+        println!("no checkpoints");
+    }
+}
+
+/// functions which `step` does not enter
+pub fn skips(debugger_info: &DebuggerInfo) {
+    if debugger_info.skip.is_empty() {
+        println!("no skips");
+    }
+    for (i, name) in debugger_info.skip.iter().enumerate() {
+        println!("{}\t{name}", i + 1);
+    }
+}
+
+/// thread and range of the history of `record`
+pub fn record(debugger_info: &DebuggerInfo) {
+    let Some(record) = &debugger_info.record else {
+        println!("not recording");
+        return;
+    };
+    let steps = &record.steps;
+    println!("recording thread {}", record.tid);
+    println!(
+        "{} instructions recorded, limit {}, {} dropped",
+        steps.len(),
+        steps.policy().limit,
+        steps.dropped()
+    );
+    if let Some((_, first)) = steps.iter().next() {
+        println!("oldest: {}", address(debugger_info, first.regs.rip));
+    }
+    println!("memory: {}", human_size(steps.memory_usage()));
+}
+
+/// entries, limits and memory of the buffers which grow during a session
+pub fn buffers(debugger_info: &DebuggerInfo) {
+    println!("Buffer   Entries  Limit    Eviction Dropped  Memory");
+    for kind in BufferKind::ALL {
+        let (len, dropped, memory) = match kind {
+            BufferKind::Stops => {
+                let stops = &debugger_info.stops;
+                (stops.len(), stops.dropped(), stops.memory_usage())
+            }
+            BufferKind::Syscalls => {
+                let syscalls = debugger_info.syscall_stack.buffer();
+                (syscalls.len(), syscalls.dropped(), syscalls.memory_usage())
+            }
+            BufferKind::Bisect => match &debugger_info.bisect {
+                Some(bisect) => (
+                    bisect.writes.len(),
+                    bisect.writes.dropped(),
+                    bisect.writes.memory_usage(),
+                ),
+                None => (0, 0, 0),
+            },
+            BufferKind::Record => match &debugger_info.record {
+                Some(record) => (
+                    record.steps.len(),
+                    record.steps.dropped(),
+                    record.steps.memory_usage(),
+                ),
+                None => (0, 0, 0),
+            },
+        };
+        let policy = debugger_info.policy(kind);
+        println!(
+            "{:<8} {:<8} {:<8} {:<8} {:<8} {}",
+            kind.name(),
+            len,
+            policy.limit,
+            policy.eviction.to_string(),
+            dropped,
+            human_size(memory)
+        );
+    }
+}
+
+fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+fn breakpoint_kind(bp: &Breakpoint) -> &'static str {
+    if bp.dprintf.is_some() {
+        "dprintf"
+    } else if bp.temporary {
+        "tbreak"
+    } else if bp.slot.is_some() {
+        "hbreak"
+    } else {
+        "breakpoint"
+    }
+}
+
+fn watchpoint_kind(wp: &Watchpoint) -> &'static str {
+    match wp.kind {
+        WatchKind::Write => "watch",
+        WatchKind::Read => "rwatch",
+        WatchKind::Access => "awatch",
+    }
+}
+
+fn enabled_mark(enabled: bool) -> &'static str {
+    if enabled {
+        "y"
+    } else {
+        "n"
+    }
+}
+
+/// `in func at file:line` of the address
+pub fn location(debug_info: &TdbDebugInfo, addr: u64) -> String {
+    let name = match debug_info.find_function_in(addr) {
+        Some(f) => symbolize::function_name(f.name()),
+        None => {
+            return match symbolize::external(debug_info.target_pid(), addr) {
+                Some(ExternalSymbol {
+                    function,
+                    line: Some(line),
+                }) => format!("in {function} at {line}"),
+                Some(symbol) => format!("in {}", symbol.function),
+                None => String::from("??"),
+            }
+        }
+    };
+    let line = debug_info
+        .to_file_addr(addr)
+        .and_then(|file_addr| debug_info.find_line(file_addr));
+    match line {
+        Some(line) => format!("in {} at {}", name, line),
+        None => format!("in {}", name),
     }
-    Ok(())
 }
 
 pub fn watchpoints(debugger_info: &DebuggerInfo) {
     for w in &debugger_info.watch_list {
-        println!("{:016x?}", w);
+        println!("{w} = 0x{:x}", w.get_value());
     }
 }
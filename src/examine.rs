@@ -0,0 +1,194 @@
+use crate::{
+    debugger::DebuggerInfo,
+    disasm, dump,
+    expr::{self, Expr},
+    mem,
+    step::MAX_INSTRUCTION_LEN,
+    variable::Type,
+};
+use std::{ascii, io};
+
+/// longest string printed by `x/s`
+const MAX_STRING_LEN: usize = 200;
+
+/// how `x` prints each unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Hex,
+    Decimal,
+    Unsigned,
+    Char,
+    /// NUL-terminated string, the size is ignored
+    String,
+    /// instruction, the size is ignored
+    Instruction,
+}
+
+/// `/NFU` of `x`, count, format and unit size in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Examine {
+    pub count: usize,
+    pub format: Format,
+    pub size: u64,
+}
+
+impl Default for Examine {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            format: Format::Hex,
+            size: 4,
+        }
+    }
+}
+
+/// parse `NFU` after `x/`, the count comes first and the letters are in any order
+pub fn parse(spec: &str) -> Result<Examine, Box<dyn std::error::Error>> {
+    let invalid = || {
+        Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "usage: x/NFU <expr>, F is x|d|u|c|s|i and U is b|h|w|g",
+        ))
+    };
+    let digits = spec.chars().take_while(char::is_ascii_digit).count();
+    let mut examine = Examine::default();
+    if digits > 0 {
+        examine.count = spec[..digits].parse()?;
+    }
+    for c in spec[digits..].chars() {
+        match c {
+            'x' => examine.format = Format::Hex,
+            'd' => examine.format = Format::Decimal,
+            'u' => examine.format = Format::Unsigned,
+            'c' => {
+                examine.format = Format::Char;
+                examine.size = 1;
+            }
+            's' => examine.format = Format::String,
+            'i' => examine.format = Format::Instruction,
+            'b' => examine.size = 1,
+            'h' => examine.size = 2,
+            'w' => examine.size = 4,
+            'g' => examine.size = 8,
+            _ => return Err(invalid()),
+        }
+    }
+    if examine.count == 0 {
+        return Err(invalid());
+    }
+    Ok(examine)
+}
+
+/// address given to `x`, an array is examined where it is
+pub fn address(
+    debugger_info: &DebuggerInfo,
+    expr: &Expr,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let value = expr::eval(expr, &debugger_info.scope())?;
+    match (value.ty.canonical(), value.addr()) {
+        (Type::Array { .. }, Some(addr)) => Ok(addr),
+        _ => Ok(value.as_u64()),
+    }
+}
+
+/// print the memory at `addr` in the format
+pub fn examine(
+    debugger_info: &DebuggerInfo,
+    examine: &Examine,
+    addr: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match examine.format {
+        Format::String => strings(debugger_info, examine.count, addr),
+        Format::Instruction => instructions(debugger_info, examine.count, addr),
+        _ => units(debugger_info, examine, addr),
+    }
+}
+
+fn units(
+    debugger_info: &DebuggerInfo,
+    examine: &Examine,
+    addr: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let size = examine.size as usize;
+    let len = examine.count as u64 * examine.size;
+    mem::check_mapping(pid, addr, len, mem::Access::Data)?;
+    let bytes = mem::read_bytes(pid, addr, len as usize)?;
+    // 1行に並べる数はgdbと同じ
+    let per_line = match (examine.format, size) {
+        (Format::Char, _) | (_, 1 | 2) => 8,
+        (_, 4) => 4,
+        _ => 2,
+    };
+    for (line, chunk) in bytes.chunks(size * per_line).enumerate() {
+        print!("0x{:016x}:", addr + (line * size * per_line) as u64);
+        for unit in chunk.chunks(size) {
+            let mut raw = [0; 8];
+            raw[..size].copy_from_slice(unit);
+            let value = u64::from_le_bytes(raw);
+            // 符号拡張のために上位に寄せてから算術シフトで戻す
+            let shift = 64 - examine.size * 8;
+            let signed = ((value << shift) as i64) >> shift;
+            match examine.format {
+                Format::Hex => print!("\t0x{:0width$x}", value, width = size * 2),
+                Format::Decimal => print!("\t{signed}"),
+                Format::Unsigned => print!("\t{value}"),
+                _ => print!("\t{} '{}'", signed, ascii::escape_default(value as u8)),
+            }
+        }
+        println!();
+    }
+    Ok(())
+}
+
+fn strings(
+    debugger_info: &DebuggerInfo,
+    count: usize,
+    addr: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let mut addr = addr;
+    for _ in 0..count {
+        mem::check_mapping(pid, addr, 1, mem::Access::Data)?;
+        let mut bytes = Vec::new();
+        let mut terminated = false;
+        while bytes.len() < MAX_STRING_LEN {
+            let Ok(word) = mem::read_bytes(pid, addr + bytes.len() as u64, 8) else {
+                break;
+            };
+            match word.iter().position(|b| *b == 0) {
+                Some(end) => {
+                    bytes.extend_from_slice(&word[..end]);
+                    terminated = true;
+                    break;
+                }
+                None => bytes.extend_from_slice(&word),
+            }
+        }
+        bytes.truncate(MAX_STRING_LEN);
+        let text: String = bytes
+            .iter()
+            .flat_map(|b| ascii::escape_default(*b))
+            .map(char::from)
+            .collect();
+        let rest = if terminated { "" } else { "..." };
+        println!("0x{addr:016x}:\t\"{text}\"{rest}");
+        addr += bytes.len() as u64 + 1;
+    }
+    Ok(())
+}
+
+fn instructions(
+    debugger_info: &DebuggerInfo,
+    count: usize,
+    addr: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    mem::check_mapping(pid, addr, 1, mem::Access::Execute)?;
+    let end = addr + count as u64 * MAX_INSTRUCTION_LEN;
+    let instructions = disasm::disassemble(pid, &debugger_info.breakpoint_manager, addr, end)?;
+    for instruction in instructions.iter().take(count) {
+        dump::instruction(debugger_info, instruction);
+    }
+    Ok(())
+}
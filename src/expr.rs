@@ -0,0 +1,1118 @@
+use crate::{
+    debug_info::TdbDebugInfo,
+    mem,
+    register::Register,
+    symbolize::demangle,
+    syscall::get_regs,
+    unwind::Frame,
+    variable::{
+        self, format_value, read_i128, read_int, read_u128, read_uint, Type, VarLocation, Variable,
+    },
+};
+use object::SymbolKind;
+use std::io::{Error, ErrorKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Integer(u128),
+    /// `1.5`, `1e-3`
+    Float(f64),
+    /// `"text"`
+    String(String),
+    Symbol(String),
+    Register(Register),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// `a[i]`
+    Index(Box<Expr>, Box<Expr>),
+    /// `a.b`
+    Field(Box<Expr>, String),
+    /// `a->b`
+    PointerField(Box<Expr>, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+    Deref,
+    AddrOf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+    /// `a =~ b`, true if the string `a` contains `b`
+    Contains,
+}
+
+impl BinaryOp {
+    fn from_token(op: &str) -> Option<(BinaryOp, u8)> {
+        use BinaryOp::*;
+        // (演算子, 優先順位)
+        let op = match op {
+            "||" => (Or, 1),
+            "&&" => (And, 2),
+            "|" => (BitOr, 3),
+            "^" => (BitXor, 4),
+            "&" => (BitAnd, 5),
+            "==" => (Eq, 6),
+            "!=" => (Ne, 6),
+            "=~" => (Contains, 6),
+            "<" => (Lt, 7),
+            "<=" => (Le, 7),
+            ">" => (Gt, 7),
+            ">=" => (Ge, 7),
+            "<<" => (Shl, 8),
+            ">>" => (Shr, 8),
+            "+" => (Add, 9),
+            "-" => (Sub, 9),
+            "*" => (Mul, 10),
+            "/" => (Div, 10),
+            "%" => (Rem, 10),
+            _ => return None,
+        };
+        Some(op)
+    }
+}
+
+/// longer operators first
+const OPERATORS: [&str; 27] = [
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "=~", "&&", "||", "+", "-", "*", "/", "%", "<", ">",
+    "&", "|", "^", "~", "!", "(", ")", "[", "]", ".",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Integer(u128),
+    Float(f64),
+    Str(String),
+    Ident(String),
+    Register(String),
+    Op(&'static str),
+}
+
+fn error(msg: impl Into<String>) -> Box<dyn std::error::Error> {
+    Box::new(Error::new(ErrorKind::InvalidInput, msg.into()))
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, Box<dyn std::error::Error>> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let literal_len = |s: &str| s.find(|c: char| !is_ident(c)).unwrap_or(s.len());
+            let mut len = literal_len(rest);
+            // タプルのフィールド`a.0.1`は小数にしない
+            let field = tokens.last() == Some(&Token::Op("."));
+            if !field && !rest.starts_with("0x") {
+                if rest[len..].starts_with('.')
+                    && rest[len + 1..].starts_with(|c: char| c.is_ascii_digit())
+                {
+                    len += 1 + literal_len(&rest[len + 1..]);
+                }
+                // 指数の符号
+                if rest[..len].ends_with(['e', 'E']) && rest[len..].starts_with(['+', '-']) {
+                    len += 1 + literal_len(&rest[len + 1..]);
+                }
+            }
+            let literal = &rest[..len];
+            let invalid = || error(format!("invalid number \"{}\"", literal));
+            if !field && !literal.starts_with("0x") && literal.contains(['.', 'e', 'E']) {
+                tokens.push(Token::Float(literal.parse::<f64>().map_err(|_| invalid())?));
+            } else {
+                let value = match literal.strip_prefix("0x") {
+                    Some(hex) => u128::from_str_radix(hex, 16),
+                    None => literal.parse::<u128>(),
+                }
+                .map_err(|_| invalid())?;
+                tokens.push(Token::Integer(value));
+            }
+            len
+        } else if c == '"' {
+            let mut value = String::new();
+            let mut chars = rest.char_indices().skip(1);
+            let len = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i + 1,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((_, c)) => value.push(c),
+                        None => return Err(error("unterminated string")),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return Err(error("unterminated string")),
+                }
+            };
+            tokens.push(Token::Str(value));
+            len
+        } else if c == '$' {
+            let len = rest[1..]
+                .find(|c: char| !is_ident(c))
+                .unwrap_or(rest.len() - 1)
+                + 1;
+            tokens.push(Token::Register(rest[..len].to_string()));
+            len
+        } else if is_ident(c) {
+            // `a::b`のようなパスも1つの識別子にする
+            let mut len = 0;
+            loop {
+                len += rest[len..]
+                    .find(|c: char| !is_ident(c))
+                    .unwrap_or(rest.len() - len);
+                match rest[len..].strip_prefix("::") {
+                    Some(next) if next.starts_with(is_ident) => len += 2,
+                    _ => break,
+                }
+            }
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            len
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| error(format!("invalid character '{}'", c)))?;
+            tokens.push(Token::Op(op));
+            op.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self.next() {
+            Some(Token::Op(o)) if o == op => Ok(()),
+            _ => Err(error(format!("expected '{}'", op))),
+        }
+    }
+
+    fn binary(&mut self, min_prec: u8) -> Result<Expr, Box<dyn std::error::Error>> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let (op, prec) = match BinaryOp::from_token(op) {
+                Some((op, prec)) if prec >= min_prec => (op, prec),
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.binary(prec + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Box<dyn std::error::Error>> {
+        let op = match self.peek() {
+            Some(Token::Op("-")) => UnaryOp::Neg,
+            Some(Token::Op("!")) => UnaryOp::Not,
+            Some(Token::Op("~")) => UnaryOp::BitNot,
+            Some(Token::Op("*")) => UnaryOp::Deref,
+            Some(Token::Op("&")) => UnaryOp::AddrOf,
+            _ => return self.postfix(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn postfix(&mut self) -> Result<Expr, Box<dyn std::error::Error>> {
+        let mut expr = self.primary()?;
+        loop {
+            match self.peek() {
+                Some(Token::Op("[")) => {
+                    self.pos += 1;
+                    let index = self.binary(0)?;
+                    self.expect("]")?;
+                    expr = Expr::Index(Box::new(expr), Box::new(index));
+                }
+                Some(Token::Op(op @ ("." | "->"))) => {
+                    let op = *op;
+                    self.pos += 1;
+                    let field = match self.next() {
+                        Some(Token::Ident(field)) => field,
+                        // タプルのフィールドは数字
+                        Some(Token::Integer(n)) => n.to_string(),
+                        _ => return Err(error("expected field name")),
+                    };
+                    expr = if op == "." {
+                        Expr::Field(Box::new(expr), field)
+                    } else {
+                        Expr::PointerField(Box::new(expr), field)
+                    };
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, Box<dyn std::error::Error>> {
+        match self.next() {
+            Some(Token::Integer(n)) => Ok(Expr::Integer(n)),
+            Some(Token::Float(x)) => Ok(Expr::Float(x)),
+            Some(Token::Str(s)) => Ok(Expr::String(s)),
+            Some(Token::Ident(name)) => Ok(Expr::Symbol(name)),
+            Some(Token::Register(name)) => Ok(Expr::Register(Register::parse(&name)?)),
+            Some(Token::Op("(")) => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(token) => Err(error(format!("unexpected token {:?}", token))),
+            None => Err(error("unexpected end of expression")),
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<Expr, Box<dyn std::error::Error>> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let expr = parser.binary(0)?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(error(format!("unexpected token {:?}", token))),
+    }
+}
+
+/// where symbols are looked up
+pub struct Scope<'a> {
+    pub debug_info: &'a TdbDebugInfo,
+    pub frame: Option<Frame>,
+    /// level of `frame` in the backtrace
+    pub level: usize,
+    /// values named by the debugger, looked up before the variables
+    pub bindings: Vec<(String, Value)>,
+}
+
+/// where the value lives in the target
+#[derive(Debug, Clone, Copy)]
+pub enum Place {
+    Memory(u64),
+    Register(Register),
+}
+
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub ty: Type,
+    pub bytes: Vec<u8>,
+    /// None if the value is computed by the debugger
+    pub place: Option<Place>,
+}
+
+impl Value {
+    pub fn integer(value: u64, signed: bool) -> Self {
+        let (name, encoding) = if signed {
+            ("long", gimli::DW_ATE_signed)
+        } else {
+            ("unsigned long", gimli::DW_ATE_unsigned)
+        };
+        Self {
+            ty: Type::Base {
+                name: String::from(name),
+                size: 8,
+                encoding,
+            },
+            bytes: value.to_le_bytes().to_vec(),
+            place: None,
+        }
+    }
+
+    /// 128-bit integer, the result of operations on `i128` or `u128`
+    fn integer128(value: u128, signed: bool) -> Self {
+        let (name, encoding) = if signed {
+            ("__int128", gimli::DW_ATE_signed)
+        } else {
+            ("unsigned __int128", gimli::DW_ATE_unsigned)
+        };
+        Self {
+            ty: Type::Base {
+                name: String::from(name),
+                size: 16,
+                encoding,
+            },
+            bytes: value.to_le_bytes().to_vec(),
+            place: None,
+        }
+    }
+
+    /// unsigned integer of `size` bytes
+    fn unsigned(value: u64, size: usize) -> Self {
+        let name = match size {
+            1 => "unsigned char",
+            2 => "unsigned short",
+            4 => "unsigned int",
+            _ => "unsigned long",
+        };
+        Self {
+            ty: Type::Base {
+                name: String::from(name),
+                size: size as u64,
+                encoding: gimli::DW_ATE_unsigned,
+            },
+            bytes: value.to_le_bytes()[..size].to_vec(),
+            place: None,
+        }
+    }
+
+    /// `char` array of the bytes
+    pub fn string(bytes: &[u8]) -> Self {
+        Self {
+            ty: Type::Array {
+                elem: Box::new(Type::Base {
+                    name: String::from("char"),
+                    size: 1,
+                    encoding: gimli::DW_ATE_signed_char,
+                }),
+                count: Some(bytes.len() as u64),
+            },
+            bytes: bytes.to_vec(),
+            place: None,
+        }
+    }
+
+    /// bytes before NUL if the value is a `char` array
+    fn as_str(&self) -> Option<&[u8]> {
+        match self.ty.canonical() {
+            Type::Array { elem, .. } if elem.resolve().size() == 1 => {
+                let len = self.bytes.iter().position(|b| *b == 0);
+                Some(&self.bytes[..len.unwrap_or(self.bytes.len())])
+            }
+            _ => None,
+        }
+    }
+
+    fn float(value: f64) -> Self {
+        Self {
+            ty: Type::Base {
+                name: String::from("double"),
+                size: 8,
+                encoding: gimli::DW_ATE_float,
+            },
+            bytes: value.to_le_bytes().to_vec(),
+            place: None,
+        }
+    }
+
+    pub fn pointer(target: Type, addr: u64) -> Self {
+        Self {
+            ty: Type::Pointer {
+                name: None,
+                target: Box::new(target),
+            },
+            bytes: addr.to_le_bytes().to_vec(),
+            place: None,
+        }
+    }
+
+    /// address of the value if it lives in memory
+    pub fn addr(&self) -> Option<u64> {
+        match self.place {
+            Some(Place::Memory(addr)) => Some(addr),
+            _ => None,
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self.ty.canonical(), Type::Base { encoding, .. } if encoding == gimli::DW_ATE_float)
+    }
+
+    fn is_signed(&self) -> bool {
+        match self.ty.canonical() {
+            Type::Base { encoding, .. } => {
+                encoding == gimli::DW_ATE_signed || encoding == gimli::DW_ATE_signed_char
+            }
+            Type::Enum { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// integer wider than 64 bits, operations on it are done in 128 bits
+    fn is_wide(&self) -> bool {
+        !self.is_float() && self.bytes.len() > 8
+    }
+
+    pub fn as_u128(&self) -> u128 {
+        if self.is_float() {
+            self.as_f64() as i128 as u128
+        } else if self.is_signed() {
+            read_i128(&self.bytes) as u128
+        } else {
+            read_u128(&self.bytes)
+        }
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        if self.is_float() {
+            self.as_f64() as u64
+        } else if self.is_signed() {
+            read_int(&self.bytes) as u64
+        } else {
+            read_uint(&self.bytes)
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match (self.is_float(), self.bytes.len()) {
+            (true, 4) => f32::from_bits(read_uint(&self.bytes) as u32) as f64,
+            (true, _) => f64::from_bits(read_uint(&self.bytes)),
+            (false, _) if self.is_signed() => read_i128(&self.bytes) as f64,
+            (false, _) => read_u128(&self.bytes) as f64,
+        }
+    }
+
+    pub fn is_true(&self) -> bool {
+        if self.is_float() {
+            self.as_f64() != 0.0
+        } else {
+            self.bytes.iter().any(|b| *b != 0)
+        }
+    }
+
+    pub fn format(&self, scope: &Scope) -> String {
+        format_value(scope.debug_info.target_pid(), &self.ty, &self.bytes)
+    }
+}
+
+fn read_value(scope: &Scope, ty: Type, addr: u64) -> Result<Value, Box<dyn std::error::Error>> {
+    let bytes = mem::read_bytes(scope.debug_info.target_pid(), addr, ty.size() as usize)
+        .map_err(|_| error(format!("cannot access memory at address 0x{:x}", addr)))?;
+    Ok(Value {
+        ty,
+        bytes,
+        place: Some(Place::Memory(addr)),
+    })
+}
+
+fn variable_value(scope: &Scope, var: Variable) -> Result<Value, Box<dyn std::error::Error>> {
+    let bytes = var
+        .read(scope.debug_info.target_pid(), scope.frame.as_ref())
+        .map_err(error)?;
+    let place = match var.location.as_deref() {
+        Ok([VarLocation::Address(addr)]) => Some(Place::Memory(*addr)),
+        Ok([VarLocation::Register(reg)]) => Some(Place::Register(Register::from(*reg))),
+        _ => None,
+    };
+    Ok(Value {
+        ty: var.ty,
+        bytes,
+        place,
+    })
+}
+
+fn symbol(scope: &Scope, name: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    if let Some((_, value)) = scope.bindings.iter().find(|(n, _)| n == name) {
+        return Ok(value.clone());
+    }
+    if let Some(frame) = &scope.frame {
+        if let Some(var) = variable::lookup(scope.debug_info, frame, scope.level, name) {
+            return variable_value(scope, var);
+        }
+    }
+    if let Some(var) = variable::global(scope.debug_info, name) {
+        return variable_value(scope, var);
+    }
+    // デバッグ情報がないシンボルはアドレスを値にする
+    let load_bias = scope.debug_info.load_bias();
+    let sym = scope.debug_info.symbols().iter().find(|sym| {
+        sym.address() != 0
+            && matches!(sym.kind(), SymbolKind::Text | SymbolKind::Data)
+            && (sym.name() == name || demangle(sym.name()) == name)
+    });
+    match sym {
+        Some(sym) => Ok(Value::pointer(Type::Void, load_bias + sym.address())),
+        None => Err(error(format!("no symbol \"{}\" in current context", name))),
+    }
+}
+
+fn deref(scope: &Scope, value: Value) -> Result<Value, Box<dyn std::error::Error>> {
+    match value.ty.canonical() {
+        Type::Pointer { target, .. } => {
+            let target = target.resolve();
+            if target.size() == 0 {
+                return Err(error("attempt to take contents of a void pointer"));
+            }
+            read_value(scope, target, read_uint(&value.bytes))
+        }
+        Type::Array { .. } => index(scope, value, 0),
+        _ => Err(error("attempt to take contents of a non-pointer value")),
+    }
+}
+
+fn index(scope: &Scope, value: Value, i: i64) -> Result<Value, Box<dyn std::error::Error>> {
+    match value.ty.canonical() {
+        Type::Array { elem, .. } | Type::Vector { elem, .. } => {
+            let size = elem.size();
+            match value.addr() {
+                Some(addr) => read_value(scope, *elem, addr.wrapping_add(offset(i, size)?)),
+                None => {
+                    let bytes = usize::try_from(i)
+                        .ok()
+                        .and_then(|i| i.checked_mul(size as usize))
+                        .and_then(|start| Some(start..start.checked_add(size as usize)?))
+                        .and_then(|range| value.bytes.get(range))
+                        .ok_or_else(|| error("array index out of range"))?;
+                    Ok(Value {
+                        ty: *elem,
+                        bytes: bytes.to_vec(),
+                        place: None,
+                    })
+                }
+            }
+        }
+        Type::Pointer { target, .. } => {
+            let target = target.resolve();
+            let addr = read_uint(&value.bytes).wrapping_add(offset(i, target.size())?);
+            read_value(scope, target, addr)
+        }
+        _ => Err(error("cannot subscript requested type")),
+    }
+}
+
+/// byte offset of the element `i` from the start, negative offsets wrap around
+fn offset(i: i64, size: u64) -> Result<u64, Box<dyn std::error::Error>> {
+    i64::try_from(size)
+        .ok()
+        .and_then(|size| i.checked_mul(size))
+        .map(|offset| offset as u64)
+        .ok_or_else(|| error("array index out of range"))
+}
+
+fn field(value: Value, name: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    let members = match value.ty.canonical() {
+        Type::Struct { members, .. } => members,
+        ty => {
+            return Err(error(format!(
+                "attempt to extract a component of a value that is not a structure ({})",
+                ty.name()
+            )))
+        }
+    };
+    let member = members
+        .into_iter()
+        // rustcはタプルのフィールドを`__0`のように出力する
+        .find(|member| member.name == name || member.name == format!("__{}", name))
+        .ok_or_else(|| error(format!("there is no member named {}", name)))?;
+    let start = member.offset as usize;
+    let end = start + member.ty.size() as usize;
+    let bytes = value
+        .bytes
+        .get(start..end)
+        .ok_or_else(|| error(format!("member {} is out of the value", name)))?;
+    Ok(Value {
+        bytes: bytes.to_vec(),
+        place: value.addr().map(|addr| Place::Memory(addr + member.offset)),
+        ty: member.ty,
+    })
+}
+
+fn unary(scope: &Scope, op: UnaryOp, value: Value) -> Result<Value, Box<dyn std::error::Error>> {
+    let value = match op {
+        UnaryOp::Neg if value.is_float() => Value::float(-value.as_f64()),
+        UnaryOp::Neg if value.is_wide() => Value::integer128(value.as_u128().wrapping_neg(), true),
+        UnaryOp::BitNot if value.is_wide() => {
+            Value::integer128(!value.as_u128(), value.is_signed())
+        }
+        UnaryOp::Neg => Value::integer(value.as_u64().wrapping_neg(), true),
+        UnaryOp::Not => Value::integer(!value.is_true() as u64, true),
+        UnaryOp::BitNot => Value::integer(!value.as_u64(), value.is_signed()),
+        UnaryOp::Deref => deref(scope, value)?,
+        UnaryOp::AddrOf => {
+            let addr = value
+                .addr()
+                .ok_or_else(|| error("attempt to take address of value not located in memory"))?;
+            Value::pointer(value.ty, addr)
+        }
+    };
+    Ok(value)
+}
+
+fn binary(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, Box<dyn std::error::Error>> {
+    use BinaryOp::*;
+
+    // 文字列は中身で比べる
+    match (op, lhs.as_str(), rhs.as_str()) {
+        (Eq, Some(l), Some(r)) => return Ok(Value::integer((l == r) as u64, true)),
+        (Ne, Some(l), Some(r)) => return Ok(Value::integer((l != r) as u64, true)),
+        (Contains, Some(l), Some(r)) => {
+            let contains = r.is_empty() || l.windows(r.len()).any(|w| w == r);
+            return Ok(Value::integer(contains as u64, true));
+        }
+        (Contains, _, _) => return Err(error("operator =~ needs strings")),
+        _ => {}
+    }
+
+    // ポインタ演算は指す先の大きさ単位
+    let pointee_size = |value: &Value| match value.ty.canonical() {
+        Type::Pointer { target, .. } => Some(target.resolve().size().max(1)),
+        _ => None,
+    };
+    match (op, pointee_size(&lhs), pointee_size(&rhs)) {
+        (Add, Some(size), None) | (Sub, Some(size), None) => {
+            let offset = rhs.as_u64().wrapping_mul(size);
+            let addr = if op == Add {
+                lhs.as_u64().wrapping_add(offset)
+            } else {
+                lhs.as_u64().wrapping_sub(offset)
+            };
+            return Ok(Value {
+                bytes: addr.to_le_bytes().to_vec(),
+                place: None,
+                ty: lhs.ty,
+            });
+        }
+        (Add, None, Some(_)) => return binary(op, rhs, lhs),
+        (Sub, Some(size), Some(_)) => {
+            let diff = (lhs.as_u64() as i64).wrapping_sub(rhs.as_u64() as i64);
+            return Ok(Value::integer((diff / size as i64) as u64, true));
+        }
+        _ => {}
+    }
+
+    if lhs.is_float() || rhs.is_float() {
+        let (l, r) = (lhs.as_f64(), rhs.as_f64());
+        let value = match op {
+            Mul => Value::float(l * r),
+            Div => Value::float(l / r),
+            Add => Value::float(l + r),
+            Sub => Value::float(l - r),
+            Lt => Value::integer((l < r) as u64, true),
+            Le => Value::integer((l <= r) as u64, true),
+            Gt => Value::integer((l > r) as u64, true),
+            Ge => Value::integer((l >= r) as u64, true),
+            Eq => Value::integer((l == r) as u64, true),
+            Ne => Value::integer((l != r) as u64, true),
+            _ => return Err(error("integer operator applied to floating point value")),
+        };
+        return Ok(value);
+    }
+
+    let signed = lhs.is_signed() && rhs.is_signed();
+    if lhs.is_wide() || rhs.is_wide() {
+        return binary128(op, lhs.as_u128(), rhs.as_u128(), signed);
+    }
+    let (l, r) = (lhs.as_u64(), rhs.as_u64());
+    let compare = |ordering: std::cmp::Ordering| {
+        if signed {
+            (l as i64).cmp(&(r as i64)) == ordering
+        } else {
+            l.cmp(&r) == ordering
+        }
+    };
+    let value = match op {
+        Mul => l.wrapping_mul(r),
+        Div | Rem if r == 0 => return Err(error("division by zero")),
+        Div if signed => (l as i64).wrapping_div(r as i64) as u64,
+        Div => l / r,
+        Rem if signed => (l as i64).wrapping_rem(r as i64) as u64,
+        Rem => l % r,
+        Add => l.wrapping_add(r),
+        Sub => l.wrapping_sub(r),
+        Shl => l.wrapping_shl(r as u32),
+        Shr if signed => (l as i64).wrapping_shr(r as u32) as u64,
+        Shr => l.wrapping_shr(r as u32),
+        Lt => compare(std::cmp::Ordering::Less) as u64,
+        Le => !compare(std::cmp::Ordering::Greater) as u64,
+        Gt => compare(std::cmp::Ordering::Greater) as u64,
+        Ge => !compare(std::cmp::Ordering::Less) as u64,
+        Eq => (l == r) as u64,
+        Ne => (l != r) as u64,
+        BitAnd => l & r,
+        BitXor => l ^ r,
+        BitOr => l | r,
+        And | Or | Contains => unreachable!(),
+    };
+    Ok(Value::integer(value, signed))
+}
+
+/// integer operation on `i128` or `u128`
+fn binary128(
+    op: BinaryOp,
+    l: u128,
+    r: u128,
+    signed: bool,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    use BinaryOp::*;
+
+    let compare = |ordering: std::cmp::Ordering| {
+        if signed {
+            (l as i128).cmp(&(r as i128)) == ordering
+        } else {
+            l.cmp(&r) == ordering
+        }
+    };
+    let value = match op {
+        Mul => l.wrapping_mul(r),
+        Div | Rem if r == 0 => return Err(error("division by zero")),
+        Div if signed => (l as i128).wrapping_div(r as i128) as u128,
+        Div => l / r,
+        Rem if signed => (l as i128).wrapping_rem(r as i128) as u128,
+        Rem => l % r,
+        Add => l.wrapping_add(r),
+        Sub => l.wrapping_sub(r),
+        Shl => l.wrapping_shl(r as u32),
+        Shr if signed => (l as i128).wrapping_shr(r as u32) as u128,
+        Shr => l.wrapping_shr(r as u32),
+        // 比較の結果は普通の整数
+        Lt => {
+            return Ok(Value::integer(
+                compare(std::cmp::Ordering::Less) as u64,
+                true,
+            ))
+        }
+        Le => {
+            return Ok(Value::integer(
+                !compare(std::cmp::Ordering::Greater) as u64,
+                true,
+            ))
+        }
+        Gt => {
+            return Ok(Value::integer(
+                compare(std::cmp::Ordering::Greater) as u64,
+                true,
+            ))
+        }
+        Ge => {
+            return Ok(Value::integer(
+                !compare(std::cmp::Ordering::Less) as u64,
+                true,
+            ))
+        }
+        Eq => return Ok(Value::integer((l == r) as u64, true)),
+        Ne => return Ok(Value::integer((l != r) as u64, true)),
+        BitAnd => l & r,
+        BitXor => l ^ r,
+        BitOr => l | r,
+        And | Or | Contains => unreachable!(),
+    };
+    Ok(Value::integer128(value, signed))
+}
+
+pub fn eval(expr: &Expr, scope: &Scope) -> Result<Value, Box<dyn std::error::Error>> {
+    match expr {
+        Expr::Integer(n) => match u64::try_from(*n) {
+            Ok(n) => Ok(Value::integer(n, true)),
+            Err(_) => Ok(Value::integer128(*n, false)),
+        },
+        Expr::Float(x) => Ok(Value::float(*x)),
+        Expr::String(s) => Ok(Value::string(s.as_bytes())),
+        Expr::Symbol(name) => symbol(scope, name),
+        Expr::Register(reg) => {
+            let regs = match &scope.frame {
+                Some(frame) => frame.regs,
+                None => get_regs(scope.debug_info.target_pid()),
+            };
+            let mut value = Value::unsigned(reg.get_value(&regs), reg.size());
+            value.place = Some(Place::Register(*reg));
+            Ok(value)
+        }
+        Expr::Unary(op, expr) => unary(scope, *op, eval(expr, scope)?),
+        // 論理演算は短絡評価する
+        Expr::Binary(BinaryOp::And, lhs, rhs) => {
+            let value = eval(lhs, scope)?.is_true() && eval(rhs, scope)?.is_true();
+            Ok(Value::integer(value as u64, true))
+        }
+        Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+            let value = eval(lhs, scope)?.is_true() || eval(rhs, scope)?.is_true();
+            Ok(Value::integer(value as u64, true))
+        }
+        Expr::Binary(op, lhs, rhs) => binary(*op, eval(lhs, scope)?, eval(rhs, scope)?),
+        Expr::Index(base, i) => {
+            let base = eval(base, scope)?;
+            let i = eval(i, scope)?.as_u64() as i64;
+            index(scope, base, i)
+        }
+        Expr::Field(base, name) => field(eval(base, scope)?, name),
+        Expr::PointerField(base, name) => field(deref(scope, eval(base, scope)?)?, name),
+    }
+}
+
+/// split `lhs = rhs` at the assignment operator
+pub fn parse_assignment(input: &str) -> Result<(Expr, Expr), Box<dyn std::error::Error>> {
+    let bytes = input.as_bytes();
+    let pos = (0..bytes.len())
+        .find(|&i| {
+            bytes[i] == b'='
+                && !matches!(bytes.get(i + 1), Some(b'=' | b'~'))
+                && !matches!(
+                    i.checked_sub(1).map(|j| bytes[j]),
+                    Some(b'=' | b'!' | b'<' | b'>')
+                )
+        })
+        .ok_or_else(|| error("expected assignment"))?;
+    Ok((parse(&input[..pos])?, parse(&input[pos + 1..])?))
+}
+
+/// `lhs = rhs`, the value is converted to the type of `lhs`
+pub fn assign(lhs: &Expr, rhs: &Expr, scope: &Scope) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = scope.debug_info.target_pid();
+    let lhs = eval(lhs, scope)?;
+    let rhs = eval(rhs, scope)?;
+    let size = lhs.ty.size() as usize;
+
+    let bytes = match lhs.ty.canonical() {
+        Type::Base { encoding, .. } if encoding == gimli::DW_ATE_float => match size {
+            4 => (rhs.as_f64() as f32).to_le_bytes().to_vec(),
+            _ => rhs.as_f64().to_le_bytes().to_vec(),
+        },
+        Type::Struct { .. } | Type::Array { .. } | Type::Vector { .. } => {
+            if rhs.bytes.len() != size {
+                return Err(error(format!(
+                    "cannot assign {} to {}",
+                    rhs.ty.name(),
+                    lhs.ty.name()
+                )));
+            }
+            rhs.bytes.clone()
+        }
+        // 型の大きさに切り詰める
+        _ => rhs.as_u128().to_le_bytes()[..size.min(16)].to_vec(),
+    };
+
+    match lhs.place {
+        Some(Place::Memory(addr)) => mem::write_bytes(pid, addr, &bytes)
+            .map_err(|_| error(format!("cannot access memory at address 0x{:x}", addr)))?,
+        Some(Place::Register(reg)) => {
+            if scope.level != 0 {
+                return Err(error("cannot write registers of outer frames"));
+            }
+            let mut regs = get_regs(pid);
+            let old = reg.get_value(&regs);
+            let mask = if size >= 8 {
+                u64::MAX
+            } else {
+                (1u64 << (size * 8)) - 1
+            };
+            reg.set_value(&mut regs, (old & !mask) | (read_uint(&bytes) & mask));
+            nix::sys::ptrace::setregs(pid, regs)?;
+        }
+        None => return Err(error("left operand of assignment is not an lvalue")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(n: u128) -> Box<Expr> {
+        Box::new(Expr::Integer(n))
+    }
+
+    fn sym(name: &str) -> Box<Expr> {
+        Box::new(Expr::Symbol(name.to_string()))
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(parse("42").unwrap(), Expr::Integer(42));
+        assert_eq!(parse("0x1f").unwrap(), Expr::Integer(0x1f));
+        assert_eq!(
+            parse("340282366920938463463374607431768211455").unwrap(),
+            Expr::Integer(u128::MAX)
+        );
+        assert!(parse("0xg").is_err());
+        assert!(parse("12ab").is_err());
+    }
+
+    #[test]
+    fn float_literals() {
+        assert_eq!(parse("1.5").unwrap(), Expr::Float(1.5));
+        assert_eq!(parse("1e3").unwrap(), Expr::Float(1000.0));
+        assert_eq!(parse("2.5e-1").unwrap(), Expr::Float(0.25));
+        assert_eq!(parse("1E+2").unwrap(), Expr::Float(100.0));
+        assert_eq!(
+            parse("1.5 * 2").unwrap(),
+            Expr::Binary(BinaryOp::Mul, Box::new(Expr::Float(1.5)), int(2))
+        );
+        assert!(parse("1e").is_err());
+    }
+
+    #[test]
+    fn tuple_fields_are_not_floats() {
+        assert_eq!(
+            parse("t.0.1").unwrap(),
+            Expr::Field(
+                Box::new(Expr::Field(sym("t"), "0".to_string())),
+                "1".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            parse("1 + 2 * 3").unwrap(),
+            Expr::Binary(
+                BinaryOp::Add,
+                int(1),
+                Box::new(Expr::Binary(BinaryOp::Mul, int(2), int(3)))
+            )
+        );
+        assert_eq!(
+            parse("(1 + 2) * 3").unwrap(),
+            Expr::Binary(
+                BinaryOp::Mul,
+                Box::new(Expr::Binary(BinaryOp::Add, int(1), int(2))),
+                int(3)
+            )
+        );
+        // 同じ優先順位は左結合
+        assert_eq!(
+            parse("8 - 4 - 2").unwrap(),
+            Expr::Binary(
+                BinaryOp::Sub,
+                Box::new(Expr::Binary(BinaryOp::Sub, int(8), int(4))),
+                int(2)
+            )
+        );
+        assert_eq!(
+            parse("a < 1 && b == 2").unwrap(),
+            Expr::Binary(
+                BinaryOp::And,
+                Box::new(Expr::Binary(BinaryOp::Lt, sym("a"), int(1))),
+                Box::new(Expr::Binary(BinaryOp::Eq, sym("b"), int(2)))
+            )
+        );
+    }
+
+    #[test]
+    fn unary_and_postfix() {
+        assert_eq!(
+            parse("-*p").unwrap(),
+            Expr::Unary(
+                UnaryOp::Neg,
+                Box::new(Expr::Unary(UnaryOp::Deref, sym("p")))
+            )
+        );
+        assert_eq!(
+            parse("&a[1]").unwrap(),
+            Expr::Unary(UnaryOp::AddrOf, Box::new(Expr::Index(sym("a"), int(1))))
+        );
+        assert_eq!(
+            parse("p->next.value").unwrap(),
+            Expr::Field(
+                Box::new(Expr::PointerField(sym("p"), "next".to_string())),
+                "value".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn symbols_strings_and_registers() {
+        assert_eq!(parse("std::env::ARGS").unwrap(), *sym("std::env::ARGS"));
+        assert_eq!(
+            parse(r#"s =~ "a\"b\n""#).unwrap(),
+            Expr::Binary(
+                BinaryOp::Contains,
+                sym("s"),
+                Box::new(Expr::String("a\"b\n".to_string()))
+            )
+        );
+        assert_eq!(
+            parse("$rax").unwrap(),
+            Expr::Register(Register::parse("$rax").unwrap())
+        );
+        assert!(parse("\"abc").is_err());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("(1").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("a.").is_err());
+        assert!(parse("1 @ 2").is_err());
+    }
+
+    #[test]
+    fn assignment() {
+        let (lhs, rhs) = parse_assignment("a[0] = b == 1").unwrap();
+        assert_eq!(lhs, Expr::Index(sym("a"), int(0)));
+        assert_eq!(rhs, Expr::Binary(BinaryOp::Eq, sym("b"), int(1)));
+        assert!(parse_assignment("a <= 1").is_err());
+        assert!(parse_assignment("s =~ \"x\"").is_err());
+    }
+
+    #[test]
+    fn element_offset() {
+        assert_eq!(offset(3, 4).unwrap(), 12);
+        assert_eq!(offset(-1, 4).unwrap(), (-4i64) as u64);
+        assert!(offset(i64::MAX, 2).is_err());
+        assert!(offset(1, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn integer_arithmetic() {
+        let eval = |op, l, r| binary(op, Value::integer(l, true), Value::integer(r, true));
+        assert_eq!(eval(BinaryOp::Add, 2, 3).unwrap().as_u64(), 5);
+        assert_eq!(
+            eval(BinaryOp::Div, (-7i64) as u64, 2).unwrap().as_u64(),
+            (-3i64) as u64
+        );
+        assert_eq!(eval(BinaryOp::Lt, (-1i64) as u64, 0).unwrap().as_u64(), 1);
+        assert!(eval(BinaryOp::Rem, 1, 0).is_err());
+        // 符号なしが混ざれば符号なしで比べる
+        let lt = binary(
+            BinaryOp::Lt,
+            Value::integer((-1i64) as u64, true),
+            Value::integer(0, false),
+        );
+        assert_eq!(lt.unwrap().as_u64(), 0);
+    }
+
+    #[test]
+    fn float_arithmetic() {
+        let value = binary(BinaryOp::Mul, Value::float(1.5), Value::integer(4, true)).unwrap();
+        assert_eq!(value.as_f64(), 6.0);
+        assert!(binary(BinaryOp::Shl, Value::float(1.5), Value::integer(1, true)).is_err());
+    }
+
+    #[test]
+    fn string_comparison() {
+        let s = |text: &str| Value::string(text.as_bytes());
+        assert!(binary(BinaryOp::Eq, s("abc"), s("abc")).unwrap().is_true());
+        assert!(binary(BinaryOp::Contains, s("hello"), s("ll"))
+            .unwrap()
+            .is_true());
+        assert!(!binary(BinaryOp::Contains, s("hello"), s("lo!"))
+            .unwrap()
+            .is_true());
+        assert!(binary(BinaryOp::Contains, Value::integer(1, true), s("a")).is_err());
+    }
+}
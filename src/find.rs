@@ -0,0 +1,200 @@
+use crate::{
+    debugger::DebuggerInfo,
+    examine,
+    expr::{self, Expr},
+    mem,
+    signal::Cancel,
+    symbolize::demangle,
+};
+use proc_maps::get_process_maps;
+use std::{io, path::Path};
+
+/// bytes read from the target at once
+const CHUNK_SIZE: u64 = 0x10000;
+
+/// end of the range searched by `find`
+#[derive(Debug, Clone)]
+pub enum End {
+    Address(Expr),
+    /// `+len`
+    Length(Expr),
+}
+
+/// `find/U start, end, value, ...`
+#[derive(Debug, Clone)]
+pub struct Find {
+    pub start: Expr,
+    pub end: End,
+    /// concatenated into the sequence searched for
+    pub values: Vec<Expr>,
+    /// size of integer values, None if it is guessed from the value
+    pub size: Option<u64>,
+}
+
+fn error(msg: impl Into<String>) -> Box<dyn std::error::Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidInput, msg.into()))
+}
+
+/// parse the size letter after `find/` and the arguments separated by `,`
+pub fn parse(unit: Option<&str>, args: &str) -> Result<Find, Box<dyn std::error::Error>> {
+    let usage = || error("usage: find[/b|h|w|g] <start>, <end|+len>, <value>[, <value>...]");
+    let size = match unit {
+        None => None,
+        Some("b") => Some(1),
+        Some("h") => Some(2),
+        Some("w") => Some(4),
+        Some("g") => Some(8),
+        Some(_) => return Err(usage()),
+    };
+    let args = split_args(args);
+    let [start, end, values @ ..] = &args[..] else {
+        return Err(usage());
+    };
+    if values.is_empty() {
+        return Err(usage());
+    }
+    let end = match end.strip_prefix('+') {
+        Some(len) => End::Length(expr::parse(len)?),
+        None => End::Address(expr::parse(end)?),
+    };
+    Ok(Find {
+        start: expr::parse(start)?,
+        end,
+        values: values
+            .iter()
+            .map(|value| expr::parse(value))
+            .collect::<Result<_, _>>()?,
+        size,
+    })
+}
+
+/// split at `,` outside string literals
+fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut begin = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(args[begin..i].trim());
+                begin = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(args[begin..].trim());
+    parts
+}
+
+/// bytes of the value, a string without its NUL or a little endian integer
+/// an integer takes 4 bytes if it fits in 32 bits and `size` is None
+pub fn bytes(
+    debugger_info: &DebuggerInfo,
+    expr: &Expr,
+    size: Option<u64>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if let Expr::String(text) = expr {
+        return Ok(text.as_bytes().to_vec());
+    }
+    let value = expr::eval(expr, &debugger_info.scope())?.as_u64();
+    let size = size.unwrap_or(if value >> 32 == 0 { 4 } else { 8 });
+    Ok(value.to_le_bytes()[..size as usize].to_vec())
+}
+
+/// print every address in the range where the values appear in order
+pub fn find(debugger_info: &DebuggerInfo, find: &Find) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let start = examine::address(debugger_info, &find.start)?;
+    let end = match &find.end {
+        End::Address(end) => examine::address(debugger_info, end)?,
+        End::Length(len) => start.saturating_add(expr::eval(len, &debugger_info.scope())?.as_u64()),
+    };
+    if end <= start {
+        return Err(error("the end of the range is before the start"));
+    }
+    let mut needle = Vec::new();
+    for value in &find.values {
+        needle.extend(bytes(debugger_info, value, find.size)?);
+    }
+    if needle.is_empty() {
+        return Err(error("empty pattern"));
+    }
+
+    let symbols = symbol_ranges(debugger_info);
+    let cancel = Cancel::start();
+    let mut hits = 0;
+    for map in get_process_maps(pid.as_raw())? {
+        let (map_start, map_end) = (map.start() as u64, (map.start() + map.size()) as u64);
+        if !map.is_read() || map_end <= start || end <= map_start {
+            continue;
+        }
+        let name = map
+            .filename()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("[anon]"));
+        let hi = end.min(map_end);
+        let mut cur = start.max(map_start);
+        while cur < hi {
+            if cancel.is_cancelled() {
+                println!("interrupted at 0x{cur:016x}");
+                return Ok(());
+            }
+            // チャンクの境目をまたぐ一致も見つかるように少し重ねて読む
+            let len = (CHUNK_SIZE + needle.len() as u64 - 1).min(hi - cur);
+            // ワード単位で読むので、揃えないと最後のワードがマッピングの外にはみ出る
+            let base = cur & !7;
+            // vvarなどptraceで読めない領域は飛ばす
+            if let Ok(chunk) = mem::read_bytes(pid, base, (len + cur - base) as usize) {
+                let chunk = &chunk[(cur - base) as usize..];
+                for (offset, window) in chunk.windows(needle.len()).enumerate() {
+                    if offset as u64 >= CHUNK_SIZE {
+                        break;
+                    }
+                    if window == needle {
+                        let addr = cur + offset as u64;
+                        println!("0x{addr:016x} in {name}{}", symbol_at(&symbols, addr));
+                        hits += 1;
+                    }
+                }
+            }
+            cur += CHUNK_SIZE;
+        }
+    }
+    match hits {
+        0 => println!("pattern not found"),
+        1 => println!("1 pattern found"),
+        n => println!("{n} patterns found"),
+    }
+    Ok(())
+}
+
+/// (start, end, name) of the symbols with a size in the target
+fn symbol_ranges(debugger_info: &DebuggerInfo) -> Vec<(u64, u64, String)> {
+    let debug_info = &debugger_info.debug_info;
+    debug_info
+        .symbols()
+        .iter()
+        .filter(|sym| sym.size() > 0)
+        .filter_map(|sym| {
+            let start = debug_info.get_actual_symbol_address(sym)?;
+            Some((start, start + sym.size(), demangle(sym.name())))
+        })
+        .collect()
+}
+
+/// ` <name+offset>` of the symbol containing the address, empty if there is none
+fn symbol_at(symbols: &[(u64, u64, String)], addr: u64) -> String {
+    match symbols
+        .iter()
+        .find(|(start, end, _)| *start <= addr && addr < *end)
+    {
+        Some((start, _, name)) if *start == addr => format!(" <{name}>"),
+        Some((start, _, name)) => format!(" <{name}+{}>", addr - start),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,367 @@
+use crate::{
+    abi::{self, Class, Passing},
+    debugger::DebuggerInfo,
+    dprintf::split_args,
+    expr::{self, Expr, Value},
+    mem, register,
+    step::run_to_any,
+    symbolize::{demangle, split_path},
+    syscall::get_regs,
+    variable::{self, format_value, read_int, read_uint, Signature, Type},
+};
+use nix::{
+    sys::{ptrace, signal::Signal, wait::WaitStatus},
+    unistd::Pid,
+};
+use object::SymbolKind;
+use std::io;
+
+/// number of registers for integer arguments, rdi, rsi, rdx, rcx, r8 and r9
+const INTEGER_REGISTERS: usize = 6;
+/// number of registers for floating point arguments, xmm0 to xmm7
+const SSE_REGISTERS: usize = 8;
+/// area below rsp which the current function may use without moving rsp
+const RED_ZONE: u64 = 128;
+/// direction flag of rflags, cleared on function entry
+const DIRECTION_FLAG: u64 = 1 << 10;
+
+/// function called by `call`
+struct Callee {
+    addr: u64,
+    /// None if the target file has no debug info for it
+    signature: Option<Signature>,
+    rust: bool,
+}
+
+/// temporaries of the call placed below the red zone
+struct Stack {
+    pid: Pid,
+    sp: u64,
+}
+
+impl Stack {
+    /// returns the address of the bytes, aligned to 16 bytes
+    fn push(&mut self, bytes: &[u8]) -> Result<u64, nix::Error> {
+        self.sp = (self.sp - bytes.len() as u64) & !0xf;
+        mem::write_bytes(self.pid, self.sp, bytes)?;
+        Ok(self.sp)
+    }
+}
+
+fn error(kind: io::ErrorKind, msg: String) -> Box<dyn std::error::Error> {
+    Box::new(io::Error::new(kind, msg))
+}
+
+/// parse `func(args...)`
+pub fn parse(input: &str) -> Result<(String, Vec<Expr>), Box<dyn std::error::Error>> {
+    let usage = || {
+        error(
+            io::ErrorKind::InvalidInput,
+            "usage: call func(args...)".into(),
+        )
+    };
+    let (name, rest) = input.split_once('(').ok_or_else(usage)?;
+    let rest = rest.trim_end().strip_suffix(')').ok_or_else(usage)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(usage());
+    }
+    let args = match rest.trim() {
+        "" => Vec::new(),
+        rest => split_args(rest)
+            .iter()
+            .map(|arg| expr::parse(arg))
+            .collect::<Result<Vec<Expr>, _>>()?,
+    };
+    Ok((name.to_string(), args))
+}
+
+/// function named `name`, a symbol of the target file, a Rust path, or `module:symbol`
+fn function(
+    debugger_info: &DebuggerInfo,
+    name: &str,
+) -> Result<Callee, Box<dyn std::error::Error>> {
+    let debug_info = &debugger_info.debug_info;
+    let is_function = |kind: SymbolKind, addr: u64| kind == SymbolKind::Text && addr != 0;
+    let segments = split_path(name);
+    let symbol = debug_info
+        .find_symbol(name)
+        .filter(|f| is_function(f.kind(), f.address()))
+        .or_else(|| {
+            // Rustの関数はデマングルしたパスの末尾で探す
+            debug_info.symbols().iter().find(|f| {
+                is_function(f.kind(), f.address())
+                    && split_path(&demangle(f.name())).ends_with(&segments)
+            })
+        });
+    let addr = match (symbol, name.split_once(':')) {
+        (Some(f), _) => f.address() + debug_info.load_bias(),
+        (None, Some((module, symbol)))
+            if !module.is_empty() && !symbol.is_empty() && !symbol.starts_with(':') =>
+        {
+            debug_info.find_module_symbol(module, symbol)?
+        }
+        _ => {
+            return Err(error(
+                io::ErrorKind::NotFound,
+                format!("no function named {name}"),
+            ))
+        }
+    };
+    let file_pc = debug_info.to_file_addr(addr);
+    Ok(Callee {
+        addr,
+        signature: file_pc.and_then(variable::signature),
+        rust: file_pc.and_then(variable::language) == Some(gimli::DW_LANG_Rust),
+    })
+}
+
+fn base(name: &str, size: u64, encoding: gimli::DwAte) -> Type {
+    Type::Base {
+        name: String::from(name),
+        size,
+        encoding,
+    }
+}
+
+/// type of an argument without a declared parameter, by the default argument promotions of C
+fn promote(ty: &Type) -> Type {
+    match ty.canonical() {
+        Type::Base { size, .. } if ty.is_float() && size < 8 => {
+            base("double", 8, gimli::DW_ATE_float)
+        }
+        Type::Base { size, .. } | Type::Enum { size, .. } if !ty.is_float() && size < 4 => {
+            base("int", 4, gimli::DW_ATE_signed)
+        }
+        _ => ty.clone(),
+    }
+}
+
+fn is_signed(ty: &Type) -> bool {
+    match ty.canonical() {
+        Type::Base { encoding, .. } => {
+            encoding == gimli::DW_ATE_signed || encoding == gimli::DW_ATE_signed_char
+        }
+        Type::Enum { .. } => true,
+        _ => false,
+    }
+}
+
+/// arrays are passed as pointers to their first elements
+/// those computed by the debugger such as string literals are copied onto the stack
+fn decay(value: Value, stack: &mut Stack) -> Result<Value, nix::Error> {
+    let elem = match value.ty.canonical() {
+        Type::Array { elem, .. } => *elem,
+        _ => return Ok(value),
+    };
+    let addr = match value.addr() {
+        Some(addr) => addr,
+        None => {
+            // 文字列として渡せるように終端のNULを付ける
+            let mut bytes = value.bytes.clone();
+            bytes.push(0);
+            stack.push(&bytes)?
+        }
+    };
+    Ok(Value::pointer(elem, addr))
+}
+
+/// bytes of the value converted to the type of the parameter
+fn convert(value: &Value, ty: &Type) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let size = ty.size() as usize;
+    match ty.canonical() {
+        Type::Base { .. } if ty.is_float() => match size {
+            4 => Ok((value.as_f64() as f32).to_le_bytes().to_vec()),
+            8 => Ok(value.as_f64().to_le_bytes().to_vec()),
+            _ => Err(error(
+                io::ErrorKind::Unsupported,
+                format!("arguments of type {} can not be passed", ty.name()),
+            )),
+        },
+        Type::Base { .. } | Type::Enum { .. } | Type::Pointer { .. } if size <= 16 => {
+            Ok(value.as_u128().to_le_bytes()[..size].to_vec())
+        }
+        _ if value.bytes.len() == size => Ok(value.bytes.clone()),
+        _ => Err(error(
+            io::ErrorKind::InvalidInput,
+            format!("can not pass {} as {}", value.ty.name(), ty.name()),
+        )),
+    }
+}
+
+fn set_integer(regs: &mut nix::libc::user_regs_struct, n: usize, value: u64) {
+    match n {
+        0 => regs.rdi = value,
+        1 => regs.rsi = value,
+        2 => regs.rdx = value,
+        3 => regs.rcx = value,
+        4 => regs.r8 = value,
+        _ => regs.r9 = value,
+    }
+}
+
+/// call the function in the target with the arguments and print the value it returns
+/// the registers are restored after the call, returns the status if the target terminated
+pub fn call(
+    debugger_info: &mut DebuggerInfo,
+    name: &str,
+    args: &[Expr],
+) -> Result<Option<WaitStatus>, Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let callee = function(debugger_info, name)?;
+    let scope = debugger_info.scope();
+    let values = args
+        .iter()
+        .map(|arg| expr::eval(arg, &scope))
+        .collect::<Result<Vec<Value>, _>>()?;
+    if let Some(signature) = &callee.signature {
+        let count = signature.params.len();
+        if values.len() < count || (values.len() > count && !signature.variadic) {
+            return Err(error(
+                io::ErrorKind::InvalidInput,
+                format!("{name} takes {count} arguments but {} given", values.len()),
+            ));
+        }
+    }
+    // デバッグ情報がなければintを返すとみなす
+    let ret = match &callee.signature {
+        Some(signature) => signature.ret.clone(),
+        None => base("int", 4, gimli::DW_ATE_signed),
+    };
+
+    let saved = get_regs(pid);
+    let saved_fp = register::get_fpregs(pid)?;
+    let selected_frame = debugger_info.selected_frame;
+    let mut stack = Stack {
+        pid,
+        sp: saved.rsp - RED_ZONE,
+    };
+    let mut integer: Vec<u64> = Vec::new();
+    let mut sse: Vec<[u8; 16]> = Vec::new();
+    let mut on_stack: Vec<Vec<u8>> = Vec::new();
+
+    // 戻り値をメモリに返す関数には書き込み先を最初の引数として渡す
+    if abi::returns_in_memory(&ret, callee.rust) {
+        integer.push(stack.push(&vec![0; ret.size() as usize])?);
+    }
+    for (i, value) in values.into_iter().enumerate() {
+        let value = decay(value, &mut stack)?;
+        let ty = match callee.signature.as_ref().and_then(|s| s.params.get(i)) {
+            Some(ty) => ty.clone(),
+            None => promote(&value.ty),
+        };
+        let bytes = convert(&value, &ty)?;
+        let passing = abi::passing(&ty, callee.rust).ok_or_else(|| {
+            error(
+                io::ErrorKind::Unsupported,
+                format!("arguments of type {} can not be passed", ty.name()),
+            )
+        })?;
+        match passing {
+            Passing::Registers(parts) => {
+                let ints = parts.iter().filter(|(.., c)| *c == Class::Integer).count();
+                // 一部でもレジスタに入りきらなければ全体をスタックに置く
+                if integer.len() + ints > INTEGER_REGISTERS
+                    || sse.len() + parts.len() - ints > SSE_REGISTERS
+                {
+                    on_stack.push(bytes);
+                    continue;
+                }
+                for (offset, len, class) in parts {
+                    let part = &bytes[offset as usize..(offset + len) as usize];
+                    match class {
+                        // 符号付き整数は64ビットに符号拡張して渡す
+                        Class::Integer if part.len() == bytes.len() && is_signed(&ty) => {
+                            integer.push(read_int(part) as u64)
+                        }
+                        Class::Integer => integer.push(read_uint(part)),
+                        _ => {
+                            let mut xmm = [0; 16];
+                            xmm[..part.len()].copy_from_slice(part);
+                            sse.push(xmm);
+                        }
+                    }
+                }
+            }
+            Passing::Stack => on_stack.push(bytes),
+            Passing::Reference => {
+                let addr = stack.push(&bytes)?;
+                match integer.len() < INTEGER_REGISTERS {
+                    true => integer.push(addr),
+                    false => on_stack.push(addr.to_le_bytes().to_vec()),
+                }
+            }
+        }
+    }
+
+    // スタック渡しの引数の先頭を16バイト境界に揃え、その下に戻りアドレスを積む
+    let slot = |bytes: &Vec<u8>| (bytes.len() as u64).div_ceil(8) * 8;
+    let mut sp = (stack.sp - on_stack.iter().map(slot).sum::<u64>()) & !0xf;
+    let mut addr = sp;
+    for bytes in &on_stack {
+        mem::write_bytes(pid, addr, bytes)?;
+        addr += slot(bytes);
+    }
+    // 戻り先はもう実行されないエントリポイントにしてint3を置く
+    let trampoline = debugger_info.debug_info.entry_address();
+    sp -= 8;
+    mem::write_bytes(pid, sp, &trampoline.to_le_bytes())?;
+
+    let mut regs = saved;
+    for (n, value) in integer.iter().enumerate() {
+        set_integer(&mut regs, n, *value);
+    }
+    // 可変長引数の関数はalで使われたxmmレジスタの数を受け取る
+    regs.rax = sse.len() as u64;
+    regs.rip = callee.addr;
+    regs.rsp = sp;
+    regs.eflags &= !DIRECTION_FLAG;
+    // システムコールの途中で止まっていても再開させない
+    regs.orig_rax = u64::MAX;
+    ptrace::setregs(pid, regs)?;
+    for (n, xmm) in sse.iter().enumerate() {
+        register::set_xmm(pid, n, xmm)?;
+    }
+
+    let restore = |debugger_info: &mut DebuggerInfo| -> Result<(), nix::Error> {
+        ptrace::setregs(pid, saved)?;
+        register::set_fpregs(pid, saved_fp)?;
+        debugger_info.selected_frame = selected_frame;
+        Ok(())
+    };
+    let (status, reached) = match run_to_any(debugger_info, &[(trampoline, sp)]) {
+        Ok(result) => result,
+        Err(e) => {
+            let _ = restore(debugger_info);
+            return Err(e);
+        }
+    };
+    if let WaitStatus::Exited(..) | WaitStatus::Signaled(..) = status {
+        println!("the program terminated during the call of {name}");
+        return Ok(Some(status));
+    }
+    let value = match (reached, ret) {
+        (None, _) => {
+            if let WaitStatus::Stopped(_, signal) = status {
+                if signal != Signal::SIGTRAP {
+                    println!("process {pid} received {signal}");
+                }
+            }
+            println!("the call of {name} was interrupted, the state before the call is restored");
+            // 呼び出した関数の中のブレークポイントのコマンドは実行しない
+            debugger_info.pending_commands.clear();
+            None
+        }
+        (Some(_), Type::Void) => None,
+        (Some(_), ty) => Some(
+            abi::return_value(pid, &ty, callee.rust).map(|bytes| format_value(pid, &ty, &bytes)),
+        ),
+    };
+    restore(debugger_info)?;
+    match value {
+        Some(Ok(value)) => println!("{value}"),
+        Some(Err(e)) => println!("{e}"),
+        None => {}
+    }
+    Ok(None)
+}
@@ -0,0 +1,121 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+/// name given by the user to [addr, addr + len)
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub addr: u64,
+    pub len: u64,
+    pub name: String,
+}
+
+impl Label {
+    fn contains(&self, addr: u64) -> bool {
+        self.addr <= addr && addr < self.addr.saturating_add(self.len.max(1))
+    }
+}
+
+/// labels of the session, saved to `<target>.labels` by default
+#[derive(Debug)]
+pub struct Labels {
+    pub path: PathBuf,
+    labels: Vec<Label>,
+}
+
+impl Labels {
+    /// load the default file of the target if it exists
+    pub fn new(target: &str) -> Self {
+        let path = PathBuf::from(format!("{target}.labels"));
+        let mut labels = Self {
+            path,
+            labels: Vec::new(),
+        };
+        if labels.path.exists() {
+            let path = labels.path.clone();
+            if let Err(e) = labels.load(&path) {
+                println!("cannot load labels from {}: {}", path.display(), e);
+            }
+        }
+        labels
+    }
+
+    /// add the label, a label at the same address is replaced
+    pub fn add(&mut self, addr: u64, len: u64, name: String) {
+        self.labels.retain(|label| label.addr != addr);
+        self.labels.push(Label { addr, len, name });
+        self.labels.sort_by_key(|label| label.addr);
+    }
+
+    /// remove the labels with the name or at the address, returns false if none
+    pub fn remove(&mut self, name: &str, addr: Option<u64>) -> bool {
+        let len = self.labels.len();
+        self.labels
+            .retain(|label| label.name != name && Some(label.addr) != addr);
+        self.labels.len() != len
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Label> {
+        self.labels.iter()
+    }
+
+    /// `name` or `name+0x10` of the innermost label containing the address
+    pub fn lookup(&self, addr: u64) -> Option<String> {
+        let label = self
+            .labels
+            .iter()
+            .filter(|label| label.contains(addr))
+            .min_by_key(|label| label.len)?;
+        Some(match addr - label.addr {
+            0 => label.name.clone(),
+            offset => format!("{}+0x{:x}", label.name, offset),
+        })
+    }
+
+    /// label of the address, or of the first label starting in [addr, addr + len)
+    pub fn lookup_range(&self, addr: u64, len: u64) -> Option<String> {
+        self.lookup(addr).or_else(|| {
+            self.labels
+                .iter()
+                .find(|label| addr < label.addr && label.addr < addr.saturating_add(len))
+                .map(|label| label.name.clone())
+        })
+    }
+
+    /// one label per line: `<addr> <len> <name>`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut text = String::new();
+        for label in &self.labels {
+            let _ = writeln!(text, "0x{:x} {} {}", label.addr, label.len, label.name);
+        }
+        fs::write(path, text)
+    }
+
+    /// add the labels in the file, returns the number of them
+    pub fn load(&mut self, path: &Path) -> io::Result<usize> {
+        let text = fs::read_to_string(path)?;
+        let mut count = 0;
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = || io::Error::new(ErrorKind::InvalidData, format!("line {}", i + 1));
+            let mut fields = line.trim().splitn(3, ' ');
+            let addr = fields
+                .next()
+                .and_then(|addr| u64::from_str_radix(addr.trim_start_matches("0x"), 16).ok())
+                .ok_or_else(invalid)?;
+            let len = fields
+                .next()
+                .and_then(|len| len.parse::<u64>().ok())
+                .ok_or_else(invalid)?;
+            let name = fields.next().ok_or_else(invalid)?;
+            self.add(addr, len, name.to_string());
+            count += 1;
+        }
+        Ok(count)
+    }
+}
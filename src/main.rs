@@ -1,31 +1,54 @@
+mod abi;
 mod args;
+mod batch;
+mod bisect;
+mod bounded;
 mod breakpoint;
 mod call_vmm;
+mod checkpoint;
 mod command;
+mod config;
 mod debug_info;
 mod debugger;
+mod debugreg;
+mod disasm;
+mod displaced;
+mod doctor;
+mod dprintf;
 mod dump;
+mod examine;
+mod expr;
+mod find;
 mod fini;
+mod infcall;
+mod label;
 mod mem;
+mod overlay;
+mod pagewatch;
+mod pattern;
+mod pretty;
+mod record;
 mod register;
+mod sharedlib;
 mod signal;
+mod step;
+mod symbolize;
 mod syscall;
 mod target;
+mod thread;
+mod unwind;
 mod util;
+mod variable;
 
-use std::path::Path;
-
-use args::Args;
+use args::{Args, Mode};
+use batch::batch_main;
 use clap::StructOpt;
-use debugger::debugger_main;
+use debugger::{attach_main, debugger_main};
 use nix::{
     sys::personality::{self, Persona},
-    unistd::{
-        fork,
-        ForkResult::{Child, Parent},
-    },
+    unistd::Pid,
 };
-use target::target_main;
+use std::fs;
 
 fn main() {
     let args = Args::parse();
@@ -36,16 +59,25 @@ fn main() {
         panic!("failed to disable ASLR {e}");
     }
 
-    let pid = unsafe { fork() };
-    let pid = match pid {
-        Ok(fork_result) => fork_result,
-        Err(e) => panic!("fork error: ERRNO = {e}"),
+    if let Some(Mode::Batch(batch_args)) = args.mode {
+        return batch_main(batch_args);
+    }
+    let script = args.script.map(|path| match fs::read_to_string(&path) {
+        Ok(script) => script
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_string())
+            .collect(),
+        Err(e) => panic!("cannot read {path}: {e}"),
+    });
+    let file = match (args.file, args.pid) {
+        (_, Some(pid)) => return attach_main(Pid::from_raw(pid), script),
+        (Some(file), None) => file,
+        (None, None) => unreachable!("clap requires the file without --pid"),
     };
-    match pid {
-        Parent { child } => debugger_main(child, &args.file),
-        Child => target_main(
-            Path::new(&args.file),
-            &args.args.iter().map(|s| &**s).collect::<Vec<&str>>(),
-        ),
+    match target::spawn(&file, &args.args) {
+        Ok(child) => debugger_main(child, &file, args.args, script),
+        Err(e) => panic!("fork error: ERRNO = {e}"),
     }
 }
@@ -1,9 +1,30 @@
-use nix::{libc::c_void, sys::ptrace, unistd::Pid};
+use nix::{
+    libc::c_void,
+    sys::{
+        ptrace,
+        signal::Signal,
+        uio::{process_vm_readv, process_vm_writev, RemoteIoVec},
+    },
+    unistd::Pid,
+};
 use proc_maps::{get_process_maps, MapRange};
-use std::{io, path::Path};
+use std::{
+    collections::HashMap,
+    io::{self, IoSlice, IoSliceMut},
+    path::Path,
+    sync::Mutex,
+};
 
 pub fn get_mmap_info(pid: Pid, filename: &str) -> Result<Vec<MapRange>, io::Error> {
     let proc_filename = Path::new(filename).file_name().unwrap().to_str().unwrap();
+    // exec前の子プロセスにもtdbがmmapした対象ファイルが見えるので、実行中のファイルも確かめる
+    let exe = std::fs::read_link(format!("/proc/{pid}/exe"))?;
+    if exe.file_name().and_then(|name| name.to_str()) != Some(proc_filename) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "target is not executed yet",
+        ));
+    }
     let maps = get_process_maps(pid.as_raw()).unwrap();
     let mut is_file_mapped = false;
 
@@ -34,8 +55,214 @@ pub struct Memory {
 
 impl Memory {
     pub fn write_value(&self, pid: Pid) {
+        clear_cache();
         unsafe {
             ptrace::write(pid, self.addr as *mut c_void, self.value as *mut c_void).unwrap();
         }
     }
 }
+
+/// how a breakpoint or watchpoint uses its address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// code is executed there
+    Execute,
+    /// data is read or written there
+    Data,
+    /// data is written there, the mapping has to be writable
+    Write,
+}
+
+/// check that [addr, addr + len) is mapped in a way that suits `access`
+/// every mapping in the range is checked, so a hole in the middle is also an error
+pub fn check_mapping(pid: Pid, addr: u64, len: u64, access: Access) -> Result<(), io::Error> {
+    let maps = get_process_maps(pid.as_raw())?;
+    let last = addr + len.max(1) - 1;
+    let mut cur = addr;
+    loop {
+        let map = maps
+            .iter()
+            .find(|map| map.start() as u64 <= cur && cur < (map.start() + map.size()) as u64)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("address 0x{cur:016x} is not mapped"),
+                )
+            })?;
+        let usable = match access {
+            Access::Execute => map.is_exec(),
+            Access::Data => map.is_read(),
+            Access::Write => map.is_write(),
+        };
+        if !usable {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("address 0x{cur:016x} is in {}", describe_mapping(map)),
+            ));
+        }
+        let end = (map.start() + map.size()) as u64;
+        if last < end {
+            return Ok(());
+        }
+        cur = end;
+    }
+}
+
+/// e.g. "a read-only data mapping of libc.so.6"
+fn describe_mapping(map: &MapRange) -> String {
+    let kind = if map.is_exec() {
+        "a code"
+    } else if !map.is_read() {
+        "an inaccessible"
+    } else if map.is_write() {
+        "a writable data"
+    } else {
+        "a read-only data"
+    };
+    let name = match map.filename() {
+        // [heap]や[stack]はそのまま表示する
+        Some(path) if path.starts_with("[") => path.to_string_lossy().into_owned(),
+        Some(path) => path
+            .file_name()
+            .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+            .into_owned(),
+        None => String::from("anonymous memory"),
+    };
+    format!("{kind} mapping of {name}")
+}
+
+/// bytes moved by a PTRACE_PEEKDATA or PTRACE_POKEDATA
+const WORD_SIZE: u64 = 8;
+
+/// longer reads such as those of `find` and `dump memory` are not cached
+const MAX_CACHED_READ: usize = 4096;
+
+/// the cache is emptied when it holds more bytes than this
+const CACHE_LIMIT: usize = 1 << 20;
+
+/// bytes read at a stop, keyed by (pid, address, length)
+/// emptied when the target runs or tdb writes its memory
+#[derive(Debug, Default)]
+struct Cache {
+    reads: HashMap<(Pid, u64, usize), Vec<u8>>,
+    size: usize,
+}
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+/// forget the cached reads, the memory of the target may have changed
+pub fn clear_cache() {
+    *CACHE.lock().unwrap() = None;
+}
+
+/// ptrace::cont after clearing the cache
+pub fn cont<T: Into<Option<Signal>>>(pid: Pid, sig: T) -> Result<(), nix::Error> {
+    clear_cache();
+    ptrace::cont(pid, sig)
+}
+
+/// ptrace::step after clearing the cache
+pub fn step<T: Into<Option<Signal>>>(pid: Pid, sig: T) -> Result<(), nix::Error> {
+    clear_cache();
+    ptrace::step(pid, sig)
+}
+
+/// ptrace::syscall after clearing the cache
+pub fn syscall<T: Into<Option<Signal>>>(pid: Pid, sig: T) -> Result<(), nix::Error> {
+    clear_cache();
+    ptrace::syscall(pid, sig)
+}
+
+/// ptrace::detach after clearing the cache
+pub fn detach<T: Into<Option<Signal>>>(pid: Pid, sig: T) -> Result<(), nix::Error> {
+    clear_cache();
+    ptrace::detach(pid, sig)
+}
+
+/// read `len` bytes from the memory of the target, small reads are cached until it runs
+pub fn read_bytes(pid: Pid, addr: u64, len: usize) -> Result<Vec<u8>, nix::Error> {
+    if len > MAX_CACHED_READ {
+        return read_uncached(pid, addr, len);
+    }
+    let key = (pid, addr, len);
+    if let Some(bytes) = CACHE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|cache| cache.reads.get(&key))
+    {
+        return Ok(bytes.clone());
+    }
+    let bytes = read_uncached(pid, addr, len)?;
+    let mut cache = CACHE.lock().unwrap();
+    let cache = cache.get_or_insert_with(Cache::default);
+    if cache.size + len > CACHE_LIMIT {
+        *cache = Cache::default();
+    }
+    cache.size += len;
+    cache.reads.insert(key, bytes.clone());
+    Ok(bytes)
+}
+
+/// process_vm_readv reads the bytes at once, PTRACE_PEEKDATA is used if it fails
+fn read_uncached(pid: Pid, addr: u64, len: usize) -> Result<Vec<u8>, nix::Error> {
+    let mut bytes = vec![0; len];
+    let remote = [RemoteIoVec {
+        base: addr as usize,
+        len,
+    }];
+    // 読み取り権限のないページはptraceでないと読めない
+    match process_vm_readv(pid, &mut [IoSliceMut::new(&mut bytes)], &remote) {
+        Ok(read) if read == len => Ok(bytes),
+        _ => peek_bytes(pid, addr, len),
+    }
+}
+
+/// write `bytes` to the memory of the target
+/// process_vm_writev writes them at once, PTRACE_POKEDATA is used if it fails
+pub fn write_bytes(pid: Pid, addr: u64, bytes: &[u8]) -> Result<(), nix::Error> {
+    clear_cache();
+    let remote = [RemoteIoVec {
+        base: addr as usize,
+        len: bytes.len(),
+    }];
+    // ブレークポイントを書くコード領域は書き込み権限がないのでptraceで書く
+    match process_vm_writev(pid, &[IoSlice::new(bytes)], &remote) {
+        Ok(written) if written == bytes.len() => Ok(()),
+        _ => poke_bytes(pid, addr, bytes),
+    }
+}
+
+/// aligned words which contain [addr, addr + len), they never cross a page
+fn words(addr: u64, len: usize) -> impl Iterator<Item = u64> {
+    let end = addr + len as u64;
+    (addr & !(WORD_SIZE - 1)..end).step_by(WORD_SIZE as usize)
+}
+
+fn peek_bytes(pid: Pid, addr: u64, len: usize) -> Result<Vec<u8>, nix::Error> {
+    let mut bytes = Vec::with_capacity(len + WORD_SIZE as usize * 2);
+    for word in words(addr, len) {
+        bytes.extend_from_slice(&ptrace::read(pid, word as *mut c_void)?.to_le_bytes());
+    }
+    let skip = (addr & (WORD_SIZE - 1)) as usize;
+    Ok(bytes[skip..skip + len].to_vec())
+}
+
+fn poke_bytes(pid: Pid, addr: u64, bytes: &[u8]) -> Result<(), nix::Error> {
+    let end = addr + bytes.len() as u64;
+    for word in words(addr, bytes.len()) {
+        // 範囲外のバイトは元の値と合成する
+        let mut value = ptrace::read(pid, word as *mut c_void)?.to_le_bytes();
+        let (start, stop) = (word.max(addr), (word + WORD_SIZE).min(end));
+        value[(start - word) as usize..(stop - word) as usize]
+            .copy_from_slice(&bytes[(start - addr) as usize..(stop - addr) as usize]);
+        unsafe {
+            ptrace::write(
+                pid,
+                word as *mut c_void,
+                i64::from_le_bytes(value) as *mut c_void,
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,207 @@
+use crate::variable::{Member, Type};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+/// struct layouts defined by the user for memory without debug information
+#[derive(Debug, Default)]
+pub struct Overlays {
+    /// type and alignment of each struct
+    structs: BTreeMap<String, (Type, u64)>,
+}
+
+/// field names and types as typed
+type Fields = Vec<(String, String)>;
+
+fn error(msg: impl Into<String>) -> Box<dyn std::error::Error> {
+    Box::new(Error::new(ErrorKind::InvalidInput, msg.into()))
+}
+
+fn base(name: &str, size: u64, encoding: gimli::DwAte) -> (Type, u64) {
+    let ty = Type::Base {
+        name: name.to_string(),
+        size,
+        encoding,
+    };
+    (ty, size)
+}
+
+impl Overlays {
+    pub fn get(&self, name: &str) -> Option<&Type> {
+        self.structs.get(name).map(|(ty, _)| ty)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Type> {
+        self.structs.values().map(|(ty, _)| ty)
+    }
+
+    /// `[packed] Name { field: type, ... }`, returns the name
+    pub fn define(&mut self, def: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let usage = || error("usage: struct [packed] Name { field: type, ... }");
+        let (head, body) = def.split_once('{').ok_or_else(usage)?;
+        let body = body.trim().strip_suffix('}').ok_or_else(usage)?;
+        let (packed, name) = match head.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["packed", name] => (true, name),
+            [name] => (false, name),
+            _ => return Err(usage()),
+        };
+        let fields = body
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                let (name, ty) = field
+                    .split_once(':')
+                    .ok_or_else(|| error(format!("expected `name: type` but got `{field}`")))?;
+                Ok((name.trim().to_string(), ty.trim().to_string()))
+            })
+            .collect::<Result<Fields, Box<dyn std::error::Error>>>()?;
+        self.insert(name, &fields, packed)?;
+        Ok(name.to_string())
+    }
+
+    /// load `[Name]` tables whose keys are fields and values are types, returns the names
+    /// `packed = true` in a table removes the padding
+    pub fn load(&mut self, path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path)?;
+        let mut tables: Vec<(String, Fields, bool)> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || error(format!("{}:{}: invalid line", path.display(), i + 1));
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name.strip_suffix(']').ok_or_else(invalid)?;
+                tables.push((name.trim().trim_matches('"').to_string(), Vec::new(), false));
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+            let (key, value) = (key.trim().trim_matches('"'), value.trim());
+            let (_, fields, packed) = tables.last_mut().ok_or_else(invalid)?;
+            if key == "packed" {
+                *packed = value == "true";
+                continue;
+            }
+            fields.push((key.to_string(), value.trim_matches('"').to_string()));
+        }
+        let mut names = Vec::new();
+        for (name, fields, packed) in tables {
+            self.insert(&name, &fields, packed)?;
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    /// lay out the fields like a C struct
+    fn insert(
+        &mut self,
+        name: &str,
+        fields: &[(String, String)],
+        packed: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if fields.is_empty() {
+            return Err(error(format!("struct {name} has no fields")));
+        }
+        let mut members = Vec::new();
+        let mut offset = 0u64;
+        let mut align = 1;
+        for (field, ty) in fields {
+            let (ty, field_align) = self.parse_type(ty)?;
+            let field_align = if packed { 1 } else { field_align };
+            offset = offset.div_ceil(field_align) * field_align;
+            let size = ty.size();
+            members.push(Member {
+                name: field.clone(),
+                offset,
+                ty,
+            });
+            offset += size;
+            align = align.max(field_align);
+        }
+        let ty = Type::Struct {
+            name: name.to_string(),
+            size: offset.div_ceil(align) * align,
+            members,
+            params: Vec::new(),
+            variant_part: None,
+        };
+        self.structs.insert(name.to_string(), (ty, align));
+        Ok(())
+    }
+
+    /// `u8`..`u64`, `i8`..`i64`, `f32`, `f64`, `bool`, `char`, `*T`, `[T; N]` or a defined struct
+    fn parse_type(&self, text: &str) -> Result<(Type, u64), Box<dyn std::error::Error>> {
+        let text = text.trim();
+        if let Some(target) = text.strip_prefix('*') {
+            let target = match target.trim() {
+                "void" => Type::Void,
+                // 自分自身などまだ定義されていない構造体も指せる
+                target if is_ident(target) && self.parse_type(target).is_err() => {
+                    Type::Unknown(target.to_string())
+                }
+                target => self.parse_type(target)?.0,
+            };
+            let ty = Type::Pointer {
+                name: None,
+                target: Box::new(target),
+            };
+            return Ok((ty, 8));
+        }
+        if let Some(array) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            let (elem, count) = array
+                .rsplit_once(';')
+                .ok_or_else(|| error(format!("expected `[type; count]` but got `{text}`")))?;
+            let count = count
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| error(format!("invalid array length in `{text}`")))?;
+            let (elem, align) = self.parse_type(elem)?;
+            let ty = Type::Array {
+                elem: Box::new(elem),
+                count: Some(count),
+            };
+            return Ok((ty, align));
+        }
+        let ty = match text {
+            "u8" => base(text, 1, gimli::DW_ATE_unsigned),
+            "u16" => base(text, 2, gimli::DW_ATE_unsigned),
+            "u32" => base(text, 4, gimli::DW_ATE_unsigned),
+            "u64" => base(text, 8, gimli::DW_ATE_unsigned),
+            "i8" => base(text, 1, gimli::DW_ATE_signed),
+            "i16" => base(text, 2, gimli::DW_ATE_signed),
+            "i32" => base(text, 4, gimli::DW_ATE_signed),
+            "i64" => base(text, 8, gimli::DW_ATE_signed),
+            "f32" => base(text, 4, gimli::DW_ATE_float),
+            "f64" => base(text, 8, gimli::DW_ATE_float),
+            "bool" => base(text, 1, gimli::DW_ATE_boolean),
+            "char" => base(text, 1, gimli::DW_ATE_signed_char),
+            name => self
+                .structs
+                .get(name)
+                .cloned()
+                .ok_or_else(|| error(format!("unknown type `{name}`")))?,
+        };
+        Ok(ty)
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// remove `# comment` outside of quotes
+pub fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
@@ -0,0 +1,130 @@
+use crate::{mem, syscall::get_regs};
+use nix::{
+    libc::{self, c_int},
+    sys::{
+        ptrace,
+        signal::Signal,
+        wait::{waitpid, WaitStatus},
+    },
+    unistd::Pid,
+};
+use proc_maps::get_process_maps;
+use std::io;
+
+pub const PAGE_SIZE: u64 = 4096;
+
+/// si_code of SIGSEGV raised by a page without the permission
+const SEGV_ACCERR: i32 = 2;
+
+/// page watched by removing its write permission
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    pub addr: u64,
+    /// PROT_* before the watchpoint was set
+    pub prot: c_int,
+}
+
+/// pages which contain [addr, addr + len) with their current protection
+pub fn pages(pid: Pid, addr: u64, len: u64) -> Result<Vec<Page>, Box<dyn std::error::Error>> {
+    let maps = get_process_maps(pid.as_raw())?;
+    let start = addr / PAGE_SIZE * PAGE_SIZE;
+    let end = (addr + len).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+    let mut pages = Vec::new();
+    for page in (start..end).step_by(PAGE_SIZE as usize) {
+        let map = maps
+            .iter()
+            .find(|m| (m.start() as u64) <= page && page < (m.start() + m.size()) as u64)
+            .ok_or(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("0x{page:016x} is not mapped"),
+            ))?;
+        if !map.is_write() {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("0x{page:016x} is not writable"),
+            )));
+        }
+        let mut prot = libc::PROT_WRITE;
+        if map.is_read() {
+            prot |= libc::PROT_READ;
+        }
+        if map.is_exec() {
+            prot |= libc::PROT_EXEC;
+        }
+        pages.push(Page { addr: page, prot });
+    }
+    Ok(pages)
+}
+
+/// remove the write permission of the pages
+pub fn protect(pid: Pid, pages: &[Page]) -> Result<(), nix::Error> {
+    for page in pages {
+        mprotect(pid, page.addr, page.prot & !libc::PROT_WRITE)?;
+    }
+    Ok(())
+}
+
+/// give the original permission back to the pages
+pub fn unprotect(pid: Pid, pages: &[Page]) -> Result<(), nix::Error> {
+    for page in pages {
+        mprotect(pid, page.addr, page.prot)?;
+    }
+    Ok(())
+}
+
+/// address which the target failed to write because of a protected page
+pub fn fault_addr(pid: Pid) -> Option<u64> {
+    let siginfo = ptrace::getsiginfo(pid).ok()?;
+    if siginfo.si_signo != Signal::SIGSEGV as i32 || siginfo.si_code != SEGV_ACCERR {
+        return None;
+    }
+    Some(unsafe { siginfo.si_addr() } as u64)
+}
+
+fn mprotect(pid: Pid, addr: u64, prot: c_int) -> Result<(), nix::Error> {
+    let ret = inject_syscall(
+        pid,
+        libc::SYS_mprotect as u64,
+        &[addr, PAGE_SIZE, prot as u64],
+    )?;
+    if ret < 0 {
+        return Err(nix::Error::from_i32(-ret as i32));
+    }
+    Ok(())
+}
+
+/// execute a syscall in the target and restore its registers and code
+fn inject_syscall(pid: Pid, number: u64, args: &[u64]) -> Result<i64, nix::Error> {
+    let saved_regs = get_regs(pid);
+    let rip = saved_regs.rip;
+    let saved_code = mem::read_bytes(pid, rip, 2)?;
+    // syscall命令
+    mem::write_bytes(pid, rip, &[0x0f, 0x05])?;
+
+    let mut regs = saved_regs;
+    regs.rax = number;
+    // 止まっていたシステムコールの再開処理をさせない
+    regs.orig_rax = u64::MAX;
+    let arg_regs = [
+        &mut regs.rdi,
+        &mut regs.rsi,
+        &mut regs.rdx,
+        &mut regs.r10,
+        &mut regs.r8,
+        &mut regs.r9,
+    ];
+    for (reg, arg) in arg_regs.into_iter().zip(args) {
+        *reg = *arg;
+    }
+    ptrace::setregs(pid, regs)?;
+    mem::step(pid, None)?;
+    let status = waitpid(pid, None)?;
+    let ret = get_regs(pid).rax as i64;
+
+    mem::write_bytes(pid, rip, &saved_code)?;
+    ptrace::setregs(pid, saved_regs)?;
+    match status {
+        WaitStatus::Stopped(_, Signal::SIGTRAP) => Ok(ret),
+        _ => Err(nix::Error::ESRCH),
+    }
+}
@@ -0,0 +1,183 @@
+use crate::util::parse_demical_or_hex;
+use std::io;
+
+/// letters of the cyclic pattern, the same as pwntools
+const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+/// every substring of this length appears only once in the cyclic pattern
+pub const SUBSEQUENCE_LEN: usize = 4;
+
+/// bytes repeated by `fill`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// a byte or a string
+    Bytes(Vec<u8>),
+    /// De Bruijn sequence of `ALPHABET`, which `pattern offset` finds offsets in
+    Cyclic,
+}
+
+fn error(msg: impl Into<String>) -> Box<dyn std::error::Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidInput, msg.into()))
+}
+
+impl Pattern {
+    /// `0xaa`, `"ABCD"` with `\n`, `\0`, `\xNN` escapes, or `cyclic`
+    pub fn parse(text: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if text == "cyclic" {
+            return Ok(Pattern::Cyclic);
+        }
+        if let Some(quoted) = text.strip_prefix('"') {
+            let bytes = unescape(quoted)?;
+            if bytes.is_empty() {
+                return Err(error("empty pattern"));
+            }
+            return Ok(Pattern::Bytes(bytes));
+        }
+        match parse_demical_or_hex(text) {
+            Ok(byte) if byte <= 0xff => Ok(Pattern::Bytes(vec![byte as u8])),
+            _ => Err(error(format!(
+                "{text} is not a byte, a \"string\" or cyclic"
+            ))),
+        }
+    }
+
+    /// the pattern repeated to `len` bytes
+    pub fn bytes(&self, len: usize) -> Vec<u8> {
+        match self {
+            Pattern::Bytes(bytes) => bytes.iter().copied().cycle().take(len).collect(),
+            Pattern::Cyclic => cyclic(len),
+        }
+    }
+}
+
+/// contents of a string literal after the opening quote
+fn unescape(quoted: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut bytes = Vec::new();
+    let mut chars = quoted.chars();
+    loop {
+        match chars.next() {
+            Some('"') if chars.as_str().is_empty() => return Ok(bytes),
+            Some('\\') => match chars.next() {
+                Some('n') => bytes.push(b'\n'),
+                Some('t') => bytes.push(b'\t'),
+                Some('0') => bytes.push(0),
+                Some(c @ ('"' | '\\')) => bytes.push(c as u8),
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    let byte = u8::from_str_radix(&hex, 16)
+                        .map_err(|_| error(format!("invalid escape sequence \\x{hex}")))?;
+                    bytes.push(byte);
+                }
+                Some(c) => return Err(error(format!("unknown escape sequence \\{c}"))),
+                None => return Err(error("unterminated string")),
+            },
+            Some('"') => return Err(error("unexpected characters after the string")),
+            Some(c) => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            None => return Err(error("unterminated string")),
+        }
+    }
+}
+
+/// first `len` bytes of the De Bruijn sequence B(26, 4), repeated if `len` is longer
+pub fn cyclic(len: usize) -> Vec<u8> {
+    let sequence = de_bruijn(ALPHABET.len(), SUBSEQUENCE_LEN);
+    sequence
+        .iter()
+        .map(|i| ALPHABET[*i])
+        .cycle()
+        .take(len)
+        .collect()
+}
+
+/// offset of `needle` in the cyclic pattern, None if it is not a part of it
+pub fn offset(needle: &[u8]) -> Option<usize> {
+    let period = ALPHABET.len().pow(SUBSEQUENCE_LEN as u32);
+    // 周期の境目をまたぐ部分も探せるように少し長く作る
+    let sequence = cyclic(period + needle.len().saturating_sub(1));
+    sequence
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// De Bruijn sequence of `k` symbols and subsequences of length `n`
+/// generated by the algorithm of Ruskey, Savage and Wang
+fn de_bruijn(k: usize, n: usize) -> Vec<usize> {
+    fn db(t: usize, p: usize, k: usize, n: usize, a: &mut Vec<usize>, out: &mut Vec<usize>) {
+        if t > n {
+            if n.is_multiple_of(p) {
+                out.extend_from_slice(&a[1..=p]);
+            }
+            return;
+        }
+        a[t] = a[t - p];
+        db(t + 1, p, k, n, a, out);
+        for j in a[t - p] + 1..k {
+            a[t] = j;
+            db(t + 1, t, k, n, a, out);
+        }
+    }
+    let mut a = vec![0; n + 1];
+    let mut out = Vec::with_capacity(k.pow(n as u32));
+    db(1, 1, k, n, &mut a, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_patterns() {
+        assert_eq!(Pattern::parse("cyclic").unwrap(), Pattern::Cyclic);
+        assert_eq!(Pattern::parse("0xaa").unwrap(), Pattern::Bytes(vec![0xaa]));
+        assert_eq!(Pattern::parse("65").unwrap(), Pattern::Bytes(vec![65]));
+        assert_eq!(
+            Pattern::parse(r#""A\n\0\x7f\"\\""#).unwrap(),
+            Pattern::Bytes(vec![b'A', b'\n', 0, 0x7f, b'"', b'\\'])
+        );
+        assert!(Pattern::parse("0x100").is_err());
+        assert!(Pattern::parse("\"\"").is_err());
+        assert!(Pattern::parse("\"abc").is_err());
+        assert!(Pattern::parse(r#""a\q""#).is_err());
+        assert!(Pattern::parse(r#""a\xzz""#).is_err());
+        assert!(Pattern::parse("\"a\"b\"").is_err());
+    }
+
+    #[test]
+    fn repeated_bytes() {
+        let pattern = Pattern::Bytes(b"ab".to_vec());
+        assert_eq!(pattern.bytes(5), b"ababa");
+        assert_eq!(pattern.bytes(0), b"");
+    }
+
+    #[test]
+    fn cyclic_is_the_same_as_pwntools() {
+        assert_eq!(cyclic(16), b"aaaabaaacaaadaaa");
+        assert_eq!(Pattern::Cyclic.bytes(8), b"aaaabaaa");
+    }
+
+    #[test]
+    fn offsets_in_the_cyclic_pattern() {
+        assert_eq!(offset(b"aaaa"), Some(0));
+        assert_eq!(offset(b"baaa"), Some(4));
+        assert_eq!(offset(b"laaa"), Some(44));
+        assert_eq!(offset(b"AAAA"), None);
+        // 部分列はどれも1回しか現れない
+        let pattern = cyclic(4096);
+        for start in [0, 1, 17, 1000, 4092] {
+            let needle = &pattern[start..start + SUBSEQUENCE_LEN];
+            assert_eq!(offset(needle), Some(start));
+        }
+    }
+
+    #[test]
+    fn offsets_across_the_period() {
+        let period = ALPHABET.len().pow(SUBSEQUENCE_LEN as u32);
+        let pattern = cyclic(period + 2);
+        assert_eq!(pattern.len(), period + 2);
+        assert_eq!(offset(&pattern[period - 2..]), Some(period - 2));
+    }
+}
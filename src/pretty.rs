@@ -0,0 +1,167 @@
+use crate::{
+    mem,
+    variable::{format_value, read_uint, Type, MAX_ELEMENTS},
+};
+use nix::unistd::Pid;
+
+/// max length of Rust strings printed
+const MAX_STR_LEN: u64 = 256;
+
+/// printer for values of types whose name matches
+pub trait PrettyPrinter: Sync {
+    fn matches(&self, name: &str) -> bool;
+    /// None if the value is not in the expected layout
+    fn format(&self, pid: Pid, ty: &Type, bytes: &[u8]) -> Option<String>;
+}
+
+/// printers tried in order, add new printers here
+const PRINTERS: [&dyn PrettyPrinter; 4] = [&StrPrinter, &StringPrinter, &VecPrinter, &SlicePrinter];
+
+/// format the value with the first matching printer
+pub fn format(pid: Pid, ty: &Type, bytes: &[u8]) -> Option<String> {
+    let name = match ty {
+        Type::Struct { name, .. } => name,
+        _ => return None,
+    };
+    PRINTERS
+        .iter()
+        .filter(|printer| printer.matches(name))
+        .find_map(|printer| printer.format(pid, ty, bytes))
+}
+
+/// `&str`
+struct StrPrinter;
+
+impl PrettyPrinter for StrPrinter {
+    fn matches(&self, name: &str) -> bool {
+        name == "&str" || name == "&mut str"
+    }
+
+    fn format(&self, pid: Pid, ty: &Type, bytes: &[u8]) -> Option<String> {
+        let (ptr, _) = member(ty, bytes, &["data_ptr"])?;
+        let (len, _) = member(ty, bytes, &["length"])?;
+        read_str(pid, ptr, len)
+    }
+}
+
+/// `alloc::string::String`
+struct StringPrinter;
+
+impl PrettyPrinter for StringPrinter {
+    fn matches(&self, name: &str) -> bool {
+        name == "String"
+    }
+
+    fn format(&self, pid: Pid, ty: &Type, bytes: &[u8]) -> Option<String> {
+        let (vec_offset, vec) = member_offset(ty, &["vec"])?;
+        let ptr_offset = find_pointer(&vec, vec_offset)?;
+        let ptr = read_uint(bytes.get(ptr_offset as usize..ptr_offset as usize + 8)?);
+        let (len, _) = member(ty, bytes, &["vec", "len"])?;
+        read_str(pid, ptr, len)
+    }
+}
+
+/// `alloc::vec::Vec<T>`
+struct VecPrinter;
+
+impl PrettyPrinter for VecPrinter {
+    fn matches(&self, name: &str) -> bool {
+        name.starts_with("Vec<")
+    }
+
+    fn format(&self, pid: Pid, ty: &Type, bytes: &[u8]) -> Option<String> {
+        let elem = match ty {
+            Type::Struct { params, .. } => params.iter().find(|(name, _)| name == "T")?.1.clone(),
+            _ => return None,
+        };
+        let (buf_offset, buf) = member_offset(ty, &["buf"])?;
+        let ptr_offset = find_pointer(&buf, buf_offset)?;
+        let ptr = read_uint(bytes.get(ptr_offset as usize..ptr_offset as usize + 8)?);
+        let (len, _) = member(ty, bytes, &["len"])?;
+        Some(format!("vec![{}]", format_elements(pid, &elem, ptr, len)?))
+    }
+}
+
+/// `&[T]`
+struct SlicePrinter;
+
+impl PrettyPrinter for SlicePrinter {
+    fn matches(&self, name: &str) -> bool {
+        name.starts_with("&[") || name.starts_with("&mut [")
+    }
+
+    fn format(&self, pid: Pid, ty: &Type, bytes: &[u8]) -> Option<String> {
+        let elem = match member_type(ty, &["data_ptr"])?.canonical() {
+            Type::Pointer { target, .. } => target.resolve(),
+            _ => return None,
+        };
+        let (ptr, _) = member(ty, bytes, &["data_ptr"])?;
+        let (len, _) = member(ty, bytes, &["length"])?;
+        Some(format!("&[{}]", format_elements(pid, &elem, ptr, len)?))
+    }
+}
+
+/// offset and type of the nested member
+fn member_offset(ty: &Type, path: &[&str]) -> Option<(u64, Type)> {
+    let mut offset = 0;
+    let mut ty = ty.canonical();
+    for name in path {
+        let member = match &ty {
+            Type::Struct { members, .. } => members.iter().find(|m| m.name == *name)?.clone(),
+            _ => return None,
+        };
+        offset += member.offset;
+        ty = member.ty.canonical();
+    }
+    Some((offset, ty))
+}
+
+fn member_type(ty: &Type, path: &[&str]) -> Option<Type> {
+    member_offset(ty, path).map(|(_, ty)| ty)
+}
+
+/// value of the nested integer or pointer member
+fn member(ty: &Type, bytes: &[u8], path: &[&str]) -> Option<(u64, Type)> {
+    let (offset, ty) = member_offset(ty, path)?;
+    let start = offset as usize;
+    let value = read_uint(bytes.get(start..start + ty.size() as usize)?);
+    Some((value, ty))
+}
+
+/// offset of the first pointer inside `ty`
+/// e.g. `RawVec { inner: RawVecInner { ptr: Unique { pointer: NonNull { pointer: *const u8 } } } }`
+fn find_pointer(ty: &Type, offset: u64) -> Option<u64> {
+    match ty.canonical() {
+        Type::Pointer { .. } => Some(offset),
+        Type::Struct { members, .. } => members
+            .iter()
+            .find_map(|m| find_pointer(&m.ty, offset + m.offset)),
+        _ => None,
+    }
+}
+
+fn read_str(pid: Pid, ptr: u64, len: u64) -> Option<String> {
+    let bytes = mem::read_bytes(pid, ptr, len.min(MAX_STR_LEN) as usize).ok()?;
+    let s = format!("{:?}", String::from_utf8_lossy(&bytes));
+    if len > MAX_STR_LEN {
+        Some(format!("{}...", s))
+    } else {
+        Some(s)
+    }
+}
+
+fn format_elements(pid: Pid, elem: &Type, ptr: u64, len: u64) -> Option<String> {
+    let elem_size = elem.size();
+    let count = len.min(MAX_ELEMENTS);
+    let bytes = mem::read_bytes(pid, ptr, (elem_size * count) as usize).ok()?;
+    let mut elems = (0..count as usize)
+        .map(|i| {
+            let start = i * elem_size as usize;
+            format_value(pid, elem, &bytes[start..start + elem_size as usize])
+        })
+        .collect::<Vec<String>>();
+    if len > MAX_ELEMENTS {
+        elems.push(String::from("..."));
+    }
+    Some(elems.join(", "))
+}
@@ -0,0 +1,165 @@
+use crate::{
+    bounded::{Bounded, HeapSize, Policy},
+    debugger::DebuggerInfo,
+    mem, register, step,
+    syscall::get_regs,
+};
+use iced_x86::{InstructionInfoFactory, Mnemonic, OpAccess, Register};
+use nix::{
+    libc::{user_fpregs_struct, user_regs_struct},
+    sys::{ptrace, signal::Signal, wait::WaitStatus},
+    unistd::Pid,
+};
+use std::{io, mem::size_of};
+
+/// state before an instruction which was stepped in record mode
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub regs: user_regs_struct,
+    pub fpregs: user_fpregs_struct,
+    /// memory which the instruction writes, (address, bytes before the write)
+    pub writes: Vec<(u64, Vec<u8>)>,
+    /// memory written by the kernel in the syscall is not known
+    pub syscall: bool,
+}
+
+impl HeapSize for Step {
+    fn heap_size(&self) -> usize {
+        self.writes.capacity() * size_of::<(u64, Vec<u8>)>()
+            + self
+                .writes
+                .iter()
+                .map(|(_, bytes)| bytes.capacity())
+                .sum::<usize>()
+    }
+}
+
+/// instructions stepped in a thread since `record`, the latest one is undone first
+#[derive(Debug)]
+pub struct Record {
+    pub tid: Pid,
+    pub steps: Bounded<Step>,
+}
+
+impl Record {
+    pub fn new(tid: Pid, policy: Policy) -> Self {
+        Self {
+            tid,
+            steps: Bounded::new(policy),
+        }
+    }
+}
+
+/// value of the register for the address of a memory operand
+fn register_value(regs: &user_regs_struct, register: Register) -> Option<u64> {
+    let value = match register.full_register() {
+        Register::RAX => regs.rax,
+        Register::RBX => regs.rbx,
+        Register::RCX => regs.rcx,
+        Register::RDX => regs.rdx,
+        Register::RSI => regs.rsi,
+        Register::RDI => regs.rdi,
+        Register::RBP => regs.rbp,
+        Register::RSP => regs.rsp,
+        Register::R8 => regs.r8,
+        Register::R9 => regs.r9,
+        Register::R10 => regs.r10,
+        Register::R11 => regs.r11,
+        Register::R12 => regs.r12,
+        Register::R13 => regs.r13,
+        Register::R14 => regs.r14,
+        Register::R15 => regs.r15,
+        Register::RIP => regs.rip,
+        // セグメントはベースアドレスを返す
+        Register::FS => regs.fs_base,
+        Register::GS => regs.gs_base,
+        Register::ES | Register::CS | Register::SS | Register::DS => 0,
+        _ => return None,
+    };
+    match register.size() {
+        4 => Some(value & 0xffff_ffff),
+        _ => Some(value),
+    }
+}
+
+/// save the registers and the memory which the instruction at rip changes
+pub fn save(debugger_info: &DebuggerInfo) -> Result<Step, Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let regs = get_regs(pid);
+    let fpregs = register::get_fpregs(pid)?;
+    let instruction = step::instruction_at(debugger_info, regs.rip)?;
+    let mut factory = InstructionInfoFactory::new();
+    let mut writes = Vec::new();
+    for memory in factory.info(&instruction).used_memory() {
+        if !matches!(
+            memory.access(),
+            OpAccess::Write | OpAccess::CondWrite | OpAccess::ReadWrite | OpAccess::ReadCondWrite
+        ) {
+            continue;
+        }
+        // 書き込み先がわからないときはgatherなどなので記録できない
+        let Some(addr) =
+            memory.virtual_address(0, |register, _, _| register_value(&regs, register))
+        else {
+            continue;
+        };
+        let len = memory.memory_size().size();
+        // 読めないアドレスへの書き込みは例外になり何も変わらない
+        if let Ok(bytes) = mem::read_bytes(pid, addr, len) {
+            writes.push((addr, bytes));
+        }
+    }
+    Ok(Step {
+        regs,
+        fpregs,
+        writes,
+        syscall: instruction.mnemonic() == Mnemonic::Syscall,
+    })
+}
+
+/// execute one instruction, it is recorded if the current thread is recorded
+pub fn step(debugger_info: &mut DebuggerInfo) -> Result<WaitStatus, Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let saved = match &debugger_info.record {
+        Some(record) if record.tid == pid => Some(save(debugger_info)?),
+        _ => None,
+    };
+    let status = debugger_info.breakpoint_manager.step()?;
+    let executed = match status {
+        WaitStatus::Stopped(_, Signal::SIGTRAP) => true,
+        // 見張っているページへの書き込みは保護を外して実行し直される
+        WaitStatus::Stopped(_, Signal::SIGSEGV) => {
+            debugger_info.breakpoint_manager.has_page_watchpoints()
+        }
+        _ => false,
+    };
+    if let (Some(step), Some(record), true) = (saved, &mut debugger_info.record, executed) {
+        record.steps.push(step);
+    }
+    Ok(status)
+}
+
+/// undo the latest recorded instruction, None if there is no more history
+pub fn reverse_step(
+    pid: Pid,
+    record: &mut Record,
+) -> Result<Option<Step>, Box<dyn std::error::Error>> {
+    if record.tid != pid {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the history was recorded in thread {}", record.tid),
+        )));
+    }
+    let Some(step) = record.steps.pop() else {
+        return Ok(None);
+    };
+    for (addr, bytes) in step.writes.iter().rev() {
+        mem::write_bytes(pid, *addr, bytes)?;
+    }
+    let mut regs = step.regs;
+    // システムコールの再開と見なされないようにする
+    regs.orig_rax = u64::MAX;
+    ptrace::setregs(pid, regs)?;
+    register::set_fpregs(pid, step.fpregs)?;
+    Ok(Some(step))
+}
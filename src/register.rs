@@ -1,8 +1,13 @@
 use crate::syscall::get_regs;
-use nix::{sys::ptrace, unistd::Pid};
-use std::io;
+use nix::{
+    errno::Errno,
+    libc::{self, c_void, user_fpregs_struct, user_regs_struct},
+    sys::ptrace,
+    unistd::Pid,
+};
+use std::{io, mem::MaybeUninit, ptr};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RegisterType {
     R15,
     R14,
@@ -29,62 +34,116 @@ pub enum RegisterType {
     Fs,
     Gs,
     Ss,
+    FsBase,
+    GsBase,
 }
 
 impl RegisterType {
-    pub fn parse(s: &str) -> Result<RegisterType, Box<dyn std::error::Error>> {
-        if s.is_empty() {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "empty string",
-            )));
-        }
-        let bytes = s.as_bytes();
-        if bytes[0] != b'$' {
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "register should start $ mark",
-            )));
+    /// all registers in the order of `regs`
+    pub const ALL: [RegisterType; 27] = [
+        RegisterType::R15,
+        RegisterType::R14,
+        RegisterType::R13,
+        RegisterType::R12,
+        RegisterType::R11,
+        RegisterType::R10,
+        RegisterType::R9,
+        RegisterType::R8,
+        RegisterType::Rax,
+        RegisterType::Rbx,
+        RegisterType::Rcx,
+        RegisterType::Rdx,
+        RegisterType::Rsi,
+        RegisterType::Rdi,
+        RegisterType::Rip,
+        RegisterType::Rsp,
+        RegisterType::Rbp,
+        RegisterType::Cs,
+        RegisterType::Ds,
+        RegisterType::Es,
+        RegisterType::Fs,
+        RegisterType::Gs,
+        RegisterType::Ss,
+        RegisterType::FsBase,
+        RegisterType::GsBase,
+        RegisterType::OrigRax,
+        RegisterType::Eflags,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RegisterType::R15 => "r15",
+            RegisterType::R14 => "r14",
+            RegisterType::R13 => "r13",
+            RegisterType::R12 => "r12",
+            RegisterType::R11 => "r11",
+            RegisterType::R10 => "r10",
+            RegisterType::R9 => "r9",
+            RegisterType::R8 => "r8",
+            RegisterType::Rax => "rax",
+            RegisterType::Rbx => "rbx",
+            RegisterType::Rcx => "rcx",
+            RegisterType::Rdx => "rdx",
+            RegisterType::Rsi => "rsi",
+            RegisterType::Rdi => "rdi",
+            RegisterType::Rbp => "rbp",
+            RegisterType::Rsp => "rsp",
+            RegisterType::Rip => "rip",
+            RegisterType::Eflags => "eflags",
+            RegisterType::OrigRax => "orig_rax",
+            RegisterType::Cs => "cs",
+            RegisterType::Ds => "ds",
+            RegisterType::Es => "es",
+            RegisterType::Fs => "fs",
+            RegisterType::Gs => "gs",
+            RegisterType::Ss => "ss",
+            RegisterType::FsBase => "fs_base",
+            RegisterType::GsBase => "gs_base",
         }
-        let reg = bytes[1..].iter().map(|c| *c as char).collect::<String>();
-        let reg_type = match reg.as_str() {
-            "r15" => RegisterType::R15,
-            "r14" => RegisterType::R14,
-            "r13" => RegisterType::R13,
-            "r12" => RegisterType::R12,
-            "r11" => RegisterType::R11,
-            "r10" => RegisterType::R10,
-            "r9" => RegisterType::R9,
-            "r8" => RegisterType::R8,
-            "rax" => RegisterType::Rax,
-            "rbx" => RegisterType::Rbx,
-            "rcx" => RegisterType::Rcx,
-            "rdx" => RegisterType::Rdx,
-            "rsi" => RegisterType::Rsi,
-            "rdi" => RegisterType::Rdi,
-            "rbp" => RegisterType::Rbp,
-            "rsp" => RegisterType::Rsp,
-            "rip" => RegisterType::Rip,
-            "eflags" => RegisterType::Eflags,
-            "orig_rax" => RegisterType::OrigRax,
-            "cs" => RegisterType::Cs,
-            "ds" => RegisterType::Ds,
-            "es" => RegisterType::Es,
-            "fs" => RegisterType::Fs,
-            "gs" => RegisterType::Gs,
-            "ss" => RegisterType::Ss,
-            _ => {
-                return Err(Box::new(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "invalid register name",
-                )))
-            }
+    }
+
+    /// register of the name without `$`
+    pub fn from_name(name: &str) -> Option<RegisterType> {
+        RegisterType::ALL
+            .into_iter()
+            .find(|reg_type| reg_type.name() == name)
+    }
+
+    /// register of the DWARF register number (System V x86-64 ABI)
+    pub fn from_dwarf(reg: u16) -> Option<RegisterType> {
+        let reg_type = match reg {
+            0 => RegisterType::Rax,
+            1 => RegisterType::Rdx,
+            2 => RegisterType::Rcx,
+            3 => RegisterType::Rbx,
+            4 => RegisterType::Rsi,
+            5 => RegisterType::Rdi,
+            6 => RegisterType::Rbp,
+            7 => RegisterType::Rsp,
+            8 => RegisterType::R8,
+            9 => RegisterType::R9,
+            10 => RegisterType::R10,
+            11 => RegisterType::R11,
+            12 => RegisterType::R12,
+            13 => RegisterType::R13,
+            14 => RegisterType::R14,
+            15 => RegisterType::R15,
+            16 => RegisterType::Rip,
+            49 => RegisterType::Eflags,
+            50 => RegisterType::Es,
+            51 => RegisterType::Cs,
+            52 => RegisterType::Ss,
+            53 => RegisterType::Ds,
+            54 => RegisterType::Fs,
+            55 => RegisterType::Gs,
+            58 => RegisterType::FsBase,
+            59 => RegisterType::GsBase,
+            _ => return None,
         };
-        Ok(reg_type)
+        Some(reg_type)
     }
 
-    pub fn get_current_value(&self, pid: Pid) -> u64 {
-        let regs = get_regs(pid);
+    pub fn get_value(&self, regs: &user_regs_struct) -> u64 {
         match self {
             RegisterType::R15 => regs.r15,
             RegisterType::R14 => regs.r14,
@@ -111,46 +170,255 @@ impl RegisterType {
             RegisterType::Fs => regs.fs,
             RegisterType::Gs => regs.gs,
             RegisterType::Ss => regs.ss,
+            RegisterType::FsBase => regs.fs_base,
+            RegisterType::GsBase => regs.gs_base,
         }
     }
+
+    pub fn set_value(&self, regs: &mut user_regs_struct, value: u64) {
+        match self {
+            RegisterType::R15 => regs.r15 = value,
+            RegisterType::R14 => regs.r14 = value,
+            RegisterType::R13 => regs.r13 = value,
+            RegisterType::R12 => regs.r12 = value,
+            RegisterType::R11 => regs.r11 = value,
+            RegisterType::R10 => regs.r10 = value,
+            RegisterType::R9 => regs.r9 = value,
+            RegisterType::R8 => regs.r8 = value,
+            RegisterType::Rax => regs.rax = value,
+            RegisterType::Rbx => regs.rbx = value,
+            RegisterType::Rcx => regs.rcx = value,
+            RegisterType::Rdx => regs.rdx = value,
+            RegisterType::Rdi => regs.rdi = value,
+            RegisterType::Rsi => regs.rsi = value,
+            RegisterType::Rbp => regs.rbp = value,
+            RegisterType::Rsp => regs.rsp = value,
+            RegisterType::Rip => regs.rip = value,
+            RegisterType::Eflags => regs.eflags = value,
+            RegisterType::OrigRax => regs.orig_rax = value,
+            RegisterType::Cs => regs.cs = value,
+            RegisterType::Ds => regs.ds = value,
+            RegisterType::Es => regs.es = value,
+            RegisterType::Fs => regs.fs = value,
+            RegisterType::Gs => regs.gs = value,
+            RegisterType::Ss => regs.ss = value,
+            RegisterType::FsBase => regs.fs_base = value,
+            RegisterType::GsBase => regs.gs_base = value,
+        }
+    }
+}
+
+/// part of a register which can be accessed by a name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    /// whole 8 bytes, e.g. `rax`
+    Qword,
+    /// low 4 bytes, e.g. `eax`
+    Dword,
+    /// low 2 bytes, e.g. `ax`
+    Word,
+    /// low byte, e.g. `al`
+    Byte,
+    /// second byte, e.g. `ah`
+    HighByte,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Width {
+    pub fn size(&self) -> usize {
+        match self {
+            Width::Qword => 8,
+            Width::Dword => 4,
+            Width::Word => 2,
+            Width::Byte | Width::HighByte => 1,
+        }
+    }
+
+    fn shift(&self) -> u32 {
+        match self {
+            Width::HighByte => 8,
+            _ => 0,
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        match self {
+            Width::Qword => u64::MAX,
+            _ => (1 << (self.size() * 8)) - 1,
+        }
+    }
+}
+
+/// names of the sub-registers in the order of dword, word, byte and high byte
+const SUB_REGISTERS: [(RegisterType, [&str; 4]); 16] = [
+    (RegisterType::Rax, ["eax", "ax", "al", "ah"]),
+    (RegisterType::Rbx, ["ebx", "bx", "bl", "bh"]),
+    (RegisterType::Rcx, ["ecx", "cx", "cl", "ch"]),
+    (RegisterType::Rdx, ["edx", "dx", "dl", "dh"]),
+    (RegisterType::Rsi, ["esi", "si", "sil", ""]),
+    (RegisterType::Rdi, ["edi", "di", "dil", ""]),
+    (RegisterType::Rbp, ["ebp", "bp", "bpl", ""]),
+    (RegisterType::Rsp, ["esp", "sp", "spl", ""]),
+    (RegisterType::R8, ["r8d", "r8w", "r8b", ""]),
+    (RegisterType::R9, ["r9d", "r9w", "r9b", ""]),
+    (RegisterType::R10, ["r10d", "r10w", "r10b", ""]),
+    (RegisterType::R11, ["r11d", "r11w", "r11b", ""]),
+    (RegisterType::R12, ["r12d", "r12w", "r12b", ""]),
+    (RegisterType::R13, ["r13d", "r13w", "r13b", ""]),
+    (RegisterType::R14, ["r14d", "r14w", "r14b", ""]),
+    (RegisterType::R15, ["r15d", "r15w", "r15b", ""]),
+];
+
+const SUB_WIDTHS: [Width; 4] = [Width::Dword, Width::Word, Width::Byte, Width::HighByte];
+
+/// register or sub-register like `eax`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Register {
     pub reg_type: RegisterType,
-    pub value: u64,
+    pub width: Width,
+}
+
+impl From<RegisterType> for Register {
+    fn from(reg_type: RegisterType) -> Self {
+        Register {
+            reg_type,
+            width: Width::Qword,
+        }
+    }
 }
 
 impl Register {
-    pub fn write_value(&self, pid: Pid) {
-        let mut regs = get_regs(pid);
-        match self.reg_type {
-            RegisterType::R15 => regs.r15 = self.value,
-            RegisterType::R14 => regs.r14 = self.value,
-            RegisterType::R13 => regs.r13 = self.value,
-            RegisterType::R12 => regs.r12 = self.value,
-            RegisterType::R11 => regs.r11 = self.value,
-            RegisterType::R10 => regs.r10 = self.value,
-            RegisterType::R9 => regs.r9 = self.value,
-            RegisterType::R8 => regs.r8 = self.value,
-            RegisterType::Rax => regs.rax = self.value,
-            RegisterType::Rbx => regs.rbx = self.value,
-            RegisterType::Rcx => regs.rcx = self.value,
-            RegisterType::Rdx => regs.rdx = self.value,
-            RegisterType::Rdi => regs.rdi = self.value,
-            RegisterType::Rsi => regs.rsi = self.value,
-            RegisterType::Rbp => regs.rbp = self.value,
-            RegisterType::Rsp => regs.rsp = self.value,
-            RegisterType::Rip => regs.rip = self.value,
-            RegisterType::Eflags => regs.eflags = self.value,
-            RegisterType::OrigRax => regs.orig_rax = self.value,
-            RegisterType::Cs => regs.cs = self.value,
-            RegisterType::Ds => regs.ds = self.value,
-            RegisterType::Es => regs.es = self.value,
-            RegisterType::Fs => regs.fs = self.value,
-            RegisterType::Gs => regs.gs = self.value,
-            RegisterType::Ss => regs.ss = self.value,
+    pub fn name(&self) -> &'static str {
+        if self.width == Width::Qword {
+            return self.reg_type.name();
+        }
+        SUB_REGISTERS
+            .iter()
+            .find(|(reg_type, _)| *reg_type == self.reg_type)
+            .and_then(|(_, names)| {
+                SUB_WIDTHS
+                    .iter()
+                    .position(|width| *width == self.width)
+                    .map(|i| names[i])
+            })
+            .unwrap_or_else(|| self.reg_type.name())
+    }
+
+    /// parse `$name`
+    pub fn parse(s: &str) -> Result<Register, Box<dyn std::error::Error>> {
+        if s.is_empty() {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "empty string",
+            )));
+        }
+        let name = match s.strip_prefix('$') {
+            Some(name) => name,
+            None => {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "register should start $ mark",
+                )))
+            }
+        };
+        if let Some(reg_type) = RegisterType::from_name(name) {
+            return Ok(Register::from(reg_type));
         }
-        ptrace::setregs(pid, regs).unwrap();
+        for (reg_type, names) in SUB_REGISTERS {
+            if let Some(i) = names.iter().position(|n| !n.is_empty() && *n == name) {
+                return Ok(Register {
+                    reg_type,
+                    width: SUB_WIDTHS[i],
+                });
+            }
+        }
+        Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid register name",
+        )))
+    }
+
+    pub fn size(&self) -> usize {
+        self.width.size()
+    }
+
+    pub fn get_value(&self, regs: &user_regs_struct) -> u64 {
+        (self.reg_type.get_value(regs) >> self.width.shift()) & self.width.mask()
     }
+
+    /// write `value` to the part and keep the other bits of the register
+    pub fn set_value(&self, regs: &mut user_regs_struct, value: u64) {
+        let mask = self.width.mask() << self.width.shift();
+        let old = self.reg_type.get_value(regs);
+        self.reg_type
+            .set_value(regs, (old & !mask) | ((value << self.width.shift()) & mask));
+    }
+
+    pub fn get_current_value(&self, pid: Pid) -> u64 {
+        self.get_value(&get_regs(pid))
+    }
+
+    pub fn set_current_value(&self, pid: Pid, value: u64) -> Result<(), nix::Error> {
+        let mut regs = get_regs(pid);
+        self.set_value(&mut regs, value);
+        ptrace::setregs(pid, regs)
+    }
+}
+
+/// x87 and SSE registers of the target
+pub fn get_fpregs(pid: Pid) -> Result<user_fpregs_struct, nix::Error> {
+    let mut fpregs = MaybeUninit::<user_fpregs_struct>::uninit();
+    // nixにはPTRACE_GETFPREGSがない
+    let res = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GETFPREGS,
+            pid.as_raw(),
+            ptr::null_mut::<c_void>(),
+            fpregs.as_mut_ptr(),
+        )
+    };
+    Errno::result(res)?;
+    Ok(unsafe { fpregs.assume_init() })
+}
+
+/// bytes of st`n` of the x87 FPU of the target, the 80-bit value and padding
+pub fn get_st(pid: Pid, n: usize) -> Result<[u8; 16], nix::Error> {
+    let fpregs = get_fpregs(pid)?;
+    let mut bytes = [0; 16];
+    for (i, word) in fpregs.st_space[n * 4..n * 4 + 4].iter().enumerate() {
+        bytes[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+/// bytes of xmm`n` of the target
+pub fn get_xmm(pid: Pid, n: usize) -> Result<[u8; 16], nix::Error> {
+    let fpregs = get_fpregs(pid)?;
+    let mut bytes = [0; 16];
+    for (i, word) in fpregs.xmm_space[n * 4..n * 4 + 4].iter().enumerate() {
+        bytes[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+/// write the bytes to the low bytes of xmm`n` of the target and clear the rest
+pub fn set_xmm(pid: Pid, n: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+    let mut fpregs = get_fpregs(pid)?;
+    let mut xmm = [0u8; 16];
+    xmm[..bytes.len()].copy_from_slice(bytes);
+    for (i, word) in fpregs.xmm_space[n * 4..n * 4 + 4].iter_mut().enumerate() {
+        *word = u32::from_le_bytes(xmm[i * 4..i * 4 + 4].try_into().unwrap());
+    }
+    set_fpregs(pid, fpregs)
+}
+
+pub fn set_fpregs(pid: Pid, mut fpregs: user_fpregs_struct) -> Result<(), nix::Error> {
+    let res = unsafe {
+        libc::ptrace(
+            libc::PTRACE_SETFPREGS,
+            pid.as_raw(),
+            ptr::null_mut::<c_void>(),
+            &mut fpregs as *mut user_fpregs_struct,
+        )
+    };
+    Errno::result(res).map(drop)
 }
@@ -0,0 +1,215 @@
+use crate::{debug_info::TdbDebugInfo, mem};
+use memmap2::Mmap;
+use nix::unistd::Pid;
+use object::{
+    elf,
+    read::elf::{Dyn, ElfFile64, FileHeader},
+    Object, ObjectSection,
+};
+use proc_maps::get_process_maps;
+use std::{fs, io, path::Path};
+
+/// where the debug information of the object was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugInfo {
+    /// `.debug_info` in the object itself
+    Embedded,
+    /// `/usr/lib/debug/.build-id/xx/yyyy.debug`, tdb does not read it
+    Separate,
+    None,
+}
+
+impl std::fmt::Display for DebugInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DebugInfo::Embedded => write!(f, "yes"),
+            DebugInfo::Separate => write!(f, "separate"),
+            DebugInfo::None => write!(f, "no"),
+        }
+    }
+}
+
+/// object mapped into the target
+#[derive(Debug, Clone)]
+pub struct Library {
+    pub path: String,
+    /// lowest address mapped from the file
+    pub base: u64,
+    /// bytes from `base` to the end of the last mapping
+    pub size: u64,
+    pub build_id: Option<Vec<u8>>,
+    /// `.symtab` is present, otherwise only the dynamic symbols can be used
+    pub symbols: bool,
+    pub dwarf: DebugInfo,
+    /// DT_NEEDED of the object
+    pub needed: Vec<String>,
+}
+
+impl Library {
+    fn read(path: &str, base: u64, size: u64) -> Self {
+        let mut library = Self {
+            path: path.to_string(),
+            base,
+            size,
+            build_id: None,
+            symbols: false,
+            dwarf: DebugInfo::None,
+            needed: Vec::new(),
+        };
+        // 読めないファイルでもアドレスは表示する
+        let Ok(map) = fs::File::open(path).and_then(|file| unsafe { Mmap::map(&file) }) else {
+            return library;
+        };
+        let Ok(file) = object::File::parse(&*map) else {
+            return library;
+        };
+        library.build_id = file.build_id().ok().flatten().map(|id| id.to_vec());
+        library.symbols = file.symbol_table().is_some();
+        library.dwarf = if file.section_by_name(".debug_info").is_some() {
+            DebugInfo::Embedded
+        } else if library
+            .build_id
+            .as_deref()
+            .is_some_and(|id| Path::new(&debug_file(id)).exists())
+        {
+            DebugInfo::Separate
+        } else {
+            DebugInfo::None
+        };
+        library.needed = needed(&map).unwrap_or_default();
+        library
+    }
+
+    /// file name which DT_NEEDED of other objects refers to
+    pub fn name(&self) -> &str {
+        Path::new(&self.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.path)
+    }
+}
+
+/// path of the separate debug file of the build-id
+fn debug_file(build_id: &[u8]) -> String {
+    let hex: String = build_id.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("/usr/lib/debug/.build-id/{}/{}.debug", &hex[..2], &hex[2..])
+}
+
+/// DT_NEEDED entries of the ELF file
+fn needed(data: &[u8]) -> Result<Vec<String>, object::Error> {
+    let elf = ElfFile64::<object::Endianness>::parse(data)?;
+    let endian = elf.endian();
+    let sections = elf.raw_header().sections(endian, data)?;
+    let Some((dynamic, link)) = sections.dynamic(endian, data)? else {
+        return Ok(Vec::new());
+    };
+    let strings = sections.strings(endian, data, link)?;
+    let mut needed = Vec::new();
+    for entry in dynamic {
+        if entry.tag32(endian) == Some(elf::DT_NEEDED) {
+            let name = entry.string(endian, strings)?;
+            needed.push(String::from_utf8_lossy(name).into_owned());
+        }
+    }
+    Ok(needed)
+}
+
+/// objects mapped into the target in the order the dynamic linker loaded them
+/// objects which the link map does not know (e.g. the target before ld.so runs)
+/// follow in the order of their addresses
+pub fn loaded(pid: Pid, debug_info: &TdbDebugInfo) -> io::Result<Vec<Library>> {
+    let maps = get_process_maps(pid.as_raw())?;
+    // ファイルごとにマップされている範囲をまとめる
+    let mut ranges: Vec<(String, u64, u64)> = Vec::new();
+    for map in &maps {
+        let Some(path) = map.filename().and_then(|path| path.to_str()) else {
+            continue;
+        };
+        // [heap]や[vdso]、削除されたファイルは除く
+        if !path.starts_with('/') || !Path::new(path).is_file() {
+            continue;
+        }
+        let (start, end) = (map.start() as u64, (map.start() + map.size()) as u64);
+        match ranges.iter_mut().find(|(other, _, _)| other == path) {
+            Some((_, base, last)) => {
+                *base = (*base).min(start);
+                *last = (*last).max(end);
+            }
+            None => ranges.push((path.to_string(), start, end)),
+        }
+    }
+    let mut order = link_map(pid, debug_info).unwrap_or_default();
+    order.insert(0, debug_info.filename().to_string());
+    let mut libraries = Vec::new();
+    for name in order {
+        let Ok(path) = fs::canonicalize(&name) else {
+            continue;
+        };
+        let Some(index) = ranges
+            .iter()
+            .position(|(other, _, _)| Path::new(other) == path)
+        else {
+            continue;
+        };
+        let (path, base, end) = ranges.remove(index);
+        libraries.push(Library::read(&path, base, end - base));
+    }
+    for (path, base, end) in ranges {
+        libraries.push(Library::read(&path, base, end - base));
+    }
+    Ok(libraries)
+}
+
+/// names of the objects in `r_debug.r_map`, the main program is not included
+fn link_map(pid: Pid, debug_info: &TdbDebugInfo) -> Option<Vec<String>> {
+    let read_u64 = |addr: u64| -> Option<u64> {
+        let bytes = mem::read_bytes(pid, addr, 8).ok()?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    };
+    // _DYNAMICのDT_DEBUGにld.soがr_debugのアドレスを書く
+    let dynamic = crate::debug_info::object().section_by_name(".dynamic")?;
+    let mut entry = dynamic.address() + debug_info.load_bias();
+    let end = entry + dynamic.size();
+    let mut r_debug = 0;
+    while entry < end {
+        match read_u64(entry)? as u32 {
+            elf::DT_NULL => break,
+            elf::DT_DEBUG => r_debug = read_u64(entry + 8)?,
+            _ => {}
+        }
+        entry += 16;
+    }
+    if r_debug == 0 {
+        return None;
+    }
+    // struct r_debug { int r_version; struct link_map *r_map; ... }
+    let mut link = read_u64(r_debug + 8)?;
+    let mut names = Vec::new();
+    // struct link_map { l_addr, l_name, l_ld, l_next, l_prev }
+    while link != 0 && names.len() < 4096 {
+        let name = read_string(pid, read_u64(link + 8)?);
+        if !name.is_empty() {
+            names.push(name);
+        }
+        link = read_u64(link + 24)?;
+    }
+    Some(names)
+}
+
+/// NUL-terminated string in the target
+fn read_string(pid: Pid, addr: u64) -> String {
+    let mut bytes = Vec::new();
+    while bytes.len() < 4096 {
+        let Ok(chunk) = mem::read_bytes(pid, addr + bytes.len() as u64, 8) else {
+            break;
+        };
+        match chunk.iter().position(|byte| *byte == 0) {
+            Some(nul) => {
+                bytes.extend_from_slice(&chunk[..nul]);
+                break;
+            }
+            None => bytes.extend_from_slice(&chunk),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -1,32 +1,160 @@
 use nix::{
     libc,
     sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal},
-    unistd::Pid,
+    unistd::{self, Pid},
 };
 use once_cell::sync::OnceCell;
-use std::{process::exit, sync::Mutex};
+use std::{
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// si_code of signals sent by the kernel such as Ctrl-C of the terminal
+const SI_KERNEL: i32 = 0x80;
 
 static CHILD_PID: OnceCell<Mutex<Pid>> = OnceCell::new();
+/// a command which can be cancelled is running
+static CANCELLABLE: AtomicBool = AtomicBool::new(false);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+/// tdb is reading a command, otherwise the target may be running
+static PROMPT: AtomicBool = AtomicBool::new(false);
 
-extern "C" fn sigint_handler(_signum: libc::c_int) {
+extern "C" fn sigint_handler(
+    _signum: libc::c_int,
+    info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    // 中断できるコマンドの実行中はそのコマンドだけを止める
+    if CANCELLABLE.load(Ordering::SeqCst) {
+        CANCELLED.store(true, Ordering::SeqCst);
+        return;
+    }
     let child_pid = CHILD_PID.get().unwrap().lock().unwrap();
+    // 実行中ならターゲットをSIGINTで止めてプロンプトに戻る
+    if !PROMPT.load(Ordering::SeqCst) {
+        // 端末のCtrl-Cは同じプロセスグループのターゲットにも届いている
+        let from_terminal = unsafe { (*info).si_code } == SI_KERNEL;
+        let same_group = unistd::getpgid(Some(*child_pid)).ok() == Some(unistd::getpgrp());
+        if !(from_terminal && same_group) {
+            let _ = signal::kill(*child_pid, Signal::SIGINT);
+        }
+        return;
+    }
     let _kr = signal::kill(*child_pid, Signal::SIGKILL);
     println!("kbd interrupt");
     exit(0);
 }
 
-fn init_child_pid(child: Pid) {
-    CHILD_PID.set(Mutex::new(child)).unwrap();
+/// process killed by Ctrl-C
+pub fn init_child_pid(child: Pid) {
+    // runで起動し直したときは入れ替える
+    let pid = CHILD_PID.get_or_init(|| Mutex::new(child));
+    *pid.lock().unwrap() = child;
 }
 
 fn init_handler() {
     let mut mask = SigSet::empty();
     mask.add(Signal::SIGINT);
-    let handler = SigHandler::Handler(sigint_handler);
-    let sigaction = SigAction::new(handler, SaFlags::empty(), mask);
+    let handler = SigHandler::SigAction(sigint_handler);
+    let sigaction = SigAction::new(handler, SaFlags::SA_RESTART | SaFlags::SA_SIGINFO, mask);
     let _ = unsafe { signal::sigaction(Signal::SIGINT, &sigaction).unwrap() };
 }
 
+/// Ctrl-C kills the target and exits while tdb reads a command
+pub fn set_prompt(prompt: bool) {
+    PROMPT.store(prompt, Ordering::SeqCst);
+}
+
+/// token of a long-running command, Ctrl-C cancels the command while it lives
+/// instead of killing the target
+pub struct Cancel(());
+
+impl Cancel {
+    pub fn start() -> Cancel {
+        CANCELLED.store(false, Ordering::SeqCst);
+        CANCELLABLE.store(true, Ordering::SeqCst);
+        Cancel(())
+    }
+
+    /// true if Ctrl-C was pressed, the command should stop at the next chunk
+    pub fn is_cancelled(&self) -> bool {
+        let cancelled = CANCELLED.load(Ordering::SeqCst);
+        if cancelled {
+            println!("cancelled");
+        }
+        cancelled
+    }
+}
+
+impl Drop for Cancel {
+    fn drop(&mut self) {
+        CANCELLABLE.store(false, Ordering::SeqCst);
+    }
+}
+
+/// what tdb does when the target receives a signal, changed by `handle`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handling {
+    /// return to the prompt
+    pub stop: bool,
+    /// announce the signal
+    pub print: bool,
+    /// deliver the signal to the target when it resumes
+    pub pass: bool,
+}
+
+impl Handling {
+    /// handling before `handle` changes it
+    /// SIGINT of Ctrl-C only interrupts the target and is not delivered
+    pub fn of(signal: Signal) -> Self {
+        Self {
+            stop: true,
+            print: true,
+            pass: signal != Signal::SIGINT,
+        }
+    }
+
+    /// stopping implies printing and not printing implies not stopping as in gdb
+    pub fn apply(&mut self, action: HandleAction) {
+        match action {
+            HandleAction::Stop(stop) => {
+                self.stop = stop;
+                self.print |= stop;
+            }
+            HandleAction::Print(print) => {
+                self.print = print;
+                self.stop &= print;
+            }
+            HandleAction::Pass(pass) => self.pass = pass,
+        }
+    }
+}
+
+/// keyword of `handle`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleAction {
+    Stop(bool),
+    Print(bool),
+    Pass(bool),
+}
+
+impl HandleAction {
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword {
+            "stop" => Some(HandleAction::Stop(true)),
+            "nostop" => Some(HandleAction::Stop(false)),
+            "print" => Some(HandleAction::Print(true)),
+            "noprint" => Some(HandleAction::Print(false)),
+            "pass" | "noignore" => Some(HandleAction::Pass(true)),
+            "nopass" | "ignore" => Some(HandleAction::Pass(false)),
+            _ => None,
+        }
+    }
+}
+
 pub fn init(pid: Pid) {
     init_child_pid(pid);
     init_handler();
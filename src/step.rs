@@ -0,0 +1,645 @@
+use crate::{
+    abi,
+    debugger::DebuggerInfo,
+    disasm, dump,
+    expr::{self, Expr},
+    mem, record, register,
+    signal::Cancel,
+    symbolize::demangle,
+    syscall::get_regs,
+    unwind,
+    variable::{self, format_value, Type},
+};
+use iced_x86::Instruction;
+use nix::{
+    libc,
+    sys::{
+        ptrace,
+        signal::Signal,
+        wait::{waitpid, WaitStatus},
+    },
+    unistd::Pid,
+};
+use std::io;
+
+/// longest x86 instruction
+pub const MAX_INSTRUCTION_LEN: u64 = 15;
+
+/// execute one instruction
+/// returns true with the status if a breakpoint is hit
+pub fn step_instruction(
+    debugger_info: &mut DebuggerInfo,
+) -> Result<(WaitStatus, bool), Box<dyn std::error::Error>> {
+    let status = record::step(debugger_info)?;
+    let hit = match status {
+        WaitStatus::Stopped(_, Signal::SIGTRAP) => check_breakpoint(debugger_info)?,
+        _ => false,
+    };
+    Ok((status, hit))
+}
+
+/// execute a REP string instruction until all iterations finish
+/// returns None if the current instruction is not a REP string instruction
+pub fn step_over_rep(
+    debugger_info: &mut DebuggerInfo,
+) -> Result<Option<(WaitStatus, bool)>, Box<dyn std::error::Error>> {
+    let regs = get_regs(debugger_info.debug_info.target_pid());
+    let instruction = instruction_at(debugger_info, regs.rip)?;
+    if !disasm::is_rep_string(&instruction) {
+        return Ok(None);
+    }
+    let (status, reached) = run_to(debugger_info, instruction.next_ip(), regs.rsp)?;
+    Ok(Some((status, !reached)))
+}
+
+/// decode the instruction at the address
+pub fn instruction_at(
+    debugger_info: &DebuggerInfo,
+    addr: u64,
+) -> Result<Instruction, Box<dyn std::error::Error>> {
+    let instructions = disasm::disassemble(
+        debugger_info.debug_info.target_pid(),
+        &debugger_info.breakpoint_manager,
+        addr,
+        addr + MAX_INSTRUCTION_LEN,
+    )?;
+    Ok(instructions[0])
+}
+
+/// handle the breakpoint if the target trapped by its int3 or stepped onto its address
+/// returns false if there is no breakpoint or its condition is false
+pub fn check_breakpoint(
+    debugger_info: &mut DebuggerInfo,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let manager = &debugger_info.breakpoint_manager;
+    let rip = get_regs(debugger_info.debug_info.target_pid()).rip;
+    // シングルステップではint3を実行せずにブレークポイントのアドレスに着く
+    let addr = manager
+        .hit_addr()
+        .or_else(|| manager.get(rip).map(|bp| bp.addr));
+    match addr {
+        Some(addr) => Ok(debugger_info.hit_breakpoint(addr)?),
+        None => Ok(false),
+    }
+}
+
+/// handle the page watchpoints if the target wrote to a write-protected page
+/// returns None if the fault is not caused by them, otherwise the status after the write
+/// and whether the target should stop
+pub fn check_page_watchpoint(
+    debugger_info: &mut DebuggerInfo,
+) -> Result<Option<(WaitStatus, bool)>, Box<dyn std::error::Error>> {
+    let (status, nums) = match debugger_info.breakpoint_manager.step_page_fault()? {
+        Some(fault) => fault,
+        None => return Ok(None),
+    };
+    if let WaitStatus::Exited(..) = status {
+        return Ok(Some((status, false)));
+    }
+    let mut stop = false;
+    for num in nums {
+        stop |= debugger_info.hit_watchpoint(num)?;
+    }
+    // 書き込んだ命令の次がブレークポイントのこともある
+    if !stop {
+        stop = check_breakpoint(debugger_info)?;
+    }
+    Ok(Some((status, stop)))
+}
+
+/// handle the watchpoints whose debug registers trapped
+/// returns None if no watchpoint trapped, otherwise whether the target should stop
+pub fn check_watchpoint(
+    debugger_info: &mut DebuggerInfo,
+) -> Result<Option<bool>, Box<dyn std::error::Error>> {
+    let nums = debugger_info.breakpoint_manager.hit_watchpoints();
+    if nums.is_empty() {
+        return Ok(None);
+    }
+    let mut stop = false;
+    for num in nums {
+        stop |= debugger_info.hit_watchpoint(num)?;
+    }
+    Ok(Some(stop))
+}
+
+/// continue until `addr` is reached in the frame whose rsp is `sp` or outer
+/// returns false with the status if the target stopped for other reasons
+pub fn run_to(
+    debugger_info: &mut DebuggerInfo,
+    addr: u64,
+    sp: u64,
+) -> Result<(WaitStatus, bool), Box<dyn std::error::Error>> {
+    let (status, reached) = run_to_any(debugger_info, &[(addr, sp)])?;
+    Ok((status, reached.is_some()))
+}
+
+/// continue until one of the addresses is reached in the frame whose rsp is its `sp` or outer
+/// returns the index of the reached one, None with the status if the target stopped for other reasons
+pub fn run_to_any(
+    debugger_info: &mut DebuggerInfo,
+    targets: &[(u64, u64)],
+) -> Result<(WaitStatus, Option<usize>), Box<dyn std::error::Error>> {
+    if debugger_info.record.is_some() {
+        return step_to_any(debugger_info, targets);
+    }
+    let pid = debugger_info.debug_info.target_pid();
+    let origs = targets
+        .iter()
+        .map(|(addr, _)| Ok(mem::read_bytes(pid, *addr, 1)?[0]))
+        .collect::<Result<Vec<u8>, nix::Error>>()?;
+    let insert = || -> Result<(), nix::Error> {
+        for (addr, _) in targets {
+            mem::write_bytes(pid, *addr, &[0xcc])?;
+        }
+        Ok(())
+    };
+    // 同じアドレスが重なっていても元の値に戻るように逆順に書き戻す
+    let restore = || -> Result<(), nix::Error> {
+        for ((addr, _), orig) in targets.iter().zip(&origs).rev() {
+            mem::write_bytes(pid, *addr, &[*orig])?;
+        }
+        Ok(())
+    };
+    insert()?;
+    loop {
+        if let Some(status) = debugger_info.breakpoint_manager.step_over()? {
+            let _ = restore();
+            return Ok((status, None));
+        }
+        mem::cont(pid, None)?;
+        let status = waitpid(pid, None)?;
+        if let WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) = status {
+            // 新しいスレッドは止めたままにして次のcontinueで他のスレッドと一緒に再開する
+            debugger_info.new_thread(pid)?;
+            continue;
+        }
+        if status != WaitStatus::Stopped(pid, Signal::SIGTRAP) {
+            // 終了しているときは書き戻せなくてもよい
+            let _ = restore();
+            return Ok((status, None));
+        }
+
+        let mut regs = get_regs(pid);
+        if !targets.iter().any(|(addr, _)| regs.rip - 1 == *addr) {
+            if debugger_info.breakpoint_manager.hit_addr().is_some()
+                && !check_breakpoint(debugger_info)?
+            {
+                // 条件が偽のブレークポイントでは止まらない
+                continue;
+            }
+            restore()?;
+            return Ok((status, None));
+        }
+        regs.rip -= 1;
+        ptrace::setregs(pid, regs)?;
+        restore()?;
+        let reached = targets
+            .iter()
+            .position(|(addr, sp)| *addr == regs.rip && regs.rsp >= *sp);
+        if reached.is_some() {
+            return Ok((status, reached));
+        }
+        // 再帰呼び出しの内側で止まったので1命令進めてから仕掛け直す
+        let status = debugger_info.breakpoint_manager.step()?;
+        if status != WaitStatus::Stopped(pid, Signal::SIGTRAP) {
+            return Ok((status, None));
+        }
+        insert()?;
+    }
+}
+
+/// `run_to_any` by recorded steps
+fn step_to_any(
+    debugger_info: &mut DebuggerInfo,
+    targets: &[(u64, u64)],
+) -> Result<(WaitStatus, Option<usize>), Box<dyn std::error::Error>> {
+    let cancel = Cancel::start();
+    loop {
+        if cancel.is_cancelled() {
+            let pid = debugger_info.debug_info.target_pid();
+            return Ok((WaitStatus::Stopped(pid, Signal::SIGTRAP), None));
+        }
+        let (status, hit) = step_instruction(debugger_info)?;
+        if !matches!(status, WaitStatus::Stopped(_, Signal::SIGTRAP)) {
+            return Ok((status, None));
+        }
+        let regs = get_regs(debugger_info.debug_info.target_pid());
+        let reached = targets
+            .iter()
+            .position(|(addr, sp)| *addr == regs.rip && regs.rsp >= *sp);
+        if reached.is_some() || hit {
+            return Ok((status, reached));
+        }
+    }
+}
+
+/// step in the `call` at `call_addr`, other calls in [start, end) are stepped over
+pub fn step_into_call(
+    debugger_info: &mut DebuggerInfo,
+    call_addr: u64,
+    start: u64,
+    end: u64,
+) -> Result<WaitStatus, Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    loop {
+        let regs = get_regs(pid);
+        if regs.rip == call_addr {
+            let (status, _) = step_instruction(debugger_info)?;
+            return Ok(status);
+        }
+        if (regs.rip < start) || (end <= regs.rip) {
+            println!("the call was not reached");
+            return Ok(WaitStatus::Stopped(pid, Signal::SIGTRAP));
+        }
+
+        let instruction = instruction_at(debugger_info, regs.rip)?;
+        let (status, stopped) = if disasm::is_call(&instruction) {
+            let (status, reached) = run_to(debugger_info, instruction.next_ip(), regs.rsp)?;
+            (status, !reached)
+        } else {
+            step_instruction(debugger_info)?
+        };
+        if stopped || status != WaitStatus::Stopped(pid, Signal::SIGTRAP) {
+            return Ok(status);
+        }
+    }
+}
+
+/// list calls on the current line and step in the one chosen by the user
+pub fn step_in_choose(
+    debugger_info: &mut DebuggerInfo,
+) -> Result<WaitStatus, Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let stopped = WaitStatus::Stopped(pid, Signal::SIGTRAP);
+    let pc = get_regs(pid).rip;
+    let load_bias = debugger_info.debug_info.load_bias();
+    let range = debugger_info
+        .debug_info
+        .to_file_addr(pc)
+        .and_then(|file_pc| debugger_info.debug_info.find_line_range(file_pc));
+    let (start, end) = match range {
+        Some((start, end)) => (start + load_bias, end + load_bias),
+        None => {
+            println!("no line information at 0x{:016x}", pc);
+            return Ok(stopped);
+        }
+    };
+
+    let calls = disasm::disassemble(pid, &debugger_info.breakpoint_manager, start, end)?
+        .into_iter()
+        .filter(|instruction| instruction.ip() >= pc && disasm::is_call(instruction))
+        .collect::<Vec<_>>();
+    if calls.is_empty() {
+        println!("no calls on this line");
+        return Ok(stopped);
+    }
+    for (i, call) in calls.iter().enumerate() {
+        let target = match disasm::call_target(call) {
+            Some(target) => match debugger_info.debug_info.find_function_in(target) {
+                Some(f) => format!(
+                    "0x{:016x} {}",
+                    target,
+                    crate::symbolize::symbolize(&debugger_info.debug_info, f.name(), target)
+                ),
+                None => format!("0x{:016x} ??", target),
+            },
+            None => format!("indirect ({})", disasm::format(call)),
+        };
+        println!("[{}] 0x{:016x}: {}", i + 1, call.ip(), target);
+    }
+
+    let choice = debugger_info.read_line("choose> ")?.unwrap_or_default();
+    let call = match choice.parse::<usize>() {
+        Ok(n) if (1..=calls.len()).contains(&n) => calls[n - 1],
+        _ => {
+            println!("invalid choice");
+            return Ok(stopped);
+        }
+    };
+
+    let status = step_into_call(debugger_info, call.ip(), start, end)?;
+    if status == stopped {
+        print_frame(debugger_info);
+    }
+    Ok(status)
+}
+
+/// source line of the actual address, None if it has no line information
+fn line_at(debugger_info: &DebuggerInfo, addr: u64) -> Option<(String, u64)> {
+    let file_addr = debugger_info.debug_info.to_file_addr(addr)?;
+    let line = debugger_info.debug_info.find_line(file_addr)?;
+    Some((line.path, line.line))
+}
+
+/// whether the actual address is the first one of its line
+fn is_line_start(debugger_info: &DebuggerInfo, addr: u64) -> bool {
+    let debug_info = &debugger_info.debug_info;
+    debug_info
+        .to_file_addr(addr)
+        .and_then(|file_addr| debug_info.find_line_range(file_addr))
+        .is_some_and(|(start, _)| start + debug_info.load_bias() == addr)
+}
+
+/// step instructions until the pc reaches the start of another source line
+/// functions without line information are stepped over, entered functions stop after the prologue
+/// all calls are stepped over if `over` is true
+/// returns None if it returned to code without line information, then the target continues
+pub fn step_line(
+    debugger_info: &mut DebuggerInfo,
+    over: bool,
+) -> Result<Option<WaitStatus>, Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let stopped = WaitStatus::Stopped(pid, Signal::SIGTRAP);
+    let pc = get_regs(pid).rip;
+    let mut line = match line_at(debugger_info, pc) {
+        Some(line) => line,
+        None => {
+            println!("no line information at 0x{:016x}", pc);
+            return Ok(Some(stopped));
+        }
+    };
+
+    loop {
+        let regs = get_regs(pid);
+        let instruction = instruction_at(debugger_info, regs.rip)?;
+        let call = disasm::is_call(&instruction);
+        if !(call && over) {
+            let (status, hit) = step_instruction(debugger_info)?;
+            if hit || status != stopped {
+                return Ok(Some(status));
+            }
+        }
+        let mut pc = get_regs(pid).rip;
+        if call {
+            if !over && line_at(debugger_info, pc).is_some() && !is_skipped(debugger_info, pc) {
+                let status = skip_prologue(debugger_info, pc)?;
+                if status == stopped {
+                    print_frame(debugger_info);
+                }
+                return Ok(Some(status));
+            }
+            // 戻り先で止める、再帰呼び出しの内側ではrspが小さいので止まらない
+            let frames = unwind::backtrace(&debugger_info.debug_info);
+            let mut targets = vec![(instruction.next_ip(), regs.rsp)];
+            targets.extend(unwind_targets(debugger_info, &frames, 0, regs.rsp));
+            let (status, reached) = run_to_any(debugger_info, &targets)?;
+            pc = match reached {
+                Some(0) => instruction.next_ip(),
+                // 例外で戻らなかったのでランディングパッドから行の区切りまで進める
+                Some(_) => {
+                    let pc = get_regs(pid).rip;
+                    // 行情報のない関数で捕まったときはそこで止める
+                    if line_at(debugger_info, pc).is_none() {
+                        print_frame(debugger_info);
+                        return Ok(Some(status));
+                    }
+                    pc
+                }
+                None => return Ok(Some(status)),
+            };
+        }
+        match line_at(debugger_info, pc) {
+            // 行情報のない呼び出し元に戻った
+            None => return Ok(None),
+            // 0行目はコンパイラが作ったコードなので止まらない
+            Some((_, 0)) => {}
+            Some(new_line) if new_line != line => {
+                if is_line_start(debugger_info, pc) {
+                    print_frame(debugger_info);
+                    return Ok(Some(stopped));
+                }
+                // 関数から戻ったときなどは行の途中なので、その行の終わりまで進める
+                line = new_line;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// true if the function at `pc` is in the list of `skip`
+fn is_skipped(debugger_info: &DebuggerInfo, pc: u64) -> bool {
+    let Some(f) = debugger_info.debug_info.find_function_in(pc) else {
+        return false;
+    };
+    let name = demangle(f.name());
+    debugger_info
+        .skip
+        .iter()
+        .any(|skip| *skip == name || skip == f.name())
+}
+
+/// run to the end of the prologue if `pc` is the entry of a function
+fn skip_prologue(
+    debugger_info: &mut DebuggerInfo,
+    pc: u64,
+) -> Result<WaitStatus, Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let load_bias = debugger_info.debug_info.load_bias();
+    let prologue_end = debugger_info
+        .debug_info
+        .find_function_in(pc)
+        .filter(|f| f.address() + load_bias == pc)
+        .map(|f| (f.address(), f.size()))
+        .map(|(start, size)| after_prologue(debugger_info, start, size) + load_bias);
+    match prologue_end {
+        // プロローグでrspが下がるのでフレームは問わない
+        Some(addr) if addr != pc => Ok(run_to(debugger_info, addr, 0)?.0),
+        _ => Ok(WaitStatus::Stopped(pid, Signal::SIGTRAP)),
+    }
+}
+
+/// file address where locals and parameters are set up, `start` if the prologue is not found
+pub fn after_prologue(debugger_info: &DebuggerInfo, start: u64, size: u64) -> u64 {
+    let end = start + size;
+    if let Some(addr) = debugger_info.debug_info.prologue_end(start, end) {
+        return addr;
+    }
+    // 行情報がなければ命令列から推測する
+    let load_bias = debugger_info.debug_info.load_bias();
+    let len = size.min(64);
+    match disasm::disassemble(
+        debugger_info.debug_info.target_pid(),
+        &debugger_info.breakpoint_manager,
+        start + load_bias,
+        start + load_bias + len,
+    ) {
+        Ok(instructions) => disasm::skip_prologue(&instructions)
+            .map(|addr| addr - load_bias)
+            .filter(|addr| *addr < end)
+            .unwrap_or(start),
+        Err(_) => start,
+    }
+}
+
+/// landing pads of `frames` from `level` with the rsp they are reached at, `sp` for the frame `level`
+/// an exception which unwinds past the return address enters one of them
+fn unwind_targets(
+    debugger_info: &DebuggerInfo,
+    frames: &[unwind::Frame],
+    level: usize,
+    sp: u64,
+) -> Vec<(u64, u64)> {
+    let debug_info = &debugger_info.debug_info;
+    let mut targets = Vec::new();
+    for (i, frame) in frames.iter().enumerate().skip(level) {
+        // 再帰呼び出しの内側のランディングパッドではrspが小さいので止まらない
+        let sp = if i == level { sp } else { frame.regs.rsp };
+        for pad in unwind::landing_pads(debug_info, frame.lookup_pc(i)) {
+            targets.push((pad, sp));
+        }
+    }
+    targets
+}
+
+pub fn print_frame(debugger_info: &DebuggerInfo) {
+    if let Some(frame) = unwind::backtrace(&debugger_info.debug_info).first() {
+        dump::frame(debugger_info, 0, frame);
+    }
+}
+
+/// run until the selected frame returns and print the return value
+pub fn finish(debugger_info: &mut DebuggerInfo) -> Result<WaitStatus, Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let stopped = WaitStatus::Stopped(pid, Signal::SIGTRAP);
+    let level = debugger_info.selected_frame;
+    let frames = unwind::backtrace(&debugger_info.debug_info);
+    let (frame, caller) = match (frames.get(level), frames.get(level + 1)) {
+        (Some(frame), Some(caller)) => (frame, caller),
+        _ => {
+            println!("\"finish\" not meaningful in the outermost frame");
+            return Ok(stopped);
+        }
+    };
+    print!("run till exit from ");
+    dump::frame(debugger_info, level, frame);
+    let file_pc = debugger_info
+        .debug_info
+        .to_file_addr(frame.lookup_pc(level));
+    let ty = file_pc.and_then(variable::return_type);
+    let rust = file_pc.and_then(variable::language) == Some(gimli::DW_LANG_Rust);
+
+    // 戻り先で呼び出し元のrspに戻ったときに止める
+    let mut targets = vec![(caller.regs.rip, frame.cfa)];
+    targets.extend(unwind_targets(debugger_info, &frames, level + 1, frame.cfa));
+    let (status, reached) = run_to_any(debugger_info, &targets)?;
+    debugger_info.selected_frame = 0;
+    match reached {
+        Some(0) => {}
+        Some(_) => {
+            println!("the function did not return, an exception reached a landing pad");
+            print_frame(debugger_info);
+            return Ok(status);
+        }
+        None => return Ok(status),
+    }
+    print_frame(debugger_info);
+    match ty {
+        None | Some(Type::Void) => {}
+        Some(ty) => match return_value(pid, &ty, rust) {
+            Ok(value) => println!("value returned: {value}"),
+            Err(e) => println!("{e}"),
+        },
+    }
+    Ok(status)
+}
+
+/// pop the selected frame and the frames called from it without running them
+/// `value` is placed in the return register for the type of the function
+pub fn force_return(
+    debugger_info: &mut DebuggerInfo,
+    value: Option<&Expr>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    let level = debugger_info.selected_frame;
+    let frames = unwind::backtrace(&debugger_info.debug_info);
+    let (frame, caller) = match (frames.get(level), frames.get(level + 1)) {
+        (Some(frame), Some(caller)) => (frame, caller),
+        _ => {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can not return from the outermost frame",
+            )))
+        }
+    };
+    // 値は戻る前のフレームで評価する
+    let value = match value {
+        Some(expr) => Some(expr::eval(expr, &debugger_info.scope())?),
+        None => None,
+    };
+    let file_pc = debugger_info
+        .debug_info
+        .to_file_addr(frame.lookup_pc(level));
+    let ty = file_pc.and_then(variable::return_type);
+    let rust = file_pc.and_then(variable::language) == Some(gimli::DW_LANG_Rust);
+
+    // 呼び出し元のレジスタは巻き戻しで復元したcallee-savedレジスタ、rsp、ripを含む
+    let mut regs = caller.regs;
+    if let Some(value) = value {
+        match &ty {
+            Some(ty) if ty.is_float() && ty.size() <= 8 => {
+                let bytes = match ty.size() {
+                    4 => (value.as_f64() as f32).to_le_bytes().to_vec(),
+                    _ => value.as_f64().to_le_bytes().to_vec(),
+                };
+                register::set_xmm(pid, 0, &bytes)?;
+            }
+            Some(ty) if abi::in_xmm(ty) && !rust => {
+                if value.bytes.len() != ty.size() as usize {
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("cannot return {} as {}", value.ty.name(), ty.name()),
+                    )));
+                }
+                register::set_xmm(pid, 0, &value.bytes)?;
+            }
+            // 128ビット整数はrdx:rax
+            Some(ty) if ty.size() == 16 && matches!(ty.canonical(), Type::Base { .. }) => {
+                let value = value.as_u128();
+                regs.rax = value as u64;
+                regs.rdx = (value >> 64) as u64;
+            }
+            Some(ty) if ty.size() > 8 => {
+                return Err(Box::new(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("return value of type {} can not be set", ty.name()),
+                )))
+            }
+            _ => regs.rax = value.as_u64(),
+        }
+    }
+    ptrace::setregs(pid, regs)?;
+    debugger_info.selected_frame = 0;
+    print_frame(debugger_info);
+    Ok(())
+}
+
+/// value in the return registers, classified by the ABI of the function
+/// `rust` if the function has the Rust ABI
+fn return_value(pid: Pid, ty: &Type, rust: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let bytes = abi::return_value(pid, ty, rust)?;
+    Ok(format_value(pid, ty, &bytes))
+}
+
+/// continue until `addr` or the return of the current frame
+/// if `frame_only`, `addr` is ignored in the frames called from the current one
+pub fn run_until(
+    debugger_info: &mut DebuggerInfo,
+    addr: u64,
+    frame_only: bool,
+) -> Result<WaitStatus, Box<dyn std::error::Error>> {
+    let pid = debugger_info.debug_info.target_pid();
+    debugger_info.selected_frame = 0;
+    let sp = if frame_only { get_regs(pid).rsp } else { 0 };
+    let mut targets = vec![(addr, sp)];
+    // 場所に着かずに関数から戻ってしまったときも止める
+    let frames = unwind::backtrace(&debugger_info.debug_info);
+    if let (Some(frame), Some(caller)) = (frames.first(), frames.get(1)) {
+        targets.push((caller.regs.rip, frame.cfa));
+    }
+    let (status, reached) = run_to_any(debugger_info, &targets)?;
+    if reached.is_some() {
+        print_frame(debugger_info);
+    }
+    Ok(status)
+}
@@ -0,0 +1,368 @@
+use crate::debug_info::TdbDebugInfo;
+use nix::{
+    poll::{poll, PollFd, PollFlags},
+    unistd::Pid,
+};
+use proc_maps::get_process_maps;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::io::AsRawFd,
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::Mutex,
+};
+use symbolic::{
+    common::Name,
+    demangle::{Demangle, DemangleOptions},
+};
+
+/// path segments which rustc generates for anonymous functions
+const ANONYMOUS_SEGMENTS: [(&str, &str); 5] = [
+    ("{{closure}}", "closure"),
+    ("{closure#", "closure"),
+    ("{async_block#", "async block"),
+    ("{async_fn_body#", "async fn body"),
+    ("{generator#", "generator"),
+];
+
+pub fn demangle(name: &str) -> String {
+    Name::from(name)
+        .try_demangle(DemangleOptions::name_only())
+        .to_string()
+}
+
+/// demangle `name` and annotate closures and async bodies with the enclosing function
+/// for callers which print the source line themselves
+pub fn function_name(name: &str) -> String {
+    match anonymous(name) {
+        Some((kind, enclosing)) => format!("{} in {}", kind, enclosing),
+        None => demangle(name),
+    }
+}
+
+/// demangle `name` and annotate closures and async bodies
+/// with the enclosing function and source line
+pub fn symbolize(debug_info: &TdbDebugInfo, name: &str, actual_addr: u64) -> String {
+    let Some((kind, enclosing)) = anonymous(name) else {
+        return demangle(name);
+    };
+    let line = debug_info
+        .to_file_addr(actual_addr)
+        .and_then(|file_addr| debug_info.find_line(file_addr));
+    match line {
+        Some(line) => format!("{} in {} at {}", kind, enclosing, line),
+        None => format!("{} in {}", kind, enclosing),
+    }
+}
+
+/// kind of the anonymous function and the path of the enclosing function
+fn anonymous(name: &str) -> Option<(&'static str, String)> {
+    let demangled = demangle(name);
+    let segments = split_path(&demangled);
+
+    let anonymous = segments.iter().enumerate().find_map(|(i, segment)| {
+        ANONYMOUS_SEGMENTS
+            .iter()
+            .find(|(prefix, _)| segment.starts_with(prefix))
+            .map(|(_, kind)| (i, *kind))
+    });
+    match anonymous {
+        Some((i, kind)) => Some((kind, segments[..i].join("::"))),
+        // `<T as Future>::poll`はpollしている型を表示する、普通のpollメソッドは除く
+        None if segments.len() == 2 && segments[1] == "poll" && is_future(segments[0]) => {
+            Some(("poll", segments[0].to_string()))
+        }
+        None => None,
+    }
+}
+
+/// true if the segment is `<T as core::future::Future>`
+fn is_future(segment: &str) -> bool {
+    let Some(qualified) = segment.strip_prefix('<').and_then(|s| s.strip_suffix('>')) else {
+        return false;
+    };
+    let Some(trait_path) = split_as(qualified).1 else {
+        return false;
+    };
+    let trait_path = split_path(trait_path);
+    trait_path.first() == Some(&"core")
+        && trait_path.last().map(|name| strip_generics(name)) == Some("Future".to_string())
+}
+
+/// module path and item name of the demangled path
+/// `<T as Trait>::f` belongs to the module of `T`
+pub fn module_path(demangled: &str) -> (Vec<String>, String) {
+    let segments = split_path(demangled);
+    let name = segments.last().unwrap_or(&"").to_string();
+    let mut path = Vec::new();
+    for (i, segment) in segments[..segments.len() - 1].iter().enumerate() {
+        match segment.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+            Some(qualified) if i == 0 => {
+                let (self_path, self_name) = module_path(self_type(qualified));
+                path.extend(self_path);
+                path.push(strip_generics(&self_name));
+            }
+            _ => path.push(strip_generics(segment)),
+        }
+    }
+    (path, name)
+}
+
+/// `T` and `Trait` of `T as Trait`
+fn split_as(qualified: &str) -> (&str, Option<&str>) {
+    let mut depth = 0;
+    for (i, c) in qualified.char_indices() {
+        match c {
+            '<' => depth += 1,
+            // `fn() -> T`の`>`は閉じ括弧ではない
+            '>' if depth > 0 && !qualified[..i].ends_with('-') => depth -= 1,
+            ' ' if depth == 0 && qualified[i..].starts_with(" as ") => {
+                return (&qualified[..i], Some(&qualified[i + 4..]));
+            }
+            _ => {}
+        }
+    }
+    (qualified, None)
+}
+
+/// `T` of `T as Trait` without references and `dyn`
+fn self_type(qualified: &str) -> &str {
+    let mut self_type = split_as(qualified).0;
+    while let Some(stripped) = ["&", "mut ", "dyn ", "*const ", "*mut "]
+        .iter()
+        .find_map(|prefix| self_type.strip_prefix(prefix))
+    {
+        self_type = stripped;
+    }
+    self_type
+}
+
+fn strip_generics(segment: &str) -> String {
+    segment.split('<').next().unwrap_or(segment).to_string()
+}
+
+/// split demangled path by `::` except inside of `<...>`
+pub fn split_path(path: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let bytes = path.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'<' => depth += 1,
+            // `fn() -> T`の`>`は閉じ括弧ではない
+            b'>' if depth > 0 && (i == 0 || bytes[i - 1] != b'-') => depth -= 1,
+            b':' if depth == 0 && bytes.get(i + 1) == Some(&b':') => {
+                segments.push(&path[start..i]);
+                start = i + 2;
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    segments.push(&path[start..]);
+    segments
+}
+
+/// how long to wait for each line of the external symbolizer
+const EXTERNAL_TIMEOUT_MS: i32 = 2000;
+
+/// external program set by `set symbolizer-cmd`
+static EXTERNAL: Mutex<Option<External>> = Mutex::new(None);
+
+/// function and `file:line` of an address answered by the external symbolizer
+#[derive(Debug, Clone)]
+pub struct ExternalSymbol {
+    pub function: String,
+    pub line: Option<String>,
+}
+
+/// program which reads `<module> 0x<offset>` lines and answers each of them
+/// with the function, `file:line` and an empty line like llvm-symbolizer
+struct External {
+    command: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    cache: HashMap<(String, u64), Option<ExternalSymbol>>,
+}
+
+impl External {
+    fn spawn(command: &str) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self {
+            command: command.to_string(),
+            child,
+            stdin,
+            stdout,
+            cache: HashMap::new(),
+        })
+    }
+
+    fn query(&mut self, module: &str, offset: u64) -> io::Result<Option<ExternalSymbol>> {
+        let key = (module.to_string(), offset);
+        if let Some(symbol) = self.cache.get(&key) {
+            return Ok(symbol.clone());
+        }
+        writeln!(self.stdin, "{module} 0x{offset:x}")?;
+        self.stdin.flush()?;
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            // 空行で1つのアドレスの答えが終わる
+            if line.is_empty() {
+                break;
+            }
+            lines.push(line);
+        }
+        // インライン展開された関数は最初の1つだけ使う
+        let function = lines.first().filter(|f| !f.starts_with("??"));
+        let symbol = function.map(|function| ExternalSymbol {
+            function: function.clone(),
+            line: lines.get(1).filter(|l| !l.starts_with("??")).cloned(),
+        });
+        self.cache.insert(key, symbol.clone());
+        Ok(symbol)
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        if self.stdout.buffer().is_empty() {
+            let mut fds = [PollFd::new(
+                self.stdout.get_ref().as_raw_fd(),
+                PollFlags::POLLIN,
+            )];
+            if poll(&mut fds, EXTERNAL_TIMEOUT_MS)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "no answer"));
+            }
+        }
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "exited"));
+        }
+        Ok(line.trim_end().to_string())
+    }
+}
+
+impl Drop for External {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// start the external symbolizer, None stops the current one
+pub fn set_external(command: Option<&str>) -> io::Result<()> {
+    let external = command.map(External::spawn).transpose()?;
+    *EXTERNAL.lock().unwrap() = external;
+    Ok(())
+}
+
+pub fn external_command() -> Option<String> {
+    EXTERNAL
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|external| external.command.clone())
+}
+
+/// ask the external symbolizer about the address which tdb cannot symbolize
+/// the symbolizer is stopped if it fails so that every stop does not wait for it
+pub fn external(pid: Pid, actual_addr: u64) -> Option<ExternalSymbol> {
+    let mut guard = EXTERNAL.lock().unwrap();
+    let external = guard.as_mut()?;
+    let (module, offset) = module_offset(pid, actual_addr)?;
+    match external.query(&module, offset) {
+        Ok(symbol) => symbol,
+        Err(e) => {
+            println!(
+                "symbolizer-cmd `{}` failed: {e}, it is disabled",
+                external.command
+            );
+            *guard = None;
+            None
+        }
+    }
+}
+
+/// file mapped at the address and the address in the file
+/// shared objects and PIEs are moved by the lowest address they are mapped at
+fn module_offset(pid: Pid, actual_addr: u64) -> Option<(String, u64)> {
+    let maps = get_process_maps(pid.as_raw()).ok()?;
+    let map = maps
+        .iter()
+        .find(|m| m.start() as u64 <= actual_addr && actual_addr < (m.start() + m.size()) as u64)?;
+    let path = map.filename()?;
+    let base = maps
+        .iter()
+        .filter(|m| m.filename() == Some(path))
+        .map(|m| m.start() as u64)
+        .min()?;
+    let module = path.to_str()?.to_string();
+    // ET_EXECはリンク時のアドレスにロードされる
+    let offset = if elf_type(path) == Some(2) {
+        actual_addr
+    } else {
+        actual_addr - base
+    };
+    Some((module, offset))
+}
+
+/// e_type of the ELF header
+fn elf_type(path: &Path) -> Option<u16> {
+    let mut header = [0u8; 18];
+    fs::File::open(path).ok()?.read_exact(&mut header).ok()?;
+    (header[..4] == *b"\x7fELF").then(|| u16::from_le_bytes([header[16], header[17]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_path_skips_generics() {
+        assert_eq!(split_path("a::b<c::d>::e"), vec!["a", "b<c::d>", "e"]);
+        assert_eq!(
+            split_path("a::b<fn() -> c::d, e::f>::g"),
+            vec!["a", "b<fn() -> c::d, e::f>", "g"]
+        );
+    }
+
+    #[test]
+    fn self_type_of_qualified_path() {
+        assert_eq!(self_type("&mut a::B as c::D"), "a::B");
+        assert_eq!(self_type("a::B<fn() -> u8> as c::D"), "a::B<fn() -> u8>");
+        assert_eq!(split_as("a::B"), ("a::B", None));
+    }
+
+    #[test]
+    fn anonymous_closure() {
+        assert_eq!(
+            anonymous("p::main::{{closure}}"),
+            Some(("closure", "p::main".to_string()))
+        );
+    }
+
+    #[test]
+    fn poll_only_of_futures() {
+        assert_eq!(
+            anonymous("<p::Task as core::future::future::Future>::poll"),
+            Some((
+                "poll",
+                "<p::Task as core::future::future::Future>".to_string()
+            ))
+        );
+        assert_eq!(anonymous("p::Socket::poll"), None);
+        assert_eq!(anonymous("<p::Socket as p::Poll>::poll"), None);
+    }
+}
@@ -1,8 +1,12 @@
 #![allow(unused)]
+use crate::{
+    bounded::{Bounded, HeapSize, Policy},
+    expr::Value,
+};
 use nix::{libc::user_regs_struct, sys::ptrace, unistd::Pid};
 use once_cell::sync::OnceCell;
-use std::{collections::LinkedList, fmt, sync::Mutex};
-use syscalls::Sysno;
+use std::{fmt, sync::Mutex};
+use syscalls::{Errno, Sysno};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SyscallNumber(u64);
@@ -47,24 +51,46 @@ impl SyscallInfo {
     }
 }
 
+impl HeapSize for SyscallInfo {
+    fn heap_size(&self) -> usize {
+        self.name.capacity()
+    }
+}
+
+/// syscalls kept by default, ones which never return such as execve pile up
+pub const SYSCALL_STACK_LIMIT: usize = 256;
+
 #[derive(Debug)]
-pub struct SyscallStack(LinkedList<SyscallInfo>);
+pub struct SyscallStack(Bounded<SyscallInfo>);
 
 impl SyscallStack {
     pub fn new() -> Self {
-        Self(LinkedList::new())
+        Self(Bounded::new(Policy::new(SYSCALL_STACK_LIMIT)))
     }
 
     pub fn push(&mut self, info: SyscallInfo) {
-        self.0.push_front(info);
+        self.0.push(info);
     }
 
     pub fn pop(&mut self) -> Option<SyscallInfo> {
-        self.0.pop_front()
+        self.0.pop()
     }
 
     pub fn top(&self) -> Option<&SyscallInfo> {
-        self.0.front()
+        self.0.last()
+    }
+
+    /// forget the syscalls of the previous process, the limit is kept
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn buffer(&self) -> &Bounded<SyscallInfo> {
+        &self.0
+    }
+
+    pub fn buffer_mut(&mut self) -> &mut Bounded<SyscallInfo> {
+        &mut self.0
     }
 
     pub fn is_exit(&self, n: SyscallNumber) -> bool {
@@ -76,6 +102,167 @@ impl SyscallStack {
     }
 }
 
+/// how a syscall argument is printed
+#[derive(Debug, Clone, Copy)]
+enum ArgKind {
+    /// `int` such as file descriptors
+    Int,
+    /// `size_t`
+    Len,
+    Hex,
+    Oct,
+    /// NUL terminated string
+    Str,
+    /// buffer whose length is the next argument
+    Buf,
+}
+
+/// longest string shown in the arguments
+const MAX_ARG_STRING: usize = 32;
+
+/// longest string bound to an argument name in conditions
+const MAX_BOUND_STRING: usize = 4096;
+
+/// names and kinds of the arguments of common syscalls
+/// others are shown as 6 hex numbers
+fn arg_kinds(sysno: Sysno) -> Option<&'static [(&'static str, ArgKind)]> {
+    use ArgKind::*;
+    let kinds: &[(&str, ArgKind)] = match sysno {
+        Sysno::read | Sysno::pread64 => &[("fd", Int), ("buf", Hex), ("count", Len)],
+        Sysno::write | Sysno::pwrite64 => &[("fd", Int), ("buf", Buf), ("count", Len)],
+        Sysno::open => &[("path", Str), ("flags", Hex), ("mode", Oct)],
+        Sysno::openat => &[("dirfd", Int), ("path", Str), ("flags", Hex), ("mode", Oct)],
+        Sysno::close => &[("fd", Int)],
+        Sysno::exit | Sysno::exit_group => &[("status", Int)],
+        Sysno::stat | Sysno::lstat => &[("path", Str), ("statbuf", Hex)],
+        Sysno::fstat => &[("fd", Int), ("statbuf", Hex)],
+        Sysno::newfstatat => &[
+            ("dirfd", Int),
+            ("path", Str),
+            ("statbuf", Hex),
+            ("flags", Hex),
+        ],
+        Sysno::access => &[("path", Str), ("mode", Oct)],
+        Sysno::execve => &[("path", Str), ("argv", Hex), ("envp", Hex)],
+        Sysno::unlink | Sysno::rmdir | Sysno::chdir => &[("path", Str)],
+        Sysno::mkdir => &[("path", Str), ("mode", Oct)],
+        Sysno::mmap => &[
+            ("addr", Hex),
+            ("length", Len),
+            ("prot", Hex),
+            ("flags", Hex),
+            ("fd", Int),
+            ("offset", Hex),
+        ],
+        Sysno::munmap => &[("addr", Hex), ("length", Len)],
+        Sysno::mprotect => &[("addr", Hex), ("length", Len), ("prot", Hex)],
+        Sysno::brk => &[("addr", Hex)],
+        Sysno::kill => &[("pid", Int), ("sig", Int)],
+        Sysno::getpid | Sysno::getppid | Sysno::fork | Sysno::vfork => &[],
+        _ => return None,
+    };
+    Some(kinds)
+}
+
+fn args(regs: &user_regs_struct) -> [u64; 6] {
+    [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9]
+}
+
+/// `name(arg, ...)` of the syscall about to be executed
+pub fn format_call(pid: Pid, regs: &user_regs_struct) -> String {
+    let args = args(regs);
+    let sysno = match Sysno::new(regs.orig_rax as usize) {
+        Some(sysno) => sysno,
+        None => return format!("syscall_{}()", regs.orig_rax),
+    };
+    let kinds = arg_kinds(sysno).unwrap_or(&[("", ArgKind::Hex); 6]);
+    let formatted: Vec<String> = kinds
+        .iter()
+        .enumerate()
+        .map(|(i, (_, kind))| match kind {
+            ArgKind::Int => (args[i] as i32).to_string(),
+            ArgKind::Len => args[i].to_string(),
+            ArgKind::Hex => format!("0x{:x}", args[i]),
+            ArgKind::Oct => format!("0o{:o}", args[i]),
+            ArgKind::Str => format_string(pid, args[i], MAX_BOUND_STRING, true),
+            ArgKind::Buf => format_string(pid, args[i], args[i + 1] as usize, false),
+        })
+        .collect();
+    format!("{}({})", sysno.name(), formatted.join(", "))
+}
+
+/// values of the arguments for the conditions of catchpoints
+/// `arg0`..`arg5` are always bound, `ret` too if the syscall has returned
+pub fn bindings(pid: Pid, regs: &user_regs_struct, exit: bool) -> Vec<(String, Value)> {
+    let args = args(regs);
+    let mut bindings: Vec<(String, Value)> = args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| (format!("arg{i}"), Value::integer(*arg, false)))
+        .collect();
+    let kinds = Sysno::new(regs.orig_rax as usize).and_then(arg_kinds);
+    for (i, (name, kind)) in kinds.unwrap_or_default().iter().enumerate() {
+        let value = match kind {
+            ArgKind::Int => Value::integer(args[i] as i32 as u64, true),
+            ArgKind::Len | ArgKind::Hex | ArgKind::Oct => Value::integer(args[i], false),
+            ArgKind::Str => match read_string(pid, args[i], MAX_BOUND_STRING, true) {
+                Some(bytes) => Value::string(&bytes),
+                None => Value::integer(args[i], false),
+            },
+            ArgKind::Buf => match read_string(pid, args[i], args[i + 1] as usize, false) {
+                Some(bytes) => Value::string(&bytes),
+                None => Value::integer(args[i], false),
+            },
+        };
+        bindings.push((name.to_string(), value));
+    }
+    if exit {
+        bindings.push((String::from("ret"), Value::integer(regs.rax, true)));
+    }
+    bindings
+}
+
+/// return value of the syscall which has just returned, with the errno name
+pub fn format_return(regs: &user_regs_struct) -> String {
+    let ret = regs.rax as i64;
+    match Errno::from_ret(regs.rax as usize) {
+        Err(errno) => format!("{} {}", ret, errno),
+        Ok(_) if ret > 0xffff => format!("0x{:x}", ret),
+        Ok(_) => ret.to_string(),
+    }
+}
+
+/// at most `len` bytes at the address, up to NUL if `nul`
+/// returns None if the address is not readable
+fn read_string(pid: Pid, addr: u64, len: usize, nul: bool) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for i in 0..len.min(MAX_BOUND_STRING) as u64 {
+        let byte = match crate::mem::read_bytes(pid, addr + i, 1) {
+            Ok(byte) => byte[0],
+            Err(_) if i == 0 => return None,
+            Err(_) => break,
+        };
+        if nul && byte == 0 {
+            break;
+        }
+        bytes.push(byte);
+    }
+    Some(bytes)
+}
+
+fn format_string(pid: Pid, addr: u64, len: usize, nul: bool) -> String {
+    // 1バイト多く読んで切り詰めたかどうかを調べる
+    let bytes = match read_string(pid, addr, len.min(MAX_ARG_STRING + 1), nul) {
+        Some(bytes) => bytes,
+        None => return format!("0x{:x}", addr),
+    };
+    if bytes.len() > MAX_ARG_STRING {
+        format!("{:?}...", String::from_utf8_lossy(&bytes[..MAX_ARG_STRING]))
+    } else {
+        format!("{:?}", String::from_utf8_lossy(&bytes))
+    }
+}
+
 pub fn get_regs(pid: Pid) -> user_regs_struct {
     for i in 0..100 {
         let maybe_reg = ptrace::getregs(pid);
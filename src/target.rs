@@ -1,13 +1,32 @@
-use nix::{sys::ptrace, unistd::execvp};
+use nix::{
+    sys::ptrace,
+    unistd::{execvp, fork, ForkResult, Pid},
+};
 use std::{
     ffi::{CStr, CString},
     fs,
     path::Path,
 };
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct DebuggeeInfo {}
 
+/// fork and exec the target, returns the pid of the child
+pub fn spawn(file: &str, args: &[String]) -> Result<Pid, nix::Error> {
+    match unsafe { fork() }? {
+        ForkResult::Parent { child } => Ok(child),
+        ForkResult::Child => {
+            target_main(
+                Path::new(file),
+                &args.iter().map(|s| &**s).collect::<Vec<&str>>(),
+            );
+            // execvpに失敗したら親に戻らない
+            std::process::exit(1);
+        }
+    }
+}
+
 pub fn target_main(path: &Path, args: &[&str]) {
     let _ = ptrace::traceme();
     let cstr = path_to_cstr(path);
@@ -0,0 +1,223 @@
+use nix::{
+    libc::{siginfo_t, user_regs_struct},
+    sys::{ptrace, signal::Signal, wait::WaitStatus},
+    unistd::Pid,
+};
+use std::fs;
+
+/// which threads run while the current thread is resumed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchedulerLocking {
+    /// all threads run
+    Off,
+    /// only the current thread runs while stepping
+    #[default]
+    Step,
+    /// only the current thread runs
+    On,
+}
+
+impl std::fmt::Display for SchedulerLocking {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchedulerLocking::Off => write!(f, "off"),
+            SchedulerLocking::Step => write!(f, "step"),
+            SchedulerLocking::On => write!(f, "on"),
+        }
+    }
+}
+
+/// whether the thread runs, threads are stopped together at every stop of the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Running,
+    Stopped,
+}
+
+#[derive(Debug, Clone)]
+pub struct Thread {
+    /// number shown to the user, the main thread is 1
+    pub num: usize,
+    pub tid: Pid,
+    pub state: ThreadState,
+    /// signal received while the thread was being stopped, delivered when it resumes
+    pub pending: Option<Signal>,
+    /// registers at the last stop
+    pub regs: Option<user_regs_struct>,
+    /// the hit of the breakpoint at rip was already reported, it is stepped over on resume
+    pub step_over: bool,
+    /// stop which the thread reported while it was being stopped, with its siginfo
+    /// the next `continue` reports it instead of resuming the threads
+    pub event: Option<(WaitStatus, siginfo_t)>,
+}
+
+/// threads of the target
+#[derive(Debug)]
+pub struct Threads {
+    /// thread group id, the pid of the process
+    pid: Pid,
+    threads: Vec<Thread>,
+    /// thread selected by the stop or `thread`
+    current: Pid,
+    next_num: usize,
+    /// threads which stopped before the event of `clone` reported them
+    early: Vec<Pid>,
+}
+
+impl Threads {
+    pub fn new(pid: Pid) -> Self {
+        let mut threads = Self {
+            pid,
+            threads: Vec::new(),
+            current: pid,
+            next_num: 1,
+            early: Vec::new(),
+        };
+        threads.add(pid);
+        threads
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn current(&self) -> Pid {
+        self.current
+    }
+
+    pub fn set_current(&mut self, tid: Pid) {
+        self.current = tid;
+    }
+
+    /// add a stopped thread and return its number
+    pub fn add(&mut self, tid: Pid) -> usize {
+        let num = self.next_num;
+        self.next_num += 1;
+        self.threads.push(Thread {
+            num,
+            tid,
+            state: ThreadState::Stopped,
+            pending: None,
+            regs: None,
+            step_over: false,
+            event: None,
+        });
+        num
+    }
+
+    /// forget the exited thread and return its number
+    pub fn remove(&mut self, tid: Pid) -> Option<usize> {
+        let index = self.threads.iter().position(|t| t.tid == tid)?;
+        Some(self.threads.remove(index).num)
+    }
+
+    pub fn get(&self, tid: Pid) -> Option<&Thread> {
+        self.threads.iter().find(|t| t.tid == tid)
+    }
+
+    pub fn get_mut(&mut self, tid: Pid) -> Option<&mut Thread> {
+        self.threads.iter_mut().find(|t| t.tid == tid)
+    }
+
+    pub fn find(&self, num: usize) -> Option<&Thread> {
+        self.threads.iter().find(|t| t.num == num)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Thread> {
+        self.threads.iter()
+    }
+
+    /// threads other than the current one
+    pub fn others(&self) -> Vec<Pid> {
+        self.threads
+            .iter()
+            .map(|t| t.tid)
+            .filter(|tid| *tid != self.current)
+            .collect()
+    }
+
+    /// take the queued stop of the thread with the smallest number
+    pub fn take_event(&mut self) -> Option<(Pid, WaitStatus, siginfo_t)> {
+        let thread = self.threads.iter_mut().find(|t| t.event.is_some())?;
+        let (status, siginfo) = thread.event.take()?;
+        Some((thread.tid, status, siginfo))
+    }
+
+    /// remember a stop of a thread which is not known yet
+    pub fn stopped_early(&mut self, tid: Pid) {
+        self.early.push(tid);
+    }
+
+    /// true if the new thread already stopped, it does not have to be waited for
+    pub fn take_early(&mut self, tid: Pid) -> bool {
+        match self.early.iter().position(|early| *early == tid) {
+            Some(index) => {
+                self.early.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// start over with the single thread of a new process or a process after exec
+    pub fn reset(&mut self, pid: Pid) {
+        *self = Self::new(pid);
+    }
+}
+
+/// threads of the process other than `pid` itself
+pub fn tasks(pid: Pid) -> Vec<Pid> {
+    let Ok(entries) = fs::read_dir(format!("/proc/{pid}/task")) else {
+        return Vec::new();
+    };
+    let mut tids: Vec<Pid> = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .map(Pid::from_raw)
+        .filter(|tid| *tid != pid)
+        .collect();
+    tids.sort();
+    tids
+}
+
+/// name of the thread set by `prctl` or `pthread_setname_np`
+pub fn name(pid: Pid, tid: Pid) -> Option<String> {
+    let comm = fs::read_to_string(format!("/proc/{pid}/task/{tid}/comm")).ok()?;
+    Some(comm.trim_end().to_string())
+}
+
+/// state of the thread seen by the kernel, the third field of `stat`
+pub fn kernel_state(pid: Pid, tid: Pid) -> Option<&'static str> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/task/{tid}/stat")).ok()?;
+    // 名前に空白や括弧が入っていてもよいように最後の括弧の後から読む
+    let (_, rest) = stat.rsplit_once(')')?;
+    let state = match rest.trim_start().chars().next()? {
+        'R' => "running",
+        'S' => "sleeping",
+        'D' => "disk sleep",
+        'T' => "stopped",
+        't' => "tracing stop",
+        'Z' => "zombie",
+        'X' => "dead",
+        'I' => "idle",
+        _ => "unknown",
+    };
+    Some(state)
+}
+
+/// stop the thread with SIGSTOP, only the thread receives it
+pub fn interrupt(pid: Pid, tid: Pid) -> Result<(), nix::Error> {
+    let ret = unsafe {
+        nix::libc::syscall(
+            nix::libc::SYS_tgkill,
+            pid.as_raw(),
+            tid.as_raw(),
+            Signal::SIGSTOP as i32,
+        )
+    };
+    nix::errno::Errno::result(ret).map(drop)
+}
+
+/// registers of the thread, None if it is not stopped
+pub fn regs(tid: Pid) -> Option<user_regs_struct> {
+    ptrace::getregs(tid).ok()
+}
@@ -0,0 +1,367 @@
+use crate::{
+    debug_info::{self, TdbDebugInfo},
+    mem,
+    register::RegisterType,
+    syscall::get_regs,
+};
+use gimli::{
+    constants, BaseAddresses, CfaRule, DwEhPe, EhFrame, EndianSlice, Pointer, Reader, RegisterRule,
+    RunTimeEndian, UnwindContext, UnwindSection, X86_64,
+};
+use nix::{
+    libc::{self, mcontext_t, ucontext_t, user_regs_struct},
+    unistd::Pid,
+};
+use object::{Object, ObjectSection};
+use std::mem::offset_of;
+
+const MAX_FRAMES: usize = 256;
+
+/// code of __restore_rt: mov rax, 15 (rt_sigreturn); syscall
+const SIGRETURN_CODE: [u8; 9] = [0x48, 0xc7, 0xc0, 0x0f, 0x00, 0x00, 0x00, 0x0f, 0x05];
+
+/// legacy vsyscall page
+const VSYSCALL_START: u64 = 0xffffffffff600000;
+const VSYSCALL_END: u64 = VSYSCALL_START + 0x1000;
+
+/// how the CFA of the frame was computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Cfi,
+    FramePointer,
+    /// signal trampoline, the caller is the frame interrupted by the signal
+    Signal,
+    /// legacy vsyscall page
+    Vsyscall,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub kind: FrameKind,
+    /// Canonical Frame Address (rsp of the caller just before the call)
+    pub cfa: u64,
+    /// register and offset which the CFA is computed from
+    pub cfa_rule: (RegisterType, i64),
+    /// where the registers of the caller are saved, rip is the return address slot
+    pub saved: Vec<(RegisterType, u64)>,
+    /// registers as of this frame, caller-saved registers of outer frames are not recovered
+    pub regs: user_regs_struct,
+    /// the frame was interrupted by a signal, so the pc is not a return address
+    pub interrupted: bool,
+}
+
+/// result of unwinding one frame
+struct Unwound {
+    cfa: u64,
+    cfa_rule: (RegisterType, i64),
+    saved: Vec<(RegisterType, u64)>,
+    caller: user_regs_struct,
+}
+
+impl Frame {
+    pub fn pc(&self) -> u64 {
+        self.regs.rip
+    }
+
+    /// address to look up the function and line of the frame
+    pub fn lookup_pc(&self, level: usize) -> u64 {
+        // 呼び出し元のフレームではreturn addressがcall命令の次を指しているので1引いて探す
+        if level == 0 || self.interrupted {
+            self.pc()
+        } else {
+            self.pc() - 1
+        }
+    }
+}
+
+/// unwind the stack of the target from the current registers
+pub fn backtrace(debug_info: &TdbDebugInfo) -> Vec<Frame> {
+    let pid = debug_info.target_pid();
+    let mut regs = get_regs(pid);
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut interrupted = false;
+
+    while frames.len() < MAX_FRAMES {
+        // 呼び出し元のフレームではreturn addressがcall命令の次を指しているので1引いて探す
+        let lookup_pc = if frames.is_empty() || interrupted {
+            regs.rip
+        } else {
+            regs.rip - 1
+        };
+        let (kind, step) = if is_sigreturn(pid, regs.rip) {
+            (FrameKind::Signal, signal_step(pid, &regs))
+        } else if (VSYSCALL_START..VSYSCALL_END).contains(&regs.rip) {
+            (FrameKind::Vsyscall, vsyscall_step(pid, &regs))
+        } else {
+            match cfi_step(debug_info, &regs, lookup_pc) {
+                Some(step) => (FrameKind::Cfi, Some(step)),
+                None => (FrameKind::FramePointer, fp_step(pid, &regs)),
+            }
+        };
+        let unwound = match step {
+            Some(unwound) => unwound,
+            None => {
+                frames.push(Frame {
+                    kind,
+                    cfa: regs.rbp.wrapping_add(16),
+                    cfa_rule: (RegisterType::Rbp, 16),
+                    saved: Vec::new(),
+                    regs,
+                    interrupted,
+                });
+                break;
+            }
+        };
+        let caller = unwound.caller;
+        frames.push(Frame {
+            kind,
+            cfa: unwound.cfa,
+            cfa_rule: unwound.cfa_rule,
+            saved: unwound.saved,
+            regs,
+            interrupted,
+        });
+
+        // スタックは上位アドレスに向かって巻き戻るはず、シグナルはsigaltstackで処理されることがある
+        if caller.rip == 0 || (kind != FrameKind::Signal && caller.rsp <= regs.rsp) {
+            break;
+        }
+        interrupted = kind == FrameKind::Signal;
+        regs = caller;
+    }
+    frames
+}
+
+/// unwind one frame with .eh_frame of the target file
+fn cfi_step(debug_info: &TdbDebugInfo, regs: &user_regs_struct, lookup_pc: u64) -> Option<Unwound> {
+    let pid = debug_info.target_pid();
+    let file_pc = debug_info.to_file_addr(lookup_pc)?;
+    let (eh_frame, bases) = eh_frame()?;
+
+    let mut ctx = UnwindContext::new();
+    let row = eh_frame
+        .unwind_info_for_address(&bases, &mut ctx, file_pc, EhFrame::cie_from_offset)
+        .ok()?;
+
+    let (cfa, cfa_rule) = match row.cfa() {
+        CfaRule::RegisterAndOffset { register, offset } => {
+            let base = RegisterType::from_dwarf(register.0)?;
+            (
+                base.get_value(regs).wrapping_add(*offset as u64),
+                (base, *offset),
+            )
+        }
+        CfaRule::Expression(_) => return None,
+    };
+
+    let mut caller = *regs;
+    let mut saved = Vec::new();
+    for reg in [
+        X86_64::RBX,
+        X86_64::RBP,
+        X86_64::R12,
+        X86_64::R13,
+        X86_64::R14,
+        X86_64::R15,
+        X86_64::RA,
+    ] {
+        let value = match row.register(reg) {
+            RegisterRule::Offset(offset) => {
+                let slot = cfa.wrapping_add(offset as u64);
+                saved.push((RegisterType::from_dwarf(reg.0)?, slot));
+                read_u64(pid, slot)?
+            }
+            RegisterRule::ValOffset(offset) => cfa.wrapping_add(offset as u64),
+            RegisterRule::Register(from) => RegisterType::from_dwarf(from.0)?.get_value(regs),
+            // return addressが定義されていないのは最も外側のフレーム
+            RegisterRule::Undefined if reg == X86_64::RA => return None,
+            _ => continue,
+        };
+        RegisterType::from_dwarf(reg.0)?.set_value(&mut caller, value);
+    }
+    caller.rsp = cfa;
+    Some(Unwound {
+        cfa,
+        cfa_rule,
+        saved,
+        caller,
+    })
+}
+
+/// .eh_frame of the target file with the addresses its pointers are relative to
+fn eh_frame() -> Option<(EhFrame<EndianSlice<'static, RunTimeEndian>>, BaseAddresses)> {
+    let object = debug_info::object();
+    let section = object.section_by_name(".eh_frame")?;
+    let eh_frame = EhFrame::new(section.data().ok()?, debug_info::endian());
+    let mut bases = BaseAddresses::default().set_eh_frame(section.address());
+    if let Some(text) = object.section_by_name(".text") {
+        bases = bases.set_text(text.address());
+    }
+    if let Some(hdr) = object.section_by_name(".eh_frame_hdr") {
+        bases = bases.set_eh_frame_hdr(hdr.address());
+    }
+    Some((eh_frame, bases))
+}
+
+/// landing pads of the function at the actual address `pc`, from its LSDA in .gcc_except_table
+/// only the unwinder jumps to them, to run cleanups or to catch an exception
+pub fn landing_pads(debug_info: &TdbDebugInfo, pc: u64) -> Vec<u64> {
+    let Some(file_pc) = debug_info.to_file_addr(pc) else {
+        return Vec::new();
+    };
+    let load_bias = debug_info.load_bias();
+    lsda_landing_pads(file_pc)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|pad| pad + load_bias)
+        .collect()
+}
+
+fn lsda_landing_pads(file_pc: u64) -> Option<Vec<u64>> {
+    let (eh_frame, bases) = eh_frame()?;
+    let fde = eh_frame
+        .fde_for_address(&bases, file_pc, EhFrame::cie_from_offset)
+        .ok()?;
+    let Pointer::Direct(lsda) = fde.lsda()? else {
+        return None;
+    };
+    let object = debug_info::object();
+    let section = object
+        .sections()
+        .find(|s| (s.address()..s.address() + s.size()).contains(&lsda))?;
+    let data = section.data().ok()?;
+    let mut reader = EndianSlice::new(
+        data.get((lsda - section.address()) as usize..)?,
+        debug_info::endian(),
+    );
+
+    // 省略されていればランディングパッドは関数の先頭からのオフセット
+    let lpstart = match DwEhPe(reader.read_u8().ok()?) {
+        constants::DW_EH_PE_omit => fde.initial_address(),
+        encoding => read_encoded(&mut reader, encoding)?,
+    };
+    let ttype = DwEhPe(reader.read_u8().ok()?);
+    if ttype != constants::DW_EH_PE_omit {
+        reader.read_uleb128().ok()?;
+    }
+    let encoding = DwEhPe(reader.read_u8().ok()?);
+    let len = reader.read_uleb128().ok()?;
+    let mut call_sites = reader.split(len as usize).ok()?;
+
+    let mut pads = Vec::new();
+    while !call_sites.is_empty() {
+        // 開始位置、長さ、ランディングパッド、アクションの順
+        read_encoded(&mut call_sites, encoding)?;
+        read_encoded(&mut call_sites, encoding)?;
+        let pad = read_encoded(&mut call_sites, encoding)?;
+        call_sites.read_uleb128().ok()?;
+        // 0は例外が来ても何もしない呼び出し
+        if pad != 0 {
+            pads.push(lpstart + pad);
+        }
+    }
+    pads.sort();
+    pads.dedup();
+    Some(pads)
+}
+
+/// value of the LSDA in `encoding`, values relative to other addresses are not supported
+fn read_encoded(reader: &mut EndianSlice<'static, RunTimeEndian>, encoding: DwEhPe) -> Option<u64> {
+    if encoding.application() != constants::DW_EH_PE_absptr {
+        return None;
+    }
+    let value = match encoding.format() {
+        constants::DW_EH_PE_absptr | constants::DW_EH_PE_udata8 => reader.read_u64().ok()?,
+        constants::DW_EH_PE_uleb128 => reader.read_uleb128().ok()?,
+        constants::DW_EH_PE_udata2 => reader.read_u16().ok()? as u64,
+        constants::DW_EH_PE_udata4 => reader.read_u32().ok()? as u64,
+        constants::DW_EH_PE_sleb128 => reader.read_sleb128().ok()? as u64,
+        constants::DW_EH_PE_sdata2 => reader.read_i16().ok()? as u64,
+        constants::DW_EH_PE_sdata4 => reader.read_i32().ok()? as u64,
+        constants::DW_EH_PE_sdata8 => reader.read_i64().ok()? as u64,
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// whether the pc points to the signal return trampoline
+fn is_sigreturn(pid: Pid, pc: u64) -> bool {
+    mem::read_bytes(pid, pc, SIGRETURN_CODE.len()).is_ok_and(|code| code == SIGRETURN_CODE)
+}
+
+/// recover the interrupted registers from the ucontext pushed by the kernel
+fn signal_step(pid: Pid, regs: &user_regs_struct) -> Option<Unwound> {
+    // 戻り先は__restore_rtなので、rspはucontextを指している
+    let gregs =
+        regs.rsp + (offset_of!(ucontext_t, uc_mcontext) + offset_of!(mcontext_t, gregs)) as u64;
+    let mut caller = *regs;
+    let mut saved = Vec::new();
+    for (reg, index) in [
+        (RegisterType::R8, libc::REG_R8),
+        (RegisterType::R9, libc::REG_R9),
+        (RegisterType::R10, libc::REG_R10),
+        (RegisterType::R11, libc::REG_R11),
+        (RegisterType::R12, libc::REG_R12),
+        (RegisterType::R13, libc::REG_R13),
+        (RegisterType::R14, libc::REG_R14),
+        (RegisterType::R15, libc::REG_R15),
+        (RegisterType::Rdi, libc::REG_RDI),
+        (RegisterType::Rsi, libc::REG_RSI),
+        (RegisterType::Rbp, libc::REG_RBP),
+        (RegisterType::Rbx, libc::REG_RBX),
+        (RegisterType::Rdx, libc::REG_RDX),
+        (RegisterType::Rax, libc::REG_RAX),
+        (RegisterType::Rcx, libc::REG_RCX),
+        (RegisterType::Rsp, libc::REG_RSP),
+        (RegisterType::Rip, libc::REG_RIP),
+        (RegisterType::Eflags, libc::REG_EFL),
+    ] {
+        let slot = gregs + 8 * index as u64;
+        reg.set_value(&mut caller, read_u64(pid, slot)?);
+        saved.push((reg, slot));
+    }
+    Some(Unwound {
+        cfa: regs.rsp,
+        cfa_rule: (RegisterType::Rsp, 0),
+        saved,
+        caller,
+    })
+}
+
+/// functions in the vsyscall page do not touch the stack before returning
+fn vsyscall_step(pid: Pid, regs: &user_regs_struct) -> Option<Unwound> {
+    let cfa = regs.rsp.checked_add(8)?;
+    let mut caller = *regs;
+    caller.rip = read_u64(pid, regs.rsp)?;
+    caller.rsp = cfa;
+    Some(Unwound {
+        cfa,
+        cfa_rule: (RegisterType::Rsp, 8),
+        saved: vec![(RegisterType::Rip, regs.rsp)],
+        caller,
+    })
+}
+
+/// unwind one frame by following the rbp chain
+fn fp_step(pid: Pid, regs: &user_regs_struct) -> Option<Unwound> {
+    let cfa = regs.rbp.checked_add(16)?;
+    let mut caller = *regs;
+    caller.rbp = read_u64(pid, regs.rbp)?;
+    caller.rip = read_u64(pid, regs.rbp + 8)?;
+    caller.rsp = cfa;
+    Some(Unwound {
+        cfa,
+        cfa_rule: (RegisterType::Rbp, 16),
+        saved: vec![
+            (RegisterType::Rbp, regs.rbp),
+            (RegisterType::Rip, regs.rbp + 8),
+        ],
+        caller,
+    })
+}
+
+fn read_u64(pid: Pid, addr: u64) -> Option<u64> {
+    let bytes = mem::read_bytes(pid, addr, 8).ok()?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
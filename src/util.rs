@@ -1,10 +1,8 @@
 pub fn parse_demical_or_hex(s: &str) -> Result<u64, Box<dyn std::error::Error>> {
     let mut n = 0;
-    let prefix = &s[0..=1];
-    if prefix == "0x" {
-        let hex_str = &s[2..];
+    if let Some(hex_str) = s.strip_prefix("0x") {
         // hex::decodeは偶数個の数字文字でないとパースしてくれないのでこうしている
-        let hex_string = if hex_str.len() % 2 == 0 {
+        let hex_string = if hex_str.len().is_multiple_of(2) {
             String::from(hex_str)
         } else {
             ["0", hex_str].concat()
@@ -18,3 +16,28 @@ pub fn parse_demical_or_hex(s: &str) -> Result<u64, Box<dyn std::error::Error>>
     }
     Ok(n)
 }
+
+/// match `text` with `pattern` which may contain `*` wildcards
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+    let (mut p, mut t) = (0, 0);
+    // 最後に見た`*`の位置と、そこから対応させ始めたtextの位置
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
@@ -0,0 +1,1297 @@
+use crate::{
+    debug_info::{self, TdbDebugInfo},
+    mem, pretty,
+    register::RegisterType,
+    syscall::get_regs,
+    unwind::Frame,
+};
+use gimli::{
+    AttributeValue, DebugInfoOffset, DebuggingInformationEntry, DwAte, EndianSlice,
+    EntriesTreeNode, EvaluationResult, Expression, Location, Piece, RunTimeEndian, Unit,
+    UnitOffset,
+};
+use nix::unistd::Pid;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+type Reader = EndianSlice<'static, RunTimeEndian>;
+
+/// how many pointers are followed when a type is parsed
+const POINTER_DEPTH: u32 = 2;
+/// max number of array elements printed
+pub const MAX_ELEMENTS: u64 = 32;
+/// max length of C strings printed
+const MAX_STRING: u64 = 64;
+
+/// radix of integers printed, set by `set output-radix`
+static OUTPUT_RADIX: AtomicU32 = AtomicU32::new(10);
+
+/// 8, 10 or 16
+pub fn set_output_radix(radix: u32) -> Result<(), String> {
+    match radix {
+        8 | 10 | 16 => {
+            OUTPUT_RADIX.store(radix, Ordering::Relaxed);
+            Ok(())
+        }
+        _ => Err(format!("unsupported output radix {radix}, use 8, 10 or 16")),
+    }
+}
+
+pub fn output_radix() -> u32 {
+    OUTPUT_RADIX.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone)]
+pub enum Type {
+    Void,
+    Base {
+        name: String,
+        size: u64,
+        encoding: DwAte,
+    },
+    Pointer {
+        name: Option<String>,
+        target: Box<Type>,
+    },
+    Struct {
+        name: String,
+        size: u64,
+        members: Vec<Member>,
+        /// template type parameters such as `T` of `Vec<T>`
+        params: Vec<(String, Type)>,
+        variant_part: Option<Box<VariantPart>>,
+    },
+    Array {
+        elem: Box<Type>,
+        count: Option<u64>,
+    },
+    /// SIMD vector such as `__m128` or `int __attribute__((vector_size(16)))`
+    Vector {
+        elem: Box<Type>,
+        count: u64,
+    },
+    Enum {
+        name: String,
+        size: u64,
+        enumerators: Vec<(String, i64)>,
+    },
+    Typedef {
+        name: String,
+        ty: Box<Type>,
+    },
+    /// type which is not parsed yet (beyond pointers)
+    Lazy(DebugInfoOffset),
+    Unknown(String),
+}
+
+/// variants of a Rust enum, one of them is selected by the discriminant
+#[derive(Debug, Clone)]
+pub struct VariantPart {
+    pub discr: Option<Member>,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    /// None for the default variant
+    pub discr_value: Option<u64>,
+    /// member whose type is the struct of the variant
+    pub member: Member,
+}
+
+impl VariantPart {
+    /// the variant active in `bytes`
+    pub fn active(&self, bytes: &[u8]) -> Option<&Variant> {
+        let discr = match &self.discr {
+            Some(discr) => discr,
+            None => return self.variants.first(),
+        };
+        let size = discr.ty.size() as usize;
+        let start = discr.offset as usize;
+        let value = read_uint(bytes.get(start..start + size)?);
+        let mask = if size >= 8 {
+            u64::MAX
+        } else {
+            (1u64 << (size * 8)) - 1
+        };
+        self.variants
+            .iter()
+            .find(|v| v.discr_value.map(|d| d & mask) == Some(value))
+            .or_else(|| self.variants.iter().find(|v| v.discr_value.is_none()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub offset: u64,
+    pub ty: Type,
+}
+
+impl Type {
+    pub fn name(&self) -> String {
+        match self {
+            Type::Void => String::from("void"),
+            Type::Base { name, .. } => name.clone(),
+            Type::Pointer {
+                name: Some(name), ..
+            } => name.clone(),
+            Type::Pointer { target, .. } => format!("{} *", target.name()),
+            Type::Struct { name, .. } => name.clone(),
+            Type::Array { elem, count } => match count {
+                Some(count) => format!("{}[{}]", elem.name(), count),
+                None => format!("{}[]", elem.name()),
+            },
+            Type::Vector { elem, count } => {
+                format!("{} __attribute__((vector_size({})))", elem.name(), count)
+            }
+            Type::Enum { name, .. } => name.clone(),
+            Type::Typedef { name, .. } => name.clone(),
+            Type::Lazy(_) => String::from("?"),
+            Type::Unknown(name) => name.clone(),
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        match self {
+            Type::Void | Type::Unknown(_) | Type::Lazy(_) => 0,
+            Type::Base { size, .. } => *size,
+            Type::Pointer { .. } => 8,
+            Type::Struct { size, .. } => *size,
+            Type::Array { elem, count } => elem.size() * count.unwrap_or(0),
+            Type::Vector { elem, count } => elem.size() * count,
+            Type::Enum { size, .. } => *size,
+            Type::Typedef { ty, .. } => ty.size(),
+        }
+    }
+
+    /// resolve lazy types
+    pub fn resolve(&self) -> Type {
+        match self {
+            Type::Lazy(offset) => parse_type_at(*offset).unwrap_or(Type::Void),
+            ty => ty.clone(),
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self.canonical(), Type::Base { encoding, .. } if encoding == gimli::DW_ATE_float)
+    }
+
+    /// SIMD vector, returned in xmm0 by the System V ABI
+    pub fn is_vector(&self) -> bool {
+        matches!(self.canonical(), Type::Vector { .. })
+    }
+
+    /// the type without typedefs
+    pub fn canonical(&self) -> Type {
+        match self {
+            Type::Typedef { ty, .. } => ty.canonical(),
+            Type::Lazy(_) => self.resolve().canonical(),
+            ty => ty.clone(),
+        }
+    }
+}
+
+/// where the value of a variable lives
+#[derive(Debug, Clone, Copy)]
+pub enum VarLocation {
+    Address(u64),
+    Register(RegisterType),
+    Value(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableKind {
+    Local,
+    Parameter,
+    Global,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: String,
+    pub ty: Type,
+    pub kind: VariableKind,
+    /// error message if the location is not available
+    pub location: Result<Vec<VarLocation>, String>,
+}
+
+impl Variable {
+    /// read the bytes of the variable as of `frame`
+    pub fn read(&self, pid: Pid, frame: Option<&Frame>) -> Result<Vec<u8>, String> {
+        let locations = self.location.as_ref().map_err(|e| e.clone())?;
+        let size = self.ty.size();
+        let mut bytes = Vec::new();
+        for location in locations {
+            match *location {
+                VarLocation::Address(addr) => {
+                    let read = mem::read_bytes(pid, addr, size as usize)
+                        .map_err(|e| format!("cannot access memory at 0x{:x}: {}", addr, e))?;
+                    bytes.extend(read);
+                }
+                VarLocation::Register(reg) => {
+                    let frame = frame.ok_or("no frame selected")?;
+                    bytes.extend(reg.get_value(&frame.regs).to_le_bytes());
+                }
+                VarLocation::Value(value) => bytes.extend(value.to_le_bytes()),
+            }
+        }
+        bytes.truncate(size as usize);
+        Ok(bytes)
+    }
+}
+
+/// local variables of the scope which contains the pc of `frame`
+pub fn locals(debug_info: &TdbDebugInfo, frame: &Frame, level: usize) -> Vec<Variable> {
+    frame_variables(debug_info, frame, level)
+        .into_iter()
+        .filter(|v| v.kind == VariableKind::Local)
+        .collect()
+}
+
+/// formal parameters of the function which contains the pc of `frame`
+pub fn args(debug_info: &TdbDebugInfo, frame: &Frame, level: usize) -> Vec<Variable> {
+    frame_variables(debug_info, frame, level)
+        .into_iter()
+        .filter(|v| v.kind == VariableKind::Parameter)
+        .collect()
+}
+
+/// variable visible from the pc of `frame`, inner scopes shadow outer ones
+pub fn lookup(
+    debug_info: &TdbDebugInfo,
+    frame: &Frame,
+    level: usize,
+    name: &str,
+) -> Option<Variable> {
+    frame_variables(debug_info, frame, level)
+        .into_iter()
+        .rev()
+        .find(|v| v.name == name)
+}
+
+/// global variable, `name` can be qualified by namespaces (e.g. `mycrate::COUNTER`)
+pub fn global(debug_info: &TdbDebugInfo, name: &str) -> Option<Variable> {
+    let dwarf = debug_info::dwarf()?;
+    let mut units = dwarf.units();
+    while let Ok(Some(header)) = units.next() {
+        let unit = match dwarf.unit(header) {
+            Ok(unit) => unit,
+            Err(_) => continue,
+        };
+        let mut tree = match unit.entries_tree(None) {
+            Ok(tree) => tree,
+            Err(_) => continue,
+        };
+        let root = match tree.root() {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+        let ctx = EvalContext {
+            debug_info,
+            frame: None,
+            unit: &unit,
+            frame_base: None,
+        };
+        if let Ok(Some(var)) = find_global(root, &ctx, "", name) {
+            return Some(var);
+        }
+    }
+    None
+}
+
+fn find_global(
+    node: EntriesTreeNode<Reader>,
+    ctx: &EvalContext,
+    namespace: &str,
+    name: &str,
+) -> gimli::Result<Option<Variable>> {
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        match entry.tag() {
+            gimli::DW_TAG_variable => {
+                let var = match variable(entry, ctx, None, VariableKind::Global)? {
+                    Some(var) => var,
+                    None => continue,
+                };
+                let qualified = format!("{}{}", namespace, var.name);
+                let matched = var.name == name
+                    || qualified == name
+                    || is_thread_local_storage(entry, ctx, &qualified, name)?;
+                if matched && var.location.is_ok() {
+                    return Ok(Some(var));
+                }
+            }
+            gimli::DW_TAG_namespace => {
+                let ns = match entry.attr_value(gimli::DW_AT_name)? {
+                    Some(ns) => attr_string(ctx.unit, ns),
+                    None => continue,
+                };
+                let namespace = format!("{}{}::", namespace, ns);
+                if let Some(var) = find_global(child, ctx, &namespace, name)? {
+                    return Ok(Some(var));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// `thread_local!` keeps the value in a hidden variable nested under the key
+/// e.g. `mycrate::COUNTER::{constant#0}::{closure#1}::__RUST_STD_INTERNAL_VAL`
+fn is_thread_local_storage(
+    entry: &DebuggingInformationEntry<Reader>,
+    ctx: &EvalContext,
+    qualified: &str,
+    name: &str,
+) -> gimli::Result<bool> {
+    let key = format!("{}::", name);
+    if !qualified.ends_with("::__RUST_STD_INTERNAL_VAL")
+        || !(qualified.starts_with(&key) || qualified.contains(&format!("::{}", key)))
+    {
+        return Ok(false);
+    }
+    // 使われない複製にもDIEが残るので、シンボルがあるものだけを採る
+    let linkage_name = match entry.attr_value(gimli::DW_AT_linkage_name)? {
+        Some(linkage_name) => attr_string(ctx.unit, linkage_name),
+        None => return Ok(false),
+    };
+    Ok(ctx
+        .debug_info
+        .symbols()
+        .iter()
+        .any(|sym| sym.name() == linkage_name))
+}
+
+/// variables of the function which contains the pc of `frame`
+fn frame_variables(debug_info: &TdbDebugInfo, frame: &Frame, level: usize) -> Vec<Variable> {
+    let dwarf = match debug_info::dwarf() {
+        Some(dwarf) => dwarf,
+        None => return Vec::new(),
+    };
+    // 呼び出し元のフレームではcall命令の位置で探す
+    let pc = frame.lookup_pc(level);
+    let file_pc = match debug_info.to_file_addr(pc) {
+        Some(file_pc) => file_pc,
+        None => return Vec::new(),
+    };
+
+    let mut units = dwarf.units();
+    while let Ok(Some(header)) = units.next() {
+        let unit = match dwarf.unit(header) {
+            Ok(unit) => unit,
+            Err(_) => continue,
+        };
+        if !unit_contains(&unit, file_pc) {
+            continue;
+        }
+        let mut tree = match unit.entries_tree(None) {
+            Ok(tree) => tree,
+            Err(_) => continue,
+        };
+        let root = match tree.root() {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+        let ctx = EvalContext {
+            debug_info,
+            frame: Some(frame),
+            unit: &unit,
+            frame_base: None,
+        };
+        let mut vars = Vec::new();
+        if find_subprogram(root, &ctx, file_pc, &mut vars).is_ok() && !vars.is_empty() {
+            return vars;
+        }
+    }
+    Vec::new()
+}
+
+/// return type of the function which contains `file_pc`, Void if it returns nothing
+pub fn return_type(file_pc: u64) -> Option<Type> {
+    signature(file_pc).map(|signature| signature.ret)
+}
+
+/// types of the return value and the parameters of a function
+#[derive(Debug, Clone)]
+pub struct Signature {
+    /// Void if the function returns nothing
+    pub ret: Type,
+    pub params: Vec<Type>,
+    /// takes more arguments after `params` like `printf`
+    pub variadic: bool,
+}
+
+/// signature of the function which contains `file_pc`
+pub fn signature(file_pc: u64) -> Option<Signature> {
+    let dwarf = debug_info::dwarf()?;
+    let mut units = dwarf.units();
+    while let Ok(Some(header)) = units.next() {
+        let unit = match dwarf.unit(header) {
+            Ok(unit) => unit,
+            Err(_) => continue,
+        };
+        if !unit_contains(&unit, file_pc) {
+            continue;
+        }
+        let mut tree = match unit.entries_tree(None) {
+            Ok(tree) => tree,
+            Err(_) => continue,
+        };
+        let root = match tree.root() {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+        if let Ok(Some(signature)) = find_signature(root, &unit, file_pc) {
+            return Some(signature);
+        }
+    }
+    None
+}
+
+/// source language of the unit which contains `file_pc`
+pub fn language(file_pc: u64) -> Option<gimli::DwLang> {
+    let dwarf = debug_info::dwarf()?;
+    let mut units = dwarf.units();
+    while let Ok(Some(header)) = units.next() {
+        let unit = match dwarf.unit(header) {
+            Ok(unit) => unit,
+            Err(_) => continue,
+        };
+        if !unit_contains(&unit, file_pc) {
+            continue;
+        }
+        let mut entries = unit.entries();
+        let (_, root) = entries.next_dfs().ok()??;
+        return match root.attr_value(gimli::DW_AT_language).ok()?? {
+            AttributeValue::Language(language) => Some(language),
+            _ => None,
+        };
+    }
+    None
+}
+
+fn find_signature(
+    node: EntriesTreeNode<Reader>,
+    unit: &Unit<Reader>,
+    file_pc: u64,
+) -> gimli::Result<Option<Signature>> {
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        if entry.tag() == gimli::DW_TAG_subprogram {
+            if die_contains(unit, entry, file_pc) {
+                let ret = match entry.attr_value(gimli::DW_AT_type)? {
+                    Some(AttributeValue::UnitRef(offset)) => {
+                        parse_type(unit, offset, POINTER_DEPTH)
+                    }
+                    _ => Type::Void,
+                };
+                let mut signature = Signature {
+                    ret,
+                    params: Vec::new(),
+                    variadic: false,
+                };
+                let mut params = child.children();
+                while let Some(param) = params.next()? {
+                    let entry = param.entry();
+                    match entry.tag() {
+                        gimli::DW_TAG_formal_parameter => {
+                            let ty = match entry.attr_value(gimli::DW_AT_type)? {
+                                Some(AttributeValue::UnitRef(offset)) => {
+                                    parse_type(unit, offset, POINTER_DEPTH)
+                                }
+                                _ => Type::Unknown(String::from("parameter")),
+                            };
+                            signature.params.push(ty);
+                        }
+                        gimli::DW_TAG_unspecified_parameters => signature.variadic = true,
+                        _ => {}
+                    }
+                }
+                return Ok(Some(signature));
+            }
+        } else if let Some(signature) = find_signature(child, unit, file_pc)? {
+            return Ok(Some(signature));
+        }
+    }
+    Ok(None)
+}
+
+struct EvalContext<'a> {
+    debug_info: &'a TdbDebugInfo,
+    frame: Option<&'a Frame>,
+    unit: &'a Unit<Reader>,
+    frame_base: Option<u64>,
+}
+
+impl EvalContext<'_> {
+    fn frame(&self) -> Result<&Frame, String> {
+        self.frame.ok_or_else(|| String::from("no frame selected"))
+    }
+}
+
+fn unit_contains(unit: &Unit<Reader>, file_pc: u64) -> bool {
+    let dwarf = debug_info::dwarf().unwrap();
+    let mut ranges = match dwarf.unit_ranges(unit) {
+        Ok(ranges) => ranges,
+        Err(_) => return false,
+    };
+    while let Ok(Some(range)) = ranges.next() {
+        if (range.begin <= file_pc) && (file_pc < range.end) {
+            return true;
+        }
+    }
+    false
+}
+
+fn die_contains(
+    unit: &Unit<Reader>,
+    entry: &gimli::DebuggingInformationEntry<Reader>,
+    file_pc: u64,
+) -> bool {
+    let dwarf = debug_info::dwarf().unwrap();
+    let mut ranges = match dwarf.die_ranges(unit, entry) {
+        Ok(ranges) => ranges,
+        Err(_) => return false,
+    };
+    while let Ok(Some(range)) = ranges.next() {
+        if (range.begin <= file_pc) && (file_pc < range.end) {
+            return true;
+        }
+    }
+    false
+}
+
+/// find DW_TAG_subprogram which contains `file_pc` and collect its variables
+fn find_subprogram(
+    node: EntriesTreeNode<Reader>,
+    ctx: &EvalContext,
+    file_pc: u64,
+    vars: &mut Vec<Variable>,
+) -> gimli::Result<()> {
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        if entry.tag() == gimli::DW_TAG_subprogram {
+            if die_contains(ctx.unit, entry, file_pc) {
+                let frame_base = match entry.attr_value(gimli::DW_AT_frame_base)? {
+                    Some(AttributeValue::Exprloc(expr)) => frame_base(ctx, expr),
+                    _ => None,
+                };
+                let ctx = EvalContext { frame_base, ..*ctx };
+                return collect_variables(child, &ctx, file_pc, vars);
+            }
+        } else {
+            // namespaceなどの中にsubprogramがあることもある
+            find_subprogram(child, ctx, file_pc, vars)?;
+            if !vars.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// collect variables from the scope tree, lexical blocks which don't contain `file_pc` are skipped
+fn collect_variables(
+    node: EntriesTreeNode<Reader>,
+    ctx: &EvalContext,
+    file_pc: u64,
+    vars: &mut Vec<Variable>,
+) -> gimli::Result<()> {
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        let kind = match entry.tag() {
+            gimli::DW_TAG_variable => VariableKind::Local,
+            gimli::DW_TAG_formal_parameter => VariableKind::Parameter,
+            gimli::DW_TAG_lexical_block => {
+                if die_contains(ctx.unit, entry, file_pc) {
+                    collect_variables(child, ctx, file_pc, vars)?;
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        if let Some(var) = variable(entry, ctx, Some(file_pc), kind)? {
+            vars.push(var);
+        }
+    }
+    Ok(())
+}
+
+/// variable of the entry, the location list is looked up by `file_pc`
+fn variable(
+    entry: &gimli::DebuggingInformationEntry<Reader>,
+    ctx: &EvalContext,
+    file_pc: Option<u64>,
+    kind: VariableKind,
+) -> gimli::Result<Option<Variable>> {
+    let name = match entry.attr_value(gimli::DW_AT_name)? {
+        Some(name) => attr_string(ctx.unit, name),
+        None => return Ok(None),
+    };
+    let ty = match entry.attr_value(gimli::DW_AT_type)? {
+        Some(AttributeValue::UnitRef(offset)) => parse_type(ctx.unit, offset, POINTER_DEPTH),
+        _ => Type::Void,
+    };
+    let location = match (entry.attr_value(gimli::DW_AT_location)?, file_pc) {
+        (Some(AttributeValue::Exprloc(expr)), _) => locate(ctx, expr),
+        (Some(AttributeValue::LocationListsRef(offset)), Some(file_pc)) => {
+            let dwarf = debug_info::dwarf().unwrap();
+            let mut locations = dwarf.locations(ctx.unit, offset)?;
+            let mut location = Err(String::from("<optimized out>"));
+            while let Some(entry) = locations.next()? {
+                if (entry.range.begin <= file_pc) && (file_pc < entry.range.end) {
+                    location = locate(ctx, entry.data);
+                    break;
+                }
+            }
+            location
+        }
+        _ => Err(String::from("<optimized out>")),
+    };
+    Ok(Some(Variable {
+        name,
+        ty,
+        kind,
+        location,
+    }))
+}
+
+fn attr_string(unit: &Unit<Reader>, attr: AttributeValue<Reader>) -> String {
+    let dwarf = debug_info::dwarf().unwrap();
+    dwarf
+        .attr_string(unit, attr)
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// value of DW_AT_frame_base
+fn frame_base(ctx: &EvalContext, expr: Expression<Reader>) -> Option<u64> {
+    let pieces = evaluate(ctx, expr).ok()?;
+    match pieces.first()?.location {
+        Location::Register { register } => {
+            Some(RegisterType::from_dwarf(register.0)?.get_value(&ctx.frame().ok()?.regs))
+        }
+        Location::Address { address } => Some(address),
+        _ => None,
+    }
+}
+
+fn locate(ctx: &EvalContext, expr: Expression<Reader>) -> Result<Vec<VarLocation>, String> {
+    let pieces = evaluate(ctx, expr)?;
+    let mut locations = Vec::new();
+    for piece in pieces {
+        let location = match piece.location {
+            Location::Address { address } => VarLocation::Address(address),
+            Location::Register { register } => VarLocation::Register(
+                RegisterType::from_dwarf(register.0)
+                    .ok_or(format!("unsupported register: {}", register.0))?,
+            ),
+            Location::Value { value } => {
+                VarLocation::Value(value.to_u64(!0).map_err(|e| e.to_string())?)
+            }
+            _ => return Err(String::from("<optimized out>")),
+        };
+        locations.push(location);
+    }
+    if locations.is_empty() {
+        Err(String::from("<optimized out>"))
+    } else {
+        Ok(locations)
+    }
+}
+
+fn evaluate(ctx: &EvalContext, expr: Expression<Reader>) -> Result<Vec<Piece<Reader>>, String> {
+    let pid = ctx.debug_info.target_pid();
+    let mut eval = expr.evaluation(ctx.unit.encoding());
+    let mut result = eval.evaluate().map_err(|e| e.to_string())?;
+    loop {
+        result = match result {
+            EvaluationResult::Complete => break,
+            EvaluationResult::RequiresMemory { address, size, .. } => {
+                let bytes = mem::read_bytes(pid, address, size as usize)
+                    .map_err(|e| format!("cannot access memory at 0x{:x}: {}", address, e))?;
+                let mut value = [0u8; 8];
+                value[..bytes.len()].copy_from_slice(&bytes);
+                eval.resume_with_memory(gimli::Value::Generic(u64::from_le_bytes(value)))
+            }
+            EvaluationResult::RequiresRegister { register, .. } => {
+                let reg = RegisterType::from_dwarf(register.0)
+                    .ok_or(format!("unsupported register: {}", register.0))?;
+                let value = reg.get_value(&ctx.frame()?.regs);
+                eval.resume_with_register(gimli::Value::Generic(value))
+            }
+            EvaluationResult::RequiresFrameBase => {
+                let frame_base = ctx.frame_base.ok_or("frame base is not available")?;
+                eval.resume_with_frame_base(frame_base)
+            }
+            EvaluationResult::RequiresCallFrameCfa => {
+                eval.resume_with_call_frame_cfa(ctx.frame()?.cfa)
+            }
+            EvaluationResult::RequiresRelocatedAddress(address) => {
+                eval.resume_with_relocated_address(address + ctx.debug_info.load_bias())
+            }
+            EvaluationResult::RequiresIndexedAddress { index, relocate } => {
+                let dwarf = debug_info::dwarf().unwrap();
+                let mut address = dwarf.address(ctx.unit, index).map_err(|e| e.to_string())?;
+                if relocate {
+                    address += ctx.debug_info.load_bias();
+                }
+                eval.resume_with_indexed_address(address)
+            }
+            EvaluationResult::RequiresTls(offset) => {
+                // スレッドポインタは止めたスレッドの fs_base
+                let fs_base = match ctx.frame {
+                    Some(frame) => frame.regs.fs_base,
+                    None => get_regs(pid).fs_base,
+                };
+                let address = ctx
+                    .debug_info
+                    .tls_address(fs_base, offset)
+                    .ok_or("no TLS segment in the target file")?;
+                eval.resume_with_tls(address)
+            }
+            // 関数の入口での値は復元できない
+            EvaluationResult::RequiresEntryValue(_) => return Err(String::from("<optimized out>")),
+            _ => return Err(String::from("unsupported location expression")),
+        }
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(eval.result())
+}
+
+/// parse the type at the offset in .debug_info
+fn parse_type_at(offset: DebugInfoOffset) -> Option<Type> {
+    let dwarf = debug_info::dwarf()?;
+    let header = dwarf.debug_info.header_from_offset(offset).ok()?;
+    let unit_offset = offset.to_unit_offset(&header)?;
+    let unit = dwarf.unit(header).ok()?;
+    Some(parse_type(&unit, unit_offset, POINTER_DEPTH))
+}
+
+fn parse_type(unit: &Unit<Reader>, offset: UnitOffset, pointer_depth: u32) -> Type {
+    let entry = match unit.entry(offset) {
+        Ok(entry) => entry,
+        Err(e) => return Type::Unknown(e.to_string()),
+    };
+    let name = entry
+        .attr_value(gimli::DW_AT_name)
+        .ok()
+        .flatten()
+        .map(|name| attr_string(unit, name));
+    let size = entry
+        .attr_value(gimli::DW_AT_byte_size)
+        .ok()
+        .flatten()
+        .and_then(|size| size.udata_value())
+        .unwrap_or(0);
+    let inner = |pointer_depth| match entry.attr_value(gimli::DW_AT_type).ok().flatten() {
+        Some(AttributeValue::UnitRef(offset)) => parse_type(unit, offset, pointer_depth),
+        _ => Type::Void,
+    };
+
+    match entry.tag() {
+        gimli::DW_TAG_base_type => {
+            let encoding = match entry.attr_value(gimli::DW_AT_encoding).ok().flatten() {
+                Some(AttributeValue::Encoding(encoding)) => encoding,
+                _ => gimli::DW_ATE_unsigned,
+            };
+            Type::Base {
+                name: name.unwrap_or_default(),
+                size,
+                encoding,
+            }
+        }
+        gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => {
+            let target = if pointer_depth == 0 {
+                match entry.attr_value(gimli::DW_AT_type).ok().flatten() {
+                    Some(AttributeValue::UnitRef(target)) => {
+                        match target.to_debug_info_offset(&unit.header) {
+                            Some(offset) => Type::Lazy(offset),
+                            None => Type::Void,
+                        }
+                    }
+                    _ => Type::Void,
+                }
+            } else {
+                inner(pointer_depth - 1)
+            };
+            Type::Pointer {
+                name,
+                target: Box::new(target),
+            }
+        }
+        gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type | gimli::DW_TAG_class_type => {
+            let mut members = Vec::new();
+            let mut params = Vec::new();
+            let mut variant_part = None;
+            if let Ok(mut tree) = unit.entries_tree(Some(offset)) {
+                if let Ok(root) = tree.root() {
+                    let mut children = root.children();
+                    while let Ok(Some(child)) = children.next() {
+                        let entry = child.entry();
+                        match entry.tag() {
+                            gimli::DW_TAG_member => {
+                                members.push(parse_member(unit, entry, pointer_depth))
+                            }
+                            gimli::DW_TAG_template_type_parameter => {
+                                let param_name = entry
+                                    .attr_value(gimli::DW_AT_name)
+                                    .ok()
+                                    .flatten()
+                                    .map(|name| attr_string(unit, name))
+                                    .unwrap_or_default();
+                                let ty = match entry.attr_value(gimli::DW_AT_type).ok().flatten() {
+                                    Some(AttributeValue::UnitRef(ty)) => {
+                                        parse_type(unit, ty, pointer_depth)
+                                    }
+                                    _ => Type::Void,
+                                };
+                                params.push((param_name, ty));
+                            }
+                            gimli::DW_TAG_variant_part => {
+                                variant_part =
+                                    Some(Box::new(parse_variant_part(unit, child, pointer_depth)))
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            Type::Struct {
+                name: name.unwrap_or_default(),
+                size,
+                members,
+                params,
+                variant_part,
+            }
+        }
+        gimli::DW_TAG_array_type => {
+            let mut counts = Vec::new();
+            if let Ok(mut tree) = unit.entries_tree(Some(offset)) {
+                if let Ok(root) = tree.root() {
+                    let mut children = root.children();
+                    while let Ok(Some(child)) = children.next() {
+                        let subrange = child.entry();
+                        if subrange.tag() != gimli::DW_TAG_subrange_type {
+                            continue;
+                        }
+                        let count = subrange
+                            .attr_value(gimli::DW_AT_count)
+                            .ok()
+                            .flatten()
+                            .and_then(|count| count.udata_value())
+                            .or_else(|| {
+                                subrange
+                                    .attr_value(gimli::DW_AT_upper_bound)
+                                    .ok()
+                                    .flatten()
+                                    .and_then(|upper| upper.udata_value())
+                                    .map(|upper| upper + 1)
+                            });
+                        counts.push(count);
+                    }
+                }
+            }
+            // SIMDのベクトル型は添字の範囲が1つの配列として出力される
+            let vector = entry
+                .attr_value(gimli::DW_AT_GNU_vector)
+                .ok()
+                .flatten()
+                .is_some();
+            if let (true, [Some(count)]) = (vector, counts.as_slice()) {
+                return Type::Vector {
+                    elem: Box::new(inner(pointer_depth)),
+                    count: *count,
+                };
+            }
+            // 多次元配列は配列の配列にする
+            let mut ty = inner(pointer_depth);
+            for count in counts.into_iter().rev() {
+                ty = Type::Array {
+                    elem: Box::new(ty),
+                    count,
+                };
+            }
+            ty
+        }
+        gimli::DW_TAG_enumeration_type => {
+            let mut enumerators = Vec::new();
+            if let Ok(mut tree) = unit.entries_tree(Some(offset)) {
+                if let Ok(root) = tree.root() {
+                    let mut children = root.children();
+                    while let Ok(Some(child)) = children.next() {
+                        let enumerator = child.entry();
+                        if enumerator.tag() != gimli::DW_TAG_enumerator {
+                            continue;
+                        }
+                        let enumerator_name = enumerator
+                            .attr_value(gimli::DW_AT_name)
+                            .ok()
+                            .flatten()
+                            .map(|name| attr_string(unit, name))
+                            .unwrap_or_default();
+                        let value = enumerator
+                            .attr_value(gimli::DW_AT_const_value)
+                            .ok()
+                            .flatten()
+                            .and_then(|value| {
+                                value
+                                    .sdata_value()
+                                    .or(value.udata_value().map(|v| v as i64))
+                            })
+                            .unwrap_or(0);
+                        enumerators.push((enumerator_name, value));
+                    }
+                }
+            }
+            let size = if size == 0 { inner(0).size() } else { size };
+            Type::Enum {
+                name: name.unwrap_or_default(),
+                size,
+                enumerators,
+            }
+        }
+        gimli::DW_TAG_typedef => Type::Typedef {
+            name: name.unwrap_or_default(),
+            ty: Box::new(inner(pointer_depth)),
+        },
+        // 修飾子は無視する
+        gimli::DW_TAG_const_type
+        | gimli::DW_TAG_volatile_type
+        | gimli::DW_TAG_restrict_type
+        | gimli::DW_TAG_atomic_type => inner(pointer_depth),
+        gimli::DW_TAG_subroutine_type => Type::Unknown(String::from("fn")),
+        tag => Type::Unknown(name.unwrap_or_else(|| tag.to_string())),
+    }
+}
+
+fn parse_member(
+    unit: &Unit<Reader>,
+    entry: &gimli::DebuggingInformationEntry<Reader>,
+    pointer_depth: u32,
+) -> Member {
+    let name = entry
+        .attr_value(gimli::DW_AT_name)
+        .ok()
+        .flatten()
+        .map(|name| attr_string(unit, name))
+        .unwrap_or_default();
+    let offset = entry
+        .attr_value(gimli::DW_AT_data_member_location)
+        .ok()
+        .flatten()
+        .and_then(|offset| offset.udata_value())
+        .unwrap_or(0);
+    let ty = match entry.attr_value(gimli::DW_AT_type).ok().flatten() {
+        Some(AttributeValue::UnitRef(ty)) => parse_type(unit, ty, pointer_depth),
+        _ => Type::Void,
+    };
+    Member { name, offset, ty }
+}
+
+/// variants of Rust enums
+fn parse_variant_part(
+    unit: &Unit<Reader>,
+    node: EntriesTreeNode<Reader>,
+    pointer_depth: u32,
+) -> VariantPart {
+    let discr_offset = match node.entry().attr_value(gimli::DW_AT_discr).ok().flatten() {
+        Some(AttributeValue::UnitRef(offset)) => Some(offset),
+        _ => None,
+    };
+    let mut discr = None;
+    let mut variants = Vec::new();
+    let mut children = node.children();
+    while let Ok(Some(child)) = children.next() {
+        let entry = child.entry();
+        match entry.tag() {
+            gimli::DW_TAG_member if Some(entry.offset()) == discr_offset => {
+                discr = Some(parse_member(unit, entry, pointer_depth));
+            }
+            gimli::DW_TAG_variant => {
+                let discr_value = entry
+                    .attr_value(gimli::DW_AT_discr_value)
+                    .ok()
+                    .flatten()
+                    .and_then(|value| {
+                        value
+                            .udata_value()
+                            .or(value.sdata_value().map(|v| v as u64))
+                    });
+                let mut members = child.children();
+                while let Ok(Some(member)) = members.next() {
+                    if member.entry().tag() == gimli::DW_TAG_member {
+                        variants.push(Variant {
+                            discr_value,
+                            member: parse_member(unit, member.entry(), pointer_depth),
+                        });
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    VariantPart { discr, variants }
+}
+
+/// format bytes of the value by its type
+pub fn format_value(pid: Pid, ty: &Type, bytes: &[u8]) -> String {
+    if let Some(s) = pretty::format(pid, ty, bytes) {
+        return s;
+    }
+    match ty {
+        Type::Base {
+            name,
+            size,
+            encoding,
+        } => format_base(name, *size, *encoding, bytes),
+        Type::Pointer { target, .. } => {
+            let addr = read_uint(bytes);
+            match target.resolve() {
+                Type::Base {
+                    size: 1, encoding, ..
+                } if encoding == gimli::DW_ATE_signed_char
+                    || encoding == gimli::DW_ATE_unsigned_char =>
+                {
+                    match read_c_string(pid, addr) {
+                        Some(s) => format!("0x{:x} {:?}", addr, s),
+                        None => format!("0x{:x}", addr),
+                    }
+                }
+                _ => format!("0x{:x}", addr),
+            }
+        }
+        Type::Struct {
+            variant_part: Some(variant_part),
+            ..
+        } => match variant_part.active(bytes) {
+            Some(variant) => format_variant(pid, variant, bytes),
+            None => String::from("<invalid enum>"),
+        },
+        // タプル
+        Type::Struct { name, members, .. } if name.starts_with('(') && is_tuple(members) => {
+            format!("({})", format_fields(pid, members, bytes, 0, false))
+        }
+        Type::Struct { members, .. } => {
+            format!("{{ {} }}", format_fields(pid, members, bytes, 0, true))
+        }
+        Type::Array { elem, count } => {
+            let elem_size = elem.size() as usize;
+            let count = count.unwrap_or(0);
+            if let Type::Base {
+                size: 1, encoding, ..
+            } = elem.as_ref()
+            {
+                if *encoding == gimli::DW_ATE_signed_char
+                    || *encoding == gimli::DW_ATE_unsigned_char
+                {
+                    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+                    return format!("{:?}", String::from_utf8_lossy(&bytes[..end]));
+                }
+            }
+            if elem_size == 0 {
+                return String::from("[]");
+            }
+            let mut elems = Vec::new();
+            for i in 0..count.min(MAX_ELEMENTS) as usize {
+                match bytes.get(i * elem_size..(i + 1) * elem_size) {
+                    Some(bytes) => elems.push(format_value(pid, elem, bytes)),
+                    None => break,
+                }
+            }
+            if count > MAX_ELEMENTS {
+                elems.push(String::from("..."));
+            }
+            format!("[{}]", elems.join(", "))
+        }
+        Type::Vector { elem, .. } => {
+            let elem_size = elem.size() as usize;
+            if elem_size == 0 {
+                return String::from("{}");
+            }
+            let elems = bytes
+                .chunks_exact(elem_size)
+                .map(|bytes| format_value(pid, elem, bytes))
+                .collect::<Vec<String>>();
+            format!("{{{}}}", elems.join(", "))
+        }
+        Type::Enum { enumerators, .. } => {
+            let value = read_int(bytes);
+            match enumerators.iter().find(|(_, v)| *v == value) {
+                Some((name, _)) => name.clone(),
+                None => value.to_string(),
+            }
+        }
+        Type::Typedef { ty, .. } => format_value(pid, ty, bytes),
+        Type::Lazy(_) => format_value(pid, &ty.resolve(), bytes),
+        Type::Void | Type::Unknown(_) => {
+            let hex = bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<String>>();
+            format!("<{}: {}>", ty.name(), hex.join(" "))
+        }
+    }
+}
+
+/// rustc names fields of tuples `__0`, `__1`, ...
+fn is_tuple(members: &[Member]) -> bool {
+    !members.is_empty() && members.iter().all(|m| m.name.starts_with("__"))
+}
+
+/// format members at `base` of `bytes`
+fn format_fields(pid: Pid, members: &[Member], bytes: &[u8], base: u64, named: bool) -> String {
+    members
+        .iter()
+        .map(|member| {
+            let start = (base + member.offset) as usize;
+            let end = start + member.ty.size() as usize;
+            let value = match bytes.get(start..end) {
+                Some(bytes) => format_value(pid, &member.ty, bytes),
+                None => String::from("<unavailable>"),
+            };
+            if named {
+                format!("{} = {}", member.name, value)
+            } else {
+                value
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// `Some(1)`, `None`, `Point { x = 1, y = 2 }`
+fn format_variant(pid: Pid, variant: &Variant, bytes: &[u8]) -> String {
+    let name = &variant.member.name;
+    let base = variant.member.offset;
+    let members = match variant.member.ty.canonical() {
+        Type::Struct { members, .. } => members,
+        _ => Vec::new(),
+    };
+    if members.is_empty() {
+        name.clone()
+    } else if is_tuple(&members) {
+        format!(
+            "{}({})",
+            name,
+            format_fields(pid, &members, bytes, base, false)
+        )
+    } else {
+        format!(
+            "{} {{ {} }}",
+            name,
+            format_fields(pid, &members, bytes, base, true)
+        )
+    }
+}
+
+fn format_base(name: &str, size: u64, encoding: DwAte, bytes: &[u8]) -> String {
+    match encoding {
+        gimli::DW_ATE_boolean => (read_uint(bytes) != 0).to_string(),
+        gimli::DW_ATE_float => match size {
+            4 => f32::from_bits(read_uint(bytes) as u32).to_string(),
+            8 => f64::from_bits(read_uint(bytes)).to_string(),
+            // x86-64のlong doubleはx87の80ビット拡張精度
+            16 if name == "long double" => x87_to_f64(bytes).to_string(),
+            _ => format!("0x{:x}", read_u128(bytes)),
+        },
+        gimli::DW_ATE_signed | gimli::DW_ATE_unsigned if output_radix() != 10 => {
+            format_radix(read_u128(bytes))
+        }
+        gimli::DW_ATE_signed if size > 8 => read_i128(bytes).to_string(),
+        gimli::DW_ATE_signed => read_int(bytes).to_string(),
+        gimli::DW_ATE_signed_char | gimli::DW_ATE_unsigned_char => {
+            let c = read_uint(bytes) as u8;
+            format!("{} {:?}", read_int(bytes), c as char)
+        }
+        gimli::DW_ATE_UTF => match char::from_u32(read_uint(bytes) as u32) {
+            Some(c) => format!("{:?}", c),
+            None => read_uint(bytes).to_string(),
+        },
+        _ if size > 8 => read_u128(bytes).to_string(),
+        _ => read_uint(bytes).to_string(),
+    }
+}
+
+/// bits of an integer in the output radix other than 10, negative values are not signed
+fn format_radix(value: u128) -> String {
+    match output_radix() {
+        8 if value == 0 => String::from("0"),
+        8 => format!("0{value:o}"),
+        _ => format!("0x{value:x}"),
+    }
+}
+
+/// 80-bit extended precision value rounded to f64
+fn x87_to_f64(bytes: &[u8]) -> f64 {
+    let mantissa = read_uint(bytes);
+    let exponent_sign = read_uint(bytes.get(8..10).unwrap_or_default()) as u16;
+    let sign = if exponent_sign & 0x8000 != 0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let exponent = (exponent_sign & 0x7fff) as i32;
+    if exponent == 0x7fff {
+        return match mantissa << 1 {
+            0 => sign * f64::INFINITY,
+            _ => f64::NAN,
+        };
+    }
+    // 整数ビットが明示されているので仮数は1.0ではなく2^63が基準
+    sign * mantissa as f64 * 2f64.powi(exponent - 16383 - 63)
+}
+
+/// little endian unsigned integer up to 8 bytes
+pub fn read_uint(bytes: &[u8]) -> u64 {
+    let mut value = [0u8; 8];
+    let len = bytes.len().min(8);
+    value[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(value)
+}
+
+/// little endian signed integer up to 8 bytes
+pub fn read_int(bytes: &[u8]) -> i64 {
+    let len = bytes.len().min(8);
+    if len == 0 {
+        return 0;
+    }
+    let shift = 64 - len * 8;
+    ((read_uint(bytes) << shift) as i64) >> shift
+}
+
+/// little endian unsigned integer up to 16 bytes
+pub fn read_u128(bytes: &[u8]) -> u128 {
+    let mut value = [0u8; 16];
+    let len = bytes.len().min(16);
+    value[..len].copy_from_slice(&bytes[..len]);
+    u128::from_le_bytes(value)
+}
+
+/// little endian signed integer up to 16 bytes
+pub fn read_i128(bytes: &[u8]) -> i128 {
+    let len = bytes.len().min(16);
+    if len == 0 {
+        return 0;
+    }
+    let shift = 128 - len * 8;
+    ((read_u128(bytes) << shift) as i128) >> shift
+}
+
+pub fn read_c_string(pid: Pid, addr: u64) -> Option<String> {
+    let mut s = Vec::new();
+    for i in 0..MAX_STRING {
+        let b = *mem::read_bytes(pid, addr + i, 1).ok()?.first()?;
+        if b == 0 {
+            break;
+        }
+        s.push(b);
+    }
+    Some(String::from_utf8_lossy(&s).to_string())
+}